@@ -0,0 +1,39 @@
+//! Locates or builds the `chisel` shared library.
+//!
+//! Set `CHISEL_LIB_DIR` to a directory holding an already built library
+//! (configured with `-DCHISEL_BUILD_SHARED=ON`). Otherwise the library is
+//! built from this repository with CMake, without the command-line tool.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=CHISEL_LIB_DIR");
+
+    let lib_dir = match env::var_os("CHISEL_LIB_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../..");
+            println!("cargo:rerun-if-changed={}", root.join("libchisel/include/chisel_c.h").display());
+            println!("cargo:rerun-if-changed={}", root.join("libchisel/src/utils/chisel_c.cpp").display());
+
+            let dst = cmake::Config::new(&root)
+                .profile("Release")
+                .define("CHISEL_BUILD_CLI", "OFF")
+                .define("CHISEL_BUILD_SHARED", "ON")
+                .build_target("chisel_shared")
+                .build();
+            let build_dir = dst.join("build").join("libchisel");
+            // multi-config generators (Visual Studio, Xcode) add the configuration
+            if build_dir.join("Release").is_dir() {
+                build_dir.join("Release")
+            } else {
+                build_dir
+            }
+        }
+    };
+
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=dylib=chisel");
+    println!("cargo:lib_dir={}", lib_dir.display());
+}
@@ -0,0 +1,78 @@
+//! Raw FFI bindings to the libchisel C API (`libchisel/include/chisel_c.h`).
+//!
+//! Use the `chisel` crate for a safe interface. The layouts and constants
+//! below must stay in sync with `chisel_c.h`.
+
+#![allow(non_camel_case_types)]
+
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+pub const CHISEL_OK: c_int = 0;
+/// A required pointer argument was null.
+pub const CHISEL_ERR_NULL_ARGUMENT: c_int = -1;
+/// The library threw; the error message holds the description.
+pub const CHISEL_ERR_EXCEPTION: c_int = -2;
+
+/// Chain processors: the output of one feeds the next.
+pub const CHISEL_MODE_PIPE: c_int = 0;
+/// Run every processor on the original and keep the smallest result.
+pub const CHISEL_MODE_PARALLEL: c_int = 1;
+
+pub const CHISEL_LOG_DEBUG: c_int = 0;
+pub const CHISEL_LOG_INFO: c_int = 1;
+pub const CHISEL_LOG_WARNING: c_int = 2;
+pub const CHISEL_LOG_ERROR: c_int = 3;
+
+/// Opaque handle owning a `chisel::Chisel` instance.
+#[repr(C)]
+pub struct ChiselHandle {
+    _private: [u8; 0],
+}
+
+/// Progress callbacks, mirroring `chisel::ChiselObserver`. Any member may be `None`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChiselObserverCallbacks {
+    pub on_file_start: Option<unsafe extern "C" fn(user_data: *mut c_void, path: *const c_char)>,
+    pub on_file_finish: Option<
+        unsafe extern "C" fn(
+            user_data: *mut c_void,
+            path: *const c_char,
+            size_before: u64,
+            size_after: u64,
+            replaced: bool,
+        ),
+    >,
+    pub on_file_error:
+        Option<unsafe extern "C" fn(user_data: *mut c_void, path: *const c_char, error: *const c_char)>,
+    pub on_log: Option<
+        unsafe extern "C" fn(user_data: *mut c_void, level: c_int, message: *const c_char, tag: *const c_char),
+    >,
+}
+
+extern "C" {
+    pub fn chisel_new() -> *mut ChiselHandle;
+    pub fn chisel_delete(handle: *mut ChiselHandle);
+
+    pub fn chisel_set_preserve_metadata(handle: *mut ChiselHandle, value: bool);
+    pub fn chisel_set_verify_checksums(handle: *mut ChiselHandle, value: bool);
+    pub fn chisel_set_dry_run(handle: *mut ChiselHandle, value: bool);
+    pub fn chisel_set_threads(handle: *mut ChiselHandle, value: c_uint);
+    pub fn chisel_set_mode(handle: *mut ChiselHandle, mode: c_int);
+    pub fn chisel_set_output_directory(handle: *mut ChiselHandle, dir: *const c_char);
+    pub fn chisel_set_observer(
+        handle: *mut ChiselHandle,
+        callbacks: *const ChiselObserverCallbacks,
+        user_data: *mut c_void,
+    );
+
+    pub fn chisel_recompress(
+        handle: *mut ChiselHandle,
+        paths: *const *const c_char,
+        count: usize,
+        error_message: *mut *mut c_char,
+    ) -> c_int;
+    pub fn chisel_stop(handle: *mut ChiselHandle);
+
+    pub fn chisel_free_error(message: *mut c_char);
+}
@@ -0,0 +1,336 @@
+//! Safe Rust API for chisel, the lossless recompression library.
+//!
+//! [`Chisel`] mirrors the `chisel::Chisel` C++ builder and [`Observer`]
+//! mirrors `chisel::ChiselObserver`.
+//!
+//! ```no_run
+//! use chisel::{Chisel, Mode};
+//!
+//! let mut chisel = Chisel::new();
+//! chisel.preserve_metadata(true).threads(4).mode(Mode::Parallel);
+//! chisel.recompress(["photo.jpg", "music.flac"])?;
+//! # Ok::<(), chisel::Error>(())
+//! ```
+
+use std::borrow::Cow;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr::{self, NonNull};
+use std::sync::Mutex;
+
+use chisel_sys as sys;
+
+/// How several processors are applied to the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Chain processors: the output of one feeds the next.
+    #[default]
+    Pipe,
+    /// Run every processor on the original file and keep the smallest result.
+    Parallel,
+}
+
+/// Severity of a message passed to [`Observer::on_log`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogLevel {
+    fn from_raw(level: c_int) -> Self {
+        match level {
+            sys::CHISEL_LOG_DEBUG => LogLevel::Debug,
+            sys::CHISEL_LOG_INFO => LogLevel::Info,
+            sys::CHISEL_LOG_WARNING => LogLevel::Warning,
+            _ => LogLevel::Error,
+        }
+    }
+}
+
+/// Receives progress and status events, like `chisel::ChiselObserver`.
+///
+/// Methods are called from the library's worker threads, so implementations
+/// must be `Send + Sync`. File events never overlap each other, but
+/// [`on_log`](Observer::on_log) may run concurrently with them. Callbacks
+/// must not call back into [`Chisel`]. A panic inside a callback is caught
+/// and discarded.
+pub trait Observer: Send + Sync {
+    /// A file is about to be processed.
+    fn on_file_start(&self, _path: &Path) {}
+
+    /// A file was processed; `replaced` tells whether the smaller result was kept.
+    fn on_file_finish(&self, _path: &Path, _size_before: u64, _size_after: u64, _replaced: bool) {}
+
+    /// A file could not be processed.
+    fn on_file_error(&self, _path: &Path, _error: &str) {}
+
+    /// A log message from the library.
+    fn on_log(&self, _level: LogLevel, _message: &str, _tag: &str) {}
+}
+
+/// Errors returned by [`Chisel::recompress`].
+#[derive(Debug)]
+pub enum Error {
+    /// A path contains a NUL byte, or is not valid Unicode on Windows.
+    InvalidPath(PathBuf),
+    /// The library failed as a whole (per-file failures go to the observer).
+    Library(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPath(path) => write!(f, "path cannot be passed to chisel: {}", path.display()),
+            Error::Library(msg) => write!(f, "chisel failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A configured chisel instance.
+///
+/// Configuration methods take `&mut self` and chain like the C++ builder.
+/// [`recompress`](Chisel::recompress) and [`stop`](Chisel::stop) take
+/// `&self`, so a shared `Chisel` can be stopped from another thread while
+/// a run is in progress; concurrent runs on the same instance wait for
+/// each other.
+pub struct Chisel {
+    handle: NonNull<sys::ChiselHandle>,
+    /// Double-boxed so the C side gets a thin pointer that stays put.
+    observer: Option<Box<Box<dyn Observer>>>,
+    output_dir: Option<PathBuf>,
+    run_lock: Mutex<()>,
+}
+
+// SAFETY: the handle is only reached through `&self` by `recompress`, which
+// serializes on `run_lock`, and by `chisel_stop`, which the C API documents
+// as safe to call from any thread. The observer is `Send + Sync`.
+unsafe impl Send for Chisel {}
+unsafe impl Sync for Chisel {}
+
+impl Chisel {
+    /// Creates an instance with the library defaults.
+    ///
+    /// # Panics
+    /// If the library cannot allocate the instance.
+    pub fn new() -> Self {
+        let handle = NonNull::new(unsafe { sys::chisel_new() }).expect("chisel_new failed to allocate");
+        Self {
+            handle,
+            observer: None,
+            output_dir: None,
+            run_lock: Mutex::new(()),
+        }
+    }
+
+    /// Keeps file metadata (tags, EXIF, ...). Default: `true`.
+    pub fn preserve_metadata(&mut self, value: bool) -> &mut Self {
+        unsafe { sys::chisel_set_preserve_metadata(self.handle.as_ptr(), value) };
+        self
+    }
+
+    /// Verifies raw checksums before replacing files. Default: `false`.
+    pub fn verify_checksums(&mut self, value: bool) -> &mut Self {
+        unsafe { sys::chisel_set_verify_checksums(self.handle.as_ptr(), value) };
+        self
+    }
+
+    /// Processes files without replacing them. Default: `false`.
+    pub fn dry_run(&mut self, value: bool) -> &mut Self {
+        unsafe { sys::chisel_set_dry_run(self.handle.as_ptr(), value) };
+        self
+    }
+
+    /// Number of worker threads; 0 selects half the available cores (the default).
+    pub fn threads(&mut self, value: u32) -> &mut Self {
+        unsafe { sys::chisel_set_threads(self.handle.as_ptr(), value) };
+        self
+    }
+
+    /// How several processors are applied to the same file. Default: [`Mode::Pipe`].
+    pub fn mode(&mut self, mode: Mode) -> &mut Self {
+        let raw = match mode {
+            Mode::Pipe => sys::CHISEL_MODE_PIPE,
+            Mode::Parallel => sys::CHISEL_MODE_PARALLEL,
+        };
+        unsafe { sys::chisel_set_mode(self.handle.as_ptr(), raw) };
+        self
+    }
+
+    /// Writes results to `dir` instead of replacing files in place.
+    ///
+    /// The path is validated by the next [`recompress`](Chisel::recompress).
+    pub fn output_directory(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.output_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Replaces files in place again (the default).
+    pub fn in_place(&mut self) -> &mut Self {
+        self.output_dir = None;
+        self
+    }
+
+    /// Installs the observer receiving progress events, replacing any previous one.
+    pub fn observer(&mut self, observer: impl Observer + 'static) -> &mut Self {
+        let boxed: Box<Box<dyn Observer>> = Box::new(Box::new(observer));
+        let callbacks = sys::ChiselObserverCallbacks {
+            on_file_start: Some(on_file_start),
+            on_file_finish: Some(on_file_finish),
+            on_file_error: Some(on_file_error),
+            on_log: Some(on_log),
+        };
+        let user_data = ptr::from_ref::<Box<dyn Observer>>(&boxed).cast_mut().cast::<c_void>();
+        unsafe { sys::chisel_set_observer(self.handle.as_ptr(), &callbacks, user_data) };
+        // the previous observer is dropped only after the C side let go of it
+        self.observer = Some(boxed);
+        self
+    }
+
+    /// Removes the observer.
+    pub fn clear_observer(&mut self) -> &mut Self {
+        unsafe { sys::chisel_set_observer(self.handle.as_ptr(), ptr::null(), ptr::null_mut()) };
+        self.observer = None;
+        self
+    }
+
+    /// Recompresses the given files and directories. Blocks until completion.
+    ///
+    /// Failures of single files are reported to the [`Observer`] and do not
+    /// make this call fail.
+    pub fn recompress<I, P>(&self, paths: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let paths = paths
+            .into_iter()
+            .map(|p| path_to_c(p.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pointers: Vec<*const c_char> = paths.iter().map(|p| p.as_ptr()).collect();
+        let output_dir = self.output_dir.as_deref().map(path_to_c).transpose()?;
+
+        let _run = self.run_lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut error_message: *mut c_char = ptr::null_mut();
+        let result = unsafe {
+            sys::chisel_set_output_directory(
+                self.handle.as_ptr(),
+                output_dir.as_ref().map_or(ptr::null(), |dir| dir.as_ptr()),
+            );
+            sys::chisel_recompress(self.handle.as_ptr(), pointers.as_ptr(), pointers.len(), &mut error_message)
+        };
+
+        if result == sys::CHISEL_OK {
+            return Ok(());
+        }
+        let message = if error_message.is_null() {
+            format!("error code {result}")
+        } else {
+            let msg = unsafe { CStr::from_ptr(error_message) }.to_string_lossy().into_owned();
+            unsafe { sys::chisel_free_error(error_message) };
+            msg
+        };
+        Err(Error::Library(message))
+    }
+
+    /// Requests cancellation of the run in progress. Safe to call from any thread.
+    pub fn stop(&self) {
+        unsafe { sys::chisel_stop(self.handle.as_ptr()) };
+    }
+}
+
+impl Default for Chisel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Chisel {
+    fn drop(&mut self) {
+        unsafe { sys::chisel_delete(self.handle.as_ptr()) };
+    }
+}
+
+impl fmt::Debug for Chisel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chisel")
+            .field("observer", &self.observer.is_some())
+            .field("output_dir", &self.output_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+fn path_to_c(path: &Path) -> Result<CString, Error> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::InvalidPath(path.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn path_to_c(path: &Path) -> Result<CString, Error> {
+    path.to_str()
+        .and_then(|s| CString::new(s).ok())
+        .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))
+}
+
+/// # Safety
+/// `ptr` must be a valid NUL-terminated string.
+#[cfg(unix)]
+unsafe fn path_from_c(ptr: *const c_char) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(CStr::from_ptr(ptr).to_bytes()))
+}
+
+/// # Safety
+/// `ptr` must be a valid NUL-terminated string.
+#[cfg(not(unix))]
+unsafe fn path_from_c(ptr: *const c_char) -> PathBuf {
+    PathBuf::from(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// # Safety
+/// `ptr` must be a valid NUL-terminated string.
+unsafe fn str_from_c<'a>(ptr: *const c_char) -> Cow<'a, str> {
+    CStr::from_ptr(ptr).to_string_lossy()
+}
+
+/// Runs `f` on the observer behind `user_data`, keeping panics out of C++.
+///
+/// # Safety
+/// `user_data` must be the pointer installed by [`Chisel::observer`].
+unsafe fn dispatch(user_data: *mut c_void, f: impl FnOnce(&dyn Observer)) {
+    let observer = &*user_data.cast::<Box<dyn Observer>>();
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| f(observer.as_ref())));
+}
+
+unsafe extern "C" fn on_file_start(user_data: *mut c_void, path: *const c_char) {
+    dispatch(user_data, |observer| observer.on_file_start(&path_from_c(path)));
+}
+
+unsafe extern "C" fn on_file_finish(
+    user_data: *mut c_void,
+    path: *const c_char,
+    size_before: u64,
+    size_after: u64,
+    replaced: bool,
+) {
+    dispatch(user_data, |observer| {
+        observer.on_file_finish(&path_from_c(path), size_before, size_after, replaced)
+    });
+}
+
+unsafe extern "C" fn on_file_error(user_data: *mut c_void, path: *const c_char, error: *const c_char) {
+    dispatch(user_data, |observer| observer.on_file_error(&path_from_c(path), &str_from_c(error)));
+}
+
+unsafe extern "C" fn on_log(user_data: *mut c_void, level: c_int, message: *const c_char, tag: *const c_char) {
+    dispatch(user_data, |observer| {
+        observer.on_log(LogLevel::from_raw(level), &str_from_c(message), &str_from_c(tag))
+    });
+}
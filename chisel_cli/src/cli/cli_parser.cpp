@@ -47,6 +47,35 @@ void setup_cli_parser(CLI::App& app, Settings& settings) {
     app.add_flag("--verify-checksums", settings.verify_checksums,
                  "Verify raw checksums before replacing files.");
 
+    app.add_flag("--lepton", settings.lepton,
+                 "Archive JPEG files as Lepton (.lep). Smaller, but no longer readable as JPEG.");
+
+    app.add_flag("--restore-lepton", settings.restore_lepton,
+                 "Restore the original JPEG files from .lep inputs instead of optimizing.");
+
+    app.add_flag("--precomp", settings.precomp,
+                 "Archive PDF, ZIP, PNG and SWF files as .pcz, with their deflate streams expanded and xz on top.\n"
+                 "Smaller, but no longer readable in the original format.");
+
+    app.add_flag("--restore-precomp", settings.restore_precomp,
+                 "Restore the original files from .pcz inputs instead of optimizing.");
+
+    app.add_option("--flac-effort", settings.flac_effort,
+                   "FLAC encoder search: 0 = one encode, 1 = try blocksizes, apodizations and LPC precisions,\n"
+                   "2 = also try a variable blocksize.")
+                   ->default_val(0)
+                   ->check(CLI::Range(0, 2));
+
+    app.add_option("--flac-padding", settings.flac_padding,
+                   "PADDING bytes to leave in re-encoded FLAC files, for taggers that edit in place.")
+                   ->default_val(0)
+                   ->check(CLI::Range(0u, (1u << 24) - 1));
+
+    app.add_option("--zopfli-iterations", settings.zopfli_iterations,
+                   "Zopfli iterations when rebuilding ZIP-based containers (ZIP, OOXML, ODF, EPUB...) and gzip streams.")
+                   ->default_val(15)
+                   ->check(CLI::Range(1u, 1000u));
+
     app.add_option("-o,--output", settings.output_path,
                    "Write optimized files to PATH instead of modifying in-place.\n"
                    "(If input is stdin, PATH is a file. Otherwise, PATH is a directory).");
@@ -120,5 +149,21 @@ void setup_cli_parser(CLI::App& app, Settings& settings) {
         if (settings.dry_run && !settings.output_path.empty()) {
             throw CLI::ValidationError("--dry-run and -o, --output cannot be used together.");
         }
+
+        if (settings.lepton && settings.restore_lepton) {
+            throw CLI::ValidationError("--lepton and --restore-lepton cannot be used together.");
+        }
+
+        if (settings.is_pipe && (settings.lepton || settings.restore_lepton)) {
+            throw CLI::ValidationError("--lepton and --restore-lepton cannot be used with stdin ('-').");
+        }
+
+        if (settings.precomp && settings.restore_precomp) {
+            throw CLI::ValidationError("--precomp and --restore-precomp cannot be used together.");
+        }
+
+        if (settings.is_pipe && (settings.precomp || settings.restore_precomp)) {
+            throw CLI::ValidationError("--precomp and --restore-precomp cannot be used with stdin ('-').");
+        }
     });
 }
\ No newline at end of file
@@ -21,8 +21,15 @@ struct Settings {
     bool dry_run = false;
     bool quiet = false;
     bool verify_checksums = false;
+    bool lepton = false;
+    bool restore_lepton = false;
+    bool precomp = false;
+    bool restore_precomp = false;
 
     unsigned num_threads = 1;
+    unsigned flac_effort = 0;
+    unsigned flac_padding = 0;
+    unsigned zopfli_iterations = 15;
     std::string log_level = "ERROR";
     std::string log_file;
     std::filesystem::path output_path;
@@ -22,6 +22,13 @@
 #include "../../libchisel/include/logger.hpp"
 #include "../../libchisel/include/file_type.hpp"
 #include "../../libchisel/include/mime_detector.hpp"
+#include "../../libchisel/include/lepton_processor.hpp"
+#include "../../libchisel/include/precomp_processor.hpp"
+#include "../../libchisel/include/flac_processor.hpp"
+#include "../../libchisel/include/archive_processor.hpp"
+#include "../../libchisel/include/ooxml_processor.hpp"
+#include "../../libchisel/include/odf_processor.hpp"
+#include "../../libchisel/include/compressed_stream_processor.hpp"
 #include "utils/file_log_sink.hpp"
 
 // Global mutex to synchronize console output from multiple threads
@@ -93,6 +100,58 @@ inline void print_progress_bar_internal(const size_t done, const size_t total, c
 using namespace chisel;
 namespace fs = std::filesystem;
 
+// restore the original file of every archived input (.lep, .pcz); returns the process exit code
+template <typename Processor>
+static int restore_files(const std::vector<fs::path>& inputs, const Settings& settings, const std::string& kind) {
+    const Processor processor;
+    int exit_code = 0;
+    size_t restored = 0;
+
+    for (const auto& input : inputs) {
+        std::string ext = input.extension().string();
+        std::transform(ext.begin(), ext.end(), ext.begin(), ::tolower);
+        if (ext != processor.get_output_extension()) {
+            continue;
+        }
+
+        const fs::path target = (settings.output_path.empty() ? input.parent_path() : settings.output_path)
+                                / Processor::restored_filename(input);
+        if (settings.dry_run) {
+            std::cerr << "[DRY-RUN] " << input.filename().string() << " -> " << target.string() << std::endl;
+            continue;
+        }
+
+        std::error_code ec;
+        if (fs::exists(target, ec)) {
+            Logger::log(LogLevel::Error, "Not restoring " + input.string() + ": " + target.string()
+                        + " already exists", "main");
+            exit_code = 1;
+            continue;
+        }
+
+        try {
+            if (!settings.output_path.empty()) {
+                fs::create_directories(settings.output_path);
+            }
+            Processor::restore(input, target);
+            if (settings.output_path.empty()) {
+                fs::remove(input);
+            }
+            ++restored;
+            if (!settings.quiet) {
+                std::cerr << GREEN << "[DONE] " << input.filename().string() << " -> "
+                          << target.filename().string() << RESET << std::endl;
+            }
+        } catch (const std::exception& e) {
+            Logger::log(LogLevel::Error, input.filename().string() + " " + e.what(), "main");
+            exit_code = 1;
+        }
+    }
+
+    Logger::log(LogLevel::Info, "Restored " + std::to_string(restored) + " " + kind + " files", "main");
+    return exit_code;
+}
+
 static std::atomic<chisel::ProcessorExecutor*> g_executor{nullptr};
 
 // handle ctrl+c or termination signals
@@ -183,8 +242,46 @@ int main(int argc, char* argv[]) {
         Logger::add_sink(std::move(consoleSink));
     }
 
+    if (settings.restore_lepton || settings.restore_precomp) {
+        const auto inputs = collect_input_files(settings.inputs, settings, settings.is_pipe);
+        int exit_code = 0;
+        if (settings.restore_lepton) {
+            exit_code |= restore_files<LeptonProcessor>(inputs, settings, "Lepton");
+        }
+        if (settings.restore_precomp) {
+            exit_code |= restore_files<PrecompProcessor>(inputs, settings, "precomp");
+        }
+        return exit_code;
+    }
+
     // registry of processors and event bus
     ProcessorRegistry registry;
+    if (settings.lepton) {
+        registry.register_processor(std::make_unique<LeptonProcessor>());
+    }
+    if (settings.precomp) {
+        registry.register_processor(std::make_unique<PrecompProcessor>());
+    }
+    for (const auto& processor : registry.all()) {
+        if (auto* flac = dynamic_cast<FlacProcessor*>(processor.get())) {
+            flac->set_effort(static_cast<FlacEffort>(settings.flac_effort));
+            FlacMetadataPolicy policy = flac->metadata_policy();
+            policy.padding = settings.flac_padding;
+            flac->set_metadata_policy(policy);
+        }
+    }
+    auto apply_zip_options = [&settings](auto* processor) {
+        if (!processor) return;
+        ZipWriterOptions options = processor->zip_options();
+        options.iterations = static_cast<int>(settings.zopfli_iterations);
+        processor->set_zip_options(options);
+    };
+    for (const auto& processor : registry.all()) {
+        apply_zip_options(dynamic_cast<ArchiveProcessor*>(processor.get()));
+        apply_zip_options(dynamic_cast<OOXMLProcessor*>(processor.get()));
+        apply_zip_options(dynamic_cast<OdfProcessor*>(processor.get()));
+        apply_zip_options(dynamic_cast<CompressedStreamProcessor*>(processor.get()));
+    }
     EventBus bus;
 
     // results collected for reporting
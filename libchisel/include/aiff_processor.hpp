@@ -22,6 +22,12 @@ namespace chisel {
      *
      * @details This processor acts as a container for extracting and
      * re-inserting cover art (via ID3v2 tags embedded in the 'ID3 ' chunk).
+     *
+     * Recompression rewrites the FORM without touching the samples: the
+     * SSND offset/blockSize padding and any bytes past the last sample
+     * frame are removed, text, comment and ID3 chunks are compacted (or
+     * dropped without metadata), and uncompressed AIFF-C (`NONE`, `twos`,
+     * `sowt`) becomes plain AIFF, byte-swapping `sowt` samples.
      */
     class AiffProcessor final : public IProcessor {
     public:
@@ -43,11 +49,22 @@ namespace chisel {
         }
 
         // --- capabilities ---
-        [[nodiscard]] bool can_recompress() const noexcept override { return false; }
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
         [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
         // --- operations ---
 
+        /**
+         * @brief Rewrites an AIFF/AIFF-C file in its smallest lossless form.
+         *
+         * Files whose chunks cannot be walked are copied unchanged.
+         *
+         * @param input Path to the AIFF file.
+         * @param output Path to write the optimized file.
+         * @param preserve_metadata If false, every chunk but `COMM`, `SSND` and a needed `FVER` is dropped,
+         *        along with any bytes after the FORM chunk; if true, those bytes are copied unchanged.
+         * @throws std::runtime_error if the output cannot be written.
+         */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
                         bool preserve_metadata) override;
@@ -59,6 +76,14 @@ namespace chisel {
 
         // --- integrity check ---
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override { return ""; }
+
+        /**
+         * @brief Compares the sample frames of two AIFF/AIFF-C files.
+         *
+         * Samples are compared big-endian, so a `sowt` file equals its
+         * plain AIFF rewrite.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path& a, const std::filesystem::path& b) const override;
     };
 
 } // namespace chisel
@@ -20,8 +20,9 @@ namespace chisel {
     /**
      * @brief Implements IProcessor for Monkey's Audio (APE) files using MACLib.
      *
-     * Performs a full decode and re-encode cycle to the highest
-     * compression level ("insane"). Also attempts to preserve APEv2 tags.
+     * Performs a full decode and re-encode cycle at every compression level
+     * from "normal" to "insane", keeping the smallest result. APEv2 tags
+     * are carried over through AudioMetadataUtil.
      */
     class ApeProcessor final : public IProcessor {
     public:
@@ -54,12 +55,14 @@ namespace chisel {
         /**
          * @brief Recompresses an APE file using MACLib.
          *
-         * Performs a full decode and re-encode cycle using the
-         * "insane" compression level (APE_COMPRESSION_LEVEL_INSANE).
+         * Encodes the audio at the normal, high, extra high and insane
+         * levels and keeps the smallest file. Levels are tried in that
+         * order, one decode per level.
          *
          * @param input Path to the source APE file.
          * @param output Path to write the optimized APE file.
-         * @param preserve_metadata If true, attempts to copy APEv2 tags.
+         * @param preserve_metadata If true, copies the APEv2 tag (text items
+         * and cover art) and any ID3v1 tag.
          * @throws std::runtime_error if the MACLib encoder or decoder fails.
          */
         void recompress(const std::filesystem::path& input,
@@ -85,15 +88,18 @@ namespace chisel {
         // --- integrity check ---
 
         /**
-         * @brief (Not Implemented) Compute a raw checksum.
+         * @brief Computes the MD5 of the decoded PCM, in WAV byte order.
          * @param file_path Path to the file.
-         * @return An empty string.
+         * @return The MD5 as a lowercase hex string.
+         * @throws std::runtime_error if the file cannot be decoded.
          */
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
 
         /**
          * @brief Compares two APE files by decoding them to raw PCM and comparing.
          *
+         * The MD5 stored in the header of b must also match its data.
+         *
          * @param a First APE file.
          * @param b Second APE file.
          * @return true if the decoded PCM data and audio parameters are identical.
@@ -11,6 +11,7 @@
 #define CHISEL_ARCHIVE_PROCESSOR_HPP
 
 #include "processor.hpp"
+#include "zip_writer.hpp"
 #include "logger.hpp"
 #include <array>
 #include <string_view>
@@ -34,16 +35,13 @@ class ArchiveProcessor final : public IProcessor {
     }
 
     [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
-        static constexpr std::array<std::string_view, 15> kMimes = {
+        static constexpr std::array<std::string_view, 12> kMimes = {
             "application/zip",
             // "application/x-7z-compressed", // 7z write support is limited/complex
             "application/x-tar",
-            //"application/gzip", // Handled as filters, not primary formats
-            "application/x-bzip2",
-            //"application/x-xz",
+            // gzip, bzip2, xz, lzma and zstd streams are handled by CompressedStreamProcessor
             "application/x-iso9660-image",
             "application/x-cpio",
-            // "application/x-lzma",
             "application/vnd.ms-cab-compressed",
             // "application/x-ms-wim", // Write not supported
             "application/java-archive",
@@ -52,23 +50,20 @@ class ArchiveProcessor final : public IProcessor {
             "application/vnd.comicbook+zip",
             "application/vnd.comicbook+tar",
             "application/epub+zip",
-            "application/x-archive",
-            "application/zstd",
-            "application/x-zstd"
+            "application/x-archive"
         };
         return {kMimes.data(), kMimes.size()};
     }
 
     [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
-        static constexpr std::array<std::string_view, 18> kExts = {
+        static constexpr std::array<std::string_view, 14> kExts = {
             ".zip", // ".7z",
-            ".tar",  ".bz2", //".gz", ".xz",
-            ".iso", ".cpio", ".lzma", ".cab", // ".wim",
+            ".tar",
+            ".iso", ".cpio", ".cab", // ".wim",
             ".jar", ".xpi", ".apk",
             ".cbz", ".cbt",
             ".epub",
-            ".a", ".ar", ".lib",
-            ".zst", ".tzst"
+            ".a", ".ar", ".lib"
         };
         return {kExts.data(), kExts.size()};
     }
@@ -87,6 +82,12 @@ class ArchiveProcessor final : public IProcessor {
      */
     [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
+    /**
+     * @brief Sets the Zopfli settings used when the ZIP is rebuilt.
+     */
+    void set_zip_options(const ZipWriterOptions& options) noexcept { zip_options_ = options; }
+    [[nodiscard]] const ZipWriterOptions& zip_options() const noexcept { return zip_options_; }
+
     // --- operations ---
 
     /**
@@ -101,6 +102,9 @@ class ArchiveProcessor final : public IProcessor {
      *
      * Uses `archive_read_...` functions (libarchive) to decompress the
      * container and write all entries to a unique temporary directory.
+     * For ZIP-based formats the original deflate streams are first
+     * analyzed with DeflateReconstructor; an archive carrying an APK
+     * signing block (which signs the archive bytes) is not extracted.
      *
      * @param input_path Path to the archive file (e.g., .zip, .rar).
      * @return An ExtractedContent struct containing the list of
@@ -112,13 +116,17 @@ class ArchiveProcessor final : public IProcessor {
     /**
      * @brief Re-builds the archive from the (modified) extracted files.
      *
-     * Uses `archive_write_...` (libarchive) to create a new archive.
+     * ZIP-based formats are written with ZipWriter (Zopfli per entry,
+     * keeping the original deflate stream of an unchanged entry when it
+     * is smaller or does not inflate cleanly); the others use
+     * `archive_write_...` (libarchive).
      * If the original format is not writable (e.g., RAR), it will
      * re-package the contents into the `target_format` (e.g., ZIP).
      *
      * @param content The ExtractedContent struct from `prepare_extraction`.
      * @param target_format The fallback format if the original is read-only.
-     * @return Path to the newly created temporary archive file.
+     * @return Path to the newly created temporary archive file, or an
+     * empty path if the archive is kept as-is.
      * @throws std::runtime_error if archive creation fails.
      */
     std::filesystem::path finalize_extraction(const ExtractedContent &content) override;
@@ -131,6 +139,9 @@ class ArchiveProcessor final : public IProcessor {
      * @return An empty string.
      */
     [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+private:
+    ZipWriterOptions zip_options_;
 };
 
 } // namespace chisel
@@ -5,6 +5,7 @@
 #ifndef CHISEL_AUDIO_METADATA_UTIL_HPP
 #define CHISEL_AUDIO_METADATA_UTIL_HPP
 
+#include <cstdint>
 #include <filesystem>
 #include <string>
 #include <vector>
@@ -58,6 +59,21 @@ struct AudioExtractionState {
     std::vector<AudioCoverInfo> extracted_covers;
 };
 
+/**
+ * @brief Tags stored around the audio stream of a file, kept as raw bytes.
+ *
+ * Used by processors that rewrite the stream themselves (e.g. TTA) and
+ * put the tags back unchanged. Empty vectors mean the tag is absent.
+ */
+struct AudioTagBlocks {
+    std::vector<unsigned char> id3v2;   // leading ID3v2 tag, header (and footer) included
+    std::vector<unsigned char> apev2;   // trailing APEv2 tag, header (if any) and footer included
+    std::vector<unsigned char> id3v1;   // trailing 128-byte ID3v1 tag
+
+    std::uint64_t audio_offset = 0;     // first byte of the audio stream
+    std::uint64_t audio_size = 0;       // bytes between the leading and trailing tags
+};
+
 /**
  * @brief Minimal utility for extracting and reinserting cover art from audio files.
  *
@@ -97,6 +113,42 @@ class AudioMetadataUtil {
      */
     static bool rebuildCovers(const std::filesystem::path& input_path,
                               const AudioExtractionState& state);
+
+    /**
+     * @brief Copy the APEv2 tag (and ID3v1 tag, if any) of a Monkey's Audio file to another.
+     *
+     * Every APEv2 item is carried over as it is: text, binary (cover art)
+     * and external locators. Items of the target with the same key are replaced.
+     *
+     * @param source_path APE file to read the tags from.
+     * @param target_path APE file to modify in place.
+     * @return true on success (including when the source has no tags); false on failure.
+     */
+    static bool copyApeTag(const std::filesystem::path& source_path,
+                           const std::filesystem::path& target_path);
+
+    /**
+     * @brief Locate the ID3v2, APEv2 and ID3v1 tags around the audio stream of a file.
+     *
+     * Looks for an ID3v2 tag at the start, then an ID3v1 tag at the end and an
+     * APEv2 tag right before it (or at the end). Anything else is part of the stream.
+     *
+     * @param path Path to the audio file.
+     * @return The raw tags and the position of the stream between them.
+     * @throws std::runtime_error if the file cannot be read.
+     */
+    static AudioTagBlocks readTagBlocks(const std::filesystem::path& path);
+
+    /**
+     * @brief Drop the padding of a raw ID3v2.3/2.4 tag in memory.
+     *
+     * Frames are kept byte for byte and the header size is rewritten.
+     * Tags using unsynchronisation, an extended header or a footer are
+     * left untouched.
+     *
+     * @param tag The complete tag, starting with its "ID3" header.
+     */
+    static void compactId3v2(std::vector<unsigned char>& tag);
 };
 
 } // namespace chisel
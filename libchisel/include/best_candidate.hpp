@@ -0,0 +1,81 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file best_candidate.hpp
+ * @brief Keeps the smallest of several encodes of the same file.
+ */
+
+#ifndef CHISEL_BEST_CANDIDATE_HPP
+#define CHISEL_BEST_CANDIDATE_HPP
+
+#include <cstdint>
+#include <filesystem>
+#include <span>
+#include <string>
+#include <string_view>
+
+namespace chisel {
+
+    /**
+     * @brief Tracks the smallest candidate of a parameter search.
+     *
+     * @details Every candidate is a complete file written next to the output,
+     * so the winner only has to be renamed into place. Candidates that lose
+     * are deleted as soon as they are offered; the current best is deleted
+     * on destruction unless it was committed, also when an encode throws.
+     */
+    class BestCandidate {
+    public:
+        /**
+         * @param output Path the winner is moved to.
+         * @param extension Extension of the candidate files (e.g. ".flac").
+         * @param companions Suffixes of companion files (e.g. "c" for .wv/.wvc)
+         * written with each candidate, counted in its size and moved or deleted with it.
+         */
+        BestCandidate(std::filesystem::path output, std::string extension,
+                      std::span<const std::string_view> companions = {});
+        ~BestCandidate();
+
+        BestCandidate(const BestCandidate&) = delete;
+        BestCandidate& operator=(const BestCandidate&) = delete;
+
+        /**
+         * @brief Returns a fresh path for the next candidate, next to the output.
+         */
+        [[nodiscard]] std::filesystem::path next_path() const;
+
+        /**
+         * @brief Keeps a candidate if it is the first or the smallest so far, deletes it otherwise.
+         * @return true if the candidate is the new best.
+         */
+        bool offer(const std::filesystem::path& candidate);
+
+        /**
+         * @brief Deletes a candidate (and its companions) that failed to encode.
+         */
+        void discard(const std::filesystem::path& candidate) const;
+
+        [[nodiscard]] bool empty() const noexcept { return best_.empty(); }
+        [[nodiscard]] const std::filesystem::path& path() const noexcept { return best_; }
+        [[nodiscard]] std::uintmax_t size() const noexcept { return best_size_; }
+
+        /**
+         * @brief Moves the best candidate and its companions to the output.
+         * @throws std::runtime_error if there is no candidate.
+         * @throws std::filesystem::filesystem_error if the files cannot be moved.
+         */
+        void commit();
+
+    private:
+        std::filesystem::path output_;
+        std::string extension_;
+        std::span<const std::string_view> companions_;
+        std::filesystem::path best_;
+        std::uintmax_t best_size_ = 0;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_BEST_CANDIDATE_HPP
@@ -34,6 +34,9 @@ enum class EncodeMode {
 
 /**
  * @brief Interface for receiving progress and status events during execution.
+ *
+ * @details Callbacks are invoked from the worker threads. Event callbacks
+ * are serialized with each other, but onLog() may run concurrently with them.
  */
 struct ChiselObserver {
     virtual ~ChiselObserver() = default;
@@ -105,6 +108,58 @@ class Chisel {
      */
     Chisel& outputDirectory(const std::filesystem::path& dir);
 
+    /**
+     * @brief Enable or disable Lepton archival of JPEG files.
+     *
+     * When enabled, top-level JPEG files are replaced by smaller `.lep`
+     * files (not readable as JPEG) after a mandatory round-trip check.
+     * The archive keeps the original name: photo.jpeg becomes photo.jpeg.lep.
+     * JPEGs inside containers are left as JPEG.
+     * Default: false.
+     */
+    Chisel& leptonArchival(bool val);
+
+    /**
+     * @brief Enable or disable precomp-style archival of deflate-heavy files.
+     *
+     * When enabled, top-level PDF, ZIP/JAR, PNG and SWF files are replaced
+     * by `.pcz` archives: every deflate stream zlib can regenerate bit for
+     * bit is stored decompressed with its parameters, and the result is
+     * compressed with xz. The archive is no longer readable in the original
+     * format and keeps the original name (report.pdf becomes report.pdf.pcz).
+     * Such files are archived as they are, without being optimized first.
+     * Default: false.
+     */
+    Chisel& precompArchival(bool val);
+
+    /**
+     * @brief Set how hard FLAC files are re-encoded.
+     *
+     * 0 encodes once with fixed settings. 1 also tries several blocksizes,
+     * apodization sets and LPC precisions and keeps the smallest file.
+     * 2 adds a variable-blocksize encode. Higher values are treated as 2.
+     * Default: 0.
+     */
+    Chisel& flacEffort(unsigned level);
+
+    /**
+     * @brief Set the PADDING written into re-encoded FLAC files, in bytes.
+     *
+     * Existing padding is always dropped; taggers that edit in place can
+     * ask for some back. Values above 16777215, the largest PADDING block,
+     * are clamped. Default: 0.
+     */
+    Chisel& flacPadding(unsigned bytes);
+
+    /**
+     * @brief Set the Zopfli iterations used when ZIP-based containers
+     * (ZIP, OOXML, ODF, EPUB...) and gzip streams are rebuilt.
+     *
+     * More iterations compress slightly better and run proportionally
+     * slower. 0 is treated as 1. Default: 15.
+     */
+    Chisel& zipIterations(unsigned iterations);
+
     // --- Observability ---
 
     /**
@@ -123,6 +178,18 @@ class Chisel {
     void recompress(const std::filesystem::path& path);
     void recompress(const std::vector<std::string>& paths);
 
+    /**
+     * @brief Restores the original JPEG from a `.lep` file, byte for byte.
+     * @throws std::runtime_error if the Lepton data cannot be decoded.
+     */
+    static void restoreLepton(const std::filesystem::path& input, const std::filesystem::path& output);
+
+    /**
+     * @brief Restores the original file from a `.pcz` archive, byte for byte.
+     * @throws std::runtime_error if the archive is damaged or does not restore exactly.
+     */
+    static void restorePrecomp(const std::filesystem::path& input, const std::filesystem::path& output);
+
     // --- Control ---
 
     /**
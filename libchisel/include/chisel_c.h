@@ -0,0 +1,126 @@
+/*
+ * Created by Giuseppe Francione on 16/10/26.
+ */
+
+/**
+ * @file chisel_c.h
+ * @brief C interface to chisel::Chisel, for bindings in other languages.
+ *
+ * Every path crossing this interface is a NUL-terminated UTF-8 string; on
+ * POSIX systems the bytes are passed through unchanged. The layouts and
+ * constants below must stay in sync with bindings/rust/chisel-sys.
+ */
+
+#ifndef CHISEL_C_H
+#define CHISEL_C_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#if defined(_WIN32)
+#  if defined(CHISEL_C_BUILDING)
+#    define CHISEL_C_API __declspec(dllexport)
+#  else
+#    define CHISEL_C_API __declspec(dllimport)
+#  endif
+#else
+#  define CHISEL_C_API __attribute__((visibility("default")))
+#endif
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* --- return codes --- */
+#define CHISEL_OK 0
+#define CHISEL_ERR_NULL_ARGUMENT (-1) /**< a required pointer argument was null */
+#define CHISEL_ERR_EXCEPTION (-2)     /**< the library threw; see error_message */
+
+/* --- chisel_set_mode() --- */
+#define CHISEL_MODE_PIPE 0     /**< chain processors, output of one feeds the next */
+#define CHISEL_MODE_PARALLEL 1 /**< run every processor on the original, keep the smallest */
+
+/* --- ChiselObserverCallbacks::on_log levels (LogLevel) --- */
+#define CHISEL_LOG_DEBUG 0
+#define CHISEL_LOG_INFO 1
+#define CHISEL_LOG_WARNING 2
+#define CHISEL_LOG_ERROR 3
+
+/** Opaque handle owning a chisel::Chisel instance. */
+typedef struct ChiselHandle ChiselHandle;
+
+/**
+ * @brief Progress callbacks, mirroring chisel::ChiselObserver.
+ *
+ * Any member may be null. Callbacks run on the worker threads, possibly
+ * concurrently, and must not call back into the library. String arguments
+ * are only valid for the duration of the call.
+ */
+typedef struct ChiselObserverCallbacks {
+    void (*on_file_start)(void* user_data, const char* path);
+    void (*on_file_finish)(void* user_data, const char* path,
+                           uint64_t size_before, uint64_t size_after, bool replaced);
+    void (*on_file_error)(void* user_data, const char* path, const char* error);
+    void (*on_log)(void* user_data, int level, const char* message, const char* tag);
+} ChiselObserverCallbacks;
+
+/** @return A new instance with the default settings, or null on allocation failure. */
+CHISEL_C_API ChiselHandle* chisel_new(void);
+
+/** Destroys an instance, stopping any run in progress. Null is ignored. */
+CHISEL_C_API void chisel_delete(ChiselHandle* handle);
+
+/** @see chisel::Chisel::preserveMetadata() */
+CHISEL_C_API void chisel_set_preserve_metadata(ChiselHandle* handle, bool value);
+
+/** @see chisel::Chisel::verifyChecksums() */
+CHISEL_C_API void chisel_set_verify_checksums(ChiselHandle* handle, bool value);
+
+/** @see chisel::Chisel::dryRun() */
+CHISEL_C_API void chisel_set_dry_run(ChiselHandle* handle, bool value);
+
+/** @see chisel::Chisel::threads(); 0 selects the default. */
+CHISEL_C_API void chisel_set_threads(ChiselHandle* handle, unsigned value);
+
+/** @param mode CHISEL_MODE_PIPE or CHISEL_MODE_PARALLEL. */
+CHISEL_C_API void chisel_set_mode(ChiselHandle* handle, int mode);
+
+/** @param dir Output directory, or null to optimize in place. */
+CHISEL_C_API void chisel_set_output_directory(ChiselHandle* handle, const char* dir);
+
+/**
+ * @brief Installs the progress callbacks, replacing any previous ones.
+ * @param callbacks Copied; null removes the observer.
+ * @param user_data Handed back to every callback; must outlive its use.
+ */
+CHISEL_C_API void chisel_set_observer(ChiselHandle* handle,
+                                      const ChiselObserverCallbacks* callbacks,
+                                      void* user_data);
+
+/**
+ * @brief Recompresses the given files. Blocks until completion.
+ *
+ * Per-file failures are reported through on_file_error and do not make
+ * this call fail.
+ *
+ * @param paths Array of `count` paths.
+ * @param error_message If not null, receives a message on failure; release
+ * it with chisel_free_error().
+ * @return CHISEL_OK or a negative CHISEL_ERR_* code.
+ */
+CHISEL_C_API int chisel_recompress(ChiselHandle* handle,
+                                   const char* const* paths, size_t count,
+                                   char** error_message);
+
+/** Requests cancellation of the run in progress. Safe to call from any thread. */
+CHISEL_C_API void chisel_stop(ChiselHandle* handle);
+
+/** Releases an error message returned by this interface. Null is ignored. */
+CHISEL_C_API void chisel_free_error(char* message);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif // CHISEL_C_H
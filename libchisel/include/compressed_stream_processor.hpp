@@ -0,0 +1,138 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file compressed_stream_processor.hpp
+ * @brief Defines the IProcessor for single-file compressed streams (gzip, bzip2, xz, lzma, zstd).
+ */
+
+#ifndef CHISEL_COMPRESSED_STREAM_PROCESSOR_HPP
+#define CHISEL_COMPRESSED_STREAM_PROCESSOR_HPP
+
+#include "processor.hpp"
+#include "zip_writer.hpp"
+#include <array>
+#include <string_view>
+#include <span>
+
+namespace chisel {
+
+/**
+ * @brief Implements IProcessor for standalone compressed streams.
+ *
+ * @details A `.gz`, `.bz2`, `.xz`, `.lzma` or `.zst` file wraps exactly one
+ * payload, which is not necessarily a tarball (e.g. `data.json.gz`). This
+ * processor decompresses the stream into a single extracted file, so the
+ * payload is optimized by its own processor (a `.tar` goes on to
+ * ArchiveProcessor), then re-emits the same stream format at maximum
+ * effort:
+ * - gzip: Zopfli, keeping the original FNAME, MTIME, OS, FEXTRA and FCOMMENT fields;
+ * - bzip2: 900k blocks;
+ * - xz and lzma: preset 9e, keeping the xz integrity check type;
+ * - zstd: level 22 with long-distance matching (`--ultra -22 --long`).
+ *
+ * Concatenated members/streams are decompressed as one payload and written
+ * back as a single one.
+ */
+class CompressedStreamProcessor final : public IProcessor {
+public:
+    // --- self-description ---
+    [[nodiscard]] std::string_view get_name() const noexcept override {
+        return "CompressedStreamProcessor";
+    }
+
+    [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
+        static constexpr std::array<std::string_view, 7> kMimes = {
+            "application/gzip",
+            "application/x-gzip",
+            "application/x-bzip2",
+            "application/x-xz",
+            "application/x-lzma",
+            "application/zstd",
+            "application/x-zstd"
+        };
+        return {kMimes.data(), kMimes.size()};
+    }
+
+    [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
+        static constexpr std::array<std::string_view, 9> kExts = {
+            ".gz", ".tgz",
+            ".bz2", ".tbz2",
+            ".xz", ".txz",
+            ".lzma",
+            ".zst", ".tzst"
+        };
+        return {kExts.data(), kExts.size()};
+    }
+
+    // --- capabilities ---
+
+    /**
+     * @brief Direct recompression is not supported (handled via extraction).
+     * @return false
+     */
+    [[nodiscard]] bool can_recompress() const noexcept override { return false; }
+
+    /**
+     * @brief This processor extracts the payload of the stream.
+     * @return true
+     */
+    [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
+
+    // --- operations ---
+
+    /**
+     * @brief Sets the Zopfli settings used when a gzip stream is rewritten.
+     */
+    void set_zip_options(const ZipWriterOptions& options) noexcept { zip_options_ = options; }
+    [[nodiscard]] const ZipWriterOptions& zip_options() const noexcept { return zip_options_; }
+
+    /**
+     * @brief (Not Implemented) Direct recompression is not supported.
+     */
+    void recompress(const std::filesystem::path&,
+                    const std::filesystem::path&,
+                    bool) override {}
+
+    /**
+     * @brief Decompresses the stream into a temp directory.
+     *
+     * The format is detected from the magic bytes (`.lzma`, which has
+     * none, from the extension). The payload is named after the gzip
+     * FNAME field when present, otherwise after the input file without
+     * its compression extension (`.tgz` becomes `.tar`).
+     *
+     * @param input_path Path to the compressed file.
+     * @return ExtractedContent holding the single payload file, or
+     * std::nullopt if the stream cannot be decoded.
+     */
+    std::optional<ExtractedContent> prepare_extraction(
+        const std::filesystem::path& input_path) override;
+
+    /**
+     * @brief Compresses the (optimized) payload back into the original stream format.
+     *
+     * @param content The ExtractedContent struct from `prepare_extraction`.
+     * @return Path to the new temporary compressed file, or an empty path
+     * if it is not smaller than the original.
+     * @throws std::runtime_error if compression fails.
+     */
+    std::filesystem::path finalize_extraction(const ExtractedContent &content) override;
+
+    // --- integrity check ---
+
+    /**
+     * @brief (Not Implemented) Compute a raw checksum.
+     * @param file_path Path to the file.
+     * @return An empty string.
+     */
+    [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+private:
+    ZipWriterOptions zip_options_;
+};
+
+} // namespace chisel
+
+#endif // CHISEL_COMPRESSED_STREAM_PROCESSOR_HPP
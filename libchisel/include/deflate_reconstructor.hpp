@@ -0,0 +1,121 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file deflate_reconstructor.hpp
+ * @brief Detects the zlib parameters that reproduce an existing deflate stream bit for bit.
+ */
+
+#ifndef CHISEL_DEFLATE_RECONSTRUCTOR_HPP
+#define CHISEL_DEFLATE_RECONSTRUCTOR_HPP
+
+#include <cstddef>
+#include <optional>
+#include <span>
+#include <vector>
+
+namespace chisel {
+
+/**
+ * @brief Framing around the deflate data.
+ */
+enum class DeflateWrapper {
+    Raw,  ///< Bare deflate blocks (ZIP entries, gzip members)
+    Zlib  ///< RFC 1950 header and Adler-32 trailer (PDF FlateDecode, PNG IDAT)
+};
+
+/**
+ * @brief How hard DeflateReconstructor::analyze() looks for reproducing parameters.
+ */
+enum class DeflateSearch {
+    None,      ///< Only inflate the stream, `params` stays unset
+    Common,    ///< Every level with memLevel 8 and the default strategy (zlib's usual settings)
+    Exhaustive ///< Every window size, memory level and strategy as well
+};
+
+/**
+ * @brief The `deflateInit2()` arguments that produced a stream.
+ *
+ * `strategy` holds zlib's Z_DEFAULT_STRATEGY, Z_FILTERED, Z_HUFFMAN_ONLY
+ * or Z_RLE value. The values are stable across zlib releases, so they can
+ * be stored next to the decompressed data and replayed later.
+ */
+struct DeflateParams {
+    int level = 6;
+    int window_bits = 15;
+    int mem_level = 8;
+    int strategy = 0;
+};
+
+/**
+ * @brief Result of analyzing one deflate stream.
+ */
+struct DeflateAnalysis {
+    bool valid = false;                      ///< The stream inflated without error
+    std::optional<DeflateParams> params;     ///< Set when zlib reproduces the stream exactly
+    std::vector<unsigned char> decompressed; ///< The inflated data (empty if !valid)
+    std::size_t consumed = 0;                ///< Compressed bytes belonging to the stream
+
+    /**
+     * @brief True if zlib rebuilds the original bytes from `decompressed` and `params`.
+     */
+    [[nodiscard]] bool reconstructible() const noexcept { return params.has_value(); }
+};
+
+/**
+ * @brief Bit-exact deflate reconstruction, in the spirit of precomp.
+ *
+ * @details A deflate stream only fixes the decompressed data, not the bytes
+ * that encode it. When those bytes must survive (signed ZIPs, archival
+ * round trips) chisel needs to know whether a stream can be regenerated
+ * from its decompressed form. This class inflates the stream, then replays
+ * zlib with candidate `deflateInit2()` parameters and compares the output
+ * against the original while it is produced, dropping a candidate at the
+ * first differing byte.
+ *
+ * A reconstructible stream was written by a stock zlib encoder: it is
+ * safe to recompress, since the original can be regenerated from the
+ * decompressed data. A stream that no parameter set reproduces came from
+ * another encoder (Zopfli, 7-Zip, kzip...) and is copied as-is whenever
+ * its exact bytes matter.
+ *
+ * A wrong candidate usually fails within the first block zlib emits, so
+ * the search costs a few partial encodes rather than full ones; callers
+ * that do not need `params` pass DeflateSearch::None.
+ */
+class DeflateReconstructor {
+public:
+    /**
+     * @brief Inflates a stream and searches for parameters that reproduce it.
+     *
+     * For zlib streams the window size and the FLEVEL hint of the header
+     * narrow the search, and level 0 is only tried when the first block
+     * is stored. Raw streams are assumed to use the 32K window unless the
+     * search is exhaustive.
+     *
+     * @param compressed The stream, possibly followed by unrelated bytes.
+     * @param wrapper The framing of the stream.
+     * @param search Which parameter sets to try.
+     * @return The analysis; `valid` is false if the data does not inflate.
+     */
+    static DeflateAnalysis analyze(std::span<const unsigned char> compressed,
+                                   DeflateWrapper wrapper,
+                                   DeflateSearch search = DeflateSearch::Common);
+
+    /**
+     * @brief Compresses data with the given parameters.
+     *
+     * Feeding the `decompressed` data and `params` of a reconstructible
+     * analysis returns the original stream, byte for byte.
+     *
+     * @return The compressed stream, or std::nullopt if zlib rejects the parameters.
+     */
+    static std::optional<std::vector<unsigned char>> reconstruct(std::span<const unsigned char> decompressed,
+                                                                 const DeflateParams& params,
+                                                                 DeflateWrapper wrapper);
+};
+
+} // namespace chisel
+
+#endif // CHISEL_DEFLATE_RECONSTRUCTOR_HPP
@@ -33,9 +33,10 @@ enum class ContainerFormat {
     GZip,
     BZip2,
     Xz,
+    Lzma,
     Rar,
     Wim,
-    //Mkv,
+    Mkv,
     Pdf,
     Docx,
     Xlsx,
@@ -68,8 +69,10 @@ inline const std::unordered_map<std::string, ContainerFormat> mime_to_format = {
     { "application/x-7z-compressed",  ContainerFormat::SevenZip },
     { "application/x-tar",            ContainerFormat::Tar },
     { "application/gzip",             ContainerFormat::GZip },
+    { "application/x-gzip",           ContainerFormat::GZip },
     { "application/x-bzip2",          ContainerFormat::BZip2 },
     { "application/x-xz",             ContainerFormat::Xz },
+    { "application/x-lzma",           ContainerFormat::Lzma },
     { "application/vnd.rar",          ContainerFormat::Rar },
     { "application/x-rar-compressed", ContainerFormat::Rar },
     //{ "video/x-matroska",             ContainerFormat::Mkv },
@@ -123,9 +126,10 @@ inline std::string container_format_to_string(const ContainerFormat fmt) {
         case ContainerFormat::GZip:     return "gz";
         case ContainerFormat::BZip2:    return "bz2";
         case ContainerFormat::Xz:       return "xz";
+        case ContainerFormat::Lzma:     return "lzma";
         case ContainerFormat::Wim:      return "wim";
         case ContainerFormat::Pdf:    return "pdf";
-        //case ContainerFormat::Mkv:      return "mkv";
+        case ContainerFormat::Mkv:      return "mkv";
         case ContainerFormat::Rar:      return "rar";
         case ContainerFormat::Docx:     return "docx";
         case ContainerFormat::Xlsx:     return "xlsx";
@@ -172,9 +176,10 @@ inline std::optional<ContainerFormat> parse_container_format(const std::string &
     if (s == "gz" || s == "gzip")    return ContainerFormat::GZip;
     if (s == "bz2" || s == "bzip2")  return ContainerFormat::BZip2;
     if (s == "xz")    return ContainerFormat::Xz;
+    if (s == "lzma")  return ContainerFormat::Lzma;
     if (s == "wim")   return ContainerFormat::Wim;
     if (s == "rar")   return ContainerFormat::Rar;
-    //if (s == "mkv")   return ContainerFormat::Mkv;
+    if (s == "mkv")   return ContainerFormat::Mkv;
     if (s == "docx")  return ContainerFormat::Docx;
     if (s == "xlsx")  return ContainerFormat::Xlsx;
     if (s == "pptx")  return ContainerFormat::Pptx;
@@ -232,6 +237,7 @@ inline bool can_write_format(const ContainerFormat fmt) {
         case ContainerFormat::GZip:
         case ContainerFormat::BZip2:
         case ContainerFormat::Xz:
+        case ContainerFormat::Lzma:
         //case ContainerFormat::Mkv:
         case ContainerFormat::Docx:
         case ContainerFormat::Xlsx:
@@ -269,8 +275,13 @@ static const std::unordered_map<std::string, std::string> ext_to_mime = {
     {".cb7",    "application/x-7z-compressed"},
     {".tar",    "application/x-tar"},
     {".gz",     "application/gzip"},
+    {".tgz",    "application/gzip"},
     {".bz2",    "application/x-bzip2"},
+    {".tbz2",   "application/x-bzip2"},
     {".xz",     "application/x-xz"},
+    {".txz",    "application/x-xz"},
+    {".zst",    "application/zstd"},
+    {".tzst",   "application/zstd"},
     {".wim",    "application/x-ms-wim"},
     {".rar",    "application/vnd.rar"},
     {".cbr",    "application/vnd.comicbook+rar"},
@@ -338,10 +349,20 @@ static const std::unordered_map<std::string, std::string> ext_to_mime = {
     {".mp3",    "audio/mpeg"},
     {".wav",    "audio/wav"},
     {".ape",    "audio/x-ape"},
+    {".tta",    "audio/x-tta"},
+    {".m4a",    "audio/mp4"},
+    {".m4b",    "audio/mp4"},
 
     // video / containers
     {".mkv",    "video/x-matroska"},
     {".webm",   "video/webm"},
+    {".mp4",    "video/mp4"},
+    {".mov",    "video/quicktime"},
+    {".swf",    "application/x-shockwave-flash"},
+
+    // fonts
+    {".woff",   "font/woff"},
+    {".woff2",  "font/woff2"},
 
     // scientific / seismic
     {".mseed",  "application/vnd.fdsn.mseed"}
@@ -40,6 +40,16 @@ namespace chisel {
      */
     void cleanup_temp_dir(const std::filesystem::path &dir,
                           std::string_view tag = "file_utils");
+
+    /**
+     * @brief Compares two files byte for byte.
+     *
+     * The fallback of raw_equal() for files a processor cannot decode:
+     * those are left as-is by recompress() and must still compare equal.
+     *
+     * @return true if both files can be read and have the same contents.
+     */
+    bool files_equal(const std::filesystem::path &a, const std::filesystem::path &b);
 } // namespace chisel
 
 #endif // CHISEL_FILE_UTILS_HPP
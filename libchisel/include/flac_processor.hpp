@@ -12,12 +12,40 @@
 
 #include "processor.hpp"
 #include <FLAC/all.h>
+#include <algorithm>
 #include <array>
 #include <string_view>
 #include <span>
 
 namespace chisel {
 
+/**
+ * @brief How much encoder parameter search FlacProcessor does.
+ */
+enum class FlacEffort {
+    Normal,  ///< One encode: level 8, fixed apodization chain, exhaustive model search.
+    High,    ///< Tries several blocksizes, apodization sets and LPC precisions; keeps the smallest.
+    Extreme  ///< As High, plus a variable-blocksize encode of the best settings.
+};
+
+/**
+ * @brief Which metadata blocks FlacProcessor keeps when re-encoding.
+ *
+ * PADDING is always replaced by `padding` bytes, VORBIS_COMMENT blocks are
+ * merged with exact duplicate fields removed, and PICTURE blocks go through
+ * the image processors (see FlacProcessor::prepare_extraction()).
+ */
+struct FlacMetadataPolicy {
+    /// Largest PADDING block: its length is a 24-bit field.
+    static constexpr unsigned max_padding = (1u << 24) - 1;
+    /// Bytes of PADDING after the metadata, for taggers that edit in place. 0 writes none; at most max_padding.
+    unsigned padding = 0;
+    /// Seconds between the points of the rebuilt SEEKTABLE, written only if the source had one. 0 drops it.
+    unsigned seek_interval = 10;
+    /// Keep every APPLICATION block; the foreign-metadata ones ("riff", "aiff", "w64 ") are always kept.
+    bool keep_application = false;
+};
+
 /**
  * @brief Implements IProcessor for FLAC files using libFLAC.
  *
@@ -27,6 +55,22 @@ namespace chisel {
  */
 class FlacProcessor final : public IProcessor {
 public:
+    explicit FlacProcessor(FlacEffort effort = FlacEffort::Normal) noexcept : effort_(effort) {}
+
+    /**
+     * @brief Sets the search effort used by later recompress() calls.
+     */
+    void set_effort(FlacEffort effort) noexcept { effort_ = effort; }
+    [[nodiscard]] FlacEffort effort() const noexcept { return effort_; }
+
+    /**
+     * @brief Sets the metadata policy used by later recompress() and finalize_extraction() calls.
+     */
+    void set_metadata_policy(const FlacMetadataPolicy& policy) noexcept {
+        metadata_policy_ = policy;
+        metadata_policy_.padding = std::min(policy.padding, FlacMetadataPolicy::max_padding);
+    }
+    [[nodiscard]] const FlacMetadataPolicy& metadata_policy() const noexcept { return metadata_policy_; }
 
     // --- self-description ---
     [[nodiscard]] std::string_view get_name() const noexcept override {
@@ -60,6 +104,10 @@ class FlacProcessor final : public IProcessor {
 
     /**
      * @brief Rebuilds the FLAC file with optimized cover art.
+     *
+     * The padding that TagLib adds while rewriting is brought back to the
+     * size set by the FlacMetadataPolicy.
+     *
      * @param content The ExtractedContent struct from prepare_extraction.
      * @param target_format (Ignored)
      * @return Path to the newly finalized FLAC file.
@@ -69,12 +117,19 @@ class FlacProcessor final : public IProcessor {
      * @brief Recompresses a FLAC file using libFLAC.
      *
      * Performs a full decode and re-encode cycle using the highest
-     * compression settings (level 8, exhaustive model search).
+     * compression settings (level 8, exhaustive model search). Above
+     * FlacEffort::Normal, the parameters are searched one at a time
+     * (blocksize, then apodization, then LPC precision) and the smallest
+     * encode is kept. FlacEffort::Extreme also builds a variable-blocksize
+     * stream, choosing per 8192-sample superblock between frames of 8192,
+     * 4096, 2048 and 1024 samples; it is kept only if it decodes to the
+     * STREAMINFO MD5.
      *
      * @param input Path to the source FLAC file.
      * @param output Path to write the optimized FLAC file.
-     * @param preserve_metadata If true, copies all metadata blocks
-     * (except STREAMINFO) to the new file.
+     * @param preserve_metadata If true, copies the metadata blocks
+     * (except STREAMINFO and PICTURE) to the new file, compacted as set
+     * by the FlacMetadataPolicy. PADDING follows the policy either way.
      * @throws std::runtime_error if libFLAC init or processing fails.
      */
     void recompress(const std::filesystem::path &input, const std::filesystem::path &output, bool preserve_metadata) override;
@@ -90,16 +145,21 @@ class FlacProcessor final : public IProcessor {
     [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
 
     /**
-     * @brief Compares two FLAC files by decoding them to raw PCM and comparing.
+     * @brief Compares two FLAC files by their STREAMINFO MD5.
      *
-     * This is a fallback comparison used if the STREAMINFO MD5s differ
-     * (e.g., if one file lacks it).
+     * When both files carry an MD5, they must match and `b` must decode
+     * to it. If either MD5 is unset, both files are decoded to raw PCM
+     * and compared.
      *
      * @param a First FLAC file.
      * @param b Second FLAC file.
      * @return true if the decoded PCM data and audio parameters are identical.
      */
     [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
+
+private:
+    FlacEffort effort_ = FlacEffort::Normal;
+    FlacMetadataPolicy metadata_policy_;
 };
 
 } // namespace chisel
@@ -0,0 +1,123 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file lepton_processor.hpp
+ * @brief Defines the opt-in IProcessor that archives JPEG files as Lepton.
+ */
+
+#ifndef CHISEL_LEPTON_PROCESSOR_HPP
+#define CHISEL_LEPTON_PROCESSOR_HPP
+
+#include "processor.hpp"
+#include <array>
+#include <string_view>
+#include <span>
+
+namespace chisel {
+
+    /**
+     * @brief Implements IProcessor for archiving JPEG files with Lepton.
+     *
+     * @details Uses lepton_jpeg_rust (through the Rust bridge) to turn a JPEG
+     * into a `.lep` file, typically about 20% smaller. The output is no longer
+     * a JPEG, so this processor is opt-in (see ProcessorRegistry::register_processor)
+     * and is only applied to top-level files, never inside containers.
+     * Every encode is decoded back and compared byte for byte with the
+     * original before it is accepted; restore() reverses the operation.
+     */
+    class LeptonProcessor final : public IProcessor {
+    public:
+        // --- self-description ---
+        [[nodiscard]] std::string_view get_name() const noexcept override {
+            return "LeptonProcessor";
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
+            static constexpr std::array<std::string_view, 1> kMimes = { "image/jpeg" };
+            return {kMimes.data(), kMimes.size()};
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
+            static constexpr std::array<std::string_view, 3> kExts = { ".jpg", ".jpeg", ".jpe" };
+            return {kExts.data(), kExts.size()};
+        }
+
+        // --- capabilities ---
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
+        [[nodiscard]] bool can_extract_contents() const noexcept override { return false; }
+
+        /// @return ".lep": the output replaces the JPEG under a new extension.
+        [[nodiscard]] std::string_view get_output_extension() const noexcept override { return ".lep"; }
+
+        // --- operations ---
+
+        /**
+         * @brief Compresses a JPEG file to Lepton.
+         *
+         * The Lepton data is decoded again and must reproduce the input byte
+         * for byte, otherwise nothing is written and an exception is thrown.
+         *
+         * @param input Path to the source JPEG file.
+         * @param output Path to write the `.lep` file.
+         * @param preserve_metadata Ignored: Lepton always keeps the whole file.
+         * @throws std::runtime_error if encoding or the round-trip check fails.
+         */
+        void recompress(const std::filesystem::path& input,
+                        const std::filesystem::path& output,
+                        bool preserve_metadata) override;
+
+        /**
+         * @brief Lepton files are not containers.
+         * @return std::nullopt
+         */
+        std::optional<ExtractedContent> prepare_extraction(
+            [[maybe_unused]] const std::filesystem::path& input_path) override { return std::nullopt; }
+
+        /**
+         * @brief Lepton files are not containers.
+         * @return Empty path.
+         */
+        std::filesystem::path finalize_extraction(const ExtractedContent &) override { return {}; }
+
+        /**
+         * @brief Restores the original JPEG from a Lepton file.
+         * @param input Path to the `.lep` file.
+         * @param output Path to write the restored JPEG.
+         * @throws std::runtime_error if the Lepton data cannot be decoded.
+         */
+        static void restore(const std::filesystem::path& input, const std::filesystem::path& output);
+
+        /**
+         * @brief Name of the JPEG a Lepton file restores to.
+         *
+         * Archived files keep their original name before `.lep` (photo.JPG.lep),
+         * which is given back as is; a bare `photo.lep` restores to `photo.jpg`.
+         *
+         * @param lepton_file Path to the `.lep` file.
+         * @return The file name of the restored JPEG.
+         */
+        [[nodiscard]] static std::filesystem::path restored_filename(const std::filesystem::path& lepton_file);
+
+        // --- integrity check ---
+
+        /**
+         * @brief Checks that a Lepton file restores to the original JPEG.
+         * @param a Path to the original JPEG file.
+         * @param b Path to the Lepton file.
+         * @return true if restoring `b` yields exactly the bytes of `a`.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
+
+        /**
+         * @brief (Not Implemented) Compute a raw checksum.
+         * @param file_path Path to the file.
+         * @return An empty string; raw_equal() compares restored bytes instead.
+         */
+        [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_LEPTON_PROCESSOR_HPP
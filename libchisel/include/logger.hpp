@@ -42,6 +42,14 @@ class Logger {
      */
     static void clear_sinks();
 
+    /**
+     * @brief Remove and destroy a single sink previously added with add_sink().
+     * Unknown or null pointers are ignored.
+     * This operation is thread-safe.
+     * @param sink The sink to remove.
+     */
+    static void remove_sink(const ILogSink* sink);
+
     /**
      * @brief Log a message to all registered sinks.
      * This operation is thread-safe.
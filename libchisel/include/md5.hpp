@@ -0,0 +1,53 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file md5.hpp
+ * @brief Incremental MD5 digest, used to check the audio checksums stored by lossless codecs.
+ */
+
+#ifndef CHISEL_MD5_HPP
+#define CHISEL_MD5_HPP
+
+#include <array>
+#include <cstddef>
+#include <cstdint>
+#include <span>
+
+namespace chisel {
+
+    /**
+     * @brief Computes an MD5 digest over data fed in any number of pieces.
+     *
+     * @details Not meant for security: WavPack and Monkey's Audio store the
+     * MD5 of the decoded samples, and this is what they are checked against.
+     */
+    class Md5 {
+    public:
+        Md5() noexcept;
+
+        /**
+         * @brief Feeds more data into the digest.
+         * @param data Bytes to append to the message.
+         */
+        void update(std::span<const std::uint8_t> data) noexcept;
+
+        /**
+         * @brief Completes the digest.
+         * @return The 16-byte MD5 of everything passed to update().
+         * @note The object must not be updated again afterwards.
+         */
+        [[nodiscard]] std::array<std::uint8_t, 16> finish() noexcept;
+
+    private:
+        void transform(const std::uint8_t* block) noexcept;
+
+        std::array<std::uint32_t, 4> state_;     ///< Running A, B, C, D words
+        std::array<std::uint8_t, 64> buffer_{};  ///< Bytes of the current, incomplete block
+        std::uint64_t length_ = 0;               ///< Message length in bytes
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_MD5_HPP
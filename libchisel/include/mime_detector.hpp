@@ -23,6 +23,8 @@ namespace chisel {
          * @param path The filesystem path to the file.
          * @return A string representing the MIME type (e.g., "image/jpeg").
          *
+         * @note TTA streams are recognized by their signature first, also
+         * behind an ID3v2 tag (which libmagic reports as audio/mpeg).
          * @note On Linux/macOS, this uses libmagic for accurate detection.
          * @note On Windows, this currently falls back to a simple
          * map of file extensions to MIME types.
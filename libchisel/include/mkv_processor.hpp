@@ -12,6 +12,25 @@
 
 namespace chisel {
 
+    /**
+     * @brief Processor for Matroska and WebM files.
+     *
+     * @details Recompression streams the file without a temporary copy,
+     * reading the clusters twice: a first pass measures each rewritten
+     * Cluster so that the SeekHead and Cues can be placed ahead of them,
+     * and a second pass writes the output. Void and CRC-32 elements are
+     * removed, each laced block gets the lacing (Xiph, EBML or fixed) with
+     * the smallest header, and a single SeekHead and the Cues are rebuilt
+     * with minimal-width positions ahead of the clusters. Without metadata, Tags and attached images are dropped;
+     * fonts and chapters are kept, since playback depends on them.
+     *
+     * As a container, the processor extracts the attached files (fonts,
+     * cover art) so that their own processors can optimize them, then
+     * rebuilds the Attachments element.
+     * Everything else in the Segment (chapters, tags, cues, clusters) is
+     * copied as-is, with SeekHead, Cues and Cluster positions moved to
+     * their new offsets.
+     */
     class MkvProcessor final : public IProcessor {
     public:
         // --- self-description ---
@@ -31,20 +50,45 @@ namespace chisel {
 
         // --- capabilities ---
         [[nodiscard]] bool can_recompress() const noexcept override { return true; }
-        [[nodiscard]] bool can_extract_contents() const noexcept override { return true; } // in futuro
+        [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
         // --- operations ---
+        /**
+         * @brief Rewrites a Matroska file in its smallest form, leaving every frame untouched.
+         *
+         * @param input Path to the Matroska file.
+         * @param output Path to write the optimized file.
+         * @param preserve_metadata If false, Tags and attached images are dropped.
+         * @throws std::runtime_error if the input cannot be opened or the output written.
+         */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
                         bool preserve_metadata) override;
 
+        /**
+         * @brief Writes every attached file with data to a temporary directory.
+         * @return The extracted files, or std::nullopt if there are no attachments.
+         */
         std::optional<ExtractedContent> prepare_extraction(
             const std::filesystem::path& input_path) override;
 
+        /**
+         * @brief Re-muxes the processed attachments into the file.
+         * @return Path to the rebuilt file, or an empty path if it did not get smaller.
+         */
         std::filesystem::path finalize_extraction(const ExtractedContent &content) override;
 
         // --- integrity check ---
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+        /**
+         * @brief Compares the frames of two files, track by track.
+         *
+         * Each track is reduced to its frame count and a CRC-32 of its
+         * frames with their timestamps and flags, so lacing and layout may
+         * differ.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path& a, const std::filesystem::path& b) const override;
     };
 
 } // namespace chisel
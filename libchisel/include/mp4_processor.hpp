@@ -16,7 +16,14 @@ namespace chisel {
      * @brief Implements IProcessor for MP4/M4A files.
      *
      * @details Extracts and re-inserts cover art (atom 'covr') using
-     * AudioMetadataUtil. Stream recompression is not yet implemented.
+     * AudioMetadataUtil.
+     *
+     * Recompression rewrites the box structure, leaving every sample byte
+     * untouched: free space ('free', 'skip', 'wide') is removed, moov is
+     * moved before the media data (faststart), chunk offsets are relocated
+     * and written as 'stco' whenever they fit in 32 bits, and the 'stts',
+     * 'stsc' and 'stsz' tables are compacted. Without metadata, 'udta' and
+     * 'meta' boxes are dropped. Fragmented files are copied unchanged.
      */
     class Mp4Processor final : public IProcessor {
     public:
@@ -25,20 +32,28 @@ namespace chisel {
         }
 
         [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
-            static constexpr std::array<std::string_view, 3> kMimes = {
-                "audio/mp4", "audio/x-m4a", "video/mp4"
+            static constexpr std::array<std::string_view, 4> kMimes = {
+                "audio/mp4", "audio/x-m4a", "video/mp4", "video/quicktime"
             };
             return {kMimes.data(), kMimes.size()};
         }
 
         [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
-            static constexpr std::array<std::string_view, 3> kExts = { ".mp4", ".m4a", ".m4b" };
+            static constexpr std::array<std::string_view, 4> kExts = { ".mp4", ".m4a", ".m4b", ".mov" };
             return {kExts.data(), kExts.size()};
         }
 
-        [[nodiscard]] bool can_recompress() const noexcept override { return false; }
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
         [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
+        /**
+         * @brief Rewrites the boxes of an ISO-BMFF/QuickTime file in their smallest lossless form.
+         *
+         * @param input Path to the MP4 file.
+         * @param output Path to write the optimized file.
+         * @param preserve_metadata If false, user data and metadata boxes are dropped.
+         * @throws std::runtime_error if the output cannot be written.
+         */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
                         bool preserve_metadata) override;
@@ -49,6 +64,15 @@ namespace chisel {
         std::filesystem::path finalize_extraction(const ExtractedContent &content) override;
 
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override { return ""; }
+
+        /**
+         * @brief Compares the sample data of two files, track by track.
+         *
+         * Each track is reduced to its sample count and a CRC-32 of its
+         * samples, read through the sample tables, so the box layout may
+         * differ.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path& a, const std::filesystem::path& b) const override;
     };
 
 } // namespace chisel
@@ -22,8 +22,16 @@ namespace chisel {
      *
      * @details This processor acts as a container for extracting and
      * re-inserting cover art (ID3v2 APIC tags), allowing the image
-     * files to be processed by image optimizers. Audio recompression
-     * logic (Phase 2) is left as a future implementation task.
+     * files to be processed by image optimizers.
+     *
+     * MPEG-1 Layer III streams are also repacked losslessly, in the
+     * manner of mp3packer: the Huffman-coded granule data is left
+     * untouched, but every frame is rewritten at the smallest bitrate
+     * that still holds its data, using the bit reservoir to carry the
+     * excess of a frame into the free space of the previous ones.
+     * Padding, junk between frames and truncated frames are dropped, and
+     * a fresh Xing/Info header (keeping the LAME extension, replacing any
+     * VBRI header) describes the new stream.
      */
     class MpegProcessor final : public IProcessor {
     public:
@@ -44,10 +52,10 @@ namespace chisel {
 
         // --- capabilities ---
         /**
-         * @brief Audio recompression is planned for the future.
-         * @return false
+         * @brief This processor repacks MP3 frames.
+         * @return true
          */
-        [[nodiscard]] bool can_recompress() const noexcept override { return false; }
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
 
         /**
          * @brief This processor extracts cover art.
@@ -58,9 +66,16 @@ namespace chisel {
         // --- operations ---
 
         /**
-         * @brief (Placeholder) Direct recompression is not implemented yet.
+         * @brief Repacks the frames of an MPEG-1 Layer III stream at minimal bitrate.
          *
-         * @throws std::runtime_error always, if this is called improperly.
+         * Files that MimeDetector::is_mpeg1_layer3 rejects (MPEG-2/2.5,
+         * layers I/II) and streams that cannot be repacked (free format,
+         * broken reservoir references) are copied unchanged.
+         *
+         * @param input Path to the MP3 file.
+         * @param output Path to write the repacked file.
+         * @param preserve_metadata If false, ID3 and APE tags are dropped.
+         * @throws std::runtime_error if the output cannot be written.
          */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
@@ -84,6 +99,17 @@ namespace chisel {
 
         // --- integrity check ---
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override { return ""; }
+
+        /**
+         * @brief Compares the decoder input of two MP3 files frame by frame.
+         *
+         * Frames are equal when their header (bitrate, padding and CRC
+         * aside), side info (reservoir offset aside) and granule data match,
+         * so a repacked file decodes to the same samples as the original.
+         * Xing/Info frames are not compared. Files that cannot be parsed
+         * as MPEG-1 Layer III are compared byte for byte.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path& a, const std::filesystem::path& b) const override;
     };
 
 } // namespace chisel
@@ -11,6 +11,7 @@
 #define CHISEL_ODF_PROCESSOR_HPP
 
 #include "processor.hpp"
+#include "zip_writer.hpp"
 #include <array>
 #include <string_view>
 #include <span>
@@ -22,8 +23,8 @@ namespace chisel {
      *
      * @details This processor handles .odt, .ods, .odp, and .odg files.
      * It treats them as ZIP archives, extracts their contents,
-     * and re-compresses embedded XML files (e.g., content.xml)
-     * using Zopfli during the finalization phase.
+     * and rebuilds the ZIP with every entry deflated by Zopfli
+     * during the finalization phase.
      */
     class OdfProcessor final : public IProcessor {
     public:
@@ -61,6 +62,12 @@ namespace chisel {
          */
         [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
+        /**
+         * @brief Sets the Zopfli settings used when the ZIP is rebuilt.
+         */
+        void set_zip_options(const ZipWriterOptions& options) noexcept { zip_options_ = options; }
+        [[nodiscard]] const ZipWriterOptions& zip_options() const noexcept { return zip_options_; }
+
         // --- operations ---
 
         /**
@@ -83,12 +90,12 @@ namespace chisel {
             const std::filesystem::path& input_path) override;
 
         /**
-         * @brief Rebuilds the ODF archive with optimized XML.
+         * @brief Rebuilds the ODF archive from the (optimized) extracted files.
          *
          * Iterates through the extracted files. The `mimetype` file is
          * stored uncompressed (as required by the ODF standard).
-         * Embedded XML files are recompressed using Zopfli.
-         * All other files (e.g., images) are re-added using standard Deflate.
+         * All other files are written with ZipWriter: deflated with
+         * Zopfli, or stored when that is smaller.
          *
          * @param content The ExtractedContent struct from `prepare_extraction`.
          * @param target_format (Ignored) This processor always writes a ZIP.
@@ -105,6 +112,9 @@ namespace chisel {
          * @return An empty string.
          */
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+    private:
+        ZipWriterOptions zip_options_;
     };
 
 } // namespace chisel
@@ -13,10 +13,11 @@
 namespace chisel {
 
     /**
-     * @brief Implements IProcessor for Ogg (Vorbis/Opus/FLAC) files.
+     * @brief Implements IProcessor for Ogg (Vorbis/Opus/FLAC/Theora/Speex) files.
      *
      * @details Supports two main operations:
-     * 1. Recompression of Ogg FLAC streams (lossless audio optimization).
+     * 1. Lossless optimization of Ogg FLAC, Vorbis and Opus streams, plus
+     * re-pagination of chained and multiplexed files (e.g. Theora + Vorbis).
      * 2. Extraction and optimization of embedded cover art for all Ogg variants
      * (Vorbis, Opus, FLAC) via TagLib.
      */
@@ -28,14 +29,14 @@ namespace chisel {
         }
 
         [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
-            static constexpr std::array<std::string_view, 3> kMimes = {
-                "audio/ogg", "audio/vorbis", "audio/opus"
+            static constexpr std::array<std::string_view, 4> kMimes = {
+                "audio/ogg", "audio/vorbis", "audio/opus", "video/ogg"
             };
             return {kMimes.data(), kMimes.size()};
         }
 
         [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
-            static constexpr std::array<std::string_view, 3> kExts = { ".ogg", ".opus", ".oga" };
+            static constexpr std::array<std::string_view, 5> kExts = { ".ogg", ".opus", ".oga", ".ogv", ".spx" };
             return {kExts.data(), kExts.size()};
         }
 
@@ -46,11 +47,17 @@ namespace chisel {
         // operations
 
         /**
-         * @brief Attempts to recompress Ogg-FLAC streams.
+         * @brief Attempts to recompress Ogg-FLAC, Ogg Vorbis and Ogg Opus streams.
          *
          * If the input is Ogg FLAC, decodes and re-encodes with maximum compression,
-         * preserving Vorbis comments. If input is lossy (Vorbis/Opus), performs a
-         * direct copy to preserve data.
+         * preserving Vorbis comments. If the input is Ogg Vorbis, it is optimized
+         * losslessly through OptiVorbis (comments and vendor string are dropped
+         * when metadata is not preserved). Ogg Opus streams are re-paginated and
+         * stripped of padding by the Rust bridge, keeping OpusHead and granule
+         * positions bit-exact (comments are dropped when metadata is not preserved).
+         * Chained or multiplexed files, and codecs without a dedicated path, go
+         * through the generic Ogg re-packer: every logical stream is re-paginated,
+         * Vorbis streams are optimized, serials and stream order are kept.
          */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
@@ -70,6 +77,14 @@ namespace chisel {
         // integrity check
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
 
+        /**
+         * @brief Compares two Ogg files by their decoded audio.
+         *
+         * Ogg FLAC streams are decoded with libFLAC, Vorbis streams through
+         * the Rust bridge (lewton); both must produce identical PCM. Opus
+         * streams must carry the same OpusHead, granule range and audio
+         * packets (padding aside). Re-packed files are compared stream by stream.
+         */
         [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
     };
 
@@ -11,6 +11,7 @@
 #define CHISEL_OOXML_PROCESSOR_HPP
 
 #include "processor.hpp"
+#include "zip_writer.hpp"
 #include <array>
 #include <string_view>
 #include <span>
@@ -22,8 +23,8 @@ namespace chisel {
  *
  * @details This processor handles .docx, .xlsx, and .pptx files.
  * It treats them as ZIP archives, extracts their contents,
- * and specifically re-compresses embedded images (PNG/JPG)
- * using Zopfli during the finalization phase.
+ * and rebuilds the ZIP with every entry deflated by Zopfli
+ * during the finalization phase.
  */
 class OOXMLProcessor final : public IProcessor {
 public:
@@ -60,6 +61,12 @@ class OOXMLProcessor final : public IProcessor {
      */
     [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
+    /**
+     * @brief Sets the Zopfli settings used when the ZIP is rebuilt.
+     */
+    void set_zip_options(const ZipWriterOptions& options) noexcept { zip_options_ = options; }
+    [[nodiscard]] const ZipWriterOptions& zip_options() const noexcept { return zip_options_; }
+
     // --- operations ---
 
     /**
@@ -82,11 +89,11 @@ class OOXMLProcessor final : public IProcessor {
         const std::filesystem::path& input_path) override;
 
     /**
-     * @brief Rebuilds the OOXML archive with optimized images.
+     * @brief Rebuilds the OOXML archive from the (optimized) extracted files.
      *
-     * Iterates through the extracted files. PNG/JPG files are
-     * recompressed using Zopfli. All other files are copied.
-     * The archive is then rebuilt as a ZIP using libarchive.
+     * `[Content_Types].xml` is written first. The archive is rebuilt
+     * with ZipWriter: each entry is deflated with Zopfli, or stored
+     * when that is smaller.
      *
      * @param content The ExtractedContent struct from `prepare_extraction`.
      * @param target_format (Ignored) This processor always writes a ZIP.
@@ -103,6 +110,9 @@ class OOXMLProcessor final : public IProcessor {
      * @return An empty string.
      */
     [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+private:
+    ZipWriterOptions zip_options_;
 };
 
 } // namespace chisel
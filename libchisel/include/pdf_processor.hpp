@@ -11,7 +11,10 @@
 #define CHISEL_PDF_PROCESSOR_HPP
 
 #include "processor.hpp"
+#include "deflate_reconstructor.hpp"
 #include <array>
+#include <cstdint>
+#include <optional>
 #include <string_view>
 #include <span>
 #include <unordered_map>
@@ -29,6 +32,11 @@ namespace chisel {
  * 2. (Recompressor) During finalization, it re-compresses any
  * internal Flate streams (like text or vector data) using Zopfli.
  *
+ * Flate streams are checked with DeflateReconstructor when extracted:
+ * a stream that does not inflate cleanly to its end (truncated or
+ * corrupt data that qpdf decodes leniently) is copied as-is, and an
+ * unchanged stream keeps its original bytes unless Zopfli beats them.
+ *
  * It uses `qpdf` for all PDF parsing and manipulation.
  */
 class PdfProcessor final : public IProcessor {
@@ -91,7 +99,9 @@ class PdfProcessor final : public IProcessor {
      * 1. Re-embeds streams (like images) that were optimized in the
      * temp directory by other processors.
      * 2. Re-compresses internal Flate streams (text, vector graphics)
-     * using Zopfli for better compression.
+     * using Zopfli for better compression. Streams whose Flate data is
+     * not clean, and unchanged streams Zopfli cannot shrink, keep their
+     * original bytes.
      *
      * Uses `QPDFWriter` to write a new, linearized, and optimized PDF.
      *
@@ -131,6 +141,10 @@ class PdfProcessor final : public IProcessor {
         bool decodable = false;       ///< True if qpdf could decode the stream
         bool has_decode_parms = false;///< True if stream has /DecodeParms
         std::filesystem::path file;   ///< Path to the extracted raw stream data
+        bool flate_valid = false;     ///< True if the single /FlateDecode data inflates cleanly to its end
+        std::size_t raw_size = 0;     ///< Size of the encoded stream data
+        std::size_t decoded_size = 0; ///< Size of the decoded data at extraction time
+        std::uint32_t decoded_crc = 0;///< CRC-32 of the decoded data at extraction time
     };
 
     /**
@@ -0,0 +1,137 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file precomp_processor.hpp
+ * @brief Defines the opt-in IProcessor that archives files with their deflate streams expanded.
+ */
+
+#ifndef CHISEL_PRECOMP_PROCESSOR_HPP
+#define CHISEL_PRECOMP_PROCESSOR_HPP
+
+#include "processor.hpp"
+#include <array>
+#include <string_view>
+#include <span>
+
+namespace chisel {
+
+    /**
+     * @brief Implements IProcessor for precomp-style archival of deflate-heavy files.
+     *
+     * @details Every deflate stream that DeflateReconstructor can regenerate
+     * bit for bit (PDF and SWF zlib streams, ZIP entries, the IDAT data of a
+     * PNG) is stored decompressed next to its zlib parameters, and the whole
+     * file is then compressed with xz. The `.pcz` output is usually much
+     * smaller, but is no longer readable in its original format, so this
+     * processor is opt-in (see ProcessorRegistry::register_processor) and is
+     * only applied to top-level files, never inside containers.
+     * Every archive is rebuilt in memory and compared byte for byte with the
+     * original before it is written; restore() reverses the operation.
+     */
+    class PrecompProcessor final : public IProcessor {
+    public:
+        // --- self-description ---
+        [[nodiscard]] std::string_view get_name() const noexcept override {
+            return "PrecompProcessor";
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
+            static constexpr std::array<std::string_view, 5> kMimes = {
+                "application/pdf",
+                "application/zip",
+                "application/java-archive",
+                "image/png",
+                "application/x-shockwave-flash"
+            };
+            return {kMimes.data(), kMimes.size()};
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
+            static constexpr std::array<std::string_view, 5> kExts = { ".pdf", ".zip", ".jar", ".png", ".swf" };
+            return {kExts.data(), kExts.size()};
+        }
+
+        // --- capabilities ---
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
+        [[nodiscard]] bool can_extract_contents() const noexcept override { return false; }
+
+        /// @return ".pcz": the output replaces the original under a new extension.
+        [[nodiscard]] std::string_view get_output_extension() const noexcept override { return ".pcz"; }
+
+        // --- operations ---
+
+        /**
+         * @brief Archives a file with its reconstructible deflate streams expanded.
+         *
+         * Streams zlib cannot reproduce are kept compressed. The archive is
+         * rebuilt in memory and must reproduce the input byte for byte,
+         * otherwise nothing is written and an exception is thrown.
+         *
+         * @param input Path to the source file.
+         * @param output Path to write the `.pcz` file.
+         * @param preserve_metadata Ignored: the whole file is always kept.
+         * @throws std::runtime_error if the input cannot be read or the round-trip check fails.
+         */
+        void recompress(const std::filesystem::path& input,
+                        const std::filesystem::path& output,
+                        bool preserve_metadata) override;
+
+        /**
+         * @brief Precomp archives are not containers.
+         * @return std::nullopt
+         */
+        std::optional<ExtractedContent> prepare_extraction(
+            [[maybe_unused]] const std::filesystem::path& input_path) override { return std::nullopt; }
+
+        /**
+         * @brief Precomp archives are not containers.
+         * @return Empty path.
+         */
+        std::filesystem::path finalize_extraction(const ExtractedContent &) override { return {}; }
+
+        /**
+         * @brief Restores the original file from a precomp archive.
+         *
+         * Each stored stream is compressed again with its zlib parameters;
+         * the result is checked against the size and CRC-32 of the original.
+         *
+         * @param input Path to the `.pcz` file.
+         * @param output Path to write the restored file.
+         * @throws std::runtime_error if the archive is damaged or does not restore exactly.
+         */
+        static void restore(const std::filesystem::path& input, const std::filesystem::path& output);
+
+        /**
+         * @brief Name of the file a precomp archive restores to.
+         *
+         * Archived files keep their original name before `.pcz` (report.pdf.pcz),
+         * which is given back as is.
+         *
+         * @param archive_file Path to the `.pcz` file.
+         * @return The file name of the restored file.
+         */
+        [[nodiscard]] static std::filesystem::path restored_filename(const std::filesystem::path& archive_file);
+
+        // --- integrity check ---
+
+        /**
+         * @brief Checks that a precomp archive restores to the original file.
+         * @param a Path to the original file.
+         * @param b Path to the `.pcz` file.
+         * @return true if restoring `b` yields exactly the bytes of `a`.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
+
+        /**
+         * @brief (Not Implemented) Compute a raw checksum.
+         * @param file_path Path to the file.
+         * @return An empty string; raw_equal() compares restored bytes instead.
+         */
+        [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_PRECOMP_PROCESSOR_HPP
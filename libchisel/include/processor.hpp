@@ -89,6 +89,30 @@ class IProcessor {
     /// @return True if this processor can extract container contents.
     [[nodiscard]] virtual bool can_extract_contents() const noexcept = 0;
 
+    /**
+     * @brief Extension of the files written by recompress(), for format-converting processors.
+     *
+     * A non-empty value (e.g. ".lep") means the output is a different format:
+     * the optimized file replaces the original under its full name plus the
+     * new extension (photo.JPG becomes photo.JPG.lep), and the processor is
+     * never applied to files nested inside containers. A top-level container
+     * it supports is handed to it as is, without being extracted.
+     *
+     * @return The new extension including the dot, or empty if the format is kept.
+     */
+    [[nodiscard]] virtual std::string_view get_output_extension() const noexcept { return {}; }
+
+    /**
+     * @brief Suffixes of the companion files that travel with a main file (e.g. "c" for .wv/.wvc).
+     *
+     * A companion lives next to its main file, under the same name plus the suffix.
+     * recompress() may write one at output + suffix: the executor counts it in the
+     * size of the result and moves or deletes it together with the main file.
+     *
+     * @return The suffixes, or an empty span if the format has no companion files.
+     */
+    [[nodiscard]] virtual std::span<const std::string_view> get_companion_suffixes() const noexcept { return {}; }
+
     // --- operations ---
 
     /**
@@ -18,7 +18,10 @@
 #include "processor_registry.hpp"
 #include <filesystem>
 #include <vector>
+#include <set>
 #include <stack>
+#include <span>
+#include <string_view>
 #include <thread>
 #include <mutex>
 #include "event_bus.hpp"
@@ -109,6 +112,8 @@ class ProcessorExecutor {
      * If it's a file, it's added to work_list_.
      * If it's a container, its contents are extracted, added to
      * work_list_, and the container is added to finalize_stack_.
+     * A top-level container with a format-converting processor is
+     * added to work_list_ as is.
      *
      * @param path The file or directory path to analyze.
      */
@@ -138,11 +143,21 @@ class ProcessorExecutor {
      * @param temp_file The path to the newly created optimized file.
      * @param original_size The size of the original file in bytes.
      * @param duration The time taken for the recompression task.
+     * @param new_extension If not empty, the optimized file is stored under
+     * this extension and, in-place, the original file is removed.
+     * @param companions Suffixes of the companion files written next to
+     * temp_file, moved or removed together with it. They are staged next to
+     * the destination and moved into place before the main file, keeping
+     * the companions they replace aside until the main file is in place.
+     * If any step fails, the previous companions are restored, the main
+     * file is left untouched and a FileProcessErrorEvent is published.
      */
     void handle_temp_file(const std::filesystem::path& original_file,
                             const std::filesystem::path& temp_file,
                             uintmax_t original_size,
-                            std::chrono::milliseconds duration) const;
+                            std::chrono::milliseconds duration,
+                            std::string_view new_extension = {},
+                            std::span<const std::string_view> companions = {}) const;
 
     ProcessorRegistry& registry_;                 ///< Reference to the processor registry
     bool preserve_metadata_;                      ///< Whether to preserve metadata
@@ -152,6 +167,7 @@ class ProcessorExecutor {
     bool has_output_dir_;                         ///< Convenience flag for !output_dir_.empty()
     bool output_is_directory_ = true;             ///< True if the output path refers to a directory
     std::vector<std::filesystem::path> work_list_;///< (Phase 1->2) Files to be recompressed
+    std::set<std::filesystem::path> nested_files_;///< (Phase 1->2) Files extracted from containers
     std::stack<ExtractedContent> finalize_stack_; ///< (Phase 1->3) Containers to be re-assembled
     ThreadPool pool_;                             ///< Thread pool for Phase 2
     std::atomic<bool> stop_flag_{false};       ///< Flag to signal interruption
@@ -38,6 +38,17 @@ class ProcessorRegistry {
      */
     ProcessorRegistry();
 
+    /**
+     * @brief Register an additional, opt-in processor.
+     *
+     * Used for processors that are not enabled by default, such as
+     * LeptonProcessor. The processor is appended after the built-in ones,
+     * so it never becomes the primary processor for analysis.
+     *
+     * @param processor The processor instance; the registry takes ownership.
+     */
+    void register_processor(std::unique_ptr<IProcessor> processor);
+
     /**
      * @brief Find all processors that support a given MIME type.
      * @param mime MIME type string (e.g. "image/png").
@@ -0,0 +1,245 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file rust_bridge.hpp
+ * @brief C declarations for the functions exported by the Rust bridge crate.
+ *
+ * The layouts and constants below must stay in sync with
+ * libchisel/rust_bridge/src/lib.rs.
+ */
+
+#ifndef CHISEL_RUST_BRIDGE_HPP
+#define CHISEL_RUST_BRIDGE_HPP
+
+#include <cstddef>
+#include <cstdint>
+
+extern "C" {
+
+// --- return codes shared by all entry points (named after the first, Vorbis, ones) ---
+constexpr int CHISEL_VORBIS_OK = 0;
+constexpr int CHISEL_VORBIS_ERR_NULL_ARGUMENT = -1;
+constexpr int CHISEL_VORBIS_ERR_INVALID_PATH = -2;
+constexpr int CHISEL_VORBIS_ERR_OPEN_INPUT = -3;
+constexpr int CHISEL_VORBIS_ERR_CREATE_OUTPUT = -4;
+constexpr int CHISEL_VORBIS_ERR_STREAM = -5; ///< corrupt or unsupported stream
+constexpr int CHISEL_VORBIS_ERR_IO = -6;     ///< I/O failure while reading or writing
+constexpr int CHISEL_VORBIS_ERR_CANCELLED = -7; ///< stopped by the progress callback
+constexpr int CHISEL_VORBIS_ERR_VERIFY = -8;    ///< output failed its round-trip check
+
+// --- ChiselVorbisSettings::comment_fields_action ---
+constexpr int CHISEL_VORBIS_COMMENTS_COPY = 0;
+constexpr int CHISEL_VORBIS_COMMENTS_DELETE = 1;
+
+// --- ChiselVorbisSettings::vendor_string_action ---
+constexpr int CHISEL_VORBIS_VENDOR_COPY = 0;
+constexpr int CHISEL_VORBIS_VENDOR_REPLACE = 1;
+constexpr int CHISEL_VORBIS_VENDOR_APPEND_TAG = 2;
+constexpr int CHISEL_VORBIS_VENDOR_APPEND_SHORT_TAG = 3;
+constexpr int CHISEL_VORBIS_VENDOR_EMPTY = 4;
+
+/**
+ * @brief OptiVorbis remuxer options.
+ *
+ * Start from chisel_vorbis_default_settings() and override what is needed.
+ */
+struct ChiselVorbisSettings {
+    int comment_fields_action;       ///< CHISEL_VORBIS_COMMENTS_*
+    int vendor_string_action;        ///< CHISEL_VORBIS_VENDOR_*
+    bool ignore_start_sample_offset; ///< Accept streams not starting at granule 0
+    bool error_on_no_vorbis_streams; ///< Fail if the file has no Vorbis stream
+};
+
+/// @return The OptiVorbis default settings.
+ChiselVorbisSettings chisel_vorbis_default_settings();
+
+/**
+ * @brief Optimizes an Ogg Vorbis file with OptiVorbis.
+ * @param input Input file path (UTF-8).
+ * @param output Output file path (UTF-8).
+ * @param settings Remuxer options, or nullptr for the defaults.
+ * @param error_message If not null, receives a description of the failure
+ * that must be released with chisel_free_string().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_optimize_vorbis_ex(const char* input, const char* output,
+                              const ChiselVorbisSettings* settings,
+                              char** error_message);
+
+/**
+ * @brief Progress and cancellation hook.
+ *
+ * Called with the caller context, the input bytes consumed so far and the
+ * input size. OptiVorbis reads the input twice, so the processed count can
+ * exceed the total. Returning non-zero cancels the operation.
+ */
+using ChiselProgressCallback = int (*)(void* context, uint64_t bytes_processed, uint64_t total_bytes);
+
+/**
+ * @brief Same as chisel_optimize_vorbis_ex(), reporting progress to a callback.
+ *
+ * When the callback cancels, the remux is aborted, the partial output is
+ * removed and CHISEL_VORBIS_ERR_CANCELLED is returned.
+ *
+ * @param callback Progress hook, or nullptr.
+ * @param context Opaque pointer handed back to the callback.
+ */
+int chisel_optimize_vorbis_progress(const char* input, const char* output,
+                                    const ChiselVorbisSettings* settings,
+                                    ChiselProgressCallback callback, void* context,
+                                    char** error_message);
+
+/**
+ * @brief Optimizes an in-memory Ogg Vorbis stream with OptiVorbis.
+ *
+ * Avoids temporary files for streams pulled out of containers or stdin.
+ *
+ * @param input Pointer to the input stream bytes.
+ * @param input_len Size of the input stream in bytes.
+ * @param settings Remuxer options, or nullptr for the defaults.
+ * @param output Receives the optimized stream, to be released with
+ * chisel_free_buffer(). Set to nullptr on failure.
+ * @param output_len Receives the size of the optimized stream.
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_optimize_vorbis_buffer(const unsigned char* input, size_t input_len,
+                                  const ChiselVorbisSettings* settings,
+                                  unsigned char** output, size_t* output_len,
+                                  char** error_message);
+
+/**
+ * @brief Decodes two Ogg Vorbis files to PCM and compares the audio.
+ * @param a First file path (UTF-8).
+ * @param b Second file path (UTF-8).
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return 1 if the decoded audio is identical, 0 if it differs, or a
+ * negative CHISEL_VORBIS_ERR_* code if a file cannot be opened or decoded.
+ */
+int chisel_vorbis_decoded_equal(const char* a, const char* b, char** error_message);
+
+/**
+ * @brief Ogg Opus optimizer options.
+ *
+ * Start from chisel_opus_default_settings() and override what is needed.
+ */
+struct ChiselOpusSettings {
+    int comment_fields_action; ///< CHISEL_VORBIS_COMMENTS_*; the vendor string is always kept
+    bool strip_packet_padding; ///< Drop the padding of code 3 audio packets
+};
+
+/// @return The default Ogg Opus optimizer settings.
+ChiselOpusSettings chisel_opus_default_settings();
+
+/**
+ * @brief Losslessly optimizes a single-stream Ogg Opus file.
+ *
+ * Re-paginates the stream with minimal framing overhead and drops packet
+ * and OpusTags padding. OpusHead (pre-skip included) and the granule
+ * positions are preserved bit-exact, so playback is unchanged.
+ *
+ * @param settings Optimizer options, or nullptr for the defaults.
+ * @param callback Progress hook, or nullptr; see chisel_optimize_vorbis_progress().
+ * @param context Opaque pointer handed back to the callback.
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_optimize_opus(const char* input, const char* output,
+                         const ChiselOpusSettings* settings,
+                         ChiselProgressCallback callback, void* context,
+                         char** error_message);
+
+/**
+ * @brief Compares the audio packets of two Ogg Opus files.
+ * @return 1 if OpusHead, the granule range and all audio packets (padding
+ * aside) match, 0 if they differ, or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_opus_streams_equal(const char* a, const char* b, char** error_message);
+
+/**
+ * @brief Re-packs any Ogg file, including chained and multiplexed ones.
+ *
+ * Every logical stream is re-paginated as a whole with minimal framing
+ * overhead, and the pages of multiplexed streams are interleaved again in
+ * the time order of the input. Vorbis streams are also optimized with
+ * OptiVorbis; other codecs (Theora, Speex, ...) keep their packets
+ * unchanged. Serial numbers and stream order are preserved.
+ *
+ * @param settings OptiVorbis options for the Vorbis streams, or nullptr.
+ * @param callback Progress hook, or nullptr; see chisel_optimize_vorbis_progress().
+ * @param context Opaque pointer handed back to the callback.
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_optimize_ogg(const char* input, const char* output,
+                        const ChiselVorbisSettings* settings,
+                        ChiselProgressCallback callback, void* context,
+                        char** error_message);
+
+/**
+ * @brief Compares two Ogg files logical stream by logical stream.
+ * @return 1 if the streams match (Vorbis by decoded PCM, other codecs by
+ * packets), 0 if they differ, or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_ogg_streams_equal(const char* a, const char* b, char** error_message);
+
+/**
+ * @brief Compresses a JPEG file to Lepton (lepton_jpeg_rust).
+ *
+ * The result is decoded again and must reproduce the input byte for byte,
+ * otherwise CHISEL_VORBIS_ERR_VERIFY is returned and nothing is written.
+ *
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_lepton_compress(const char* input, const char* output, char** error_message);
+
+/**
+ * @brief Restores the original JPEG file from a Lepton file.
+ * @param error_message As in chisel_optimize_vorbis_ex().
+ * @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+ */
+int chisel_lepton_restore(const char* input, const char* output, char** error_message);
+
+/**
+ * @brief Describes a processor implemented in Rust.
+ *
+ * Mirrors `ChiselProcessorVTable` in rust_bridge/src/processor.rs. All
+ * pointers stay valid for the lifetime of the program; `processor` must be
+ * passed back as the first argument of both functions.
+ */
+struct ChiselProcessorVTable {
+    const void* processor;
+    const char* name;
+    const char* const* mime_types;
+    size_t mime_type_count;
+    const char* const* extensions;
+    size_t extension_count;
+    bool can_recompress;
+    /// @return CHISEL_VORBIS_OK or a negative CHISEL_VORBIS_ERR_* code.
+    int (*recompress)(const void* processor, const char* input, const char* output,
+                      bool preserve_metadata, char** error_message);
+    /// @return 1 if equal, 0 if different, or a negative CHISEL_VORBIS_ERR_* code.
+    int (*raw_equal)(const void* processor, const char* a, const char* b, char** error_message);
+};
+
+/// @return The number of processors implemented in Rust.
+size_t chisel_rust_processor_count();
+
+/// @return The vtable of the Rust processor at `index`, or nullptr if out of range.
+const ChiselProcessorVTable* chisel_rust_processor_get(size_t index);
+
+/// Same as chisel_optimize_vorbis_ex() with default settings and no message.
+int chisel_optimize_vorbis(const char* input, const char* output);
+
+/// Releases a string returned by the bridge. Null is ignored.
+void chisel_free_string(char* s);
+
+/// Releases a buffer returned by the bridge. Null is ignored.
+void chisel_free_buffer(unsigned char* data, size_t len);
+
+} // extern "C"
+
+#endif // CHISEL_RUST_BRIDGE_HPP
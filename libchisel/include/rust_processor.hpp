@@ -0,0 +1,106 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file rust_processor.hpp
+ * @brief Defines the IProcessor adapter over processors implemented in Rust.
+ */
+
+#ifndef CHISEL_RUST_PROCESSOR_HPP
+#define CHISEL_RUST_PROCESSOR_HPP
+
+#include "processor.hpp"
+#include "rust_bridge.hpp"
+#include <string>
+#include <string_view>
+#include <span>
+#include <vector>
+
+namespace chisel {
+
+    /**
+     * @brief Implements IProcessor on top of a Rust processor vtable.
+     *
+     * @details The Rust bridge exports every processor implementing its
+     * `Processor` trait as a ChiselProcessorVTable; ProcessorRegistry wraps
+     * each of them in a RustProcessor, so a new Rust optimizer needs no
+     * C++ code. Rust processors never extract contents.
+     */
+    class RustProcessor final : public IProcessor {
+    public:
+        /**
+         * @brief Wraps a vtable returned by chisel_rust_processor_get().
+         * @param vtable The vtable; it outlives the processor.
+         */
+        explicit RustProcessor(const ChiselProcessorVTable& vtable);
+
+        // --- self-description ---
+        [[nodiscard]] std::string_view get_name() const noexcept override { return name_; }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
+            return mime_types_;
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
+            return extensions_;
+        }
+
+        // --- capabilities ---
+        [[nodiscard]] bool can_recompress() const noexcept override { return vtable_.can_recompress; }
+        [[nodiscard]] bool can_extract_contents() const noexcept override { return false; }
+
+        // --- operations ---
+
+        /**
+         * @brief Runs the Rust processor's recompress function.
+         * @param input Path to the source file.
+         * @param output Path to write the optimized file.
+         * @param preserve_metadata Passed through to the Rust processor.
+         * @throws std::runtime_error if the Rust processor reports an error.
+         */
+        void recompress(const std::filesystem::path& input,
+                        const std::filesystem::path& output,
+                        bool preserve_metadata) override;
+
+        /**
+         * @brief Rust processors are not containers.
+         * @return std::nullopt
+         */
+        std::optional<ExtractedContent> prepare_extraction(
+            [[maybe_unused]] const std::filesystem::path& input_path) override { return std::nullopt; }
+
+        /**
+         * @brief Rust processors are not containers.
+         * @return Empty path.
+         */
+        std::filesystem::path finalize_extraction(const ExtractedContent &) override { return {}; }
+
+        // --- integrity check ---
+
+        /**
+         * @brief (Not Implemented) Compute a raw checksum.
+         * @param file_path Path to the file.
+         * @return An empty string; raw_equal() is used instead.
+         */
+        [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+        /**
+         * @brief Runs the Rust processor's raw_equal function.
+         * @param a First file.
+         * @param b Second file.
+         * @return true if the Rust processor reports equal content; false if
+         * it differs or cannot be compared.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
+
+    private:
+        const ChiselProcessorVTable& vtable_;
+        std::string_view name_;
+        std::vector<std::string_view> mime_types_;
+        std::vector<std::string_view> extensions_;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_RUST_PROCESSOR_HPP
@@ -0,0 +1,46 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file stop_scope.hpp
+ * @brief Exposes the stop token of the running task to processors.
+ */
+
+#ifndef CHISEL_STOP_SCOPE_HPP
+#define CHISEL_STOP_SCOPE_HPP
+
+#include <stop_token>
+
+namespace chisel {
+
+    /**
+     * @brief Binds a stop token to the current thread for the lifetime of the scope.
+     *
+     * @details ProcessorExecutor opens one around every task it runs, so that
+     * processors with long-running operations can poll stop_requested()
+     * without the token being threaded through the IProcessor interface.
+     * Scopes nest: the previous token is restored on destruction.
+     */
+    class StopScope {
+    public:
+        explicit StopScope(std::stop_token token) noexcept;
+        ~StopScope();
+
+        StopScope(const StopScope&) = delete;
+        StopScope& operator=(const StopScope&) = delete;
+
+    private:
+        std::stop_token previous_; ///< Token active before this scope
+    };
+
+    /**
+     * @brief Checks whether the task running on this thread should stop.
+     * @return true if the token bound by the innermost StopScope has been
+     * signalled, false if it has not or if no scope is active.
+     */
+    [[nodiscard]] bool stop_requested() noexcept;
+
+} // namespace chisel
+
+#endif // CHISEL_STOP_SCOPE_HPP
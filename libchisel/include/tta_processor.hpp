@@ -0,0 +1,111 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file tta_processor.hpp
+ * @brief Defines the IProcessor implementation for TTA (True Audio) files.
+ */
+
+#ifndef CHISEL_TTA_PROCESSOR_HPP
+#define CHISEL_TTA_PROCESSOR_HPP
+
+#include "processor.hpp"
+#include <array>
+#include <string_view>
+#include <span>
+
+namespace chisel {
+
+    /**
+     * @brief Implements IProcessor for TTA (True Audio) files using libttaR.
+     *
+     * @details Every frame is decoded and encoded again, and the TTA1 header
+     * and seek table are rebuilt. ID3v2, APEv2 and ID3v1 tags are located
+     * through AudioMetadataUtil and written back around the new stream;
+     * cover art in the ID3v2 tag is extracted for optimization.
+     */
+    class TtaProcessor final : public IProcessor {
+    public:
+        // --- self-description ---
+        [[nodiscard]] std::string_view get_name() const noexcept override {
+            return "TtaProcessor";
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_mime_types() const noexcept override {
+            static constexpr std::array<std::string_view, 2> kMimes = { "audio/x-tta", "audio/tta" };
+            return {kMimes.data(), kMimes.size()};
+        }
+
+        [[nodiscard]] std::span<const std::string_view> get_supported_extensions() const noexcept override {
+            static constexpr std::array<std::string_view, 1> kExts = { ".tta" };
+            return {kExts.data(), kExts.size()};
+        }
+
+        // --- capabilities ---
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
+
+        /**
+         * @brief This processor extracts cover art using AudioMetadataUtil.
+         * @return true
+         */
+        [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
+
+        // --- operations ---
+
+        /**
+         * @brief Re-encodes a TTA file frame by frame with libttaR.
+         *
+         * Junk between the tags and the stream is dropped and the ID3v2 tag
+         * loses its padding. Password-protected files and files that cannot
+         * be parsed or fail their CRC checks are copied unchanged.
+         *
+         * @param input Path to the source TTA file.
+         * @param output Path to write the optimized TTA file.
+         * @param preserve_metadata If false, the ID3v2, APEv2 and ID3v1 tags are dropped.
+         * @throws std::runtime_error if the output cannot be written.
+         */
+        void recompress(const std::filesystem::path& input,
+                        const std::filesystem::path& output,
+                        bool preserve_metadata) override;
+
+        /**
+         * @brief Extracts embedded cover art from the ID3v2 tag.
+         * @param input_path Path to the TTA file.
+         * @return ExtractedContent struct with cover art files, or nullopt.
+         */
+        std::optional<ExtractedContent> prepare_extraction(
+            const std::filesystem::path& input_path) override;
+
+        /**
+         * @brief Re-inserts optimized cover art into the TTA file.
+         * @param content Content descriptor from prepare_extraction.
+         * @return Path to the finalized TTA file.
+         */
+        std::filesystem::path finalize_extraction(const ExtractedContent &content) override;
+
+        // --- integrity check ---
+
+        /**
+         * @brief Computes the MD5 of the decoded PCM, in WAV byte order.
+         * @param file_path Path to the file.
+         * @return The MD5 as a lowercase hex string.
+         * @throws std::runtime_error if the file cannot be decoded.
+         */
+        [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
+
+        /**
+         * @brief Compares two TTA files by decoding them to raw PCM, frame by frame.
+         *
+         * Files that cannot be decoded are compared byte by byte.
+         *
+         * @param a First TTA file.
+         * @param b Second TTA file.
+         * @return true if the decoded PCM data and audio parameters are identical.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path &a, const std::filesystem::path &b) const override;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_TTA_PROCESSOR_HPP
@@ -20,8 +20,15 @@ namespace chisel {
     /**
      * @brief Implements IProcessor for WAV files.
      *
-     * @details This processor currently acts as a container for extracting and
+     * @details This processor acts as a container for extracting and
      * re-inserting cover art (via ID3v2 tags embedded in RIFF chunks).
+     *
+     * Recompression rewrites the RIFF structure without touching the
+     * samples: JUNK/PAD/FLLR filler and the `fact` chunk of PCM files are
+     * dropped, LIST/INFO and `id3 ` chunks are compacted, a
+     * WAVE_FORMAT_EXTENSIBLE header that says nothing plain PCM does not
+     * becomes plain PCM, and chunk padding and sizes are fixed. Without
+     * metadata, only `fmt `, `data` and a needed `fact` chunk remain.
      */
     class WavProcessor final : public IProcessor {
     public:
@@ -43,11 +50,21 @@ namespace chisel {
         }
 
         // --- capabilities ---
-        [[nodiscard]] bool can_recompress() const noexcept override { return false; }
+        [[nodiscard]] bool can_recompress() const noexcept override { return true; }
         [[nodiscard]] bool can_extract_contents() const noexcept override { return true; }
 
         // --- operations ---
 
+        /**
+         * @brief Rewrites the chunks of a RIFF/WAVE file in their smallest lossless form.
+         *
+         * RF64 files and files whose chunks cannot be walked are copied unchanged.
+         *
+         * @param input Path to the WAV file.
+         * @param output Path to write the optimized file.
+         * @param preserve_metadata If false, every chunk but `fmt `, `data` and `fact` is dropped.
+         * @throws std::runtime_error if the output cannot be written.
+         */
         void recompress(const std::filesystem::path& input,
                         const std::filesystem::path& output,
                         bool preserve_metadata) override;
@@ -59,6 +76,13 @@ namespace chisel {
 
         // --- integrity check ---
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override { return ""; }
+
+        /**
+         * @brief Compares the sample format and the `data` chunk of two WAV files.
+         *
+         * Equivalent fmt headers (plain vs. extensible PCM) compare equal.
+         */
+        [[nodiscard]] bool raw_equal(const std::filesystem::path& a, const std::filesystem::path& b) const override;
     };
 
 } // namespace chisel
@@ -20,8 +20,10 @@ namespace chisel {
     /**
      * @brief Implements IProcessor for WavPack files (.wv) using libwavpack.
      *
-     * Performs a full decode and re-encode cycle using the highest
-     * compression settings. Also handles copying of APEv2 tags.
+     * Performs a full decode and re-encode cycle, trying every compression
+     * mode and keeping the smallest result. Hybrid files are handled
+     * together with their .wvc correction file. Also handles copying of
+     * APEv2 tags.
      */
     class WavPackProcessor final : public IProcessor {
     public:
@@ -44,15 +46,26 @@ namespace chisel {
         [[nodiscard]] bool can_recompress() const noexcept override { return true; }
         [[nodiscard]] bool can_extract_contents() const noexcept override { return false; }
 
+        /// @return "c": a hybrid .wv is written together with its .wvc correction file.
+        [[nodiscard]] std::span<const std::string_view> get_companion_suffixes() const noexcept override {
+            static constexpr std::array<std::string_view, 1> kSuffixes = { "c" };
+            return {kSuffixes.data(), kSuffixes.size()};
+        }
+
         // --- operations ---
 
         /**
          * @brief Recompresses a WavPack file using libwavpack.
          *
-         * Performs a full decode and re-encode cycle using the
-         * "very high" compression flag (CONFIG_VERY_HIGH_FLAG).
-         * This processor correctly handles both single (.wv) and
-         * dual-file (.wv + .wvc) inputs.
+         * Brute-forces the "high" and "very high" modes with extra modes 1-6
+         * and keeps the smallest output. Channel mask, float exponent, the
+         * RIFF header and trailer and the stored MD5 are carried over.
+         *
+         * A hybrid .wv whose .wvc sits next to it is re-encoded as a pair at
+         * the same lossy bitrate, so the two still decode losslessly; the new
+         * correction file is written to output + "c". Correction files on
+         * their own, lossy files without their .wvc and DSD audio are copied
+         * unchanged.
          *
          * @param input Path to the source WavPack file (.wv).
          * @param output Path to write the optimized WavPack file.
@@ -80,15 +93,24 @@ namespace chisel {
         // --- integrity check ---
 
         /**
-         * @brief (Not Implemented) Compute a raw checksum.
+         * @brief Computes the MD5 of the decoded audio (with the .wvc, if present).
+         *
+         * Samples are hashed in the layout of the original file, the same way
+         * WavPack computes the MD5 it stores.
+         *
          * @param file_path Path to the file.
-         * @return An empty string.
+         * @return The MD5 as a lowercase hex string.
+         * @throws std::runtime_error if the file cannot be decoded.
          */
         [[nodiscard]] std::string get_raw_checksum(const std::filesystem::path& file_path) const override;
 
         /**
          * @brief Compares two WavPack files by decoding them to raw PCM and comparing.
          *
+         * Both files are decoded with their .wvc, if present. When b stores an
+         * MD5, it must also match the decoded audio. Files libwavpack cannot
+         * open are compared byte by byte.
+         *
          * @param a First WavPack file.
          * @param b Second WavPack file.
          * @return true if the decoded PCM data and audio parameters are identical.
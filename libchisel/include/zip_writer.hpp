@@ -0,0 +1,128 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file zip_writer.hpp
+ * @brief Defines a ZIP writer that deflates every entry with Zopfli.
+ */
+
+#ifndef CHISEL_ZIP_WRITER_HPP
+#define CHISEL_ZIP_WRITER_HPP
+
+#include <cstdint>
+#include <filesystem>
+#include <fstream>
+#include <span>
+#include <string>
+#include <vector>
+
+namespace chisel {
+
+    /**
+     * @brief Compression settings for ZipWriter.
+     */
+    struct ZipWriterOptions {
+        int iterations = 15;         ///< Zopfli iterations per deflate block.
+        bool block_splitting = true; ///< Let Zopfli split entries into several deflate blocks.
+    };
+
+    /**
+     * @brief Writes ZIP archives whose entries are compressed with Zopfli.
+     *
+     * @details Shared by every processor that rebuilds a ZIP-based container
+     * (ArchiveProcessor, OOXMLProcessor, OdfProcessor). Each entry is
+     * deflated with Zopfli and kept as STORE when that is not smaller.
+     * Entries get a fixed 1980-01-01 timestamp so the output is
+     * deterministic, and ZIP64 records are written only when sizes, offsets
+     * or the entry count overflow the classic fields.
+     *
+     * Entries are written in the order they are added; callers put entries
+     * such as `mimetype` first when the format requires it.
+     */
+    class ZipWriter {
+    public:
+        /**
+         * @brief Creates the output file.
+         * @throws std::runtime_error if the file cannot be created.
+         */
+        explicit ZipWriter(const std::filesystem::path& path, ZipWriterOptions options = {});
+        ~ZipWriter();
+
+        ZipWriter(const ZipWriter&) = delete;
+        ZipWriter& operator=(const ZipWriter&) = delete;
+
+        /**
+         * @brief Adds a regular file entry.
+         * @param name Entry name, using '/' as separator.
+         * @param data The uncompressed entry content.
+         * @param allow_deflate False to force STORE (e.g. the ODF/EPUB `mimetype`).
+         * @param original_deflate An existing raw deflate stream of `data`, copied
+         * instead of the Zopfli output when it is not larger.
+         * @throws std::runtime_error on write failure.
+         */
+        void add_file(const std::string& name, const std::vector<unsigned char>& data, bool allow_deflate = true,
+                      std::span<const unsigned char> original_deflate = {});
+
+        /**
+         * @brief Reads a file from disk and adds it as a regular file entry.
+         * @throws std::runtime_error if the file cannot be read or written.
+         */
+        void add_file_from(const std::string& name, const std::filesystem::path& source, bool allow_deflate = true);
+
+        /**
+         * @brief Adds a regular file entry from an existing raw deflate stream, copied as-is.
+         *
+         * Used to carry over entries whose exact compressed bytes must be kept.
+         * @param deflated The raw deflate stream.
+         * @param crc CRC-32 of the uncompressed content.
+         * @param size Size of the uncompressed content.
+         */
+        void add_deflated(const std::string& name, std::span<const unsigned char> deflated,
+                          std::uint32_t crc, std::uint64_t size);
+
+        /**
+         * @brief Adds a symbolic link entry storing `target` as its content.
+         */
+        void add_symlink(const std::string& name, const std::string& target);
+
+        /**
+         * @brief Writes the central directory and closes the file.
+         * @throws std::runtime_error on write failure.
+         */
+        void finish();
+
+        /**
+         * @brief Computes the CRC-32 stored in ZIP headers.
+         */
+        static std::uint32_t crc_of(std::span<const unsigned char> data);
+
+    private:
+        struct Entry {
+            std::string name;
+            std::uint16_t flags = 0;
+            std::uint16_t method = 0;
+            std::uint32_t crc = 0;
+            std::uint64_t compressed_size = 0;
+            std::uint64_t size = 0;
+            std::uint64_t offset = 0;
+            std::uint32_t external_attributes = 0;
+        };
+
+        void write_entry(const std::string& name, const std::vector<unsigned char>& data,
+                         bool allow_deflate, std::uint32_t mode,
+                         std::span<const unsigned char> original_deflate = {});
+        void write_record(Entry entry, std::span<const unsigned char> payload);
+        void write_bytes(std::span<const unsigned char> bytes);
+
+        std::ofstream out_;
+        std::filesystem::path path_;
+        ZipWriterOptions options_;
+        std::vector<Entry> entries_;
+        std::uint64_t offset_ = 0;
+        bool finished_ = false;
+    };
+
+} // namespace chisel
+
+#endif // CHISEL_ZIP_WRITER_HPP
@@ -0,0 +1,65 @@
+//! Lepton archival compression of JPEG files.
+//!
+//! Lepton output is not a JPEG, so every encode is decoded back and
+//! compared with the original bytes before it is handed out.
+
+use std::fmt;
+use std::io::Cursor;
+
+use lepton_jpeg::{decode_lepton, encode_lepton, EnabledFeatures, LeptonError};
+
+/// Worker threads per call; chisel already runs one file per pool thread.
+const LEPTON_THREADS: usize = 1;
+
+#[derive(Debug)]
+pub enum LeptonFailure {
+    /// The encoder or decoder rejected the data.
+    Codec(LeptonError),
+    /// The decoded JPEG differs from the original.
+    RoundTrip { original: usize, restored: usize },
+}
+
+impl fmt::Display for LeptonFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeptonFailure::Codec(e) => write!(f, "{e}"),
+            LeptonFailure::RoundTrip { original, restored } => write!(
+                f,
+                "round trip mismatch: restored {restored} bytes differ from the {original} original bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LeptonFailure {}
+
+impl From<LeptonError> for LeptonFailure {
+    fn from(e: LeptonError) -> Self {
+        LeptonFailure::Codec(e)
+    }
+}
+
+/// Decodes a Lepton file back to the original JPEG bytes.
+pub fn restore(lepton: &[u8]) -> Result<Vec<u8>, LeptonFailure> {
+    let mut jpeg = Vec::with_capacity(lepton.len() * 5 / 4);
+    decode_lepton(&mut Cursor::new(lepton), &mut jpeg, LEPTON_THREADS)?;
+    Ok(jpeg)
+}
+
+/// Encodes a JPEG to Lepton, failing unless decoding reproduces it exactly.
+pub fn compress(jpeg: &[u8]) -> Result<Vec<u8>, LeptonFailure> {
+    let mut lepton = Cursor::new(Vec::with_capacity(jpeg.len()));
+    encode_lepton(
+        &mut Cursor::new(jpeg),
+        &mut lepton,
+        LEPTON_THREADS,
+        &EnabledFeatures::compat_lepton_vector_write(),
+    )?;
+    let lepton = lepton.into_inner();
+
+    let restored = restore(&lepton)?;
+    if restored != jpeg {
+        return Err(LeptonFailure::RoundTrip { original: jpeg.len(), restored: restored.len() });
+    }
+    Ok(lepton)
+}
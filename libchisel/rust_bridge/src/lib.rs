@@ -1,42 +1,747 @@
-use std::ffi::CStr;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt::Debug;
 use std::fs::File;
-use std::os::raw::{c_char, c_int};
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
 
-use optivorbis::{OggToOgg, Remuxer};
+use optivorbis::remux::ogg_to_ogg::Settings as OggToOggSettings;
+use optivorbis::{
+    OggToOgg, Remuxer, VorbisCommentFieldsAction, VorbisOptimizerSettings,
+    VorbisVendorStringAction,
+};
 
+mod lepton;
+mod ogg;
+mod ogg_remux;
+mod opus;
+mod png_opt;
+mod processor;
+mod progress;
+mod vorbis_verify;
+mod woff;
+mod woff_glyf;
+
+pub use progress::ChiselProgressCallback;
+use progress::{Progress, ProgressReader, ProgressWriter, CANCELLED_MESSAGE};
+
+/// The call succeeded.
+pub const CHISEL_VORBIS_OK: c_int = 0;
+/// A required pointer argument was null.
+pub const CHISEL_VORBIS_ERR_NULL_ARGUMENT: c_int = -1;
+/// A path argument was not valid UTF-8.
+pub const CHISEL_VORBIS_ERR_INVALID_PATH: c_int = -2;
+/// The input file could not be opened.
+pub const CHISEL_VORBIS_ERR_OPEN_INPUT: c_int = -3;
+/// The output file could not be created.
+pub const CHISEL_VORBIS_ERR_CREATE_OUTPUT: c_int = -4;
+/// The stream was rejected as corrupt, unsupported or of the wrong codec.
+pub const CHISEL_VORBIS_ERR_STREAM: c_int = -5;
+/// An I/O error happened while reading or writing a stream.
+pub const CHISEL_VORBIS_ERR_IO: c_int = -6;
+/// The progress callback asked to stop; no output was left behind.
+pub const CHISEL_VORBIS_ERR_CANCELLED: c_int = -7;
+/// The output did not survive its round-trip verification; nothing was written.
+pub const CHISEL_VORBIS_ERR_VERIFY: c_int = -8;
+
+/// Keep the Vorbis comment fields as they are.
+pub const CHISEL_VORBIS_COMMENTS_COPY: c_int = 0;
+/// Drop every Vorbis comment field.
+pub const CHISEL_VORBIS_COMMENTS_DELETE: c_int = 1;
+
+/// Keep the original vendor string.
+pub const CHISEL_VORBIS_VENDOR_COPY: c_int = 0;
+/// Replace the vendor string with the OptiVorbis one.
+pub const CHISEL_VORBIS_VENDOR_REPLACE: c_int = 1;
+/// Append the OptiVorbis tag to the original vendor string.
+pub const CHISEL_VORBIS_VENDOR_APPEND_TAG: c_int = 2;
+/// Append the short OptiVorbis tag to the original vendor string.
+pub const CHISEL_VORBIS_VENDOR_APPEND_SHORT_TAG: c_int = 3;
+/// Write an empty vendor string.
+pub const CHISEL_VORBIS_VENDOR_EMPTY: c_int = 4;
+
+/// OptiVorbis remuxer options exposed over the C ABI.
+///
+/// Obtain a populated instance with `chisel_vorbis_default_settings` and
+/// override only the fields you care about.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChiselVorbisSettings {
+    /// One of the `CHISEL_VORBIS_COMMENTS_*` constants.
+    pub comment_fields_action: c_int,
+    /// One of the `CHISEL_VORBIS_VENDOR_*` constants.
+    pub vendor_string_action: c_int,
+    /// Do not fail on streams whose first granule position is not zero.
+    pub ignore_start_sample_offset: bool,
+    /// Fail when the Ogg file contains no Vorbis stream at all.
+    pub error_on_no_vorbis_streams: bool,
+}
+
+impl Default for ChiselVorbisSettings {
+    fn default() -> Self {
+        let remuxer = OggToOggSettings::default();
+        Self {
+            comment_fields_action: CHISEL_VORBIS_COMMENTS_COPY,
+            vendor_string_action: CHISEL_VORBIS_VENDOR_COPY,
+            ignore_start_sample_offset: remuxer.ignore_start_sample_offset,
+            error_on_no_vorbis_streams: remuxer.error_on_no_vorbis_streams,
+        }
+    }
+}
+
+impl ChiselVorbisSettings {
+    fn to_remuxer(self) -> OggToOgg {
+        let remuxer_settings = OggToOggSettings {
+            ignore_start_sample_offset: self.ignore_start_sample_offset,
+            error_on_no_vorbis_streams: self.error_on_no_vorbis_streams,
+            ..Default::default()
+        };
+
+        let optimizer_settings = VorbisOptimizerSettings {
+            comment_fields_action: match self.comment_fields_action {
+                CHISEL_VORBIS_COMMENTS_DELETE => VorbisCommentFieldsAction::Delete,
+                _ => VorbisCommentFieldsAction::Copy,
+            },
+            vendor_string_action: match self.vendor_string_action {
+                CHISEL_VORBIS_VENDOR_REPLACE => VorbisVendorStringAction::Replace,
+                CHISEL_VORBIS_VENDOR_APPEND_TAG => VorbisVendorStringAction::AppendTag,
+                CHISEL_VORBIS_VENDOR_APPEND_SHORT_TAG => VorbisVendorStringAction::AppendShortTag,
+                CHISEL_VORBIS_VENDOR_EMPTY => VorbisVendorStringAction::Empty,
+                _ => VorbisVendorStringAction::Copy,
+            },
+        };
+
+        OggToOgg::new(remuxer_settings, optimizer_settings)
+    }
+}
+
+/// Ogg Opus optimizer options exposed over the C ABI.
+///
+/// Obtain a populated instance with `chisel_opus_default_settings`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChiselOpusSettings {
+    /// One of the `CHISEL_VORBIS_COMMENTS_*` constants; the vendor string is always kept.
+    pub comment_fields_action: c_int,
+    /// Remove the padding bytes of code 3 audio packets.
+    pub strip_packet_padding: bool,
+}
+
+impl Default for ChiselOpusSettings {
+    fn default() -> Self {
+        Self {
+            comment_fields_action: CHISEL_VORBIS_COMMENTS_COPY,
+            strip_packet_padding: true,
+        }
+    }
+}
+
+impl ChiselOpusSettings {
+    fn to_options(self) -> opus::OpusOptions {
+        opus::OpusOptions {
+            delete_comments: self.comment_fields_action == CHISEL_VORBIS_COMMENTS_DELETE,
+            strip_packet_padding: self.strip_packet_padding,
+        }
+    }
+}
+
+/// A failure carrying both the C return code and a readable description.
+struct BridgeError {
+    code: c_int,
+    message: String,
+}
+
+impl BridgeError {
+    fn new(code: c_int, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// Classifies an OptiVorbis error, naming its kind in the message.
+    fn from_remux<E: Error + Debug + 'static>(err: &E) -> Self {
+        let code = if caused_by_io(err) { CHISEL_VORBIS_ERR_IO } else { CHISEL_VORBIS_ERR_STREAM };
+        Self::new(code, format!("{}: {}", error_kind(err), err))
+    }
+}
+
+/// Returns the enum variant name of an error from its `Debug` output.
+fn error_kind<E: Debug>(err: &E) -> String {
+    let debug = format!("{err:?}");
+    debug
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Walks the source chain looking for an `io::Error`.
+fn caused_by_io(err: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(err);
+    while let Some(e) = current {
+        if e.is::<io::Error>() {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+fn path_arg<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, BridgeError> {
+    if ptr.is_null() {
+        return Err(BridgeError::new(CHISEL_VORBIS_ERR_NULL_ARGUMENT, format!("{what} path is null")));
+    }
+    let c_str = unsafe { CStr::from_ptr(ptr) };
+    c_str
+        .to_str()
+        .map_err(|_| BridgeError::new(CHISEL_VORBIS_ERR_INVALID_PATH, format!("{what} path is not valid UTF-8")))
+}
+
+fn settings_arg(settings: *const ChiselVorbisSettings) -> ChiselVorbisSettings {
+    if settings.is_null() {
+        ChiselVorbisSettings::default()
+    } else {
+        unsafe { *settings }
+    }
+}
+
+/// Stores `message` into `*error_message` as an owned C string, if requested.
+fn report_error(error_message: *mut *mut c_char, message: &str) {
+    if error_message.is_null() {
+        return;
+    }
+    let sanitized = message.replace('\0', " ");
+    let owned = CString::new(sanitized).unwrap_or_default();
+    unsafe { *error_message = owned.into_raw() };
+}
+
+fn finish(result: Result<(), BridgeError>, error_message: *mut *mut c_char) -> c_int {
+    match result {
+        Ok(()) => CHISEL_VORBIS_OK,
+        Err(err) => {
+            report_error(error_message, &err.message);
+            err.code
+        }
+    }
+}
+
+fn optimize_file(
+    input: &str,
+    output: &str,
+    settings: ChiselVorbisSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+) -> Result<(), BridgeError> {
+    let input_file = File::open(input)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open input: {e}")))?;
+    let total = input_file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    let output_file = File::create(output)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_CREATE_OUTPUT, format!("cannot create output: {e}")))?;
+
+    let progress = Progress::new(callback, context, total);
+    let result = remux(
+        ProgressReader::new(input_file, &progress),
+        ProgressWriter::new(output_file, &progress),
+        settings,
+    );
+
+    if progress.cancelled() {
+        // the sink has been dropped by now, so the partial file can go
+        let _ = std::fs::remove_file(output);
+        return Err(BridgeError::new(CHISEL_VORBIS_ERR_CANCELLED, CANCELLED_MESSAGE));
+    }
+    progress.finish();
+    result
+}
+
+fn remux<R: Read + Seek, W: Write>(source: R, sink: W, settings: ChiselVorbisSettings) -> Result<(), BridgeError> {
+    settings
+        .to_remuxer()
+        .remux(source, sink)
+        .map(|_| ())
+        .map_err(|e| BridgeError::from_remux(&e))
+}
+
+/// Reads a whole input file, reporting progress and honouring cancellation.
+fn read_input(
+    input: &str,
+    progress: &mut Option<Progress>,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+) -> Result<Vec<u8>, BridgeError> {
+    let file = File::open(input)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open input: {e}")))?;
+    let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let progress = progress.insert(Progress::new(callback, context, total));
+
+    let mut data = Vec::with_capacity(total as usize);
+    let read = ProgressReader::new(file, progress).read_to_end(&mut data);
+    if progress.cancelled() {
+        return Err(BridgeError::new(CHISEL_VORBIS_ERR_CANCELLED, CANCELLED_MESSAGE));
+    }
+    read.map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_IO, format!("cannot read input: {e}")))?;
+    Ok(data)
+}
+
+fn optimize_opus_file(
+    input: &str,
+    output: &str,
+    settings: ChiselOpusSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+) -> Result<(), BridgeError> {
+    let mut progress = None;
+    let data = read_input(input, &mut progress, callback, context)?;
+
+    let optimized = opus::optimize(&data, settings.to_options()).map_err(|e| {
+        BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e))
+    })?;
+
+    std::fs::write(output, optimized)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_CREATE_OUTPUT, format!("cannot write output: {e}")))?;
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    Ok(())
+}
+
+fn optimize_ogg_file(
+    input: &str,
+    output: &str,
+    settings: ChiselVorbisSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+) -> Result<(), BridgeError> {
+    let mut progress = None;
+    let data = read_input(input, &mut progress, callback, context)?;
+
+    let optimized = ogg_remux::repack(&data, |stream| {
+        let mut optimized = Vec::with_capacity(stream.len());
+        remux(Cursor::new(stream), &mut optimized, settings).ok().map(|_| optimized)
+    })
+    .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e)))?;
+
+    std::fs::write(output, optimized)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_CREATE_OUTPUT, format!("cannot write output: {e}")))?;
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    Ok(())
+}
+
+/// Runs `transform` over a whole input file and writes the result.
+fn transform_file<E: Error + Debug>(
+    input: &str,
+    output: &str,
+    transform: impl FnOnce(&[u8]) -> Result<Vec<u8>, E>,
+    error_code: impl FnOnce(&E) -> c_int,
+) -> Result<(), BridgeError> {
+    let data = std::fs::read(input)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open input: {e}")))?;
+    let transformed =
+        transform(&data).map_err(|e| BridgeError::new(error_code(&e), format!("{}: {}", error_kind(&e), e)))?;
+    std::fs::write(output, transformed)
+        .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_CREATE_OUTPUT, format!("cannot write output: {e}")))
+}
+
+/// Shared body of `chisel_optimize_vorbis_ex` and `chisel_optimize_vorbis_progress`.
+fn optimize_vorbis(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    settings: *const ChiselVorbisSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = path_arg(input_path, "input").and_then(|input| {
+        let output = path_arg(output_path, "output")?;
+        optimize_file(input, output, settings_arg(settings), callback, context)
+    });
+    finish(result, error_message)
+}
+
+/// Hands a byte vector over to C; release it with `chisel_free_buffer`.
+fn into_raw_buffer(data: Vec<u8>) -> (*mut u8, usize) {
+    let boxed = data.into_boxed_slice();
+    let len = boxed.len();
+    (Box::into_raw(boxed).cast::<u8>(), len)
+}
+
+/// Returns the OptiVorbis default settings.
 #[no_mangle]
-pub extern "C" fn chisel_optimize_vorbis(
+pub extern "C" fn chisel_vorbis_default_settings() -> ChiselVorbisSettings {
+    ChiselVorbisSettings::default()
+}
+
+/// Optimizes an Ogg Vorbis file with OptiVorbis.
+///
+/// `settings` may be null to use the defaults. When `error_message` is not
+/// null and the call fails, it receives a string describing the failure,
+/// which the caller must release with `chisel_free_string`.
+#[no_mangle]
+pub extern "C" fn chisel_optimize_vorbis_ex(
     input_path: *const c_char,
-    output_path: *const c_char
+    output_path: *const c_char,
+    settings: *const ChiselVorbisSettings,
+    error_message: *mut *mut c_char,
 ) -> c_int {
-    if input_path.is_null() || output_path.is_null() { return -1; }
+    optimize_vorbis(input_path, output_path, settings, None, ptr::null_mut(), error_message)
+}
 
-    let input_c = unsafe { CStr::from_ptr(input_path) };
-    let output_c = unsafe { CStr::from_ptr(output_path) };
+/// Optimizes an Ogg Vorbis file with OptiVorbis, reporting progress.
+///
+/// `callback` (may be null) is called with `context` as the input is
+/// consumed; returning non-zero from it aborts the remux, removes the
+/// partial output and yields `CHISEL_VORBIS_ERR_CANCELLED`. The other
+/// arguments behave as in `chisel_optimize_vorbis_ex`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, `settings` and
+/// `error_message` must be null or valid, and `callback` must be safe to
+/// call with `context` until the function returns.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_optimize_vorbis_progress(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    settings: *const ChiselVorbisSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    optimize_vorbis(input_path, output_path, settings, callback, context, error_message)
+}
 
-    let input_str = match input_c.to_str() {
-        Ok(s) => s,
-        Err(_) => return -2,
+/// Optimizes an in-memory Ogg Vorbis stream with OptiVorbis.
+///
+/// On success `*output` and `*output_len` describe a newly allocated buffer
+/// holding the optimized stream, which the caller must release with
+/// `chisel_free_buffer`. On failure they are set to null and zero.
+/// `settings` and `error_message` behave as in `chisel_optimize_vorbis_ex`.
+///
+/// # Safety
+/// `input` must point to `input_len` readable bytes (it may be null when
+/// `input_len` is zero), `output` and `output_len` must be valid for
+/// writes, and `settings` and `error_message` must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_optimize_vorbis_buffer(
+    input: *const u8,
+    input_len: usize,
+    settings: *const ChiselVorbisSettings,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+    if output.is_null() || output_len.is_null() || (input.is_null() && input_len > 0) {
+        return finish(
+            Err(BridgeError::new(CHISEL_VORBIS_ERR_NULL_ARGUMENT, "buffer argument is null")),
+            error_message,
+        );
+    }
+    unsafe {
+        *output = ptr::null_mut();
+        *output_len = 0;
+    }
+
+    let data: &[u8] = if input_len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(input, input_len) }
     };
-    let output_str = match output_c.to_str() {
-        Ok(s) => s,
-        Err(_) => return -2,
+
+    let mut optimized = Vec::with_capacity(input_len);
+    let result = remux(Cursor::new(data), &mut optimized, settings_arg(settings));
+    if result.is_ok() {
+        let (data_ptr, data_len) = into_raw_buffer(optimized);
+        unsafe {
+            *output = data_ptr;
+            *output_len = data_len;
+        }
+    }
+    finish(result, error_message)
+}
+
+/// Decodes two Ogg Vorbis files and compares their audio.
+///
+/// Returns 1 if both decode to identical PCM, 0 if they differ, or a
+/// negative `CHISEL_VORBIS_ERR_*` code if either file cannot be opened or
+/// decoded. `error_message` behaves as in `chisel_optimize_vorbis_ex`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, and
+/// `error_message` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_vorbis_decoded_equal(
+    path_a: *const c_char,
+    path_b: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let open = |path: &str| {
+        File::open(path)
+            .map(io::BufReader::new)
+            .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open {path}: {e}")))
     };
 
-    let mut input_file = match File::open(input_str) {
-        Ok(f) => f,
-        Err(_) => return -3,
+    let result = path_arg(path_a, "first").and_then(|a| {
+        let b = path_arg(path_b, "second")?;
+        vorbis_verify::decoded_streams_equal(open(a)?, open(b)?).map_err(|e| {
+            BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e))
+        })
+    });
+
+    match result {
+        Ok(equal) => c_int::from(equal),
+        Err(err) => {
+            report_error(error_message, &err.message);
+            err.code
+        }
+    }
+}
+
+/// Returns the default Ogg Opus optimizer settings.
+#[no_mangle]
+pub extern "C" fn chisel_opus_default_settings() -> ChiselOpusSettings {
+    ChiselOpusSettings::default()
+}
+
+/// Losslessly optimizes a single-stream Ogg Opus file.
+///
+/// The stream is re-paginated with minimal framing overhead, code 3 packet
+/// padding and OpusTags padding are dropped, and OpusHead and the granule
+/// positions are kept bit-exact. `settings` may be null for the defaults;
+/// `callback`, `context` and `error_message` behave as in
+/// `chisel_optimize_vorbis_progress`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, `settings` and
+/// `error_message` must be null or valid, and `callback` must be safe to
+/// call with `context` until the function returns.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_optimize_opus(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    settings: *const ChiselOpusSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let settings = if settings.is_null() { ChiselOpusSettings::default() } else { unsafe { *settings } };
+    let result = path_arg(input_path, "input").and_then(|input| {
+        let output = path_arg(output_path, "output")?;
+        optimize_opus_file(input, output, settings, callback, context)
+    });
+    finish(result, error_message)
+}
+
+/// Compares the audio packets of two Ogg Opus files.
+///
+/// Returns 1 if OpusHead, the granule range and every audio packet (code 3
+/// padding aside) match, 0 if they differ, or a negative
+/// `CHISEL_VORBIS_ERR_*` code if either file cannot be read or parsed.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, and
+/// `error_message` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_opus_streams_equal(
+    path_a: *const c_char,
+    path_b: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let read = |path: &str| {
+        std::fs::read(path)
+            .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open {path}: {e}")))
     };
 
-    let mut output_file = match File::create(output_str) {
-        Ok(f) => f,
-        Err(_) => return -4,
+    let result = path_arg(path_a, "first").and_then(|a| {
+        let b = path_arg(path_b, "second")?;
+        opus::streams_equal(&read(a)?, &read(b)?)
+            .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e)))
+    });
+
+    match result {
+        Ok(equal) => c_int::from(equal),
+        Err(err) => {
+            report_error(error_message, &err.message);
+            err.code
+        }
+    }
+}
+
+/// Re-packs any Ogg file, including chained and multiplexed ones.
+///
+/// Every logical stream is re-paginated as a whole with minimal framing
+/// overhead, and multiplexed streams are interleaved again in the time
+/// order of the input; Vorbis streams are additionally optimized with
+/// OptiVorbis using `settings` (may be null for the defaults), other codecs
+/// keep their packets unchanged. Serial numbers and stream order are preserved.
+/// `callback`, `context` and `error_message` behave as in
+/// `chisel_optimize_vorbis_progress`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, `settings` and
+/// `error_message` must be null or valid, and `callback` must be safe to
+/// call with `context` until the function returns.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_optimize_ogg(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    settings: *const ChiselVorbisSettings,
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = path_arg(input_path, "input").and_then(|input| {
+        let output = path_arg(output_path, "output")?;
+        optimize_ogg_file(input, output, settings_arg(settings), callback, context)
+    });
+    finish(result, error_message)
+}
+
+/// Compares two Ogg files logical stream by logical stream.
+///
+/// Returns 1 if both hold the same streams in the same order, with Vorbis
+/// streams decoding to identical PCM and every other stream carrying
+/// identical packets; 0 if they differ; or a negative `CHISEL_VORBIS_ERR_*`
+/// code if either file cannot be read or parsed.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, and
+/// `error_message` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_ogg_streams_equal(
+    path_a: *const c_char,
+    path_b: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let read = |path: &str| {
+        std::fs::read(path)
+            .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open {path}: {e}")))
     };
 
-    let remuxer = OggToOgg::new_with_defaults();
+    let result = path_arg(path_a, "first").and_then(|a| {
+        let b = path_arg(path_b, "second")?;
+        ogg_remux::streams_equal(&read(a)?, &read(b)?)
+            .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e)))
+    });
+
+    match result {
+        Ok(equal) => c_int::from(equal),
+        Err(err) => {
+            report_error(error_message, &err.message);
+            err.code
+        }
+    }
+}
+
+/// Compresses a JPEG file to Lepton.
+///
+/// The Lepton data is decoded again and must reproduce the input byte for
+/// byte; otherwise `CHISEL_VORBIS_ERR_VERIFY` is returned and no output is
+/// written. `error_message` behaves as in `chisel_optimize_vorbis_ex`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, and
+/// `error_message` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_lepton_compress(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = path_arg(input_path, "input").and_then(|input| {
+        let output = path_arg(output_path, "output")?;
+        transform_file(input, output, lepton::compress, |e| match e {
+            lepton::LeptonFailure::RoundTrip { .. } => CHISEL_VORBIS_ERR_VERIFY,
+            lepton::LeptonFailure::Codec(_) => CHISEL_VORBIS_ERR_STREAM,
+        })
+    });
+    finish(result, error_message)
+}
 
-    match remuxer.remux(&mut input_file, &mut output_file) {
-        Ok(_) => 0,
-        Err(_) => -5
+/// Restores the original JPEG file from a Lepton file.
+///
+/// `error_message` behaves as in `chisel_optimize_vorbis_ex`.
+///
+/// # Safety
+/// The paths must be null or valid NUL-terminated strings, and
+/// `error_message` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_lepton_restore(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = path_arg(input_path, "input").and_then(|input| {
+        let output = path_arg(output_path, "output")?;
+        transform_file(input, output, lepton::restore, |_| CHISEL_VORBIS_ERR_STREAM)
+    });
+    finish(result, error_message)
+}
+
+/// Optimizes an Ogg Vorbis file with the default OptiVorbis settings.
+#[no_mangle]
+pub extern "C" fn chisel_optimize_vorbis(
+    input_path: *const c_char,
+    output_path: *const c_char
+) -> c_int {
+    chisel_optimize_vorbis_ex(input_path, output_path, ptr::null(), ptr::null_mut())
+}
+
+/// Releases a string allocated by this library. Null is ignored.
+///
+/// # Safety
+/// `s` must be null or a string returned by this library that has not been
+/// released yet.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Releases a buffer returned by this library. Null is ignored.
+///
+/// # Safety
+/// `data` must be null or a buffer returned by this library, with the
+/// length it was returned with, that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn chisel_free_buffer(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
     }
-}
\ No newline at end of file
+}
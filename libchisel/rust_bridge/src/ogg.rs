@@ -0,0 +1,360 @@
+//! Minimal Ogg page layer: parsing, packet reassembly and re-pagination.
+//!
+//! OptiVorbis only handles Vorbis; the other Ogg mappings go through this
+//! module, which rebuilds pages with as little framing overhead as the
+//! granule position rules allow.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+const HEADER_LEN: usize = 27;
+const MAX_SEGMENTS: usize = 255;
+
+/// Page header flag: the first packet continues from the previous page.
+pub const FLAG_CONTINUED: u8 = 0x01;
+/// Page header flag: first page of a logical bitstream.
+pub const FLAG_BOS: u8 = 0x02;
+/// Page header flag: last page of a logical bitstream.
+pub const FLAG_EOS: u8 = 0x04;
+
+/// Granule position of a page on which no packet completes.
+pub const NO_GRANULE: u64 = u64::MAX;
+
+#[derive(Debug)]
+pub enum OggError {
+    /// The data does not start with a valid page.
+    BadCapture { offset: usize },
+    /// A page header is truncated or its body runs past the end of the data.
+    Truncated { offset: usize },
+    /// A page has an unsupported stream structure version.
+    BadVersion { offset: usize },
+    /// A page checksum does not match its contents.
+    BadChecksum { offset: usize },
+    /// Packet continuation flags are inconsistent.
+    BadContinuation { serial: u32 },
+    /// The stream layout cannot be represented after re-pagination.
+    Layout(String),
+}
+
+impl fmt::Display for OggError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OggError::BadCapture { offset } => write!(f, "missing Ogg capture pattern at offset {offset}"),
+            OggError::Truncated { offset } => write!(f, "truncated Ogg page at offset {offset}"),
+            OggError::BadVersion { offset } => write!(f, "unsupported Ogg version at offset {offset}"),
+            OggError::BadChecksum { offset } => write!(f, "Ogg page checksum mismatch at offset {offset}"),
+            OggError::BadContinuation { serial } => write!(f, "broken packet continuation in stream {serial:#010x}"),
+            OggError::Layout(msg) => write!(f, "cannot re-paginate stream: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for OggError {}
+
+/// One physical Ogg page.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub flags: u8,
+    pub granule: u64,
+    pub serial: u32,
+    pub lacing: Vec<u8>,
+    pub body: Vec<u8>,
+}
+
+/// A reassembled packet.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    /// Logical stream index, see `assign_streams`.
+    pub stream: usize,
+    /// Index of the page the packet completed on.
+    pub page_index: usize,
+    pub data: Vec<u8>,
+    /// Granule of the page it completed on, if it was the last packet
+    /// completing there.
+    pub granule: Option<u64>,
+    /// Completed on the last page of its logical stream.
+    pub eos: bool,
+}
+
+fn crc_table() -> &'static [u32; 256] {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut r = (i as u32) << 24;
+            for _ in 0..8 {
+                r = if r & 0x8000_0000 != 0 { (r << 1) ^ 0x04c1_1db7 } else { r << 1 };
+            }
+            *entry = r;
+        }
+        table
+    })
+}
+
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let table = crc_table();
+    let mut crc = 0u32;
+    for chunk in chunks {
+        for &b in *chunk {
+            crc = (crc << 8) ^ table[((crc >> 24) as u8 ^ b) as usize];
+        }
+    }
+    crc
+}
+
+/// Splits a complete Ogg file into pages, validating every checksum.
+pub fn read_pages(data: &[u8]) -> Result<Vec<Page>, OggError> {
+    let mut pages = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let header = data.get(offset..offset + HEADER_LEN).ok_or(OggError::Truncated { offset })?;
+        if &header[0..4] != CAPTURE_PATTERN {
+            return Err(OggError::BadCapture { offset });
+        }
+        if header[4] != 0 {
+            return Err(OggError::BadVersion { offset });
+        }
+
+        let segment_count = header[26] as usize;
+        let lacing_end = offset + HEADER_LEN + segment_count;
+        let lacing = data.get(offset + HEADER_LEN..lacing_end).ok_or(OggError::Truncated { offset })?;
+        let body_len: usize = lacing.iter().map(|&v| v as usize).sum();
+        let body = data.get(lacing_end..lacing_end + body_len).ok_or(OggError::Truncated { offset })?;
+
+        let stored_crc = u32::from_le_bytes(header[22..26].try_into().unwrap());
+        let mut zeroed = [0u8; HEADER_LEN];
+        zeroed.copy_from_slice(header);
+        zeroed[22..26].fill(0);
+        if crc32(&[&zeroed, lacing, body]) != stored_crc {
+            return Err(OggError::BadChecksum { offset });
+        }
+
+        pages.push(Page {
+            flags: header[5],
+            granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            lacing: lacing.to_vec(),
+            body: body.to_vec(),
+        });
+        offset = lacing_end + body_len;
+    }
+
+    Ok(pages)
+}
+
+/// Returns the logical stream index of every page.
+///
+/// Streams are numbered in order of appearance. A BOS page always opens a
+/// new stream, so chained links that reuse a serial number stay apart.
+pub fn assign_streams(pages: &[Page]) -> Vec<usize> {
+    let mut current: HashMap<u32, usize> = HashMap::new();
+    let mut count = 0;
+
+    pages
+        .iter()
+        .map(|page| {
+            if page.flags & FLAG_BOS != 0 || !current.contains_key(&page.serial) {
+                current.insert(page.serial, count);
+                count += 1;
+            }
+            current[&page.serial]
+        })
+        .collect()
+}
+
+/// Reassembles the packets of every logical stream, in completion order.
+pub fn read_packets(pages: &[Page]) -> Result<Vec<Packet>, OggError> {
+    struct Partial {
+        data: Vec<u8>,
+        open: bool,
+    }
+
+    let streams = assign_streams(pages);
+    let mut partials: HashMap<usize, Partial> = HashMap::new();
+    let mut packets = Vec::new();
+
+    for (page_index, (page, &stream)) in pages.iter().zip(&streams).enumerate() {
+        let partial = partials.entry(stream).or_insert(Partial {
+            data: Vec::new(),
+            open: false,
+        });
+
+        let continued = page.flags & FLAG_CONTINUED != 0;
+        if continued != partial.open {
+            return Err(OggError::BadContinuation { serial: page.serial });
+        }
+
+        let last_complete = page.lacing.iter().rposition(|&v| v < 255);
+        let mut pos = 0;
+        for (i, &value) in page.lacing.iter().enumerate() {
+            let len = value as usize;
+            partial.data.extend_from_slice(&page.body[pos..pos + len]);
+            pos += len;
+            partial.open = true;
+
+            if value < 255 {
+                let is_last = Some(i) == last_complete;
+                packets.push(Packet {
+                    stream,
+                    page_index,
+                    data: std::mem::take(&mut partial.data),
+                    granule: is_last.then_some(page.granule),
+                    eos: is_last && page.flags & FLAG_EOS != 0,
+                });
+                partial.open = false;
+            }
+        }
+    }
+
+    if let Some((&stream, _)) = partials.iter().find(|(_, p)| p.open) {
+        let serial = streams.iter().position(|&s| s == stream).map_or(0, |i| pages[i].serial);
+        return Err(OggError::BadContinuation { serial });
+    }
+    Ok(packets)
+}
+
+/// A packet queued for re-pagination.
+#[derive(Debug, Clone)]
+pub struct OutPacket {
+    pub data: Vec<u8>,
+    /// Granule position valid for a page on which this packet is the last
+    /// to complete, or `None` if the packet must never end a page.
+    pub granule: Option<u64>,
+    /// Close the page right after this packet (e.g. after codec headers).
+    pub end_page: bool,
+    /// Position of the packet in the input, used to re-interleave streams.
+    pub position: usize,
+}
+
+/// One lacing value of the flattened stream.
+struct Slot {
+    value: u8,
+    start: usize,
+    /// Completes a packet with this granule (`Some(None)` = unknown granule).
+    completes: Option<Option<u64>>,
+    end_page: bool,
+    position: usize,
+}
+
+/// A rebuilt page with the input position of its last packet.
+pub struct OutPage {
+    pub page: Page,
+    pub position: usize,
+}
+
+/// Paginates one logical stream with as few pages as possible.
+///
+/// Pages are filled up to 255 lacing values; a page may only end where the
+/// last packet completed on it has a known granule position, so the granule
+/// of every page stays exact. `eos` marks the final page.
+pub fn paginate(serial: u32, packets: &[OutPacket], eos: bool) -> Result<Vec<OutPage>, OggError> {
+    let mut body = Vec::new();
+    let mut slots = Vec::new();
+
+    for packet in packets {
+        let start = body.len();
+        body.extend_from_slice(&packet.data);
+        let full = packet.data.len() / 255;
+        for i in 0..=full {
+            let last = i == full;
+            slots.push(Slot {
+                value: if last { (packet.data.len() % 255) as u8 } else { 255 },
+                start: start + i * 255,
+                completes: last.then_some(packet.granule),
+                end_page: last && packet.end_page,
+                position: packet.position,
+            });
+        }
+    }
+
+    let mut pages: Vec<OutPage> = Vec::new();
+    let mut start = 0;
+
+    while start < slots.len() {
+        let limit = (start + MAX_SEGMENTS).min(slots.len());
+        let end = match slots[start..limit].iter().position(|s| s.end_page) {
+            // a forced break (after codec headers) always wins
+            Some(forced) => Some(start + forced + 1).filter(|&end| valid_cut(&slots[start..end])),
+            None => (start + 1..=limit).rev().find(|&end| valid_cut(&slots[start..end])),
+        }
+        .ok_or_else(|| OggError::Layout("no valid page boundary within 255 segments".into()))?;
+
+        let continued = pages.last().is_some_and(|p| p.page.lacing.last() == Some(&255));
+        pages.push(build_page(serial, &slots, &body, start, end, continued));
+        start = end;
+    }
+
+    if let Some(first) = pages.first_mut() {
+        first.page.flags |= FLAG_BOS;
+    }
+    if eos {
+        if let Some(last) = pages.last_mut() {
+            last.page.flags |= FLAG_EOS;
+        }
+    }
+    Ok(pages)
+}
+
+/// A page may end after `window` if its last completed packet (if any) has a known granule.
+fn valid_cut(window: &[Slot]) -> bool {
+    match window.iter().rev().find_map(|s| s.completes) {
+        None => true,
+        Some(granule) => granule.is_some(),
+    }
+}
+
+fn build_page(serial: u32, slots: &[Slot], body: &[u8], start: usize, end: usize, continued: bool) -> OutPage {
+    let window = &slots[start..end];
+    let granule = window.iter().rev().find_map(|s| s.completes).flatten().unwrap_or(NO_GRANULE);
+    let body_start = window[0].start;
+    let body_end = window.last().map_or(body_start, |s| s.start + s.value as usize);
+
+    OutPage {
+        page: Page {
+            flags: if continued { FLAG_CONTINUED } else { 0 },
+            granule,
+            serial,
+            lacing: window.iter().map(|s| s.value).collect(),
+            body: body[body_start..body_end].to_vec(),
+        },
+        position: window[window.len() - 1].position,
+    }
+}
+
+/// Writes pages, numbering them per logical stream.
+pub struct PageWriter<W: Write> {
+    sink: W,
+    sequences: HashMap<u32, u32>,
+}
+
+impl<W: Write> PageWriter<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink, sequences: Default::default() }
+    }
+
+    pub fn write_page(&mut self, page: &Page) -> io::Result<()> {
+        let sequence = self.sequences.entry(page.serial).or_insert(0);
+
+        let mut header = [0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(CAPTURE_PATTERN);
+        header[5] = page.flags;
+        header[6..14].copy_from_slice(&page.granule.to_le_bytes());
+        header[14..18].copy_from_slice(&page.serial.to_le_bytes());
+        header[18..22].copy_from_slice(&sequence.to_le_bytes());
+        header[26] = page.lacing.len() as u8;
+        let crc = crc32(&[&header, &page.lacing, &page.body]);
+        header[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        *sequence = sequence.wrapping_add(1);
+        self.sink.write_all(&header)?;
+        self.sink.write_all(&page.lacing)?;
+        self.sink.write_all(&page.body)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
@@ -0,0 +1,218 @@
+//! Codec-agnostic Ogg re-packing for chained and multiplexed files.
+//!
+//! Every logical stream is demuxed to packets and re-paginated with the
+//! fewest pages its granule positions allow. Vorbis streams are first run
+//! through OptiVorbis; all other codecs (Theora, Speex, Skeleton, ...)
+//! keep their packets byte for byte. Serial numbers and header page
+//! breaks are preserved.
+//!
+//! Each logical stream is paginated as a whole, then the pages of a
+//! multiplexed file are interleaved again by where their last packet
+//! completed in the input. That page carried the same granule position,
+//! so the output keeps the time order the original muxer chose, and the
+//! streams of a chain link stay ahead of the next link.
+
+use std::fmt;
+
+use crate::ogg::{self, OggError, OutPacket, Packet, Page, PageWriter};
+
+const VORBIS_SIGNATURE: &[u8; 7] = b"\x01vorbis";
+
+#[derive(Debug)]
+pub enum RepackError {
+    /// The Ogg framing is broken or cannot be rebuilt.
+    Ogg(OggError),
+    /// A Vorbis stream could not be decoded for comparison.
+    Vorbis(lewton::VorbisError),
+}
+
+impl fmt::Display for RepackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepackError::Ogg(e) => write!(f, "{e}"),
+            RepackError::Vorbis(e) => write!(f, "Vorbis decoding failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RepackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepackError::Ogg(e) => Some(e),
+            RepackError::Vorbis(e) => Some(e),
+        }
+    }
+}
+
+impl From<OggError> for RepackError {
+    fn from(e: OggError) -> Self {
+        RepackError::Ogg(e)
+    }
+}
+
+/// One logical bitstream (a serial number within one chain link).
+struct LogicalStream {
+    serial: u32,
+    pages: Vec<usize>,
+    packets: Vec<Packet>,
+    eos: bool,
+}
+
+impl LogicalStream {
+    fn is_vorbis(&self) -> bool {
+        self.packets.first().is_some_and(|p| p.data.starts_with(VORBIS_SIGNATURE))
+    }
+
+    /// The stream on its own, as a standalone Ogg file.
+    fn standalone(&self, pages: &[Page]) -> Vec<u8> {
+        let mut writer = PageWriter::new(Vec::new());
+        for &index in &self.pages {
+            // writing to a Vec cannot fail
+            let _ = writer.write_page(&pages[index]);
+        }
+        writer.into_inner()
+    }
+}
+
+/// A demuxed Ogg file.
+struct Demuxed {
+    pages: Vec<Page>,
+    streams: Vec<LogicalStream>,
+}
+
+impl Demuxed {
+    fn parse(data: &[u8]) -> Result<Self, OggError> {
+        let pages = ogg::read_pages(data)?;
+        let stream_ids = ogg::assign_streams(&pages);
+
+        let mut streams: Vec<LogicalStream> = Vec::new();
+        for (index, (page, &id)) in pages.iter().zip(&stream_ids).enumerate() {
+            if id == streams.len() {
+                streams.push(LogicalStream { serial: page.serial, pages: Vec::new(), packets: Vec::new(), eos: false });
+            }
+            streams[id].pages.push(index);
+            streams[id].eos |= page.flags & ogg::FLAG_EOS != 0;
+        }
+
+        for packet in ogg::read_packets(&pages)? {
+            streams[packet.stream].packets.push(packet);
+        }
+
+        Ok(Self { pages, streams })
+    }
+
+    /// Flags the packets that end a page among the leading granule-0
+    /// header pages of a stream; those page breaks are kept.
+    fn header_breaks(&self, stream: &LogicalStream) -> Vec<bool> {
+        let mut in_headers = true;
+        stream
+            .packets
+            .iter()
+            .map(|packet| {
+                in_headers &= self.pages[packet.page_index].granule == 0;
+                in_headers && packet.granule.is_some()
+            })
+            .collect()
+    }
+}
+
+/// Re-packs every logical stream of an Ogg file.
+///
+/// `optimize_vorbis` receives each Vorbis stream as a standalone Ogg file
+/// and returns its optimized form, or `None` to keep the stream as is.
+/// Its result is only used if it holds the same number of packets.
+pub fn repack<F>(data: &[u8], mut optimize_vorbis: F) -> Result<Vec<u8>, OggError>
+where
+    F: FnMut(&[u8]) -> Option<Vec<u8>>,
+{
+    let demuxed = Demuxed::parse(data)?;
+    let mut out_pages = Vec::new();
+
+    for stream in &demuxed.streams {
+        if stream.packets.is_empty() {
+            // nothing to repaginate, carry the pages over untouched
+            for &index in &stream.pages {
+                out_pages.push((index, demuxed.pages[index].clone()));
+            }
+            continue;
+        }
+
+        let mut packets: Vec<OutPacket> = stream
+            .packets
+            .iter()
+            .zip(demuxed.header_breaks(stream))
+            .map(|(packet, end_page)| OutPacket {
+                data: packet.data.clone(),
+                granule: packet.granule,
+                end_page,
+                position: packet.page_index,
+            })
+            .collect();
+
+        if stream.is_vorbis() {
+            let optimized = optimize_vorbis(&stream.standalone(&demuxed.pages))
+                .and_then(|bytes| ogg::read_pages(&bytes).ok())
+                .and_then(|pages| ogg::read_packets(&pages).ok())
+                .filter(|optimized| optimized.len() == packets.len());
+            if let Some(optimized) = optimized {
+                for (packet, new) in packets.iter_mut().zip(optimized) {
+                    // sample positions are unchanged, so either granule is valid
+                    packet.granule = packet.granule.or(new.granule);
+                    packet.data = new.data;
+                }
+            }
+        }
+
+        for page in ogg::paginate(stream.serial, &packets, stream.eos)? {
+            out_pages.push((page.position, page.page));
+        }
+    }
+
+    // an input page belongs to a single stream, and the sort is stable, so
+    // the pages of each stream keep their order
+    out_pages.sort_by_key(|(position, _)| *position);
+
+    let mut writer = PageWriter::new(Vec::with_capacity(data.len()));
+    for (_, page) in &out_pages {
+        // writing to a Vec cannot fail
+        let _ = writer.write_page(page);
+    }
+    Ok(writer.into_inner())
+}
+
+/// Compares two Ogg files stream by stream.
+///
+/// Streams must match in number, order and serial. Vorbis streams are
+/// compared by their decoded audio; any other stream must carry identical
+/// packets and final granule position.
+pub fn streams_equal(a: &[u8], b: &[u8]) -> Result<bool, RepackError> {
+    let left = Demuxed::parse(a)?;
+    let right = Demuxed::parse(b)?;
+
+    if left.streams.len() != right.streams.len() {
+        return Ok(false);
+    }
+
+    for (x, y) in left.streams.iter().zip(&right.streams) {
+        if x.serial != y.serial || x.is_vorbis() != y.is_vorbis() {
+            return Ok(false);
+        }
+
+        let equal = if x.is_vorbis() {
+            crate::vorbis_verify::decoded_streams_equal(
+                std::io::Cursor::new(x.standalone(&left.pages)),
+                std::io::Cursor::new(y.standalone(&right.pages)),
+            )
+            .map_err(RepackError::Vorbis)?
+        } else {
+            let final_granule = |s: &LogicalStream| s.packets.iter().rev().find_map(|p| p.granule);
+            final_granule(x) == final_granule(y)
+                && x.packets.len() == y.packets.len()
+                && x.packets.iter().zip(&y.packets).all(|(p, q)| p.data == q.data)
+        };
+        if !equal {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,297 @@
+//! Lossless Ogg Opus optimization.
+//!
+//! Opus packets are never re-encoded. The stream is re-paginated with the
+//! fewest pages the granule positions allow, code 3 packet padding is
+//! dropped and the OpusTags header is rewritten without trailing padding.
+//! OpusHead (and with it the pre-skip) and every page granule needed to
+//! reproduce the exact sample range are kept as they were.
+
+use std::fmt;
+
+use crate::ogg::{self, OggError, OutPacket, Packet, PageWriter};
+
+const OPUS_HEAD: &[u8; 8] = b"OpusHead";
+const OPUS_TAGS: &[u8; 8] = b"OpusTags";
+
+#[derive(Debug)]
+pub enum OpusError {
+    /// The Ogg framing is broken.
+    Ogg(OggError),
+    /// The file is not a single-stream Ogg Opus file.
+    NotOpus(String),
+    /// The Opus stream violates the Ogg Opus mapping.
+    Malformed(String),
+}
+
+impl fmt::Display for OpusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpusError::Ogg(e) => write!(f, "{e}"),
+            OpusError::NotOpus(msg) => write!(f, "not an Ogg Opus stream: {msg}"),
+            OpusError::Malformed(msg) => write!(f, "malformed Opus stream: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for OpusError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpusError::Ogg(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<OggError> for OpusError {
+    fn from(e: OggError) -> Self {
+        OpusError::Ogg(e)
+    }
+}
+
+/// What to do with the OpusTags packet and the audio packets.
+#[derive(Debug, Clone, Copy)]
+pub struct OpusOptions {
+    /// Drop every user comment and binary metadata, keeping only the vendor string.
+    pub delete_comments: bool,
+    /// Remove padding from code 3 audio packets.
+    pub strip_packet_padding: bool,
+}
+
+/// A parsed single-stream Ogg Opus file.
+struct OpusStream {
+    serial: u32,
+    head: Vec<u8>,
+    tags: Vec<u8>,
+    audio: Vec<Packet>,
+}
+
+impl OpusStream {
+    fn parse(data: &[u8]) -> Result<Self, OpusError> {
+        let pages = ogg::read_pages(data)?;
+        let mut packets = ogg::read_packets(&pages)?.into_iter();
+
+        let serial = pages.first().map(|p| p.serial).ok_or_else(|| OpusError::NotOpus("empty file".into()))?;
+        let chained = pages.iter().filter(|p| p.flags & ogg::FLAG_BOS != 0).count() > 1;
+        if chained || pages.iter().any(|p| p.serial != serial) {
+            return Err(OpusError::NotOpus("multiplexed or chained streams are not supported".into()));
+        }
+
+        let head = packets.next().ok_or_else(|| OpusError::NotOpus("no packets".into()))?;
+        if head.data.len() < 19 || &head.data[..8] != OPUS_HEAD {
+            return Err(OpusError::NotOpus("missing OpusHead".into()));
+        }
+        if head.data[8] >> 4 != 0 {
+            return Err(OpusError::NotOpus(format!("unsupported OpusHead version {}", head.data[8])));
+        }
+
+        let tags = packets.next().ok_or_else(|| OpusError::Malformed("missing OpusTags".into()))?;
+        if tags.data.len() < 16 || &tags.data[..8] != OPUS_TAGS {
+            return Err(OpusError::Malformed("missing OpusTags".into()));
+        }
+        // both headers must end their pages, so the first audio page starts clean
+        if head.granule.is_none() || tags.granule.is_none() {
+            return Err(OpusError::Malformed("header packets share a page with other packets".into()));
+        }
+
+        Ok(Self {
+            serial,
+            head: head.data,
+            tags: tags.data,
+            audio: packets.collect(),
+        })
+    }
+
+    /// True when each audio packet holds a single Opus stream, so the TOC
+    /// byte and padding apply to the whole packet.
+    fn single_opus_stream(&self) -> bool {
+        self.head[18] == 0 || self.head.get(19) == Some(&1)
+    }
+
+    /// Granule position after each audio packet, with the original final granule.
+    fn granules(&self) -> Result<Vec<u64>, OpusError> {
+        let durations = self
+            .audio
+            .iter()
+            .map(|p| packet_duration(&p.data))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(anchor) = self.audio.iter().position(|p| p.granule.is_some()) else {
+            return Ok(Vec::new());
+        };
+        let anchor_granule = self.audio[anchor].granule.unwrap_or_default();
+        let before: u64 = durations[..=anchor].iter().sum();
+
+        // a lone final page may end-trim below the decoded length; then the stream starts at zero
+        let start = match anchor_granule.checked_sub(before) {
+            Some(start) => start,
+            None if self.audio[anchor].eos => 0,
+            None => return Err(OpusError::Malformed("first audio granule is smaller than its samples".into())),
+        };
+
+        let mut granules = Vec::with_capacity(durations.len());
+        let mut position = start;
+        for (packet, duration) in self.audio.iter().zip(&durations) {
+            position += duration;
+            match packet.granule {
+                Some(original) if packet.eos => granules.push(original),
+                Some(original) if original != position => {
+                    return Err(OpusError::Malformed(format!(
+                        "page granule {original} does not match the {position} decoded samples"
+                    )));
+                }
+                _ => granules.push(position),
+            }
+        }
+        Ok(granules)
+    }
+}
+
+/// Samples at 48 kHz of one Opus frame, from the TOC configuration.
+fn frame_samples(toc: u8) -> u64 {
+    let config = toc >> 3;
+    match config {
+        // SILK-only: 10, 20, 40, 60 ms
+        0..=11 => [480, 960, 1920, 2880][(config & 3) as usize],
+        // hybrid: 10, 20 ms
+        12..=15 => [480, 960][(config & 1) as usize],
+        // CELT-only: 2.5, 5, 10, 20 ms
+        _ => [120, 240, 480, 960][(config & 3) as usize],
+    }
+}
+
+/// Duration in 48 kHz samples of an Opus packet.
+fn packet_duration(packet: &[u8]) -> Result<u64, OpusError> {
+    let toc = *packet.first().ok_or_else(|| OpusError::Malformed("empty audio packet".into()))?;
+    let frames = match toc & 3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => {
+            let count = packet.get(1).ok_or_else(|| OpusError::Malformed("truncated code 3 packet".into()))?;
+            u64::from(count & 0x3f)
+        }
+    };
+    Ok(frames * frame_samples(toc))
+}
+
+/// Removes the padding of a code 3 packet, returning `None` if there is none.
+fn strip_padding(packet: &[u8]) -> Option<Vec<u8>> {
+    if packet.len() < 2 || packet[0] & 3 != 3 || packet[1] & 0x40 == 0 {
+        return None;
+    }
+
+    let mut pos = 2;
+    let mut padding = 0usize;
+    loop {
+        let value = *packet.get(pos)?;
+        pos += 1;
+        if value == 255 {
+            padding += 254;
+        } else {
+            padding += value as usize;
+            break;
+        }
+    }
+    if pos + padding > packet.len() {
+        return None;
+    }
+
+    let mut stripped = Vec::with_capacity(packet.len() - padding - (pos - 2));
+    stripped.push(packet[0]);
+    stripped.push(packet[1] & !0x40);
+    stripped.extend_from_slice(&packet[pos..packet.len() - padding]);
+    Some(stripped)
+}
+
+/// Rebuilds OpusTags without trailing padding; returns `None` if it cannot be parsed.
+fn compact_tags(tags: &[u8], delete_comments: bool) -> Option<Vec<u8>> {
+    let read_u32 = |pos: usize| -> Option<usize> {
+        Some(u32::from_le_bytes(tags.get(pos..pos + 4)?.try_into().ok()?) as usize)
+    };
+
+    let vendor_len = read_u32(8)?;
+    let vendor_end = 12usize.checked_add(vendor_len)?;
+    let vendor = tags.get(12..vendor_end)?;
+    let count = read_u32(vendor_end)?;
+
+    let comments_start = vendor_end + 4;
+    let mut pos = comments_start;
+    for _ in 0..count {
+        let len = read_u32(pos)?;
+        pos = pos.checked_add(4 + len)?;
+        if pos > tags.len() {
+            return None;
+        }
+    }
+
+    let mut out = Vec::with_capacity(tags.len());
+    out.extend_from_slice(OPUS_TAGS);
+    out.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    out.extend_from_slice(vendor);
+    if delete_comments {
+        out.extend_from_slice(&0u32.to_le_bytes());
+        return Some(out);
+    }
+
+    out.extend_from_slice(&(count as u32).to_le_bytes());
+    out.extend_from_slice(&tags[comments_start..pos]);
+    // trailing bytes are binary metadata if the first one has its LSB set, padding otherwise
+    let extra = &tags[pos..];
+    if extra.first().is_some_and(|b| b & 1 == 1) {
+        out.extend_from_slice(extra);
+    }
+    Some(out)
+}
+
+/// Optimizes a complete Ogg Opus file held in memory.
+pub fn optimize(data: &[u8], options: OpusOptions) -> Result<Vec<u8>, OpusError> {
+    let stream = OpusStream::parse(data)?;
+    let granules = stream.granules()?;
+    let strip = options.strip_packet_padding && stream.single_opus_stream();
+
+    // packets past the end-trimmed final granule must not close a page,
+    // or a page granule would exceed the last one
+    let final_granule = granules.last().copied().unwrap_or_default();
+
+    let tags = compact_tags(&stream.tags, options.delete_comments).unwrap_or_else(|| stream.tags.clone());
+    let mut packets = vec![
+        OutPacket { data: stream.head.clone(), granule: Some(0), end_page: true, position: 0 },
+        OutPacket { data: tags, granule: Some(0), end_page: true, position: 0 },
+    ];
+
+    let last = stream.audio.len().saturating_sub(1);
+    for (i, packet) in stream.audio.iter().enumerate() {
+        let data = if strip { strip_padding(&packet.data) } else { None }.unwrap_or_else(|| packet.data.clone());
+        let granule = granules.get(i).copied().filter(|&g| i == last || g <= final_granule);
+        packets.push(OutPacket { data, granule, end_page: false, position: 0 });
+    }
+
+    let eos = stream.audio.last().is_none_or(|p| p.eos);
+    let mut writer = PageWriter::new(Vec::with_capacity(data.len()));
+    for page in ogg::paginate(stream.serial, &packets, eos)? {
+        writer.write_page(&page.page).map_err(|e| OpusError::Malformed(e.to_string()))?;
+    }
+    Ok(writer.into_inner())
+}
+
+/// Compares two Ogg Opus files at the packet level.
+///
+/// The streams match when their OpusHead, the audio packets (ignoring
+/// code 3 padding) and the granule range are identical, which implies the
+/// decoded audio is identical as well.
+pub fn streams_equal(a: &[u8], b: &[u8]) -> Result<bool, OpusError> {
+    let left = OpusStream::parse(a)?;
+    let right = OpusStream::parse(b)?;
+
+    if left.head != right.head || left.audio.len() != right.audio.len() {
+        return Ok(false);
+    }
+    if left.granules()? != right.granules()? {
+        return Ok(false);
+    }
+
+    let single = left.single_opus_stream();
+    let normalize = |data: &[u8]| {
+        if single { strip_padding(data) } else { None }.unwrap_or_else(|| data.to_vec())
+    };
+    Ok(left.audio.iter().zip(&right.audio).all(|(x, y)| normalize(&x.data) == normalize(&y.data)))
+}
@@ -0,0 +1,151 @@
+//! PNG optimization with oxipng, exported as the `OxipngProcessor`.
+//!
+//! Every row filter, including the heuristic strategies, is tried with
+//! libdeflater and, when enabled, once more with Zopfli; the smaller of
+//! the two results wins. The lossy options (alpha optimization and 16-bit
+//! scaling) stay off, so the decoded pixels never change.
+
+use std::io::Cursor;
+use std::num::NonZeroU8;
+
+use oxipng::{Deflaters, Options, PngError, RowFilter, StripChunks};
+use png::{BitDepth, ColorType, Transformations};
+
+use crate::processor::Processor;
+use crate::{error_kind, transform_file, BridgeError, CHISEL_VORBIS_ERR_OPEN_INPUT, CHISEL_VORBIS_ERR_STREAM};
+
+/// libdeflater level of the first trial (its maximum).
+const LIBDEFLATER_LEVEL: u8 = 12;
+
+/// Highest oxipng preset.
+const MAX_PRESET: u8 = 6;
+
+const ALL_FILTERS: [RowFilter; 10] = [
+    RowFilter::None,
+    RowFilter::Sub,
+    RowFilter::Up,
+    RowFilter::Average,
+    RowFilter::Paeth,
+    RowFilter::MinSum,
+    RowFilter::Entropy,
+    RowFilter::Bigrams,
+    RowFilter::BigEnt,
+    RowFilter::Brute,
+];
+
+/// Default oxipng preset.
+const DEFAULT_PRESET: u8 = 4;
+
+/// Default Zopfli iterations, as used by `ZopfliPngProcessor`.
+const DEFAULT_ZOPFLI_ITERATIONS: u8 = 15;
+
+/// How hard oxipng works and what it may drop.
+#[derive(Debug, Clone, Copy)]
+pub struct OxipngOptions {
+    /// oxipng preset (0-6) driving the reduction trials.
+    pub preset: u8,
+    /// Zopfli iterations of the second trial; 0 keeps libdeflater only.
+    pub zopfli_iterations: u8,
+    /// Drop the chunks that do not affect rendering.
+    pub strip_metadata: bool,
+}
+
+impl OxipngOptions {
+    fn to_options(self, deflate: Deflaters) -> Options {
+        let mut options = Options::from_preset(self.preset.min(MAX_PRESET));
+        options.filter = ALL_FILTERS.into_iter().collect();
+        options.deflate = deflate;
+        options.strip = if self.strip_metadata { StripChunks::Safe } else { StripChunks::None };
+        options.optimize_alpha = false;
+        options.scale_16 = false;
+        options
+    }
+}
+
+/// Optimizes a complete PNG file held in memory.
+///
+/// The input is returned unchanged when no trial makes it smaller.
+pub fn optimize(data: &[u8], options: OxipngOptions) -> Result<Vec<u8>, PngError> {
+    let libdeflater = options.to_options(Deflaters::Libdeflater { compression: LIBDEFLATER_LEVEL });
+    let mut best = oxipng::optimize_from_memory(data, &libdeflater)?;
+
+    if let Some(iterations) = NonZeroU8::new(options.zopfli_iterations) {
+        let zopfli = oxipng::optimize_from_memory(data, &options.to_options(Deflaters::Zopfli { iterations }))?;
+        if zopfli.len() < best.len() {
+            best = zopfli;
+        }
+    }
+    Ok(best)
+}
+
+/// Decodes the default image of a PNG file to RGBA with 16 bits per channel.
+///
+/// 8-bit samples are widened (`v * 257`), so an image reduced losslessly
+/// from 16 to 8 bits compares equal to its original.
+fn decode_rgba16(data: &[u8]) -> Result<(u32, u32, Vec<u16>), png::DecodingError> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    let buf = &buf[..frame.buffer_size()];
+
+    let channels = match frame.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        // EXPAND turns palettes into RGB or RGBA
+        ColorType::Rgba | ColorType::Indexed => 4,
+    };
+    let samples: Vec<u16> = match frame.bit_depth {
+        BitDepth::Sixteen => buf.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect(),
+        _ => buf.iter().map(|&v| u16::from(v) * 257).collect(),
+    };
+
+    let mut rgba = Vec::with_capacity(frame.width as usize * frame.height as usize * 4);
+    for pixel in samples.chunks_exact(channels) {
+        match *pixel {
+            [gray] => rgba.extend_from_slice(&[gray, gray, gray, u16::MAX]),
+            [gray, alpha] => rgba.extend_from_slice(&[gray, gray, gray, alpha]),
+            [r, g, b] => rgba.extend_from_slice(&[r, g, b, u16::MAX]),
+            _ => rgba.extend_from_slice(pixel),
+        }
+    }
+    Ok((frame.width, frame.height, rgba))
+}
+
+/// PNG candidate next to `PngProcessor` and `ZopfliPngProcessor`.
+pub struct OxipngProcessor;
+
+impl Processor for OxipngProcessor {
+    fn name(&self) -> &'static str {
+        "OxipngProcessor"
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["image/png"]
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &[".png"]
+    }
+
+    fn recompress(&self, input: &str, output: &str, preserve_metadata: bool) -> Result<(), BridgeError> {
+        let options = OxipngOptions {
+            preset: DEFAULT_PRESET,
+            zopfli_iterations: DEFAULT_ZOPFLI_ITERATIONS,
+            strip_metadata: !preserve_metadata,
+        };
+        transform_file(input, output, |data| optimize(data, options), |_| CHISEL_VORBIS_ERR_STREAM)
+    }
+
+    fn raw_equal(&self, a: &str, b: &str) -> Result<bool, BridgeError> {
+        let decode = |path: &str| {
+            let data = std::fs::read(path)
+                .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open {path}: {e}")))?;
+            decode_rgba16(&data)
+                .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e)))
+        };
+        Ok(decode(a)? == decode(b)?)
+    }
+}
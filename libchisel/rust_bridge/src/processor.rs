@@ -0,0 +1,179 @@
+//! Rust processors exposed to C++ through one vtable.
+//!
+//! Implement [`Processor`] and list the type in [`PROCESSORS`]: the C++
+//! `ProcessorRegistry` enumerates them with `chisel_rust_processor_count`
+//! and `chisel_rust_processor_get` and wraps each in a `RustProcessor`, so
+//! no C++ code has to change.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::{finish, path_arg, report_error, BridgeError};
+
+/// A file optimizer implemented in Rust, mirroring the C++ `IProcessor`.
+///
+/// Methods are called concurrently from the worker threads.
+pub trait Processor: Sync {
+    /// Unique name, as returned by `IProcessor::get_name`.
+    fn name(&self) -> &'static str;
+    /// Handled MIME types.
+    fn mime_types(&self) -> &'static [&'static str];
+    /// Handled extensions, with the leading dot.
+    fn extensions(&self) -> &'static [&'static str];
+    fn can_recompress(&self) -> bool {
+        true
+    }
+    /// Writes an optimized copy of `input` to `output`.
+    fn recompress(&self, input: &str, output: &str, preserve_metadata: bool) -> Result<(), BridgeError>;
+    /// Tells whether two files hold the same content once decoded.
+    fn raw_equal(&self, a: &str, b: &str) -> Result<bool, BridgeError>;
+}
+
+/// Every processor exported to C++, in registration order.
+static PROCESSORS: &[&dyn Processor] = &[&crate::png_opt::OxipngProcessor, &crate::woff::WoffProcessor];
+
+/// Describes one Rust processor over the C ABI.
+///
+/// All pointers stay valid for the lifetime of the program. `processor`
+/// must be handed back as the first argument of `recompress` and
+/// `raw_equal`.
+#[repr(C)]
+pub struct ChiselProcessorVTable {
+    pub processor: *const c_void,
+    pub name: *const c_char,
+    pub mime_types: *const *const c_char,
+    pub mime_type_count: usize,
+    pub extensions: *const *const c_char,
+    pub extension_count: usize,
+    pub can_recompress: bool,
+    /// Returns `CHISEL_VORBIS_OK` or a negative `CHISEL_VORBIS_ERR_*` code.
+    pub recompress: extern "C" fn(
+        processor: *const c_void,
+        input_path: *const c_char,
+        output_path: *const c_char,
+        preserve_metadata: bool,
+        error_message: *mut *mut c_char,
+    ) -> c_int,
+    /// Returns 1 if equal, 0 if different, or a negative `CHISEL_VORBIS_ERR_*` code.
+    pub raw_equal: extern "C" fn(
+        processor: *const c_void,
+        path_a: *const c_char,
+        path_b: *const c_char,
+        error_message: *mut *mut c_char,
+    ) -> c_int,
+}
+
+/// A vtable together with the C strings it points into.
+struct Entry {
+    vtable: ChiselProcessorVTable,
+    _strings: Vec<CString>,
+    _mime_types: Vec<*const c_char>,
+    _extensions: Vec<*const c_char>,
+}
+
+// SAFETY: the pointers refer to the owned, never modified strings of the
+// entry and to the immutable `PROCESSORS` table.
+unsafe impl Send for Entry {}
+unsafe impl Sync for Entry {}
+
+fn c_string(s: &str) -> CString {
+    CString::new(s).expect("processor strings must not contain NUL")
+}
+
+impl Entry {
+    fn new(processor: &'static &'static dyn Processor) -> Self {
+        let name = c_string(processor.name());
+        let mimes: Vec<CString> = processor.mime_types().iter().map(|s| c_string(s)).collect();
+        let exts: Vec<CString> = processor.extensions().iter().map(|s| c_string(s)).collect();
+        let mime_types: Vec<*const c_char> = mimes.iter().map(|s| s.as_ptr()).collect();
+        let extensions: Vec<*const c_char> = exts.iter().map(|s| s.as_ptr()).collect();
+
+        let vtable = ChiselProcessorVTable {
+            processor: ptr::from_ref(processor).cast(),
+            name: name.as_ptr(),
+            mime_types: mime_types.as_ptr(),
+            mime_type_count: mime_types.len(),
+            extensions: extensions.as_ptr(),
+            extension_count: extensions.len(),
+            can_recompress: processor.can_recompress(),
+            recompress: recompress_trampoline,
+            raw_equal: raw_equal_trampoline,
+        };
+
+        let mut strings = vec![name];
+        strings.extend(mimes);
+        strings.extend(exts);
+        Self { vtable, _strings: strings, _mime_types: mime_types, _extensions: extensions }
+    }
+}
+
+fn entries() -> &'static [Entry] {
+    static ENTRIES: OnceLock<Vec<Entry>> = OnceLock::new();
+    ENTRIES.get_or_init(|| PROCESSORS.iter().map(Entry::new).collect())
+}
+
+/// # Safety
+/// `processor` must be the `processor` field of a vtable from this module.
+unsafe fn processor_arg(processor: *const c_void) -> Result<&'static dyn Processor, BridgeError> {
+    if processor.is_null() {
+        return Err(BridgeError::new(crate::CHISEL_VORBIS_ERR_NULL_ARGUMENT, "processor is null"));
+    }
+    Ok(*processor.cast::<&'static dyn Processor>())
+}
+
+extern "C" fn recompress_trampoline(
+    processor: *const c_void,
+    input_path: *const c_char,
+    output_path: *const c_char,
+    preserve_metadata: bool,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = unsafe { processor_arg(processor) }.and_then(|processor| {
+        let input = path_arg(input_path, "input")?;
+        let output = path_arg(output_path, "output")?;
+        processor.recompress(input, output, preserve_metadata)
+    });
+    finish(result, error_message)
+}
+
+extern "C" fn raw_equal_trampoline(
+    processor: *const c_void,
+    path_a: *const c_char,
+    path_b: *const c_char,
+    error_message: *mut *mut c_char,
+) -> c_int {
+    if !error_message.is_null() {
+        unsafe { *error_message = ptr::null_mut() };
+    }
+
+    let result = unsafe { processor_arg(processor) }.and_then(|processor| {
+        let a = path_arg(path_a, "first")?;
+        let b = path_arg(path_b, "second")?;
+        processor.raw_equal(a, b)
+    });
+    match result {
+        Ok(equal) => c_int::from(equal),
+        Err(err) => {
+            report_error(error_message, &err.message);
+            err.code
+        }
+    }
+}
+
+/// Returns the number of Rust processors.
+#[no_mangle]
+pub extern "C" fn chisel_rust_processor_count() -> usize {
+    entries().len()
+}
+
+/// Returns the vtable of the Rust processor at `index`, or null if out of range.
+#[no_mangle]
+pub extern "C" fn chisel_rust_processor_get(index: usize) -> *const ChiselProcessorVTable {
+    entries().get(index).map_or(ptr::null(), |entry| &entry.vtable)
+}
@@ -0,0 +1,132 @@
+//! Progress reporting and cooperative cancellation for long-running calls.
+
+use std::cell::Cell;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_int, c_void};
+
+/// Callback invoked while a stream is processed.
+///
+/// Receives the caller context, the number of input bytes consumed so far
+/// and the total input size. Returning a non-zero value cancels the call.
+/// Because some optimizers make more than one pass over the input, the
+/// processed count may exceed the total.
+pub type ChiselProgressCallback =
+    Option<extern "C" fn(context: *mut c_void, bytes_processed: u64, total_bytes: u64) -> c_int>;
+
+/// Bytes consumed between two progress reports.
+const REPORT_INTERVAL: u64 = 256 * 1024;
+
+/// Message of the error returned once the callback has cancelled the call.
+pub const CANCELLED_MESSAGE: &str = "operation cancelled by caller";
+
+/// Shared state between the reader and writer wrappers of one call.
+pub struct Progress {
+    callback: ChiselProgressCallback,
+    context: *mut c_void,
+    total: u64,
+    processed: Cell<u64>,
+    next_report: Cell<u64>,
+    cancelled: Cell<bool>,
+}
+
+impl Progress {
+    pub fn new(callback: ChiselProgressCallback, context: *mut c_void, total: u64) -> Self {
+        Self {
+            callback,
+            context,
+            total,
+            processed: Cell::new(0),
+            next_report: Cell::new(0),
+            cancelled: Cell::new(false),
+        }
+    }
+
+    /// True once the callback has asked to stop.
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+
+    /// Error returned to the optimizer once the call has been cancelled.
+    fn cancelled_error() -> io::Error {
+        io::Error::other(CANCELLED_MESSAGE)
+    }
+
+    /// Reports progress when due and polls for cancellation.
+    fn poll(&self, force: bool) -> io::Result<()> {
+        if self.cancelled.get() {
+            return Err(Self::cancelled_error());
+        }
+        let Some(callback) = self.callback else {
+            return Ok(());
+        };
+
+        let processed = self.processed.get();
+        if !force && processed < self.next_report.get() {
+            return Ok(());
+        }
+        self.next_report.set(processed + REPORT_INTERVAL);
+
+        if callback(self.context, processed, self.total) != 0 {
+            self.cancelled.set(true);
+            return Err(Self::cancelled_error());
+        }
+        Ok(())
+    }
+
+    /// Reports the final count, unless the call was cancelled.
+    pub fn finish(&self) {
+        if !self.cancelled.get() {
+            let _ = self.poll(true);
+        }
+    }
+}
+
+/// Reader wrapper that counts consumed bytes and honours cancellation.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.progress.poll(false)?;
+        let read = self.inner.read(buf)?;
+        self.progress.processed.set(self.progress.processed.get() + read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Writer wrapper that only polls for cancellation.
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    progress: &'a Progress,
+}
+
+impl<'a, W> ProgressWriter<'a, W> {
+    pub fn new(inner: W, progress: &'a Progress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.progress.poll(false)?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
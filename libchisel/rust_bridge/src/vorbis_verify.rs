@@ -0,0 +1,80 @@
+//! Decode-level comparison of two Ogg Vorbis streams.
+
+use std::io::{Read, Seek};
+
+use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use lewton::VorbisError;
+
+/// Decoder state for one side of the comparison.
+struct DecodedStream<R: Read + Seek> {
+    reader: OggStreamReader<R>,
+    pending: Vec<f32>,
+    finished: bool,
+}
+
+impl<R: Read + Seek> DecodedStream<R> {
+    fn new(source: R) -> Result<Self, VorbisError> {
+        Ok(Self {
+            reader: OggStreamReader::new(source)?,
+            pending: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// Channel count and sample rate of the current (possibly chained) stream.
+    fn format(&self) -> (u8, u32) {
+        (self.reader.ident_hdr.audio_channels, self.reader.ident_hdr.audio_sample_rate)
+    }
+
+    /// Appends the next decoded packet to the pending samples.
+    fn fill(&mut self) -> Result<(), VorbisError> {
+        while !self.finished && self.pending.is_empty() {
+            match self.reader.read_dec_packet_generic::<InterleavedSamples<f32>>()? {
+                Some(packet) => self.pending.extend_from_slice(&packet.samples),
+                None => self.finished = true,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes both streams to PCM and compares them sample by sample.
+///
+/// Samples are compared bit for bit as decoded floats, so any change in the
+/// audio (not just one that survives 16-bit quantization) is reported.
+/// Vorbis comments are ignored, since they may legitimately be stripped.
+pub fn decoded_streams_equal<A, B>(a: A, b: B) -> Result<bool, VorbisError>
+where
+    A: Read + Seek,
+    B: Read + Seek,
+{
+    let mut left = DecodedStream::new(a)?;
+    let mut right = DecodedStream::new(b)?;
+
+    loop {
+        left.fill()?;
+        right.fill()?;
+
+        if left.format() != right.format() {
+            return Ok(false);
+        }
+
+        let common = left.pending.len().min(right.pending.len());
+        if common == 0 {
+            // at least one side ended: equal only if both did
+            return Ok(left.finished && right.finished && left.pending.is_empty() && right.pending.is_empty());
+        }
+
+        let same = left.pending[..common]
+            .iter()
+            .zip(&right.pending[..common])
+            .all(|(x, y)| x.to_bits() == y.to_bits());
+        if !same {
+            return Ok(false);
+        }
+
+        left.pending.drain(..common);
+        right.pending.drain(..common);
+    }
+}
@@ -0,0 +1,824 @@
+//! WOFF and WOFF2 web font recompression, exported as the `WoffProcessor`.
+//!
+//! WOFF files keep their format: every table and the extended metadata are
+//! re-deflated with Zopfli, tables that do not shrink are stored. WOFF2
+//! files are re-encoded with Brotli at quality 11 in font mode, with and
+//! without the `glyf`/`loca` (and, where it applies, `hmtx`) transform, and
+//! the smaller result wins; the transform is only tried when its
+//! reconstruction reproduces the original tables byte for byte. Every
+//! output is decoded again and must yield the same SFNT tables as the input.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::num::NonZeroU64;
+
+use brotli::enc::backward_references::{BrotliEncoderMode, BrotliEncoderParams};
+use flate2::read::ZlibDecoder;
+
+use crate::processor::Processor;
+use crate::woff_glyf;
+use crate::{
+    error_kind, transform_file, BridgeError, CHISEL_VORBIS_ERR_OPEN_INPUT, CHISEL_VORBIS_ERR_STREAM,
+    CHISEL_VORBIS_ERR_VERIFY,
+};
+
+const WOFF_SIGNATURE: &[u8; 4] = b"wOFF";
+const WOFF2_SIGNATURE: &[u8; 4] = b"wOF2";
+const COLLECTION_FLAVOR: u32 = u32::from_be_bytes(*b"ttcf");
+
+const WOFF_HEADER_LEN: usize = 44;
+const WOFF_ENTRY_LEN: usize = 20;
+const WOFF2_HEADER_LEN: usize = 48;
+const SFNT_HEADER_LEN: usize = 12;
+const SFNT_ENTRY_LEN: usize = 16;
+
+/// Zopfli iterations for WOFF table data, as used by `ZopfliPngProcessor`.
+const ZOPFLI_ITERATIONS: u64 = 15;
+
+/// Brotli quality and window size for WOFF2 data (both the maximum).
+const BROTLI_QUALITY: i32 = 11;
+const BROTLI_WINDOW: i32 = 24;
+
+/// Transform version marking `glyf` and `loca` as stored untransformed.
+const GLYF_NULL_TRANSFORM: u8 = 3;
+/// Transform version of the `hmtx` transform.
+const HMTX_TRANSFORM: u8 = 1;
+/// Table directory flag value announcing an explicit tag.
+const ARBITRARY_TAG: u8 = 63;
+
+/// Offset of `checkSumAdjustment` in `head`.
+const HEAD_CHECKSUM_ADJUSTMENT: usize = 8;
+/// Offset of `indexToLocFormat` in `head`.
+const HEAD_INDEX_TO_LOC_FORMAT: usize = 50;
+/// Offset of `numberOfHMetrics` in `hhea`.
+const HHEA_NUMBER_OF_HMETRICS: usize = 34;
+
+const GLYF: [u8; 4] = *b"glyf";
+const LOCA: [u8; 4] = *b"loca";
+const HMTX: [u8; 4] = *b"hmtx";
+const HEAD: [u8; 4] = *b"head";
+const HHEA: [u8; 4] = *b"hhea";
+
+/// Tags with a one-byte code in the WOFF2 table directory, by code.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm", b"glyf", b"loca",
+    b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+    b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL",
+    b"SVG ", b"sbix", b"acnt", b"avar", b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+    b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+#[derive(Debug)]
+pub enum WoffError {
+    /// The data is neither a WOFF nor a WOFF2 file.
+    NotWoff,
+    /// A structure is truncated or points outside the file.
+    Truncated,
+    /// The file violates the WOFF or WOFF2 specification.
+    Malformed(String),
+    /// The file uses a feature this module does not handle.
+    Unsupported(String),
+    /// A zlib or Brotli stream could not be compressed or decompressed.
+    Compression(io::Error),
+    /// The re-encoded font does not decode to the original tables.
+    Mismatch,
+}
+
+impl fmt::Display for WoffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WoffError::NotWoff => write!(f, "not a WOFF or WOFF2 file"),
+            WoffError::Truncated => write!(f, "truncated font data"),
+            WoffError::Malformed(msg) => write!(f, "malformed font: {msg}"),
+            WoffError::Unsupported(msg) => write!(f, "unsupported font: {msg}"),
+            WoffError::Compression(e) => write!(f, "compressed stream failed: {e}"),
+            WoffError::Mismatch => write!(f, "re-encoded font does not decode to the original tables"),
+        }
+    }
+}
+
+impl std::error::Error for WoffError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WoffError::Compression(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Big-endian cursor over a byte slice.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The bytes not read yet.
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], WoffError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or(WoffError::Truncated)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), WoffError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, WoffError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, WoffError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn i16(&mut self) -> Result<i16, WoffError> {
+        self.u16().map(|v| v as i16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, WoffError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4], WoffError> {
+        let b = self.bytes(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+
+    /// Reads a WOFF2 `UIntBase128`.
+    fn base128(&mut self) -> Result<u32, WoffError> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            if i == 0 && byte == 0x80 {
+                return Err(WoffError::Malformed("UIntBase128 with leading zeros".into()));
+            }
+            if value & 0xfe00_0000 != 0 {
+                return Err(WoffError::Malformed("UIntBase128 overflow".into()));
+            }
+            value = (value << 7) | u32::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(WoffError::Malformed("UIntBase128 longer than 5 bytes".into()))
+    }
+
+    /// Reads a WOFF2 `255UInt16`.
+    pub fn u255(&mut self) -> Result<u16, WoffError> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => u16::from(self.u8()?) + 506,
+            255 => u16::from(self.u8()?) + 253,
+            code => u16::from(code),
+        })
+    }
+}
+
+fn write_base128(out: &mut Vec<u8>, value: u32) {
+    let mut len = 1;
+    while len < 5 && value >> (7 * len) != 0 {
+        len += 1;
+    }
+    for i in (0..len).rev() {
+        let byte = ((value >> (7 * i)) & 0x7f) as u8;
+        out.push(if i == 0 { byte } else { byte | 0x80 });
+    }
+}
+
+/// Writes a WOFF2 `255UInt16`.
+pub fn write_u255(out: &mut Vec<u8>, value: u16) {
+    match value {
+        0..=252 => out.push(value as u8),
+        253..=505 => out.extend_from_slice(&[255, (value - 253) as u8]),
+        506..=761 => out.extend_from_slice(&[254, (value - 506) as u8]),
+        _ => {
+            out.push(253);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_be_bytes());
+}
+
+fn pad4(out: &mut Vec<u8>) {
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8], WoffError> {
+    offset.checked_add(len).and_then(|end| data.get(offset..end)).ok_or(WoffError::Truncated)
+}
+
+fn read_exact_len(reader: impl Read, expected: usize) -> Result<Vec<u8>, WoffError> {
+    let mut out = Vec::with_capacity(expected);
+    reader.take(expected as u64 + 1).read_to_end(&mut out).map_err(WoffError::Compression)?;
+    if out.len() != expected {
+        return Err(WoffError::Malformed(format!("stream decodes to {} bytes, expected {expected}", out.len())));
+    }
+    Ok(out)
+}
+
+fn zopfli_zlib(data: &[u8]) -> Result<Vec<u8>, WoffError> {
+    let options = zopfli::Options {
+        iteration_count: NonZeroU64::new(ZOPFLI_ITERATIONS).unwrap_or(NonZeroU64::MIN),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    zopfli::compress(options, zopfli::Format::Zlib, data, &mut out).map_err(WoffError::Compression)?;
+    Ok(out)
+}
+
+fn brotli_compress(data: &[u8], mode: BrotliEncoderMode) -> Result<Vec<u8>, WoffError> {
+    let params = BrotliEncoderParams {
+        quality: BROTLI_QUALITY,
+        lgwin: BROTLI_WINDOW,
+        mode,
+        size_hint: data.len(),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut out, &params).map_err(WoffError::Compression)?;
+    Ok(out)
+}
+
+fn brotli_decompress(data: &[u8], expected: usize) -> Result<Vec<u8>, WoffError> {
+    read_exact_len(brotli::Decompressor::new(data, 4096), expected)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Woff,
+    Woff2,
+}
+
+/// One SFNT table.
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Table {
+    /// The table data with `head.checkSumAdjustment` zeroed: it covers the
+    /// whole SFNT layout, which decoders are free to rebuild differently.
+    fn comparable(&self) -> std::borrow::Cow<'_, [u8]> {
+        if self.tag == HEAD && self.data.len() >= HEAD_CHECKSUM_ADJUSTMENT + 4 {
+            let mut data = self.data.clone();
+            data[HEAD_CHECKSUM_ADJUSTMENT..HEAD_CHECKSUM_ADJUSTMENT + 4].fill(0);
+            data.into()
+        } else {
+            self.data.as_slice().into()
+        }
+    }
+
+    /// The SFNT table checksum.
+    fn checksum(&self) -> u32 {
+        self.comparable()
+            .chunks(4)
+            .map(|c| {
+                let mut word = [0u8; 4];
+                word[..c.len()].copy_from_slice(c);
+                u32::from_be_bytes(word)
+            })
+            .fold(0u32, u32::wrapping_add)
+    }
+}
+
+/// A decoded WOFF or WOFF2 file.
+#[derive(Debug)]
+pub struct Font {
+    pub format: Format,
+    pub flavor: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    /// Sorted by tag.
+    pub tables: Vec<Table>,
+    /// Decompressed extended metadata (XML).
+    pub metadata: Option<Vec<u8>>,
+    pub private_data: Option<Vec<u8>>,
+}
+
+impl Font {
+    fn sort_tables(&mut self) -> Result<(), WoffError> {
+        self.tables.sort_by_key(|t| t.tag);
+        if self.tables.windows(2).any(|w| w[0].tag == w[1].tag) {
+            return Err(WoffError::Malformed("duplicate table tag".into()));
+        }
+        Ok(())
+    }
+
+    fn table(&self, tag: [u8; 4]) -> Option<&[u8]> {
+        self.tables.iter().find(|t| t.tag == tag).map(|t| t.data.as_slice())
+    }
+
+    /// Reads a 16-bit field at byte offset `at` of a table.
+    fn u16_field(&self, tag: [u8; 4], at: usize) -> Option<u16> {
+        self.table(tag)?.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    /// Size of the uncompressed SFNT font.
+    fn sfnt_size(&self) -> usize {
+        SFNT_HEADER_LEN
+            + SFNT_ENTRY_LEN * self.tables.len()
+            + self.tables.iter().map(|t| t.data.len().next_multiple_of(4)).sum::<usize>()
+    }
+
+    /// Tells whether both fonts decode to the same SFNT tables.
+    pub fn same_tables(&self, other: &Font) -> bool {
+        self.flavor == other.flavor
+            && self.tables.len() == other.tables.len()
+            && self.tables.iter().zip(&other.tables).all(|(a, b)| a.tag == b.tag && a.comparable() == b.comparable())
+    }
+}
+
+fn parse_woff(data: &[u8]) -> Result<Font, WoffError> {
+    let mut r = Reader::new(data);
+    r.skip(4)?;
+    let flavor = r.u32()?;
+    if r.u32()? as usize != data.len() {
+        return Err(WoffError::Malformed("header length does not match the file size".into()));
+    }
+    let num_tables = r.u16()?;
+    r.skip(2 + 4)?; // reserved, totalSfntSize
+    let major_version = r.u16()?;
+    let minor_version = r.u16()?;
+    let [meta_offset, meta_length, meta_orig_length, private_offset, private_length] =
+        [r.u32()?, r.u32()?, r.u32()?, r.u32()?, r.u32()?].map(|v| v as usize);
+
+    let mut tables = Vec::with_capacity(usize::from(num_tables));
+    for _ in 0..num_tables {
+        let tag = r.tag()?;
+        let [offset, comp_length, orig_length] = [r.u32()?, r.u32()?, r.u32()?].map(|v| v as usize);
+        r.skip(4)?; // origChecksum, recomputed on output
+        let stored = slice(data, offset, comp_length)?;
+        let data = match comp_length.cmp(&orig_length) {
+            std::cmp::Ordering::Equal => stored.to_vec(),
+            std::cmp::Ordering::Less => read_exact_len(ZlibDecoder::new(stored), orig_length)?,
+            std::cmp::Ordering::Greater => {
+                return Err(WoffError::Malformed("table stored larger than its original size".into()))
+            }
+        };
+        tables.push(Table { tag, data });
+    }
+
+    let metadata = if meta_length != 0 {
+        Some(read_exact_len(ZlibDecoder::new(slice(data, meta_offset, meta_length)?), meta_orig_length)?)
+    } else {
+        None
+    };
+    let private_data =
+        if private_length != 0 { Some(slice(data, private_offset, private_length)?.to_vec()) } else { None };
+
+    let mut font = Font {
+        format: Format::Woff,
+        flavor,
+        major_version,
+        minor_version,
+        tables,
+        metadata,
+        private_data,
+    };
+    font.sort_tables()?;
+    Ok(font)
+}
+
+fn write_woff(font: &Font) -> Result<Vec<u8>, WoffError> {
+    let header_len = WOFF_HEADER_LEN + WOFF_ENTRY_LEN * font.tables.len();
+    let mut directory = Vec::with_capacity(WOFF_ENTRY_LEN * font.tables.len());
+    let mut body = Vec::new();
+
+    for table in &font.tables {
+        let compressed = zopfli_zlib(&table.data)?;
+        let block = if compressed.len() < table.data.len() { &compressed } else { &table.data };
+        directory.extend_from_slice(&table.tag);
+        push_u32(&mut directory, header_len + body.len());
+        push_u32(&mut directory, block.len());
+        push_u32(&mut directory, table.data.len());
+        directory.extend_from_slice(&table.checksum().to_be_bytes());
+        body.extend_from_slice(block);
+        pad4(&mut body);
+    }
+
+    let (meta_offset, meta_length, meta_orig_length) = match &font.metadata {
+        Some(metadata) => {
+            let compressed = zopfli_zlib(metadata)?;
+            let offset = header_len + body.len();
+            body.extend_from_slice(&compressed);
+            (offset, compressed.len(), metadata.len())
+        }
+        None => (0, 0, 0),
+    };
+    let (private_offset, private_length) = match &font.private_data {
+        Some(private_data) => {
+            pad4(&mut body);
+            let offset = header_len + body.len();
+            body.extend_from_slice(private_data);
+            (offset, private_data.len())
+        }
+        None => (0, 0),
+    };
+
+    let mut out = Vec::with_capacity(header_len + body.len());
+    out.extend_from_slice(WOFF_SIGNATURE);
+    out.extend_from_slice(&font.flavor.to_be_bytes());
+    push_u32(&mut out, header_len + body.len());
+    push_u16(&mut out, font.tables.len() as u16);
+    push_u16(&mut out, 0);
+    push_u32(&mut out, font.sfnt_size());
+    push_u16(&mut out, font.major_version);
+    push_u16(&mut out, font.minor_version);
+    for value in [meta_offset, meta_length, meta_orig_length, private_offset, private_length] {
+        push_u32(&mut out, value);
+    }
+    out.extend(directory);
+    out.extend(body);
+    Ok(out)
+}
+
+/// Rebuilds `hmtx` from its transformed form (WOFF2 section 5.4).
+fn reconstruct_hmtx(data: &[u8], num_hmetrics: usize, x_mins: &[i16]) -> Result<Vec<u8>, WoffError> {
+    let mut r = Reader::new(data);
+    let flags = r.u8()?;
+    if flags & 0xfc != 0 {
+        return Err(WoffError::Malformed("reserved hmtx transform flags set".into()));
+    }
+    if num_hmetrics == 0 || num_hmetrics > x_mins.len() {
+        return Err(WoffError::Malformed("numberOfHMetrics out of range".into()));
+    }
+
+    let advances = (0..num_hmetrics).map(|_| r.u16()).collect::<Result<Vec<_>, _>>()?;
+    let mut side_bearings = Vec::with_capacity(x_mins.len());
+    for (i, &x_min) in x_mins.iter().enumerate() {
+        let omitted = if i < num_hmetrics { flags & 0x01 != 0 } else { flags & 0x02 != 0 };
+        side_bearings.push(if omitted { x_min } else { r.i16()? });
+    }
+
+    let mut hmtx = Vec::with_capacity(num_hmetrics * 4 + (x_mins.len() - num_hmetrics) * 2);
+    for (i, lsb) in side_bearings.iter().enumerate() {
+        if let Some(advance) = advances.get(i) {
+            push_u16(&mut hmtx, *advance);
+        }
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+    }
+    Ok(hmtx)
+}
+
+/// A WOFF2 table directory entry.
+struct Woff2Entry {
+    tag: [u8; 4],
+    version: u8,
+    orig_length: usize,
+    stored_length: usize,
+    transformed: bool,
+}
+
+fn parse_woff2(data: &[u8]) -> Result<Font, WoffError> {
+    let mut r = Reader::new(data);
+    r.skip(4)?;
+    let flavor = r.u32()?;
+    if flavor == COLLECTION_FLAVOR {
+        return Err(WoffError::Unsupported("font collections".into()));
+    }
+    if r.u32()? as usize != data.len() {
+        return Err(WoffError::Malformed("header length does not match the file size".into()));
+    }
+    let num_tables = r.u16()?;
+    r.skip(2 + 4)?; // reserved, totalSfntSize
+    let compressed_length = r.u32()? as usize;
+    let major_version = r.u16()?;
+    let minor_version = r.u16()?;
+    let [meta_offset, meta_length, meta_orig_length, private_offset, private_length] =
+        [r.u32()?, r.u32()?, r.u32()?, r.u32()?, r.u32()?].map(|v| v as usize);
+
+    let mut entries = Vec::with_capacity(usize::from(num_tables));
+    for _ in 0..num_tables {
+        let flags = r.u8()?;
+        let tag = match flags & 0x3f {
+            ARBITRARY_TAG => r.tag()?,
+            code => *KNOWN_TAGS[usize::from(code)],
+        };
+        let version = flags >> 6;
+        let orig_length = r.base128()? as usize;
+        let transformed = if tag == GLYF || tag == LOCA { version != GLYF_NULL_TRANSFORM } else { version != 0 };
+        let stored_length = if transformed { r.base128()? as usize } else { orig_length };
+        entries.push(Woff2Entry { tag, version, orig_length, stored_length, transformed });
+    }
+
+    let total = entries
+        .iter()
+        .try_fold(0usize, |sum, e| sum.checked_add(e.stored_length))
+        .ok_or_else(|| WoffError::Malformed("table sizes overflow".into()))?;
+    let stream = brotli_decompress(r.bytes(compressed_length)?, total)?;
+
+    let mut tables = Vec::with_capacity(entries.len());
+    let mut transformed_glyf = None;
+    let mut transformed_loca = None;
+    let mut transformed_hmtx = None;
+    let mut offset = 0;
+    for entry in &entries {
+        let stored = &stream[offset..offset + entry.stored_length];
+        offset += entry.stored_length;
+        if !entry.transformed {
+            tables.push(Table { tag: entry.tag, data: stored.to_vec() });
+            continue;
+        }
+        match (entry.tag, entry.version) {
+            (GLYF, 0) => transformed_glyf = Some(stored),
+            (LOCA, 0) if entry.stored_length == 0 => transformed_loca = Some(entry.orig_length),
+            (HMTX, HMTX_TRANSFORM) => transformed_hmtx = Some((stored, entry.orig_length)),
+            (tag, version) => {
+                return Err(WoffError::Unsupported(format!(
+                    "transform {version} of table '{}'",
+                    String::from_utf8_lossy(&tag)
+                )))
+            }
+        }
+    }
+
+    match (transformed_glyf, transformed_loca) {
+        (Some(glyf_data), Some(loca_length)) => {
+            let (glyf, loca) = woff_glyf::reconstruct(glyf_data)?;
+            if loca.len() != loca_length {
+                return Err(WoffError::Malformed("reconstructed loca has the wrong size".into()));
+            }
+            tables.push(Table { tag: GLYF, data: glyf });
+            tables.push(Table { tag: LOCA, data: loca });
+        }
+        (None, None) => {}
+        _ => return Err(WoffError::Malformed("glyf and loca must be transformed together".into())),
+    }
+
+    let mut font = Font {
+        format: Format::Woff2,
+        flavor,
+        major_version,
+        minor_version,
+        tables,
+        metadata: None,
+        private_data: None,
+    };
+
+    if let Some((hmtx_data, orig_length)) = transformed_hmtx {
+        let (Some(glyf), Some(loca), Some(index_format), Some(num_hmetrics)) = (
+            font.table(GLYF),
+            font.table(LOCA),
+            font.u16_field(HEAD, HEAD_INDEX_TO_LOC_FORMAT),
+            font.u16_field(HHEA, HHEA_NUMBER_OF_HMETRICS),
+        ) else {
+            return Err(WoffError::Malformed("hmtx transform without glyf, loca, head or hhea".into()));
+        };
+        let num_hmetrics = usize::from(num_hmetrics);
+        let x_mins = woff_glyf::x_mins(glyf, loca, index_format)?;
+        let hmtx = reconstruct_hmtx(hmtx_data, num_hmetrics, &x_mins)?;
+        if hmtx.len() != orig_length {
+            return Err(WoffError::Malformed("reconstructed hmtx has the wrong size".into()));
+        }
+        font.tables.push(Table { tag: HMTX, data: hmtx });
+    }
+
+    if meta_length != 0 {
+        font.metadata = Some(brotli_decompress(slice(data, meta_offset, meta_length)?, meta_orig_length)?);
+    }
+    if private_length != 0 {
+        font.private_data = Some(slice(data, private_offset, private_length)?.to_vec());
+    }
+    font.sort_tables()?;
+    Ok(font)
+}
+
+/// The optional WOFF2 table transforms of a font.
+struct Transforms {
+    glyf: Vec<u8>,
+    hmtx: Option<Vec<u8>>,
+}
+
+/// The transformed `glyf`, if it rebuilds the font's own `glyf` and `loca`,
+/// along with the transformed `hmtx` when some side bearings can be
+/// derived from the glyph bounding boxes.
+fn transforms(font: &Font) -> Option<Transforms> {
+    let glyf = font.table(GLYF)?;
+    let loca = font.table(LOCA)?;
+    let index_format = font.u16_field(HEAD, HEAD_INDEX_TO_LOC_FORMAT)?;
+    let transformed = woff_glyf::transform(glyf, loca, index_format).ok()?;
+    let (rebuilt_glyf, rebuilt_loca) = woff_glyf::reconstruct(&transformed).ok()?;
+    if rebuilt_glyf != glyf || rebuilt_loca != loca {
+        return None;
+    }
+    let x_mins = woff_glyf::x_mins(glyf, loca, index_format).ok()?;
+    Some(Transforms { glyf: transformed, hmtx: transformed_hmtx(font, &x_mins) })
+}
+
+/// The transformed `hmtx` (WOFF2 section 5.4), if either group of side
+/// bearings equals the glyphs' `xMin`.
+fn transformed_hmtx(font: &Font, x_mins: &[i16]) -> Option<Vec<u8>> {
+    let hmtx = font.table(HMTX)?;
+    let num_hmetrics = usize::from(font.u16_field(HHEA, HHEA_NUMBER_OF_HMETRICS)?);
+    let num_glyphs = x_mins.len();
+    if num_hmetrics == 0 || num_hmetrics > num_glyphs || hmtx.len() != num_hmetrics * 2 + num_glyphs * 2 {
+        return None;
+    }
+
+    let word = |at: usize| [hmtx[at], hmtx[at + 1]];
+    let advances: Vec<[u8; 2]> = (0..num_hmetrics).map(|i| word(i * 4)).collect();
+    let side_bearings: Vec<i16> = (0..num_glyphs)
+        .map(|i| i16::from_be_bytes(word(if i < num_hmetrics { i * 4 + 2 } else { num_hmetrics * 2 + i * 2 })))
+        .collect();
+    let proportional = side_bearings[..num_hmetrics] == x_mins[..num_hmetrics];
+    let monospace = side_bearings[num_hmetrics..] == x_mins[num_hmetrics..];
+    if !proportional && !monospace {
+        return None;
+    }
+
+    let mut out = vec![u8::from(proportional) | (u8::from(monospace) << 1)];
+    out.extend(advances.concat());
+    if !proportional {
+        out.extend(side_bearings[..num_hmetrics].iter().flat_map(|v| v.to_be_bytes()));
+    }
+    if !monospace {
+        out.extend(side_bearings[num_hmetrics..].iter().flat_map(|v| v.to_be_bytes()));
+    }
+    Some(out)
+}
+
+fn write_woff2(font: &Font, transforms: Option<&Transforms>) -> Result<Vec<u8>, WoffError> {
+    // tag order, except that loca has to follow glyf
+    let mut order: Vec<&Table> = font.tables.iter().filter(|t| t.tag != LOCA).collect();
+    if let Some(loca) = font.tables.iter().find(|t| t.tag == LOCA) {
+        let at = order.iter().position(|t| t.tag == GLYF).map_or(order.len(), |i| i + 1);
+        order.insert(at, loca);
+    }
+
+    let mut directory = Vec::new();
+    let mut stream = Vec::new();
+    for table in order {
+        // the reconstructed loca comes out of the glyf data
+        let transformed = match (table.tag, transforms) {
+            (GLYF, Some(t)) => Some(t.glyf.as_slice()),
+            (LOCA, Some(_)) => Some(&[][..]),
+            (HMTX, Some(t)) => t.hmtx.as_deref(),
+            _ => None,
+        };
+        let version = match (table.tag, transformed) {
+            (GLYF | LOCA, None) => GLYF_NULL_TRANSFORM,
+            (HMTX, Some(_)) => HMTX_TRANSFORM,
+            _ => 0,
+        };
+        match KNOWN_TAGS.iter().position(|known| **known == table.tag) {
+            Some(code) => directory.push((version << 6) | code as u8),
+            None => {
+                directory.push((version << 6) | ARBITRARY_TAG);
+                directory.extend_from_slice(&table.tag);
+            }
+        }
+        write_base128(&mut directory, table.data.len() as u32);
+        match transformed {
+            Some(data) => {
+                write_base128(&mut directory, data.len() as u32);
+                stream.extend_from_slice(data);
+            }
+            None => stream.extend_from_slice(&table.data),
+        }
+    }
+
+    let compressed = brotli_compress(&stream, BrotliEncoderMode::BROTLI_MODE_FONT)?;
+    let mut body = compressed.clone();
+    pad4(&mut body);
+    let header_len = WOFF2_HEADER_LEN + directory.len();
+
+    let (meta_offset, meta_length, meta_orig_length) = match &font.metadata {
+        Some(metadata) => {
+            let compressed_metadata = brotli_compress(metadata, BrotliEncoderMode::BROTLI_MODE_TEXT)?;
+            let offset = header_len + body.len();
+            body.extend_from_slice(&compressed_metadata);
+            pad4(&mut body);
+            (offset, compressed_metadata.len(), metadata.len())
+        }
+        None => (0, 0, 0),
+    };
+    let (private_offset, private_length) = match &font.private_data {
+        Some(private_data) => {
+            let offset = header_len + body.len();
+            body.extend_from_slice(private_data);
+            pad4(&mut body);
+            (offset, private_data.len())
+        }
+        None => (0, 0),
+    };
+
+    let mut out = Vec::with_capacity(header_len + body.len());
+    out.extend_from_slice(WOFF2_SIGNATURE);
+    out.extend_from_slice(&font.flavor.to_be_bytes());
+    push_u32(&mut out, header_len + body.len());
+    push_u16(&mut out, font.tables.len() as u16);
+    push_u16(&mut out, 0);
+    push_u32(&mut out, font.sfnt_size());
+    push_u32(&mut out, compressed.len());
+    push_u16(&mut out, font.major_version);
+    push_u16(&mut out, font.minor_version);
+    for value in [meta_offset, meta_length, meta_orig_length, private_offset, private_length] {
+        push_u32(&mut out, value);
+    }
+    out.extend(directory);
+    out.extend(body);
+    Ok(out)
+}
+
+fn encode_woff2(font: &Font) -> Result<Vec<u8>, WoffError> {
+    let mut best = write_woff2(font, None)?;
+    if let Some(transforms) = transforms(font) {
+        let transformed = write_woff2(font, Some(&transforms))?;
+        if transformed.len() < best.len() {
+            best = transformed;
+        }
+    }
+    Ok(best)
+}
+
+/// Decodes a WOFF or WOFF2 file.
+pub fn decode(data: &[u8]) -> Result<Font, WoffError> {
+    match data.get(..4) {
+        Some(signature) if signature == WOFF_SIGNATURE => parse_woff(data),
+        Some(signature) if signature == WOFF2_SIGNATURE => parse_woff2(data),
+        _ => Err(WoffError::NotWoff),
+    }
+}
+
+/// Re-encodes a WOFF or WOFF2 file in its own format.
+///
+/// Without `keep_metadata` the extended metadata and private data blocks
+/// are dropped; the font tables are never touched.
+pub fn optimize(data: &[u8], keep_metadata: bool) -> Result<Vec<u8>, WoffError> {
+    let mut font = decode(data)?;
+    if !keep_metadata {
+        font.metadata = None;
+        font.private_data = None;
+    }
+    let encoded = match font.format {
+        Format::Woff => write_woff(&font)?,
+        Format::Woff2 => encode_woff2(&font)?,
+    };
+    if !decode(&encoded)?.same_tables(&font) {
+        return Err(WoffError::Mismatch);
+    }
+    Ok(encoded)
+}
+
+/// Web font recompressor for WOFF and WOFF2.
+pub struct WoffProcessor;
+
+impl Processor for WoffProcessor {
+    fn name(&self) -> &'static str {
+        "WoffProcessor"
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["font/woff", "font/woff2", "application/font-woff"]
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &[".woff", ".woff2"]
+    }
+
+    fn recompress(&self, input: &str, output: &str, preserve_metadata: bool) -> Result<(), BridgeError> {
+        transform_file(
+            input,
+            output,
+            |data| optimize(data, preserve_metadata),
+            |e| match e {
+                WoffError::Mismatch => CHISEL_VORBIS_ERR_VERIFY,
+                _ => CHISEL_VORBIS_ERR_STREAM,
+            },
+        )
+    }
+
+    fn raw_equal(&self, a: &str, b: &str) -> Result<bool, BridgeError> {
+        let load = |path: &str| {
+            let data = std::fs::read(path)
+                .map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_OPEN_INPUT, format!("cannot open {path}: {e}")))?;
+            decode(&data).map_err(|e| BridgeError::new(CHISEL_VORBIS_ERR_STREAM, format!("{}: {}", error_kind(&e), e)))
+        };
+        Ok(load(a)?.same_tables(&load(b)?))
+    }
+}
@@ -0,0 +1,497 @@
+//! The WOFF2 `glyf`/`loca` transform (WOFF2 section 5.1).
+//!
+//! `transform` splits the outlines into the separate streams Brotli
+//! compresses much better than TrueType's interleaved layout. `reconstruct`
+//! rebuilds the tables the way the reference decoder does (same flag
+//! encoding, glyphs padded to four bytes), so the encoder can check that a
+//! font survives the round trip before using the transform.
+
+use crate::woff::{write_u255, Reader, WoffError};
+
+const ON_CURVE: u8 = 0x01;
+const X_SHORT: u8 = 0x02;
+const Y_SHORT: u8 = 0x04;
+const REPEAT: u8 = 0x08;
+const X_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+/// `optionFlags` bit: the overlapSimpleBitmap follows the streams.
+const OVERLAP_SIMPLE_BITMAP: u16 = 0x0001;
+
+/// An outline point in absolute coordinates.
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+    on_curve: bool,
+}
+
+/// The seven streams of a transformed `glyf` table, in file order.
+#[derive(Default)]
+struct Streams {
+    n_contour: Vec<u8>,
+    n_points: Vec<u8>,
+    flag: Vec<u8>,
+    glyph: Vec<u8>,
+    composite: Vec<u8>,
+    bbox: Vec<u8>,
+    instruction: Vec<u8>,
+}
+
+/// Reads the glyph offsets out of `loca`; there is one more than there are glyphs.
+pub fn glyph_offsets(loca: &[u8], index_format: u16) -> Result<Vec<usize>, WoffError> {
+    let offsets: Vec<usize> = match index_format {
+        0 if loca.len().is_multiple_of(2) => {
+            loca.chunks_exact(2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])) * 2).collect()
+        }
+        1 if loca.len().is_multiple_of(4) => {
+            loca.chunks_exact(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize).collect()
+        }
+        _ => return Err(WoffError::Malformed(format!("loca does not match index format {index_format}"))),
+    };
+    if offsets.is_empty() || offsets.windows(2).any(|w| w[0] > w[1]) {
+        return Err(WoffError::Malformed("loca offsets are not ascending".into()));
+    }
+    Ok(offsets)
+}
+
+/// Returns the `xMin` of every glyph, 0 for empty ones, as needed by the
+/// `hmtx` transform.
+pub fn x_mins(glyf: &[u8], loca: &[u8], index_format: u16) -> Result<Vec<i16>, WoffError> {
+    let offsets = glyph_offsets(loca, index_format)?;
+    offsets
+        .windows(2)
+        .map(|w| {
+            let glyph = glyf.get(w[0]..w[1]).ok_or(WoffError::Truncated)?;
+            Ok(match glyph.get(2..4) {
+                Some(b) => i16::from_be_bytes([b[0], b[1]]),
+                None => 0,
+            })
+        })
+        .collect()
+}
+
+/// Returns the length of the component records at the start of `data` and
+/// whether any component asks for instructions.
+fn composite_len(data: &[u8]) -> Result<(usize, bool), WoffError> {
+    let mut r = Reader::new(data);
+    let mut have_instructions = false;
+    loop {
+        let flags = r.u16()?;
+        have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+        let mut len = 2 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            len += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            len += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            len += 8;
+        }
+        r.skip(len)?;
+        if flags & MORE_COMPONENTS == 0 {
+            return Ok((r.position(), have_instructions));
+        }
+    }
+}
+
+fn bounding_box(points: &[Point]) -> [i16; 4] {
+    let Some(first) = points.first() else {
+        return [0; 4];
+    };
+    let mut bbox = [first.x, first.y, first.x, first.y];
+    for p in points {
+        bbox[0] = bbox[0].min(p.x);
+        bbox[1] = bbox[1].min(p.y);
+        bbox[2] = bbox[2].max(p.x);
+        bbox[3] = bbox[3].max(p.y);
+    }
+    bbox.map(|v| v as i16)
+}
+
+/// Appends one point delta as a WOFF2 triplet.
+fn write_triplet(streams: &mut Streams, on_curve: bool, dx: i32, dy: i32) {
+    let abs_x = dx.unsigned_abs();
+    let abs_y = dy.unsigned_abs();
+    let on_curve_bit = if on_curve { 0 } else { 0x80 };
+    let x_sign = u32::from(dx >= 0);
+    let y_sign = u32::from(dy >= 0);
+    let xy_signs = x_sign + 2 * y_sign;
+
+    let (flag, bytes): (u32, &[u32]) = if dx == 0 && abs_y < 1280 {
+        (((abs_y & 0xf00) >> 7) + y_sign, &[abs_y])
+    } else if dy == 0 && abs_x < 1280 {
+        (10 + ((abs_x & 0xf00) >> 7) + x_sign, &[abs_x])
+    } else if abs_x < 65 && abs_y < 65 {
+        let (x, y) = (abs_x - 1, abs_y - 1);
+        (20 + (x & 0x30) + ((y & 0x30) >> 2) + xy_signs, &[((x & 0xf) << 4) | (y & 0xf)])
+    } else if abs_x < 769 && abs_y < 769 {
+        let (x, y) = (abs_x - 1, abs_y - 1);
+        (84 + 12 * ((x & 0x300) >> 8) + ((y & 0x300) >> 6) + xy_signs, &[x, y])
+    } else if abs_x < 4096 && abs_y < 4096 {
+        (120 + xy_signs, &[abs_x >> 4, ((abs_x & 0xf) << 4) | (abs_y >> 8), abs_y])
+    } else {
+        (124 + xy_signs, &[abs_x >> 8, abs_x, abs_y >> 8, abs_y])
+    };
+    streams.flag.push(on_curve_bit | flag as u8);
+    streams.glyph.extend(bytes.iter().map(|&b| b as u8));
+}
+
+/// Reads one triplet; `flag` is the flag byte without its on-curve bit.
+fn read_triplet(flag: u8, glyph: &mut Reader) -> Result<(i32, i32), WoffError> {
+    fn with_sign(flag: u8, value: i32) -> i32 {
+        if flag & 1 != 0 {
+            value
+        } else {
+            -value
+        }
+    }
+
+    let f = i32::from(flag);
+    let mut byte = || glyph.u8().map(i32::from);
+    Ok(if flag < 10 {
+        (0, with_sign(flag, ((f & 14) << 7) + byte()?))
+    } else if flag < 20 {
+        (with_sign(flag, (((f - 10) & 14) << 7) + byte()?), 0)
+    } else if flag < 84 {
+        let b0 = f - 20;
+        let b1 = byte()?;
+        (with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)), with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+    } else if flag < 120 {
+        let b0 = f - 84;
+        let (b1, b2) = (byte()?, byte()?);
+        (with_sign(flag, 1 + ((b0 / 12) << 8) + b1), with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2))
+    } else if flag < 124 {
+        let (b1, b2, b3) = (byte()?, byte()?, byte()?);
+        (with_sign(flag, (b1 << 4) + (b2 >> 4)), with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3))
+    } else {
+        let (b1, b2, b3, b4) = (byte()?, byte()?, byte()?, byte()?);
+        (with_sign(flag, (b1 << 8) + b2), with_sign(flag >> 1, (b3 << 8) + b4))
+    })
+}
+
+/// Splits a simple glyph into the streams. Returns the stored bounding box
+/// when it differs from the one computed from the points, and whether the
+/// first point carries the overlap flag.
+fn transform_simple(
+    glyph: &[u8],
+    n_contours: usize,
+    streams: &mut Streams,
+) -> Result<(Option<[i16; 4]>, bool), WoffError> {
+    let mut r = Reader::new(glyph);
+    r.skip(2)?;
+    let stored_bbox = [r.i16()?, r.i16()?, r.i16()?, r.i16()?];
+
+    let mut n_points = 0usize;
+    for _ in 0..n_contours {
+        let end = usize::from(r.u16()?) + 1;
+        let count = end
+            .checked_sub(n_points)
+            .ok_or_else(|| WoffError::Malformed("contour end points are not ascending".into()))?;
+        write_u255(&mut streams.n_points, count as u16);
+        n_points = end;
+    }
+    let instruction_len = r.u16()?;
+    let instructions = r.bytes(usize::from(instruction_len))?;
+
+    let mut flags = Vec::with_capacity(n_points);
+    while flags.len() < n_points {
+        let flag = r.u8()?;
+        let repeat = if flag & REPEAT != 0 { usize::from(r.u8()?) } else { 0 };
+        flags.extend(std::iter::repeat_n(flag, repeat + 1));
+    }
+    if flags.len() != n_points {
+        return Err(WoffError::Malformed("glyph flags overrun the point count".into()));
+    }
+
+    let mut read_deltas = |short: u8, same: u8| -> Result<Vec<i32>, WoffError> {
+        flags
+            .iter()
+            .map(|&flag| {
+                Ok(if flag & short != 0 {
+                    let v = i32::from(r.u8()?);
+                    if flag & same != 0 {
+                        v
+                    } else {
+                        -v
+                    }
+                } else if flag & same != 0 {
+                    0
+                } else {
+                    i32::from(r.i16()?)
+                })
+            })
+            .collect()
+    };
+    let dxs = read_deltas(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let dys = read_deltas(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let mut points = Vec::with_capacity(n_points);
+    let (mut x, mut y) = (0, 0);
+    for ((&flag, &dx), &dy) in flags.iter().zip(&dxs).zip(&dys) {
+        x += dx;
+        y += dy;
+        points.push(Point { x, y, on_curve: flag & ON_CURVE != 0 });
+        write_triplet(streams, flag & ON_CURVE != 0, dx, dy);
+    }
+    write_u255(&mut streams.glyph, instruction_len);
+    streams.instruction.extend_from_slice(instructions);
+
+    let explicit_bbox = (bounding_box(&points) != stored_bbox).then_some(stored_bbox);
+    let overlap = flags.first().is_some_and(|&f| f & OVERLAP_SIMPLE != 0);
+    Ok((explicit_bbox, overlap))
+}
+
+/// Splits a composite glyph into the streams, returning its bounding box.
+fn transform_composite(glyph: &[u8], streams: &mut Streams) -> Result<[i16; 4], WoffError> {
+    let mut r = Reader::new(glyph);
+    r.skip(2)?;
+    let bbox = [r.i16()?, r.i16()?, r.i16()?, r.i16()?];
+
+    let (len, have_instructions) = composite_len(r.rest())?;
+    streams.composite.extend_from_slice(r.bytes(len)?);
+    if have_instructions {
+        let instruction_len = r.u16()?;
+        write_u255(&mut streams.glyph, instruction_len);
+        streams.instruction.extend_from_slice(r.bytes(usize::from(instruction_len))?);
+    }
+    Ok(bbox)
+}
+
+/// Builds the transformed `glyf` table out of `glyf` and `loca`.
+pub fn transform(glyf: &[u8], loca: &[u8], index_format: u16) -> Result<Vec<u8>, WoffError> {
+    let offsets = glyph_offsets(loca, index_format)?;
+    let num_glyphs = offsets.len() - 1;
+    let num_glyphs_u16 =
+        u16::try_from(num_glyphs).map_err(|_| WoffError::Malformed("too many glyphs in loca".into()))?;
+
+    let mut streams = Streams::default();
+    let mut bbox_bitmap = vec![0u8; num_glyphs.div_ceil(32) * 4];
+    let mut bboxes = Vec::new();
+    let mut overlap_bitmap = vec![0u8; num_glyphs.div_ceil(8)];
+    let mut has_overlap = false;
+
+    for (i, range) in offsets.windows(2).enumerate() {
+        let glyph = glyf.get(range[0]..range[1]).ok_or(WoffError::Truncated)?;
+        let n_contours = match glyph.get(..2) {
+            Some(b) => i16::from_be_bytes([b[0], b[1]]),
+            None => 0,
+        };
+        streams.n_contour.extend_from_slice(&n_contours.to_be_bytes());
+
+        let explicit_bbox = match n_contours {
+            0 => None,
+            n if n < 0 => Some(transform_composite(glyph, &mut streams)?),
+            n => {
+                let (bbox, overlap) = transform_simple(glyph, n as usize, &mut streams)?;
+                if overlap {
+                    overlap_bitmap[i >> 3] |= 0x80 >> (i & 7);
+                    has_overlap = true;
+                }
+                bbox
+            }
+        };
+        if let Some(bbox) = explicit_bbox {
+            bbox_bitmap[i >> 3] |= 0x80 >> (i & 7);
+            bboxes.extend(bbox.iter().flat_map(|v| v.to_be_bytes()));
+        }
+    }
+    streams.bbox = bbox_bitmap;
+    streams.bbox.extend(bboxes);
+
+    let option_flags = if has_overlap { OVERLAP_SIMPLE_BITMAP } else { 0 };
+    let mut out = Vec::new();
+    for field in [0, option_flags, num_glyphs_u16, index_format] {
+        out.extend_from_slice(&field.to_be_bytes());
+    }
+    let all = [
+        &streams.n_contour,
+        &streams.n_points,
+        &streams.flag,
+        &streams.glyph,
+        &streams.composite,
+        &streams.bbox,
+        &streams.instruction,
+    ];
+    for stream in all {
+        out.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+    }
+    for stream in all {
+        out.extend_from_slice(stream);
+    }
+    if has_overlap {
+        out.extend(overlap_bitmap);
+    }
+    Ok(out)
+}
+
+/// Writes the points of a simple glyph the way the reference decoder does.
+fn store_points(out: &mut Vec<u8>, points: &[Point], overlap: bool) {
+    fn delta(d: i32, short: u8, same_or_positive: u8, coords: &mut Vec<u8>) -> u8 {
+        if d == 0 {
+            same_or_positive
+        } else if (-255..=255).contains(&d) {
+            coords.push(d.unsigned_abs() as u8);
+            short | if d > 0 { same_or_positive } else { 0 }
+        } else {
+            coords.extend_from_slice(&(d as i16).to_be_bytes());
+            0
+        }
+    }
+
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let mut last_flag = None;
+    let mut repeat = 0u8;
+    let (mut last_x, mut last_y) = (0, 0);
+
+    for (i, p) in points.iter().enumerate() {
+        let mut flag = if p.on_curve { ON_CURVE } else { 0 };
+        if overlap && i == 0 {
+            flag |= OVERLAP_SIMPLE;
+        }
+        flag |= delta(p.x - last_x, X_SHORT, X_SAME_OR_POSITIVE, &mut xs);
+        flag |= delta(p.y - last_y, Y_SHORT, Y_SAME_OR_POSITIVE, &mut ys);
+
+        if last_flag == Some(flag) && repeat != u8::MAX {
+            if let Some(previous) = flags.last_mut() {
+                *previous |= REPEAT;
+            }
+            repeat += 1;
+        } else {
+            if repeat != 0 {
+                flags.push(repeat);
+            }
+            flags.push(flag);
+            repeat = 0;
+        }
+        last_flag = Some(flag);
+        (last_x, last_y) = (p.x, p.y);
+    }
+    if repeat != 0 {
+        flags.push(repeat);
+    }
+    out.extend(flags);
+    out.extend(xs);
+    out.extend(ys);
+}
+
+/// Rebuilds `glyf` and `loca` from a transformed `glyf` table.
+pub fn reconstruct(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), WoffError> {
+    let mut r = Reader::new(data);
+    r.skip(2)?;
+    let option_flags = r.u16()?;
+    let num_glyphs = usize::from(r.u16()?);
+    let index_format = r.u16()?;
+    let mut sizes = [0usize; 7];
+    for size in &mut sizes {
+        *size = r.u32()? as usize;
+    }
+    let mut n_contour = Reader::new(r.bytes(sizes[0])?);
+    let mut n_points = Reader::new(r.bytes(sizes[1])?);
+    let mut flag_stream = Reader::new(r.bytes(sizes[2])?);
+    let mut glyph_stream = Reader::new(r.bytes(sizes[3])?);
+    let mut composite = Reader::new(r.bytes(sizes[4])?);
+    let mut bbox_stream = Reader::new(r.bytes(sizes[5])?);
+    let mut instruction = Reader::new(r.bytes(sizes[6])?);
+    let bbox_bitmap = bbox_stream.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let overlap_bitmap = if option_flags & OVERLAP_SIMPLE_BITMAP != 0 {
+        Some(r.bytes(num_glyphs.div_ceil(8))?)
+    } else {
+        None
+    };
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    offsets.push(0);
+    for i in 0..num_glyphs {
+        let bit = 0x80 >> (i & 7);
+        let has_bbox = bbox_bitmap[i >> 3] & bit != 0;
+        let n_contours = n_contour.i16()?;
+        let start = glyf.len();
+
+        if n_contours == 0 {
+            if has_bbox {
+                return Err(WoffError::Malformed(format!("empty glyph {i} has a bounding box")));
+            }
+        } else if n_contours < 0 {
+            if !has_bbox {
+                return Err(WoffError::Malformed(format!("composite glyph {i} has no bounding box")));
+            }
+            let bbox = bbox_stream.bytes(8)?;
+            let (len, have_instructions) = composite_len(composite.rest())?;
+            glyf.extend_from_slice(&n_contours.to_be_bytes());
+            glyf.extend_from_slice(bbox);
+            glyf.extend_from_slice(composite.bytes(len)?);
+            if have_instructions {
+                let instruction_len = glyph_stream.u255()?;
+                glyf.extend_from_slice(&instruction_len.to_be_bytes());
+                glyf.extend_from_slice(instruction.bytes(usize::from(instruction_len))?);
+            }
+        } else {
+            let mut end_points = Vec::with_capacity(n_contours as usize);
+            let mut total = 0usize;
+            for _ in 0..n_contours {
+                total += usize::from(n_points.u255()?);
+                let end = total
+                    .checked_sub(1)
+                    .and_then(|e| u16::try_from(e).ok())
+                    .ok_or_else(|| WoffError::Malformed(format!("bad point count in glyph {i}")))?;
+                end_points.push(end);
+            }
+
+            let mut points = Vec::with_capacity(total);
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..total {
+                let flag = flag_stream.u8()?;
+                let (dx, dy) = read_triplet(flag & 0x7f, &mut glyph_stream)?;
+                x += dx;
+                y += dy;
+                points.push(Point { x, y, on_curve: flag & 0x80 == 0 });
+            }
+            let instruction_len = glyph_stream.u255()?;
+            let bbox = if has_bbox {
+                let b = bbox_stream.bytes(8)?;
+                [0, 2, 4, 6].map(|j| i16::from_be_bytes([b[j], b[j + 1]]))
+            } else {
+                bounding_box(&points)
+            };
+            let overlap = overlap_bitmap.is_some_and(|bitmap| bitmap[i >> 3] & bit != 0);
+
+            glyf.extend_from_slice(&n_contours.to_be_bytes());
+            glyf.extend(bbox.iter().flat_map(|v| v.to_be_bytes()));
+            glyf.extend(end_points.iter().flat_map(|v| v.to_be_bytes()));
+            glyf.extend_from_slice(&instruction_len.to_be_bytes());
+            glyf.extend_from_slice(instruction.bytes(usize::from(instruction_len))?);
+            store_points(&mut glyf, &points, overlap);
+        }
+
+        if glyf.len() > start {
+            glyf.resize(glyf.len().next_multiple_of(4), 0);
+        }
+        offsets.push(glyf.len());
+    }
+
+    let loca = match index_format {
+        0 => {
+            let mut loca = Vec::with_capacity(offsets.len() * 2);
+            for offset in offsets {
+                let half = u16::try_from(offset / 2)
+                    .map_err(|_| WoffError::Malformed("glyf too large for short loca offsets".into()))?;
+                loca.extend_from_slice(&half.to_be_bytes());
+            }
+            loca
+        }
+        1 => offsets.iter().flat_map(|&o| (o as u32).to_be_bytes()).collect(),
+        _ => return Err(WoffError::Malformed(format!("unknown loca format {index_format}"))),
+    };
+    Ok((glyf, loca))
+}
@@ -7,8 +7,17 @@
 #include "../../include/audio_metadata_util.hpp"
 #include "../../include/file_utils.hpp"
 #include "../../include/random_utils.hpp"
+#include <algorithm>
+#include <array>
+#include <cstdint>
+#include <cstring>
+#include <fstream>
+#include <optional>
+#include <span>
 #include <stdexcept>
 #include <filesystem>
+#include <string>
+#include <vector>
 #include "file_type.hpp"
 
 namespace chisel {
@@ -18,17 +27,357 @@ static const char* processor_tag() {
     return "AiffProcessor";
 }
 
+namespace {
+
+    constexpr std::size_t COPY_BLOCK = 1 << 20;
+
+    using Bytes = std::vector<unsigned char>;
+
+    /**
+     * @brief One chunk of the FORM. The payload of `SSND` is not loaded.
+     */
+    struct Chunk {
+        std::string id;
+        std::uint64_t offset = 0; ///< Payload position in the file
+        std::uint64_t size = 0;   ///< Payload size, without the pad byte
+        Bytes payload;
+    };
+
+    /**
+     * @brief Sample layout described by the COMM chunk.
+     */
+    struct SampleFormat {
+        std::uint16_t channels = 0;
+        std::uint32_t frames = 0;
+        std::uint16_t bits = 0;
+        std::array<unsigned char, 10> rate{}; ///< 80-bit IEEE extended, compared as stored
+        std::string compression = "NONE";
+
+        [[nodiscard]] std::size_t sample_bytes() const { return (bits + 7u) / 8u; }
+
+        /// True for integer PCM: AIFF, or AIFF-C with an uncompressed encoding
+        [[nodiscard]] bool pcm() const {
+            return compression == "NONE" || compression == "twos" || compression == "sowt";
+        }
+
+        [[nodiscard]] bool little_endian() const { return compression == "sowt" && sample_bytes() > 1; }
+    };
+
+    struct AiffLayout {
+        bool aifc = false;
+        std::vector<Chunk> chunks;
+        SampleFormat format;
+        std::optional<std::size_t> comm;
+        std::optional<std::size_t> ssnd;
+        std::uint64_t samples_offset = 0; ///< First sample byte, after the SSND offset padding
+        std::uint64_t samples_size = 0;   ///< Sample bytes, trimmed to the frame count for PCM
+        std::uint64_t trailing_offset = 0; ///< End of the FORM chunk
+        std::uint64_t trailing_size = 0;   ///< Bytes past the FORM chunk (e.g. a trailing ID3 tag)
+    };
+
+    std::uint16_t be16_at(const unsigned char* p) {
+        return static_cast<std::uint16_t>(p[0] << 8 | p[1]);
+    }
+
+    std::uint32_t be32_at(const unsigned char* p) {
+        return static_cast<std::uint32_t>(p[0]) << 24 | static_cast<std::uint32_t>(p[1]) << 16 |
+               static_cast<std::uint32_t>(p[2]) << 8 | p[3];
+    }
+
+    void put_be32(Bytes& out, std::uint32_t v) {
+        for (int i = 3; i >= 0; --i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    bool valid_chunk_id(const unsigned char* p) {
+        return std::all_of(p, p + 4, [](unsigned char c) { return c >= 0x20 && c <= 0x7E; });
+    }
+
+    bool read_at(std::ifstream& in, std::uint64_t offset, unsigned char* dst, std::size_t size) {
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        in.read(reinterpret_cast<char*>(dst), static_cast<std::streamsize>(size));
+        return static_cast<std::size_t>(in.gcount()) == size;
+    }
+
+    /**
+     * @brief Copies `size` bytes from `in` at `offset` to `out`.
+     */
+    void copy_range(std::ifstream& in, std::uint64_t offset, std::uint64_t size, std::ofstream& out) {
+        std::vector<char> buf(COPY_BLOCK);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        while (size > 0) {
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(size, buf.size()));
+            if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                throw std::runtime_error("AiffProcessor: read failed");
+            }
+            out.write(buf.data(), static_cast<std::streamsize>(n));
+            size -= n;
+        }
+    }
+
+    /**
+     * @brief Walks the chunks of an AIFF or AIFF-C file.
+     * @throws std::runtime_error if the file is not a well-formed AIFF/AIFF-C file.
+     */
+    AiffLayout parse_aiff(std::ifstream& in, const fs::path& path) {
+        AiffLayout layout;
+        const std::uint64_t file_size = fs::file_size(path);
+
+        unsigned char header[12];
+        if (!read_at(in, 0, header, sizeof(header)) || std::memcmp(header, "FORM", 4) != 0) {
+            throw std::runtime_error("AiffProcessor: not an IFF FORM file");
+        }
+        if (std::memcmp(header + 8, "AIFC", 4) == 0) {
+            layout.aifc = true;
+        } else if (std::memcmp(header + 8, "AIFF", 4) != 0) {
+            throw std::runtime_error("AiffProcessor: not an AIFF/AIFF-C file");
+        }
+
+        const std::uint64_t end = std::min<std::uint64_t>(file_size, 8 + std::uint64_t{be32_at(header + 4)});
+        layout.trailing_offset = end;
+        layout.trailing_size = file_size - end;
+        std::uint64_t pos = 12;
+        unsigned char ch[8];
+        while (pos + 8 <= end && read_at(in, pos, ch, 8) && valid_chunk_id(ch)) {
+            Chunk chunk;
+            chunk.id.assign(reinterpret_cast<const char*>(ch), 4);
+            chunk.offset = pos + 8;
+            chunk.size = be32_at(ch + 4);
+            if (chunk.size > end - chunk.offset) {
+                throw std::runtime_error("AiffProcessor: chunk '" + chunk.id + "' is truncated");
+            }
+
+            if (chunk.id == "SSND") {
+                if (layout.ssnd || chunk.size < 8) throw std::runtime_error("AiffProcessor: bad SSND chunk");
+                unsigned char ssnd[8];
+                if (!read_at(in, chunk.offset, ssnd, sizeof(ssnd))) throw std::runtime_error("AiffProcessor: read failed");
+                const std::uint32_t padding = be32_at(ssnd);
+                if (padding > chunk.size - 8) throw std::runtime_error("AiffProcessor: SSND offset out of range");
+                layout.samples_offset = chunk.offset + 8 + padding;
+                layout.samples_size = chunk.size - 8 - padding;
+                layout.ssnd = layout.chunks.size();
+            } else {
+                chunk.payload.resize(chunk.size);
+                if (!read_at(in, chunk.offset, chunk.payload.data(), chunk.payload.size())) {
+                    throw std::runtime_error("AiffProcessor: read failed in chunk '" + chunk.id + "'");
+                }
+                if (chunk.id == "COMM") {
+                    if (layout.comm || chunk.size < (layout.aifc ? 22u : 18u)) {
+                        throw std::runtime_error("AiffProcessor: bad COMM chunk");
+                    }
+                    const unsigned char* c = chunk.payload.data();
+                    layout.format.channels = be16_at(c);
+                    layout.format.frames = be32_at(c + 2);
+                    layout.format.bits = be16_at(c + 6);
+                    std::copy_n(c + 8, 10, layout.format.rate.begin());
+                    if (layout.aifc) layout.format.compression.assign(reinterpret_cast<const char*>(c + 18), 4);
+                    layout.comm = layout.chunks.size();
+                }
+            }
+            pos = chunk.offset + chunk.size + (chunk.size % 2);
+            layout.chunks.push_back(std::move(chunk));
+        }
+
+        if (!layout.comm || layout.format.channels == 0 || layout.format.bits == 0 || layout.format.bits > 32) {
+            throw std::runtime_error("AiffProcessor: missing or invalid COMM chunk");
+        }
+        if (layout.format.pcm()) {
+            // bytes past the last sample frame are not audio
+            const std::uint64_t frame_bytes = std::uint64_t{layout.format.channels} * layout.format.sample_bytes();
+            layout.samples_size = std::min(layout.samples_size, frame_bytes * layout.format.frames);
+        }
+        return layout;
+    }
+
+    /**
+     * @brief Reads sample bytes in blocks, turning little-endian (`sowt`) samples to big-endian.
+     */
+    class SampleReader {
+    public:
+        SampleReader(std::ifstream& in, const AiffLayout& layout)
+            : in_(in), remaining_(layout.samples_size),
+              swap_width_(layout.format.little_endian() ? layout.format.sample_bytes() : 0) {
+            in_.clear();
+            in_.seekg(static_cast<std::streamoff>(layout.samples_offset));
+        }
+
+        /// Next block of big-endian sample bytes; empty at the end.
+        std::span<const char> next() {
+            // a multiple of 12 holds whole samples of 1 to 4 bytes
+            const std::size_t block = COPY_BLOCK - COPY_BLOCK % 12;
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(remaining_, block));
+            buf_.resize(n);
+            if (n > 0 && !in_.read(buf_.data(), static_cast<std::streamsize>(n))) {
+                throw std::runtime_error("AiffProcessor: read failed in SSND chunk");
+            }
+            if (swap_width_ > 1) {
+                for (std::size_t i = 0; i + swap_width_ <= n; i += swap_width_) {
+                    std::reverse(buf_.begin() + static_cast<std::ptrdiff_t>(i),
+                                 buf_.begin() + static_cast<std::ptrdiff_t>(i + swap_width_));
+                }
+            }
+            remaining_ -= n;
+            return {buf_.data(), n};
+        }
+
+    private:
+        std::ifstream& in_;
+        std::uint64_t remaining_;
+        std::size_t swap_width_;
+        std::vector<char> buf_;
+    };
+
+    /**
+     * @brief Rewrites a text chunk (NAME, AUTH, "(c) ", ANNO) without surplus NUL terminators.
+     *
+     * Trailing spaces are part of the value and are kept.
+     * @return False if the text is empty.
+     */
+    bool compact_text(Bytes& payload) {
+        while (!payload.empty() && payload.back() == 0) payload.pop_back();
+        return !payload.empty();
+    }
+
+    /**
+     * @brief Drops the empty comments of a COMT chunk.
+     * @return False if no comment is left.
+     */
+    bool compact_comments(Bytes& payload) {
+        if (payload.size() < 2) return false;
+        Bytes out(2, 0);
+        std::uint16_t kept = 0;
+        std::size_t pos = 2;
+        for (std::uint16_t i = 0, count = be16_at(payload.data()); i < count && pos + 8 <= payload.size(); ++i) {
+            const std::size_t length = be16_at(payload.data() + pos + 6);
+            if (length > payload.size() - pos - 8) break;
+            const std::size_t total = 8 + length + (length % 2);
+            if (length > 0) {
+                out.insert(out.end(), payload.begin() + static_cast<std::ptrdiff_t>(pos),
+                           payload.begin() + static_cast<std::ptrdiff_t>(std::min(pos + total, payload.size())));
+                if (out.size() % 2 != 0) out.push_back(0);
+                ++kept;
+            }
+            pos += total;
+        }
+        out[0] = static_cast<unsigned char>(kept >> 8);
+        out[1] = static_cast<unsigned char>(kept);
+        payload = std::move(out);
+        return kept > 0;
+    }
+
+} // namespace
+
 void AiffProcessor::recompress(const fs::path& input,
                               const fs::path& output,
-                              bool /*preserve_metadata*/) {
-    Logger::log(LogLevel::Error, "Recompress called on AiffProcessor placeholder.", processor_tag());
+                              bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "AIFF: Optimizing chunks of: " + input.string(), processor_tag());
+
+    std::ifstream in(input, std::ios::binary);
+    if (!in) {
+        throw std::runtime_error("AiffProcessor: cannot open " + input.string());
+    }
+
+    AiffLayout layout;
+    try {
+        layout = parse_aiff(in, input);
+        if (!layout.ssnd && layout.format.frames != 0) {
+            throw std::runtime_error("AiffProcessor: missing SSND chunk");
+        }
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Warning, std::string("AIFF: Left as-is: ") + e.what(), processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
+    }
+
+    // uncompressed AIFF-C is written as plain AIFF, which needs neither FVER
+    // nor the compression fields of COMM
+    const bool to_aiff = !layout.aifc || layout.format.pcm();
+
+    std::vector<const Chunk*> kept;
+    std::size_t dropped = 0;
+    for (auto& chunk : layout.chunks) {
+        bool keep = true;
+        if (chunk.id == "COMM") {
+            if (to_aiff) chunk.payload.resize(18);
+        } else if (chunk.id == "FVER") {
+            keep = !to_aiff;
+        } else if (!preserve_metadata && chunk.id != "SSND") {
+            keep = false;
+        } else if (chunk.id == "NAME" || chunk.id == "AUTH" || chunk.id == "(c) " || chunk.id == "ANNO") {
+            keep = compact_text(chunk.payload);
+        } else if (chunk.id == "COMT") {
+            keep = compact_comments(chunk.payload);
+        } else if (chunk.id == "ID3 " || chunk.id == "id3 ") {
+            AudioMetadataUtil::compactId3v2(chunk.payload);
+        }
+        if (keep) {
+            kept.push_back(&chunk);
+        } else {
+            ++dropped;
+        }
+    }
 
-    std::error_code ec;
-    fs::copy_file(input, output, fs::copy_options::overwrite_existing, ec);
+    // SSND keeps its offset/blockSize fields, both zero
+    const std::uint64_t ssnd_size = 8 + layout.samples_size;
+    std::uint64_t form_size = 4;
+    for (const Chunk* chunk : kept) {
+        const std::uint64_t size = chunk->id == "SSND" ? ssnd_size : chunk->payload.size();
+        form_size += 8 + size + (size % 2);
+    }
+    if (form_size > 0xFFFFFFFFu) {
+        Logger::log(LogLevel::Warning, "AIFF: Output would exceed the FORM size limit, left as-is", processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
+    }
 
-    if (ec) {
-        throw std::runtime_error("Placeholder recompress failed to copy file.");
+    std::ofstream out(output, std::ios::binary | std::ios::trunc);
+    if (!out) {
+        throw std::runtime_error("AiffProcessor: cannot create " + output.string());
     }
+    auto write = [&out](const Bytes& bytes) {
+        out.write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+    };
+
+    Bytes head = {'F', 'O', 'R', 'M'};
+    put_be32(head, static_cast<std::uint32_t>(form_size));
+    const char* form_type = to_aiff ? "AIFF" : "AIFC";
+    head.insert(head.end(), form_type, form_type + 4);
+    write(head);
+    for (const Chunk* chunk : kept) {
+        const std::uint64_t size = chunk->id == "SSND" ? ssnd_size : chunk->payload.size();
+        Bytes chunk_header(chunk->id.begin(), chunk->id.end());
+        put_be32(chunk_header, static_cast<std::uint32_t>(size));
+        if (chunk->id == "SSND") {
+            chunk_header.resize(chunk_header.size() + 8, 0);
+            write(chunk_header);
+            // sowt samples are byte-swapped along with the switch to AIFF
+            if (to_aiff) {
+                SampleReader reader(in, layout);
+                for (auto block = reader.next(); !block.empty(); block = reader.next()) {
+                    out.write(block.data(), static_cast<std::streamsize>(block.size()));
+                }
+            } else {
+                copy_range(in, layout.samples_offset, layout.samples_size, out);
+            }
+        } else {
+            write(chunk_header);
+            write(chunk->payload);
+        }
+        if (size % 2 != 0) out.put('\0');
+    }
+    // bytes after the FORM chunk (often an appended ID3 tag) count as metadata
+    if (preserve_metadata && layout.trailing_size > 0) {
+        copy_range(in, layout.trailing_offset, layout.trailing_size, out);
+    }
+
+    out.close();
+    if (!out) {
+        throw std::runtime_error("AiffProcessor: write failed for " + output.string());
+    }
+    Logger::log(LogLevel::Debug, "AIFF: " + std::to_string(dropped) + " chunks dropped" +
+                (layout.aifc && to_aiff ? ", AIFF-C '" + layout.format.compression + "' written as AIFF" : ""),
+                processor_tag());
 }
 
 std::optional<ExtractedContent> AiffProcessor::prepare_extraction(const fs::path& input_path) {
@@ -87,4 +436,41 @@ std::filesystem::path AiffProcessor::finalize_extraction(const ExtractedContent
     return final_temp_path;
 }
 
-} // namespace chisel
\ No newline at end of file
+bool AiffProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    std::ifstream in_a(a, std::ios::binary);
+    std::ifstream in_b(b, std::ios::binary);
+    if (!in_a || !in_b) return false;
+
+    AiffLayout aiff_a;
+    AiffLayout aiff_b;
+    try {
+        aiff_a = parse_aiff(in_a, a);
+        aiff_b = parse_aiff(in_b, b);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("AIFF: Sample comparison unavailable: ") + e.what(), processor_tag());
+        return files_equal(a, b);
+    }
+
+    const SampleFormat& fa = aiff_a.format;
+    const SampleFormat& fb = aiff_b.format;
+    if (fa.channels != fb.channels || fa.frames != fb.frames || fa.bits != fb.bits || fa.rate != fb.rate ||
+        aiff_a.samples_size != aiff_b.samples_size) {
+        return false;
+    }
+    // compressed AIFF-C data only compares equal to the same encoding
+    if (!(fa.pcm() && fb.pcm()) && fa.compression != fb.compression) {
+        return false;
+    }
+
+    SampleReader reader_a(in_a, aiff_a);
+    SampleReader reader_b(in_b, aiff_b);
+    for (auto block_a = reader_a.next(); !block_a.empty(); block_a = reader_a.next()) {
+        const auto block_b = reader_b.next();
+        if (block_a.size() != block_b.size() || !std::equal(block_a.begin(), block_a.end(), block_b.begin())) {
+            return false;
+        }
+    }
+    return true;
+}
+
+} // namespace chisel
@@ -5,176 +5,185 @@
 #include "../../include/ape_processor.hpp"
 #include "../../include/logger.hpp"
 #include "../../include/audio_metadata_util.hpp"
+#include "../../include/best_candidate.hpp"
 #include "../../include/file_utils.hpp"
+#include "../../include/md5.hpp"
 #include "../../include/random_utils.hpp"
+#include "../../include/stop_scope.hpp"
 #include <MACLib.h>
-#include <APETag.h>
 #include <vector>
 #include <stdexcept>
 #include <filesystem>
 #include <algorithm>
+#include <array>
 #include <cstring>
 #include <any>
+#include <iomanip>
+#include <memory>
+#include <span>
+#include <sstream>
+#include <utility>
 #include "file_type.hpp"
 
 namespace {
 
-/**
- * @brief Copies an APEv2 tag from an input file to an output file.
- * @param input The path to the source file with the APE tag.
- * @param output The path to the destination file.
- * @return True if the tag was copied successfully, false otherwise.
- */
-bool copy_apetag(const std::filesystem::path &input,
-                 const std::filesystem::path &output) {
-    try {
-        APE::CAPETag inTag(input.wstring().c_str(), true);
-        if (!inTag.GetAnalyzed())
-            return false;
-
-        APE::CAPETag outTag(output.wstring().c_str(), true);
-        outTag.ClearFields();
-        outTag.SetIgnoreReadOnly(true);
-
-        for (int i = 0;; ++i) {
-            APE::CAPETagField *field = inTag.GetTagField(i);
-            if (!field) break;
-
-            const APE::str_utfn *name = field->GetFieldName();
-            const char *value = field->GetFieldValue();
-            const int valueSize = field->GetFieldValueSize();
-            const int flags = field->GetFieldFlags();
-            if (!name || valueSize <= 0) continue;
-
-            const bool isBinary = (flags & TAG_FIELD_FLAG_DATA_TYPE_BINARY) != 0;
-
-            if (isBinary) {
-                outTag.SetFieldBinary(name, value, valueSize, flags);
-            } else {
-                outTag.SetFieldString(name, value, true, nullptr);
-            }
-        }
+namespace fs = std::filesystem;
 
-        return outTag.Save();
-    } catch (const std::exception& e) {
-        // log known exceptions
-        Logger::log(LogLevel::Warning, "Failed to copy APE tag: " + std::string(e.what()), "ape_processor");
-        return false;
-    } catch (...) {
-        // log unknown exceptions
-        Logger::log(LogLevel::Warning, "Failed to copy APE tag: Unknown exception caught", "ape_processor");
-        return false;
-    }
-}
+using DecompressPtr = std::unique_ptr<APE::IAPEDecompress>;
+using CompressPtr = std::unique_ptr<APE::IAPECompress>;
 
-} // namespace
+constexpr int kBlockFramesRequest = 16384;
 
-namespace chisel {
+// compression levels tried by recompress(), fastest first
+constexpr std::array<std::pair<int, const char*>, 4> kSearchLevels = {{
+    {APE_COMPRESSION_LEVEL_NORMAL, "normal"},
+    {APE_COMPRESSION_LEVEL_HIGH, "high"},
+    {APE_COMPRESSION_LEVEL_EXTRA_HIGH, "extra high"},
+    {APE_COMPRESSION_LEVEL_INSANE, "insane"},
+}};
 
-void ApeProcessor::recompress(const std::filesystem::path& input,
-                              const std::filesystem::path& output,
-                              bool preserve_metadata) {
-    Logger::log(LogLevel::Info, "Starting APE re-encoding: " + input.string(), "ape_processor");
+/// Audio parameters of an opened APE file.
+struct ApeFormat {
+    unsigned sample_rate = 0;
+    unsigned channels = 0;
+    unsigned bits_per_sample = 0;
+    int64_t total_blocks = 0;
 
-    if (std::filesystem::exists(output)) {
-        std::filesystem::remove(output);
+    [[nodiscard]] int block_align() const {
+        return static_cast<int>(channels * (bits_per_sample / 8));
     }
+};
 
+/**
+ * @brief Opens an APE file for decoding, with CRC checking.
+ * @throws std::runtime_error if MACLib cannot open the file or its parameters are invalid.
+ */
+DecompressPtr open_ape(const fs::path& file, ApeFormat& format) {
     int err = 0;
-    APE::IAPEDecompress *pDecompress =
-        CreateIAPEDecompress(input.wstring().c_str(),
-                             &err,
-                             true,
-                             true,
-                             false);
-    if (!pDecompress || err != ERROR_SUCCESS) {
-        if (pDecompress) delete pDecompress;
-        throw std::runtime_error("ApeProcessor: cannot create APE decompress");
+    DecompressPtr dec(CreateIAPEDecompress(file.wstring().c_str(),
+                                           &err,
+                                           true,  // read-only
+                                           true,  // analyze tag now
+                                           false));
+    if (!dec || err != ERROR_SUCCESS) {
+        throw std::runtime_error("APE open failed");
+    }
+
+    format.sample_rate     = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_SAMPLE_RATE));
+    format.channels        = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_CHANNELS));
+    format.bits_per_sample = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_BITS_PER_SAMPLE));
+    format.total_blocks    = dec->GetInfo(APE::IAPEDecompress::APE_INFO_TOTAL_BLOCKS);
+
+    if (format.channels == 0 || format.sample_rate == 0 || format.bits_per_sample == 0 ||
+        format.bits_per_sample % 8 != 0 || format.bits_per_sample > 32) {
+        throw std::runtime_error("Invalid APE parameters");
     }
+    return dec;
+}
+
+/**
+ * @brief Decodes every block of an opened APE file, passing the interleaved
+ * little-endian PCM bytes (as in a WAV file) to the callback.
+ * @throws std::runtime_error if decoding fails.
+ */
+template <typename Callback>
+void read_ape_blocks(APE::IAPEDecompress& dec, const ApeFormat& format, Callback&& callback) {
+    const int block_align = format.block_align();
+    std::vector<uint8_t> block(static_cast<size_t>(kBlockFramesRequest) * block_align);
 
-    const unsigned sample_rate     = static_cast<unsigned>(pDecompress->GetInfo(APE::IAPEDecompress::APE_INFO_SAMPLE_RATE));
-    const unsigned channels        = static_cast<unsigned>(pDecompress->GetInfo(APE::IAPEDecompress::APE_INFO_CHANNELS));
-    const unsigned bits_per_sample = static_cast<unsigned>(pDecompress->GetInfo(APE::IAPEDecompress::APE_INFO_BITS_PER_SAMPLE));
-    const int64_t total_frames     = pDecompress->GetInfo(APE::IAPEDecompress::APE_INFO_TOTAL_BLOCKS);
+    int64_t frames_processed = 0;
+    while (frames_processed < format.total_blocks) {
+        APE::int64 blocks_retrieved = 0;
+        const int rc = dec.GetData(block.data(), kBlockFramesRequest, &blocks_retrieved);
+        if (rc != ERROR_SUCCESS) {
+            throw std::runtime_error("APE decode failed");
+        }
+        if (blocks_retrieved <= 0) break;
 
-    if (channels == 0 || sample_rate == 0 || bits_per_sample == 0) {
-        delete pDecompress;
-        throw std::runtime_error("ApeProcessor: invalid APE file parameters");
+        callback(std::span<const uint8_t>(block.data(), static_cast<size_t>(blocks_retrieved) * block_align));
+        frames_processed += blocks_retrieved;
     }
+}
 
-    const int bytes_per_sample = static_cast<int>(bits_per_sample / 8);
-    const int block_align = static_cast<int>(channels) * bytes_per_sample;
-    constexpr int block_frames_request = 16384;
-    const int block_bytes = block_frames_request * block_align;
+/**
+ * @brief Re-encodes an APE file at the given compression level.
+ * @return Size of the output file.
+ * @throws std::runtime_error if the MACLib encoder or decoder fails.
+ */
+std::uintmax_t encode_ape(const fs::path& input, const fs::path& output, const int level) {
+    ApeFormat format;
+    const DecompressPtr dec = open_ape(input, format);
 
     APE::WAVEFORMATEX wfeAudioFormat{};
     FillWaveFormatEx(&wfeAudioFormat, WAVE_FORMAT_PCM,
-                     static_cast<APE::int32>(sample_rate),
-                     static_cast<unsigned short>(bits_per_sample),
-                     static_cast<unsigned short>(channels));
+                     static_cast<APE::int32>(format.sample_rate),
+                     static_cast<unsigned short>(format.bits_per_sample),
+                     static_cast<unsigned short>(format.channels));
 
-    APE::IAPECompress *pCompress = CreateIAPECompress();
-    if (!pCompress) {
-        delete pDecompress;
+    const CompressPtr compress(CreateIAPECompress());
+    if (!compress) {
         throw std::runtime_error("ApeProcessor: cannot create APE encoder");
     }
 
-    constexpr int level = APE_COMPRESSION_LEVEL_INSANE;
-    const APE::int64 maxAudioBytes = static_cast<APE::int64>(total_frames) * block_align;
-
-    int nRetVal = pCompress->Start(
-        output.wstring().c_str(),
-        &wfeAudioFormat,
-        false,
-        maxAudioBytes,
-        level,
-        NULL,
-        0
-    );
-
-    if (nRetVal != 0) {
-        APE_SAFE_DELETE(pCompress)
-        delete pDecompress;
+    const APE::int64 maxAudioBytes = static_cast<APE::int64>(format.total_blocks) * format.block_align();
+    if (compress->Start(output.wstring().c_str(), &wfeAudioFormat, false, maxAudioBytes, level, NULL, 0) != 0) {
         throw std::runtime_error("ApeProcessor: encoder start failed");
     }
 
-    std::vector<uint8_t> block(static_cast<size_t>(block_bytes));
-    int64_t frames_processed_total = 0;
+    try {
+        read_ape_blocks(*dec, format, [&](const std::span<const uint8_t> bytes) {
+            if (compress->AddData(const_cast<uint8_t*>(bytes.data()), static_cast<APE::int64>(bytes.size())) != 0) {
+                throw std::runtime_error("ApeProcessor: AddData failed");
+            }
+        });
+    } catch (...) {
+        compress->Finish(NULL, 0, 0);
+        throw;
+    }
 
-    while (frames_processed_total < total_frames) {
-        APE::int64 blocks_retrieved = 0;
-        int rc = pDecompress->GetData(block.data(), block_frames_request, &blocks_retrieved);
-        if (rc != ERROR_SUCCESS) {
-            pCompress->Finish(NULL, 0, 0);
-            APE_SAFE_DELETE(pCompress)
-            delete pDecompress;
-            throw std::runtime_error("ApeProcessor: decoding failed");
-        }
-        if (blocks_retrieved <= 0) break;
+    if (compress->Finish(NULL, 0, 0) != 0) {
+        throw std::runtime_error("ApeProcessor: Finish failed");
+    }
+    return fs::file_size(output);
+}
 
-        const size_t bytes_to_add = static_cast<size_t>(blocks_retrieved) * static_cast<size_t>(block_align);
-        int add_rc = pCompress->AddData(block.data(), static_cast<APE::int64>(bytes_to_add));
-        if (add_rc != 0) {
-            pCompress->Finish(NULL, 0, 0);
-            APE_SAFE_DELETE(pCompress)
-            delete pDecompress;
-            throw std::runtime_error("ApeProcessor: AddData failed");
-        }
+} // namespace
 
-        frames_processed_total += blocks_retrieved;
-    }
+namespace chisel {
+
+void ApeProcessor::recompress(const std::filesystem::path& input,
+                              const std::filesystem::path& output,
+                              bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "Starting APE re-encoding: " + input.string(), "ape_processor");
 
-    delete pDecompress;
+    if (std::filesystem::exists(output)) {
+        std::filesystem::remove(output);
+    }
 
-    if (pCompress->Finish(NULL, 0, 0) != 0) {
-        APE_SAFE_DELETE(pCompress)
-        throw std::runtime_error("ApeProcessor: Finish failed");
+    BestCandidate best(output, ".ape");
+    const char* chosen = nullptr;
+    for (const auto& [level, name] : kSearchLevels) {
+        if (stop_requested()) break;
+        const fs::path candidate = best.next_path();
+        std::uintmax_t size = 0;
+        try {
+            size = encode_ape(input, candidate, level);
+        } catch (const std::exception&) {
+            best.discard(candidate);
+            throw;
+        }
+        Logger::log(LogLevel::Debug, std::string("APE: Level ") + name + ": " + std::to_string(size) + " bytes", "ape_processor");
+        if (best.offer(candidate)) chosen = name;
     }
-    APE_SAFE_DELETE(pCompress)
+    if (best.empty()) {
+        throw std::runtime_error("ApeProcessor: re-encoding interrupted");
+    }
+    best.commit();
+    Logger::log(LogLevel::Debug, std::string("APE: Best level: ") + chosen, "ape_processor");
 
     if (preserve_metadata) {
-        if (!copy_apetag(input, output)) {
-            Logger::log(LogLevel::Debug, "APEv2 metadata copy skipped or failed", "ape_processor");
+        if (!AudioMetadataUtil::copyApeTag(input, output)) {
+            Logger::log(LogLevel::Warning, "APE: Failed to copy APEv2 tag", "ape_processor");
         }
     }
 
@@ -237,9 +246,18 @@ std::filesystem::path ApeProcessor::finalize_extraction(const ExtractedContent &
     return final_temp_path;
 }
 
-std::string ApeProcessor::get_raw_checksum(const std::filesystem::path&) const {
-    // TODO: implement checksum of raw APE data
-    return "";
+std::string ApeProcessor::get_raw_checksum(const std::filesystem::path& file_path) const {
+    ApeFormat format;
+    const DecompressPtr dec = open_ape(file_path, format);
+
+    Md5 md5;
+    read_ape_blocks(*dec, format, [&](const std::span<const uint8_t> bytes) { md5.update(bytes); });
+
+    std::ostringstream oss;
+    for (const uint8_t byte : md5.finish()) {
+        oss << std::hex << std::setw(2) << std::setfill('0') << static_cast<int>(byte);
+    }
+    return oss.str();
 }
 
 /**
@@ -254,65 +272,30 @@ std::vector<int32_t> decode_ape_pcm(const std::filesystem::path& file,
                                     unsigned& sample_rate,
                                     unsigned& channels,
                                     unsigned& bps) {
-    int err = 0;
-    APE::IAPEDecompress* dec = CreateIAPEDecompress(file.wstring().c_str(),
-                                                    &err,
-                                                    true,  // full header analysis
-                                                    true,  // check CRC
-                                                    false);
-    if (!dec || err != ERROR_SUCCESS) {
-        if (dec) delete dec;
-        throw std::runtime_error("APE open failed");
-    }
-
-    sample_rate = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_SAMPLE_RATE));
-    channels    = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_CHANNELS));
-    bps         = static_cast<unsigned>(dec->GetInfo(APE::IAPEDecompress::APE_INFO_BITS_PER_SAMPLE));
-    const int64_t total_blocks = dec->GetInfo(APE::IAPEDecompress::APE_INFO_TOTAL_BLOCKS);
-
-    if (channels == 0 || sample_rate == 0 || bps == 0) {
-        delete dec;
-        throw std::runtime_error("Invalid APE parameters");
-    }
-
-    const int bytes_per_sample =static_cast<int>(bps) / 8;
-    const int block_align = static_cast<int>(channels) * bytes_per_sample;
-    constexpr int block_frames_request = 16384;
-
+    ApeFormat format;
+    const DecompressPtr dec = open_ape(file, format);
+    sample_rate = format.sample_rate;
+    channels    = format.channels;
+    bps         = format.bits_per_sample;
+
+    // MACLib returns WAV-style samples: 8-bit unsigned, wider ones signed little-endian
+    const size_t bytes_per_sample = bps / 8;
     std::vector<int32_t> pcm;
-    std::vector<uint8_t> block(static_cast<size_t>(block_frames_request) * block_align);
-
-    int64_t frames_processed = 0;
-    while (frames_processed < total_blocks) {
-        APE::int64 blocks_retrieved = 0;
-        int rc = dec->GetData(block.data(), block_frames_request, &blocks_retrieved);
-        if (rc != ERROR_SUCCESS) {
-            delete dec;
-            throw std::runtime_error("APE decode failed");
-        }
-        if (blocks_retrieved <= 0) break;
-
-        const size_t bytes_to_copy = static_cast<size_t>(blocks_retrieved) * block_align;
-        const auto* src16 = reinterpret_cast<const int16_t*>(block.data());
-        const auto* src32 = reinterpret_cast<const int32_t*>(block.data());
-
-        if (bps == 16) {
-            for (size_t i = 0; i < bytes_to_copy / 2; ++i) {
-                pcm.push_back(static_cast<int32_t>(src16[i]));
+    pcm.reserve(static_cast<size_t>(std::max<int64_t>(format.total_blocks, 0)) * channels);
+    read_ape_blocks(*dec, format, [&](const std::span<const uint8_t> bytes) {
+        for (size_t i = 0; i + bytes_per_sample <= bytes.size(); i += bytes_per_sample) {
+            if (bytes_per_sample == 1) {
+                pcm.push_back(static_cast<int32_t>(bytes[i]) - 128);
+                continue;
             }
-        } else if (bps == 24 || bps == 32) {
-            for (size_t i = 0; i < bytes_to_copy / 4; ++i) {
-                pcm.push_back(src32[i]);
+            uint32_t value = 0;
+            for (size_t b = 0; b < bytes_per_sample; ++b) {
+                value |= static_cast<uint32_t>(bytes[i + b]) << (8 * b);
             }
-        } else {
-            delete dec;
-            throw std::runtime_error("Unsupported bit depth in APE");
+            const unsigned shift = 32 - static_cast<unsigned>(bytes_per_sample) * 8;
+            pcm.push_back(static_cast<int32_t>(value << shift) >> shift);
         }
-
-        frames_processed += blocks_retrieved;
-    }
-
-    delete dec;
+    });
     return pcm;
 }
 
@@ -324,7 +307,16 @@ bool ApeProcessor::raw_equal(const std::filesystem::path& a,
     const auto pcmB = decode_ape_pcm(b, rb, cb, bpsb);
 
     if (ra != rb || ca != cb || bpsa != bpsb) return false;
-    return pcmA == pcmB;
+    if (pcmA != pcmB) return false;
+
+    // the header MD5 covers the compressed stream of b, so a damaged write shows up here
+    ApeFormat format;
+    const DecompressPtr dec = open_ape(b, format);
+    if (dec->GetInfo(APE::IAPEDecompress::APE_INFO_MD5_MATCHES) != 1) {
+        Logger::log(LogLevel::Warning, "APE: MD5 in the header does not match " + b.string(), "ape_processor");
+        return false;
+    }
+    return true;
 }
 
-} // namespace chisel
\ No newline at end of file
+} // namespace chisel
@@ -19,7 +19,11 @@
 #include <algorithm>
 #include <chrono>
 #include <cctype>
+#include <cstdint>
+#include <iterator>
 #include "file_utils.hpp"
+#include "../../include/zip_writer.hpp"
+#include "../../include/deflate_reconstructor.hpp"
 #ifndef _WIN32
 #include <sys/stat.h>
 #endif
@@ -310,26 +314,14 @@ struct PairHash {
 };
 
 /**
- * @brief Creates an archive from a source directory using libarchive.
- * @param src_dir The directory containing the files to be archived.
- * @param out_path The path to the output archive file.
- * @param fmt The target container format for the new archive.
- * @return True on successful creation, false otherwise.
+ * @brief Tells whether a container format is a ZIP archive under the hood.
+ * @param fmt The container format.
+ * @return True for formats written by ZipWriter instead of libarchive.
  */
-static bool create_with_libarchive(const fs::path& src_dir, const fs::path& out_path, ContainerFormat fmt) {
-    archive* a = archive_write_new();
-    if (!a) return false;
-
-    int r = ARCHIVE_OK;
-
+static bool is_zip_based(ContainerFormat fmt) {
     switch (fmt) {
-        case ContainerFormat::Epub:
-            r = archive_write_set_format_zip(a);
-            if (r == ARCHIVE_OK) {
-                archive_write_set_format_option(a, "zip", "compression", "store");
-            }
-            break;
         case ContainerFormat::Zip:
+        case ContainerFormat::Epub:
         case ContainerFormat::Cbz:
         case ContainerFormat::Jar:
         case ContainerFormat::Xpi:
@@ -337,53 +329,269 @@ static bool create_with_libarchive(const fs::path& src_dir, const fs::path& out_
         case ContainerFormat::Dwfx:
         case ContainerFormat::Xps:
         case ContainerFormat::Apk:
-            r = archive_write_set_format_zip(a);
-            if (r == ARCHIVE_OK) {
-                archive_write_set_format_option(a, "zip", "compression", "deflate");
-                archive_write_set_format_option(a, "zip", "compression-level", "9");
+            return true;
+        default:
+            return false;
+    }
+}
+
+// --- original ZIP streams ---
+
+/**
+ * @brief An entry's deflate stream in the original ZIP, as found by scan_zip_source().
+ */
+struct OriginalDeflate {
+    std::uint64_t offset = 0;            ///< Offset of the compressed data in the archive
+    std::uint64_t compressed_size = 0;
+    std::uint64_t size = 0;              ///< Uncompressed size
+    std::uint32_t crc = 0;               ///< CRC-32 of the uncompressed data
+    bool valid = false;                  ///< The stream inflates cleanly to its end
+};
+
+/**
+ * @brief State carried from prepare_extraction() to finalize_extraction() for ZIP-based archives.
+ */
+struct ZipSourceState {
+    bool copy_as_is = false; ///< A whole-file signature covers the archive bytes
+    std::unordered_map<std::string, OriginalDeflate> entries; ///< Deflated entries by name
+};
+
+static std::uint64_t le16_at(const std::vector<unsigned char>& b, std::size_t o) {
+    return static_cast<std::uint64_t>(b[o]) | static_cast<std::uint64_t>(b[o + 1]) << 8;
+}
+
+static std::uint64_t le32_at(const std::vector<unsigned char>& b, std::size_t o) {
+    return le16_at(b, o) | le16_at(b, o + 2) << 16;
+}
+
+static std::uint64_t le64_at(const std::vector<unsigned char>& b, std::size_t o) {
+    return le32_at(b, o) | le32_at(b, o + 4) << 32;
+}
+
+/**
+ * @brief Reads `size` bytes at `offset`, or returns an empty vector if the range is not in the file.
+ */
+static std::vector<unsigned char> read_range(std::ifstream& in, std::uint64_t offset, std::uint64_t size) {
+    std::vector<unsigned char> buf(static_cast<std::size_t>(size));
+    in.clear();
+    in.seekg(static_cast<std::streamoff>(offset));
+    in.read(reinterpret_cast<char*>(buf.data()), static_cast<std::streamsize>(size));
+    if (!in || static_cast<std::uint64_t>(in.gcount()) != size) return {};
+    return buf;
+}
+
+/**
+ * @brief Reads the central directory of a ZIP and analyzes every deflated entry.
+ *
+ * An APK Signing Block (v2+ signatures, stored just before the central
+ * directory) signs the archive bytes themselves, so such archives are
+ * flagged to be copied as-is. Otherwise each deflate stream is inflated
+ * with DeflateReconstructor, so finalize_extraction() can tell streams
+ * that may be recompressed from streams that must be carried over.
+ *
+ * @param path The ZIP file.
+ * @param st Receives the signature flag and the per-entry analysis.
+ * @return False if the ZIP structure cannot be read.
+ */
+static bool scan_zip_source(const fs::path& path, ZipSourceState& st) {
+    constexpr std::uint64_t EOCD_SIZE = 22;
+    constexpr std::uint64_t MAX_COMMENT = 0xFFFF;
+    constexpr std::string_view APK_SIG_MAGIC = "APK Sig Block 42";
+
+    std::error_code ec;
+    const std::uint64_t file_size = fs::file_size(path, ec);
+    if (ec || file_size < EOCD_SIZE) return false;
+    std::ifstream in(path, std::ios::binary);
+    if (!in) return false;
+
+    const std::uint64_t tail_size = std::min(file_size, EOCD_SIZE + MAX_COMMENT);
+    const std::uint64_t tail_offset = file_size - tail_size;
+    const auto tail = read_range(in, tail_offset, tail_size);
+    if (tail.empty()) return false;
+
+    std::size_t eocd = tail.size() - EOCD_SIZE;
+    while (le32_at(tail, eocd) != 0x06054b50) {
+        if (eocd == 0) return false;
+        --eocd;
+    }
+    std::uint64_t count = le16_at(tail, eocd + 10);
+    std::uint64_t cd_size = le32_at(tail, eocd + 12);
+    std::uint64_t cd_offset = le32_at(tail, eocd + 16);
+
+    if ((count == 0xFFFF || cd_size == 0xFFFFFFFF || cd_offset == 0xFFFFFFFF) &&
+        eocd >= 20 && le32_at(tail, eocd - 20) == 0x07064b50) {
+        const auto zip64 = read_range(in, le64_at(tail, eocd - 12), 56);
+        if (zip64.empty() || le32_at(zip64, 0) != 0x06064b50) return false;
+        count = le64_at(zip64, 32);
+        cd_size = le64_at(zip64, 40);
+        cd_offset = le64_at(zip64, 48);
+    }
+    if (cd_offset > file_size || cd_size > file_size - cd_offset) return false;
+
+    if (cd_offset >= APK_SIG_MAGIC.size()) {
+        const auto magic = read_range(in, cd_offset - APK_SIG_MAGIC.size(), APK_SIG_MAGIC.size());
+        if (!magic.empty() && std::equal(magic.begin(), magic.end(), APK_SIG_MAGIC.begin())) {
+            st.copy_as_is = true;
+            return true;
+        }
+    }
+
+    const auto cd = read_range(in, cd_offset, cd_size);
+    if (cd.size() != cd_size) return false;
+
+    std::size_t pos = 0;
+    for (std::uint64_t i = 0; i < count; ++i) {
+        if (pos + 46 > cd.size() || le32_at(cd, pos) != 0x02014b50) return false;
+        const auto flags = le16_at(cd, pos + 8);
+        const auto method = le16_at(cd, pos + 10);
+        OriginalDeflate od;
+        od.crc = static_cast<std::uint32_t>(le32_at(cd, pos + 16));
+        od.compressed_size = le32_at(cd, pos + 20);
+        od.size = le32_at(cd, pos + 24);
+        const auto name_len = le16_at(cd, pos + 28);
+        const auto extra_len = le16_at(cd, pos + 30);
+        const auto comment_len = le16_at(cd, pos + 32);
+        std::uint64_t local_offset = le32_at(cd, pos + 42);
+        if (pos + 46 + name_len + extra_len + comment_len > cd.size()) return false;
+
+        const std::string name(cd.begin() + static_cast<std::ptrdiff_t>(pos + 46),
+                               cd.begin() + static_cast<std::ptrdiff_t>(pos + 46 + name_len));
+
+        // ZIP64 extended information: only the overflowing fields, in this order
+        for (std::size_t x = pos + 46 + name_len; x + 4 <= pos + 46 + name_len + extra_len;) {
+            const auto id = le16_at(cd, x);
+            const auto len = le16_at(cd, x + 2);
+            std::size_t f = x + 4;
+            if (id == 0x0001) {
+                if (od.size == 0xFFFFFFFF && f + 8 <= x + 4 + len) { od.size = le64_at(cd, f); f += 8; }
+                if (od.compressed_size == 0xFFFFFFFF && f + 8 <= x + 4 + len) { od.compressed_size = le64_at(cd, f); f += 8; }
+                if (local_offset == 0xFFFFFFFF && f + 8 <= x + 4 + len) { local_offset = le64_at(cd, f); }
             }
-            break;
-        case ContainerFormat::Tar:
-        case ContainerFormat::Cbt:
-            r = archive_write_set_format_pax_restricted(a);
-            break;
-        case ContainerFormat::GZip:
-            r = archive_write_set_format_pax_restricted(a);
-            if (r == ARCHIVE_OK) {
-                r = archive_write_add_filter_gzip(a);
-                if (r == ARCHIVE_OK) {
-                    archive_write_set_filter_option(a, "gzip", "compression-level", "9");
+            x += 4 + len;
+        }
+        pos += 46 + name_len + extra_len + comment_len;
+
+        // encrypted and non-deflate entries have no stream to analyze
+        if (method != 8 || (flags & 1) != 0) continue;
+
+        const auto local = read_range(in, local_offset, 30);
+        if (local.empty() || le32_at(local, 0) != 0x04034b50) continue;
+        od.offset = local_offset + 30 + le16_at(local, 26) + le16_at(local, 28);
+
+        const auto stream = read_range(in, od.offset, od.compressed_size);
+        if (stream.size() != od.compressed_size) continue;
+        // only the decompressed data is compared, the zlib parameters are never needed here
+        const auto analysis = DeflateReconstructor::analyze(stream, DeflateWrapper::Raw, DeflateSearch::None);
+        od.valid = analysis.valid && analysis.decompressed.size() == od.size &&
+                   ZipWriter::crc_of(analysis.decompressed) == od.crc;
+        st.entries[name] = od;
+    }
+    return true;
+}
+
+/**
+ * @brief Creates a ZIP-based archive from a source directory, deflating entries with Zopfli.
+ *
+ * Entries left unchanged since extraction are compared against their
+ * original deflate stream, which is copied when Zopfli does not beat it.
+ * Streams that did not inflate cleanly are always copied as-is.
+ *
+ * @param src_dir The directory containing the files to be archived.
+ * @param out_path The path to the output archive file.
+ * @param fmt The target container format (one for which is_zip_based() holds).
+ * @param original_path The archive the files were extracted from.
+ * @param source The scan_zip_source() result for that archive, or nullptr.
+ * @param options Zopfli settings for the new entries.
+ * @return True on successful creation, false otherwise.
+ */
+static bool create_with_zip_writer(const fs::path& src_dir, const fs::path& out_path, ContainerFormat fmt,
+                                   const fs::path& original_path, const ZipSourceState* source,
+                                   const ZipWriterOptions& options) {
+    const fs::path root(src_dir);
+    std::error_code ec;
+
+    std::vector<fs::path> files;
+    for (auto it = fs::recursive_directory_iterator(root, ec); !ec && it != fs::recursive_directory_iterator(); ++it) {
+        std::error_code ec2;
+        if (fs::is_regular_file(it->path(), ec2) || fs::is_symlink(it->path(), ec2)) {
+            files.push_back(it->path());
+        }
+    }
+    if (fmt == ContainerFormat::Cbz) {
+        std::sort(files.begin(), files.end(), [&](const fs::path& a, const fs::path& b) {
+            return natural_less_path(a, b, root);
+        });
+    }
+    if (fmt == ContainerFormat::Epub) {
+        // EPUB requires "mimetype" as the first entry, stored
+        std::stable_partition(files.begin(), files.end(), [&](const fs::path& p) {
+            return rel_path_of(root, p) == "mimetype";
+        });
+    }
+
+    try {
+        std::ifstream original(original_path, std::ios::binary);
+        ZipWriter writer(out_path, options);
+        for (const auto& p : files) {
+            const std::string rel = rel_path_of(root, p);
+            if (fs::is_symlink(p, ec)) {
+                const auto target = fs::read_symlink(p, ec);
+                if (ec) {
+                    Logger::log(LogLevel::Warning, "Can't read symlink, skipping: " + p.string(), processor_tag());
+                    continue;
                 }
-            }
-            break;
-        case ContainerFormat::BZip2:
-            r = archive_write_set_format_pax_restricted(a);
-            if (r == ARCHIVE_OK) {
-                r = archive_write_add_filter_bzip2(a);
-                if (r == ARCHIVE_OK) {
-                    archive_write_set_filter_option(a, "bzip2", "compression-level", "9");
+                writer.add_symlink(rel, target.generic_string());
+            } else {
+                const bool stored = fmt == ContainerFormat::Epub && rel == "mimetype";
+                const OriginalDeflate* od = nullptr;
+                if (source && !stored) {
+                    if (const auto it = source->entries.find(rel); it != source->entries.end()) od = &it->second;
                 }
-            }
-            break;
-        case ContainerFormat::Xz:
-            r = archive_write_set_format_pax_restricted(a);
-            if (r == ARCHIVE_OK) {
-                r = archive_write_add_filter_xz(a);
-                if (r == ARCHIVE_OK) {
-                    archive_write_set_filter_option(a, "xz", "compression-level", "9");
+                if (!od) {
+                    writer.add_file_from(rel, p, !stored);
+                    continue;
                 }
-            }
-            break;
-        case ContainerFormat::Zstd:
-            r = archive_write_set_format_pax_restricted(a);
-            if (r == ARCHIVE_OK) {
-                r = archive_write_add_filter_zstd(a);
-                if (r == ARCHIVE_OK) {
-                    archive_write_set_filter_option(a, "zstd", "compression-level", "22");
+
+                std::ifstream ifs(p, std::ios::binary);
+                const std::vector<unsigned char> data((std::istreambuf_iterator<char>(ifs)), std::istreambuf_iterator<char>());
+                const bool unchanged = data.size() == od->size && ZipWriter::crc_of(data) == od->crc;
+                const auto stream = unchanged ? read_range(original, od->offset, od->compressed_size)
+                                              : std::vector<unsigned char>{};
+                if (!unchanged || stream.empty()) {
+                    writer.add_file(rel, data);
+                } else if (!od->valid) {
+                    writer.add_deflated(rel, stream, od->crc, od->size);
+                } else {
+                    writer.add_file(rel, data, true, stream);
                 }
             }
-            break;
+        }
+        writer.finish();
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, std::string("ZIP writing failed: ") + e.what(), processor_tag());
+        return false;
+    }
+    return true;
+}
 
+/**
+ * @brief Creates an archive from a source directory using libarchive.
+ * @param src_dir The directory containing the files to be archived.
+ * @param out_path The path to the output archive file.
+ * @param fmt The target container format for the new archive.
+ * @return True on successful creation, false otherwise.
+ */
+static bool create_with_libarchive(const fs::path& src_dir, const fs::path& out_path, ContainerFormat fmt) {
+    archive* a = archive_write_new();
+    if (!a) return false;
+
+    int r = ARCHIVE_OK;
+
+    switch (fmt) {
+        case ContainerFormat::Tar:
+        case ContainerFormat::Cbt:
+            r = archive_write_set_format_pax_restricted(a);
+            break;
         case ContainerFormat::Iso:
             r = archive_write_set_format_iso9660(a);
             if (r == ARCHIVE_OK) {
@@ -434,52 +642,14 @@ static bool create_with_libarchive(const fs::path& src_dir, const fs::path& out_
     const fs::path root(src_dir);
     std::unordered_map<std::pair<uintmax_t,uintmax_t>, std::string, PairHash> hardlink_map;
 
-    if (fmt == ContainerFormat::Epub) {
-        fs::path mimetype_path = fs::path(src_dir) / "mimetype";
-        if (fs::exists(mimetype_path)) {
-
-            std::ifstream ifs(mimetype_path, std::ios::binary);
-            std::vector<char> buf((std::istreambuf_iterator<char>(ifs)), std::istreambuf_iterator<char>());
-
-            archive_entry* entry = archive_entry_new();
-            archive_entry_set_pathname(entry, "mimetype");
-            archive_entry_set_size(entry, buf.size());
-            archive_entry_set_filetype(entry, AE_IFREG);
-            archive_entry_set_perm(entry, 0644);
-            archive_entry_set_mtime(entry, 0, 0);
-
-            int rh = archive_write_header(a, entry);
-            if (rh != ARCHIVE_OK && rh != ARCHIVE_WARN) {
-                Logger::log(LogLevel::Error, "archive_write_header (mimetype): " + std::string(archive_error_string(a)), processor_tag());
-                archive_entry_free(entry);
-                archive_write_close(a);
-                archive_write_free(a);
-                return false;
-            }
-            if (!buf.empty()) {
-                la_ssize_t wrote = archive_write_data(a, buf.data(), buf.size());
-                if (wrote < 0) {
-                    Logger::log(LogLevel::Error, "archive_write_data (mimetype): " + std::string(archive_error_string(a)), processor_tag());
-                    archive_entry_free(entry);
-                    archive_write_close(a);
-                    archive_write_free(a);
-                    return false;
-                }
-            }
-            archive_write_finish_entry(a); // finish this entry
-            archive_entry_free(entry);
-        }
-    }
-
     std::vector<fs::path> files;
     for (auto it = fs::recursive_directory_iterator(root, ec); !ec && it != fs::recursive_directory_iterator(); ++it) {
         std::error_code ec2;
         if (fs::is_regular_file(it->path(), ec2) || fs::is_symlink(it->path(), ec2)) {
-            if (fmt == ContainerFormat::Epub && it->path().filename() == "mimetype") continue;
             files.push_back(it->path());
         }
     }
-    if (fmt == ContainerFormat::Cbz || fmt == ContainerFormat::Cbt) {
+    if (fmt == ContainerFormat::Cbt) {
         std::sort(files.begin(), files.end(), [&](const fs::path& a, const fs::path& b) {
             return natural_less_path(a, b, root);
         });
@@ -610,6 +780,20 @@ std::optional<ExtractedContent> ArchiveProcessor::prepare_extraction(const std::
         return content;
     }
 
+    if (is_zip_based(content.format)) {
+        ZipSourceState source;
+        if (!scan_zip_source(input_path, source)) {
+            Logger::log(LogLevel::Debug, "Can't read the ZIP directory, original streams won't be reused: " +
+                        input_path.filename().string(), processor_tag());
+        } else if (source.copy_as_is) {
+            Logger::log(LogLevel::Info, "APK signing block found, keeping archive as-is: " +
+                        input_path.filename().string(), processor_tag());
+            content.extras = std::move(source);
+            return content;
+        }
+        content.extras = std::move(source);
+    }
+
     Logger::log(LogLevel::Info, "Extracting archive: " + input_path.filename().string() + " -> " + content.temp_dir.filename().string(), processor_tag());
 
     if (!extract_with_libarchive(input_path, content.temp_dir)) {
@@ -643,6 +827,12 @@ std::optional<ExtractedContent> ArchiveProcessor::prepare_extraction(const std::
 std::filesystem::path ArchiveProcessor::finalize_extraction(const ExtractedContent& content) {
     const auto out_fmt = content.format;
     const fs::path src_path(content.original_path);
+
+    const auto* source = std::any_cast<ZipSourceState>(&content.extras);
+    if (source && source->copy_as_is) {
+        chisel::cleanup_temp_dir(content.temp_dir, processor_tag());
+        return {};
+    }
     const std::string out_ext = "." + container_format_to_string(out_fmt);
 
     const fs::path tmp_archive = fs::temp_directory_path() /
@@ -650,10 +840,15 @@ std::filesystem::path ArchiveProcessor::finalize_extraction(const ExtractedConte
 
     Logger::log(LogLevel::Info, "Recreating archive: " + tmp_archive.string(), processor_tag());
 
-    if (!create_with_libarchive(content.temp_dir, tmp_archive, out_fmt)) {
+    const bool created = is_zip_based(out_fmt)
+        ? create_with_zip_writer(content.temp_dir, tmp_archive, out_fmt, src_path, source, zip_options_)
+        : create_with_libarchive(content.temp_dir, tmp_archive, out_fmt);
+    if (!created) {
         Logger::log(LogLevel::Error, "Archive creation failed: " + tmp_archive.string(), processor_tag());
+        std::error_code rm_ec;
+        fs::remove(tmp_archive, rm_ec);
         fs::remove_all(content.temp_dir);
-        throw std::runtime_error("ArchiveProcessor: create_with_libarchive failed");
+        throw std::runtime_error("ArchiveProcessor: archive creation failed");
     }
 
     std::error_code ec;
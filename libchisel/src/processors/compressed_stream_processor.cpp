@@ -0,0 +1,619 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/compressed_stream_processor.hpp"
+#include "../../include/file_type.hpp"
+#include "../../include/logger.hpp"
+#include "../../include/random_utils.hpp"
+#include "file_utils.hpp"
+#include <algorithm>
+#include <any>
+#include <array>
+#include <cstdint>
+#include <cstdio>
+#include <cstdlib>
+#include <memory>
+#include <stdexcept>
+#include <string>
+#include <vector>
+#include <bzlib.h>
+#include <lzma.h>
+#include <zlib.h>
+#include <zstd.h>
+#include "zopfli.h"
+
+namespace chisel {
+
+namespace fs = std::filesystem;
+
+namespace {
+
+const char* processor_tag() {
+    return "CompressedStreamProcessor";
+}
+
+constexpr std::size_t CHUNK = 256 * 1024;
+
+// gzip header flags (RFC 1952)
+constexpr unsigned char GZ_FTEXT = 0x01;
+constexpr unsigned char GZ_FEXTRA = 0x04;
+constexpr unsigned char GZ_FNAME = 0x08;
+constexpr unsigned char GZ_FCOMMENT = 0x10;
+constexpr unsigned char GZ_XFL_MAX = 0x02;
+
+/**
+ * @brief The gzip header fields carried over to the recompressed stream.
+ */
+struct GzipHeader {
+    unsigned char flags = 0;
+    std::uint32_t mtime = 0;
+    unsigned char os = 255;
+    std::vector<unsigned char> extra;
+    std::string name;
+    std::string comment;
+};
+
+/**
+ * @brief State handed from prepare_extraction() to finalize_extraction().
+ */
+struct StreamState {
+    GzipHeader gzip;
+    lzma_check xz_check = LZMA_CHECK_CRC64;
+    bool zstd_checksum = false;
+};
+
+struct FileCloser {
+    void operator()(FILE* f) const { if (f) std::fclose(f); }
+};
+using FilePtr = std::unique_ptr<FILE, FileCloser>;
+
+FilePtr open_or_throw(const fs::path& path, const char* mode) {
+    FilePtr f(open_file(path, mode));
+    if (!f) {
+        throw std::runtime_error("CompressedStreamProcessor: cannot open " + path.string());
+    }
+    return f;
+}
+
+std::size_t read_chunk(FILE* in, std::vector<unsigned char>& buf) {
+    const std::size_t n = std::fread(buf.data(), 1, buf.size(), in);
+    if (n == 0 && std::ferror(in)) {
+        throw std::runtime_error("CompressedStreamProcessor: read error");
+    }
+    return n;
+}
+
+void write_all(FILE* out, const void* data, std::size_t size) {
+    if (size > 0 && std::fwrite(data, 1, size, out) != size) {
+        throw std::runtime_error("CompressedStreamProcessor: write error");
+    }
+}
+
+std::string stream_name(ContainerFormat fmt) {
+    switch (fmt) {
+        case ContainerFormat::GZip:  return "gzip";
+        case ContainerFormat::BZip2: return "bzip2";
+        case ContainerFormat::Xz:    return "xz";
+        case ContainerFormat::Lzma:  return "lzma";
+        case ContainerFormat::Zstd:  return "zstd";
+        default:                     return "unknown";
+    }
+}
+
+/**
+ * @brief Detects the stream format from the magic bytes.
+ *
+ * Legacy `.lzma` files have no magic number, so they are recognized by
+ * extension only.
+ */
+ContainerFormat detect_stream_format(const fs::path& path) {
+    std::array<unsigned char, 6> magic{};
+    {
+        const FilePtr f = open_or_throw(path, "rb");
+        if (std::fread(magic.data(), 1, magic.size(), f.get()) < 4) return ContainerFormat::Unknown;
+    }
+
+    if (magic[0] == 0x1f && magic[1] == 0x8b) return ContainerFormat::GZip;
+    if (magic[0] == 'B' && magic[1] == 'Z' && magic[2] == 'h') return ContainerFormat::BZip2;
+    if (magic == std::array<unsigned char, 6>{0xfd, '7', 'z', 'X', 'Z', 0x00}) return ContainerFormat::Xz;
+    if (magic[0] == 0x28 && magic[1] == 0xb5 && magic[2] == 0x2f && magic[3] == 0xfd) return ContainerFormat::Zstd;
+
+    std::string ext = path.extension().string();
+    std::ranges::transform(ext, ext.begin(), [](unsigned char c) { return static_cast<char>(std::tolower(c)); });
+    if (ext == ".lzma") return ContainerFormat::Lzma;
+    return ContainerFormat::Unknown;
+}
+
+/**
+ * @brief Parses the header of the first gzip member.
+ */
+GzipHeader read_gzip_header(const fs::path& path) {
+    const FilePtr f = open_or_throw(path, "rb");
+    auto byte = [&]() -> unsigned char {
+        const int c = std::fgetc(f.get());
+        if (c == EOF) throw std::runtime_error("CompressedStreamProcessor: truncated gzip header");
+        return static_cast<unsigned char>(c);
+    };
+    auto zero_terminated = [&]() {
+        std::string s;
+        for (unsigned char c = byte(); c != 0; c = byte()) s.push_back(static_cast<char>(c));
+        return s;
+    };
+
+    GzipHeader h;
+    if (byte() != 0x1f || byte() != 0x8b || byte() != Z_DEFLATED) {
+        throw std::runtime_error("CompressedStreamProcessor: not a deflate gzip stream");
+    }
+    h.flags = byte();
+    for (int i = 0; i < 4; ++i) h.mtime |= static_cast<std::uint32_t>(byte()) << (8 * i);
+    byte(); // XFL, rewritten on output
+    h.os = byte();
+    if (h.flags & GZ_FEXTRA) {
+        const unsigned len = byte() | (byte() << 8);
+        for (unsigned i = 0; i < len; ++i) h.extra.push_back(byte());
+    }
+    if (h.flags & GZ_FNAME) h.name = zero_terminated();
+    if (h.flags & GZ_FCOMMENT) h.comment = zero_terminated();
+    return h;
+}
+
+/**
+ * @brief Reads the integrity check type from the xz stream header.
+ */
+lzma_check read_xz_check(const fs::path& path) {
+    std::array<unsigned char, 8> header{};
+    const FilePtr f = open_or_throw(path, "rb");
+    if (std::fread(header.data(), 1, header.size(), f.get()) != header.size()) {
+        throw std::runtime_error("CompressedStreamProcessor: truncated xz header");
+    }
+    const auto check = static_cast<lzma_check>(header[7] & 0x0f);
+    return lzma_check_is_supported(check) ? check : LZMA_CHECK_CRC64;
+}
+
+/**
+ * @brief Tells whether the first zstd frame carries a content checksum.
+ */
+bool read_zstd_checksum_flag(const fs::path& path) {
+    std::array<unsigned char, 5> header{};
+    const FilePtr f = open_or_throw(path, "rb");
+    if (std::fread(header.data(), 1, header.size(), f.get()) != header.size()) return false;
+    return (header[4] & 0x04) != 0; // Content_Checksum_flag of the frame header descriptor
+}
+
+/**
+ * @brief Picks the payload file name.
+ *
+ * Uses the gzip FNAME field when it is a plain file name, otherwise strips
+ * the compression extension; the short tarball extensions become `.tar`.
+ */
+fs::path payload_name(const fs::path& input_path, const GzipHeader& gzip) {
+    if (!gzip.name.empty()) {
+        const fs::path name = fs::path(gzip.name).filename();
+        if (!name.empty() && name != "." && name != "..") return name;
+    }
+
+    std::string ext = input_path.extension().string();
+    std::ranges::transform(ext, ext.begin(), [](unsigned char c) { return static_cast<char>(std::tolower(c)); });
+    std::string stem = input_path.stem().string();
+    if (stem.empty()) stem = "payload";
+    if (ext == ".tgz" || ext == ".tbz2" || ext == ".txz" || ext == ".tzst") return stem + ".tar";
+    return stem;
+}
+
+// --- decompression ---
+
+void inflate_gzip(FILE* in, FILE* out) {
+    z_stream zs{};
+    if (inflateInit2(&zs, 16 + MAX_WBITS) != Z_OK) {
+        throw std::runtime_error("CompressedStreamProcessor: inflateInit2 failed");
+    }
+    const std::unique_ptr<z_stream, decltype(&inflateEnd)> guard(&zs, inflateEnd);
+
+    std::vector<unsigned char> inbuf(CHUNK), outbuf(CHUNK);
+    bool member_done = false;
+    for (;;) {
+        if (zs.avail_in == 0) {
+            const std::size_t n = read_chunk(in, inbuf);
+            if (n == 0) break;
+            zs.next_in = inbuf.data();
+            zs.avail_in = static_cast<uInt>(n);
+        }
+        if (member_done) {
+            // gzip allows concatenated members; anything else is trailing garbage
+            if (zs.next_in[0] != 0x1f) {
+                Logger::log(LogLevel::Warning, "Ignoring trailing data after the gzip stream", processor_tag());
+                break;
+            }
+            inflateReset(&zs);
+            member_done = false;
+        }
+        zs.next_out = outbuf.data();
+        zs.avail_out = static_cast<uInt>(outbuf.size());
+        const int ret = inflate(&zs, Z_NO_FLUSH);
+        if (ret == Z_STREAM_END) {
+            member_done = true;
+        } else if (ret != Z_OK && ret != Z_BUF_ERROR) {
+            throw std::runtime_error(std::string("CompressedStreamProcessor: corrupt gzip data: ") +
+                                     (zs.msg ? zs.msg : "inflate failed"));
+        }
+        write_all(out, outbuf.data(), outbuf.size() - zs.avail_out);
+    }
+    if (!member_done) {
+        throw std::runtime_error("CompressedStreamProcessor: truncated gzip stream");
+    }
+}
+
+void decompress_bzip2(FILE* in, FILE* out) {
+    bz_stream bs{};
+    if (BZ2_bzDecompressInit(&bs, 0, 0) != BZ_OK) {
+        throw std::runtime_error("CompressedStreamProcessor: BZ2_bzDecompressInit failed");
+    }
+
+    std::vector<unsigned char> inbuf(CHUNK), outbuf(CHUNK);
+    bool stream_done = false;
+    try {
+        for (;;) {
+            if (bs.avail_in == 0) {
+                const std::size_t n = read_chunk(in, inbuf);
+                if (n == 0) break;
+                bs.next_in = reinterpret_cast<char*>(inbuf.data());
+                bs.avail_in = static_cast<unsigned>(n);
+            }
+            if (stream_done) {
+                // pbzip2 and friends write one stream per block group
+                BZ2_bzDecompressEnd(&bs);
+                char* next_in = bs.next_in;
+                const unsigned avail_in = bs.avail_in;
+                bs = bz_stream{};
+                if (BZ2_bzDecompressInit(&bs, 0, 0) != BZ_OK) {
+                    throw std::runtime_error("CompressedStreamProcessor: BZ2_bzDecompressInit failed");
+                }
+                bs.next_in = next_in;
+                bs.avail_in = avail_in;
+                stream_done = false;
+            }
+            bs.next_out = reinterpret_cast<char*>(outbuf.data());
+            bs.avail_out = static_cast<unsigned>(outbuf.size());
+            const int ret = BZ2_bzDecompress(&bs);
+            if (ret == BZ_STREAM_END) {
+                stream_done = true;
+            } else if (ret != BZ_OK) {
+                throw std::runtime_error("CompressedStreamProcessor: corrupt bzip2 data (" + std::to_string(ret) + ")");
+            }
+            write_all(out, outbuf.data(), outbuf.size() - bs.avail_out);
+        }
+        if (!stream_done) {
+            throw std::runtime_error("CompressedStreamProcessor: truncated bzip2 stream");
+        }
+    } catch (...) {
+        BZ2_bzDecompressEnd(&bs);
+        throw;
+    }
+    BZ2_bzDecompressEnd(&bs);
+}
+
+/**
+ * @brief Runs an initialized liblzma coder over the whole input.
+ */
+void run_lzma(lzma_stream& ls, FILE* in, FILE* out, const char* what) {
+    const std::unique_ptr<lzma_stream, decltype(&lzma_end)> guard(&ls, lzma_end);
+
+    std::vector<unsigned char> inbuf(CHUNK), outbuf(CHUNK);
+    lzma_action action = LZMA_RUN;
+    for (;;) {
+        if (ls.avail_in == 0 && action == LZMA_RUN) {
+            const std::size_t n = read_chunk(in, inbuf);
+            ls.next_in = inbuf.data();
+            ls.avail_in = n;
+            if (n == 0) action = LZMA_FINISH;
+        }
+        ls.next_out = outbuf.data();
+        ls.avail_out = outbuf.size();
+        const lzma_ret ret = lzma_code(&ls, action);
+        write_all(out, outbuf.data(), outbuf.size() - ls.avail_out);
+        if (ret == LZMA_STREAM_END) return;
+        if (ret != LZMA_OK) {
+            throw std::runtime_error(std::string("CompressedStreamProcessor: ") + what +
+                                     " failed (lzma error " + std::to_string(ret) + ")");
+        }
+    }
+}
+
+void decompress_lzma(FILE* in, FILE* out, bool xz) {
+    lzma_stream ls = LZMA_STREAM_INIT;
+    const lzma_ret ret = xz
+        ? lzma_stream_decoder(&ls, UINT64_MAX, LZMA_CONCATENATED)
+        : lzma_alone_decoder(&ls, UINT64_MAX);
+    if (ret != LZMA_OK) {
+        throw std::runtime_error("CompressedStreamProcessor: cannot initialize the lzma decoder");
+    }
+    run_lzma(ls, in, out, xz ? "xz decoding" : "lzma decoding");
+}
+
+void decompress_zstd(FILE* in, FILE* out) {
+    const std::unique_ptr<ZSTD_DCtx, decltype(&ZSTD_freeDCtx)> dctx(ZSTD_createDCtx(), ZSTD_freeDCtx);
+    if (!dctx) throw std::runtime_error("CompressedStreamProcessor: ZSTD_createDCtx failed");
+    // accept windows up to --long=31
+    ZSTD_DCtx_setParameter(dctx.get(), ZSTD_d_windowLogMax, 31);
+
+    std::vector<unsigned char> inbuf(ZSTD_DStreamInSize()), outbuf(ZSTD_DStreamOutSize());
+    std::size_t last = 0;
+    bool any_input = false;
+    while (const std::size_t n = read_chunk(in, inbuf)) {
+        any_input = true;
+        ZSTD_inBuffer input{inbuf.data(), n, 0};
+        while (input.pos < input.size) {
+            ZSTD_outBuffer output{outbuf.data(), outbuf.size(), 0};
+            last = ZSTD_decompressStream(dctx.get(), &output, &input);
+            if (ZSTD_isError(last)) {
+                throw std::runtime_error(std::string("CompressedStreamProcessor: corrupt zstd data: ") +
+                                         ZSTD_getErrorName(last));
+            }
+            write_all(out, outbuf.data(), output.pos);
+        }
+    }
+    if (!any_input || last != 0) {
+        throw std::runtime_error("CompressedStreamProcessor: truncated zstd stream");
+    }
+}
+
+// --- compression ---
+
+void put_le32(std::vector<unsigned char>& out, std::uint32_t v) {
+    for (int i = 0; i < 4; ++i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+}
+
+/**
+ * @brief Writes a single-member gzip file deflated with Zopfli.
+ *
+ * FTEXT, FEXTRA, FNAME, FCOMMENT, MTIME and OS come from the original
+ * header; FHCRC is dropped and XFL is set to "maximum compression".
+ * Zopfli runs with the iterations and block splitting of `options`.
+ */
+void compress_gzip(const fs::path& payload, FILE* out, const GzipHeader& h, const ZipWriterOptions& options) {
+    std::vector<unsigned char> data;
+    {
+        const FilePtr in = open_or_throw(payload, "rb");
+        std::vector<unsigned char> buf(CHUNK);
+        while (const std::size_t n = read_chunk(in.get(), buf)) {
+            data.insert(data.end(), buf.begin(), buf.begin() + static_cast<std::ptrdiff_t>(n));
+        }
+    }
+
+    std::vector<unsigned char> header = {0x1f, 0x8b, Z_DEFLATED};
+    header.push_back(static_cast<unsigned char>(h.flags & (GZ_FTEXT | GZ_FEXTRA | GZ_FNAME | GZ_FCOMMENT)));
+    put_le32(header, h.mtime);
+    header.push_back(GZ_XFL_MAX);
+    header.push_back(h.os);
+    if (h.flags & GZ_FEXTRA) {
+        header.push_back(static_cast<unsigned char>(h.extra.size()));
+        header.push_back(static_cast<unsigned char>(h.extra.size() >> 8));
+        header.insert(header.end(), h.extra.begin(), h.extra.end());
+    }
+    if (h.flags & GZ_FNAME) {
+        header.insert(header.end(), h.name.begin(), h.name.end());
+        header.push_back(0);
+    }
+    if (h.flags & GZ_FCOMMENT) {
+        header.insert(header.end(), h.comment.begin(), h.comment.end());
+        header.push_back(0);
+    }
+
+    ZopfliOptions opts;
+    ZopfliInitOptions(&opts);
+    opts.numiterations = options.iterations;
+    opts.blocksplitting = options.block_splitting ? 1 : 0;
+    unsigned char* deflated = nullptr;
+    size_t deflated_size = 0;
+    ZopfliCompress(&opts, ZOPFLI_FORMAT_DEFLATE, data.data(), data.size(), &deflated, &deflated_size);
+    const std::unique_ptr<unsigned char, decltype(&std::free)> deflated_guard(deflated, std::free);
+
+    uLong crc = crc32(0L, Z_NULL, 0);
+    for (std::size_t pos = 0; pos < data.size(); pos += CHUNK) {
+        const std::size_t len = std::min(CHUNK, data.size() - pos);
+        crc = crc32(crc, data.data() + pos, static_cast<uInt>(len));
+    }
+    std::vector<unsigned char> trailer;
+    put_le32(trailer, static_cast<std::uint32_t>(crc));
+    put_le32(trailer, static_cast<std::uint32_t>(data.size())); // ISIZE is the size modulo 2^32
+
+    write_all(out, header.data(), header.size());
+    write_all(out, deflated, deflated_size);
+    write_all(out, trailer.data(), trailer.size());
+}
+
+void compress_bzip2(FILE* in, FILE* out) {
+    bz_stream bs{};
+    if (BZ2_bzCompressInit(&bs, 9, 0, 0) != BZ_OK) {
+        throw std::runtime_error("CompressedStreamProcessor: BZ2_bzCompressInit failed");
+    }
+
+    std::vector<unsigned char> inbuf(CHUNK), outbuf(CHUNK);
+    try {
+        int action = BZ_RUN;
+        for (;;) {
+            if (bs.avail_in == 0 && action == BZ_RUN) {
+                const std::size_t n = read_chunk(in, inbuf);
+                bs.next_in = reinterpret_cast<char*>(inbuf.data());
+                bs.avail_in = static_cast<unsigned>(n);
+                if (n == 0) action = BZ_FINISH;
+            }
+            bs.next_out = reinterpret_cast<char*>(outbuf.data());
+            bs.avail_out = static_cast<unsigned>(outbuf.size());
+            const int ret = BZ2_bzCompress(&bs, action);
+            write_all(out, outbuf.data(), outbuf.size() - bs.avail_out);
+            if (ret == BZ_STREAM_END) break;
+            if (ret != BZ_RUN_OK && ret != BZ_FINISH_OK) {
+                throw std::runtime_error("CompressedStreamProcessor: bzip2 compression failed (" + std::to_string(ret) + ")");
+            }
+        }
+    } catch (...) {
+        BZ2_bzCompressEnd(&bs);
+        throw;
+    }
+    BZ2_bzCompressEnd(&bs);
+}
+
+void compress_lzma(FILE* in, FILE* out, bool xz, lzma_check check) {
+    lzma_stream ls = LZMA_STREAM_INIT;
+    lzma_ret ret;
+    if (xz) {
+        ret = lzma_easy_encoder(&ls, 9 | LZMA_PRESET_EXTREME, check);
+    } else {
+        lzma_options_lzma opts;
+        if (lzma_lzma_preset(&opts, 9 | LZMA_PRESET_EXTREME)) {
+            throw std::runtime_error("CompressedStreamProcessor: unsupported lzma preset");
+        }
+        ret = lzma_alone_encoder(&ls, &opts);
+    }
+    if (ret != LZMA_OK) {
+        throw std::runtime_error("CompressedStreamProcessor: cannot initialize the lzma encoder");
+    }
+    run_lzma(ls, in, out, xz ? "xz encoding" : "lzma encoding");
+}
+
+void compress_zstd(FILE* in, FILE* out, std::uint64_t size, bool checksum) {
+    const std::unique_ptr<ZSTD_CCtx, decltype(&ZSTD_freeCCtx)> cctx(ZSTD_createCCtx(), ZSTD_freeCCtx);
+    if (!cctx) throw std::runtime_error("CompressedStreamProcessor: ZSTD_createCCtx failed");
+
+    // equivalent of `zstd --ultra -22 --long`
+    ZSTD_CCtx_setParameter(cctx.get(), ZSTD_c_compressionLevel, ZSTD_maxCLevel());
+    ZSTD_CCtx_setParameter(cctx.get(), ZSTD_c_enableLongDistanceMatching, 1);
+    ZSTD_CCtx_setParameter(cctx.get(), ZSTD_c_checksumFlag, checksum ? 1 : 0);
+    ZSTD_CCtx_setPledgedSrcSize(cctx.get(), size);
+
+    std::vector<unsigned char> inbuf(ZSTD_CStreamInSize()), outbuf(ZSTD_CStreamOutSize());
+    for (;;) {
+        const std::size_t n = read_chunk(in, inbuf);
+        const ZSTD_EndDirective mode = n < inbuf.size() ? ZSTD_e_end : ZSTD_e_continue;
+        ZSTD_inBuffer input{inbuf.data(), n, 0};
+        bool finished = false;
+        do {
+            ZSTD_outBuffer output{outbuf.data(), outbuf.size(), 0};
+            const std::size_t remaining = ZSTD_compressStream2(cctx.get(), &output, &input, mode);
+            if (ZSTD_isError(remaining)) {
+                throw std::runtime_error(std::string("CompressedStreamProcessor: zstd compression failed: ") +
+                                         ZSTD_getErrorName(remaining));
+            }
+            write_all(out, outbuf.data(), output.pos);
+            finished = mode == ZSTD_e_end ? remaining == 0 : input.pos == input.size;
+        } while (!finished);
+        if (mode == ZSTD_e_end) break;
+    }
+}
+
+} // namespace
+
+// --- IProcessor implementation ---
+
+std::optional<ExtractedContent> CompressedStreamProcessor::prepare_extraction(const std::filesystem::path& input_path) {
+    ExtractedContent content;
+    content.original_path = input_path;
+
+    ContainerFormat fmt;
+    try {
+        fmt = detect_stream_format(input_path);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, e.what(), processor_tag());
+        return std::nullopt;
+    }
+    if (fmt == ContainerFormat::Unknown) {
+        Logger::log(LogLevel::Warning, "Unrecognized compressed stream: " + input_path.filename().string(), processor_tag());
+        return std::nullopt;
+    }
+    content.format = fmt;
+    content.temp_dir = make_temp_dir_for(input_path, "stream");
+
+    try {
+        StreamState state;
+        if (fmt == ContainerFormat::GZip) state.gzip = read_gzip_header(input_path);
+        if (fmt == ContainerFormat::Xz) state.xz_check = read_xz_check(input_path);
+        if (fmt == ContainerFormat::Zstd) state.zstd_checksum = read_zstd_checksum_flag(input_path);
+
+        const fs::path payload = content.temp_dir / payload_name(input_path, state.gzip);
+        Logger::log(LogLevel::Info, "Decompressing " + stream_name(fmt) + " stream: " +
+                    input_path.filename().string() + " -> " + payload.filename().string(), processor_tag());
+
+        const FilePtr in = open_or_throw(input_path, "rb");
+        const FilePtr out = open_or_throw(payload, "wb");
+        switch (fmt) {
+            case ContainerFormat::GZip:  inflate_gzip(in.get(), out.get()); break;
+            case ContainerFormat::BZip2: decompress_bzip2(in.get(), out.get()); break;
+            case ContainerFormat::Xz:    decompress_lzma(in.get(), out.get(), true); break;
+            case ContainerFormat::Lzma:  decompress_lzma(in.get(), out.get(), false); break;
+            case ContainerFormat::Zstd:  decompress_zstd(in.get(), out.get()); break;
+            default: break;
+        }
+
+        content.extracted_files.push_back(payload);
+        content.extras = std::make_any<StreamState>(std::move(state));
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, "Decompression failed for " + input_path.filename().string() + ": " + e.what(),
+                    processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return std::nullopt;
+    }
+
+    return content;
+}
+
+std::filesystem::path CompressedStreamProcessor::finalize_extraction(const ExtractedContent& content) {
+    const auto* state = std::any_cast<StreamState>(&content.extras);
+    if (!state || content.extracted_files.size() != 1) {
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        throw std::runtime_error("CompressedStreamProcessor: invalid extraction state");
+    }
+
+    const fs::path& src_path = content.original_path;
+    const fs::path& payload = content.extracted_files.front();
+    const fs::path tmp_path = fs::temp_directory_path() /
+                              (src_path.stem().string() + "_tmp" + RandomUtils::random_suffix() + src_path.extension().string());
+
+    Logger::log(LogLevel::Info, "Recompressing " + stream_name(content.format) + " stream: " +
+                src_path.filename().string(), processor_tag());
+
+    std::error_code ec;
+    try {
+        const FilePtr out = open_or_throw(tmp_path, "wb");
+        if (content.format == ContainerFormat::GZip) {
+            compress_gzip(payload, out.get(), state->gzip, zip_options_);
+        } else {
+            const FilePtr in = open_or_throw(payload, "rb");
+            switch (content.format) {
+                case ContainerFormat::BZip2: compress_bzip2(in.get(), out.get()); break;
+                case ContainerFormat::Xz:    compress_lzma(in.get(), out.get(), true, state->xz_check); break;
+                case ContainerFormat::Lzma:  compress_lzma(in.get(), out.get(), false, LZMA_CHECK_NONE); break;
+                case ContainerFormat::Zstd:
+                    compress_zstd(in.get(), out.get(), fs::file_size(payload), state->zstd_checksum);
+                    break;
+                default:
+                    throw std::runtime_error("CompressedStreamProcessor: unsupported stream format");
+            }
+        }
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, "Recompression failed for " + src_path.filename().string() + ": " + e.what(),
+                    processor_tag());
+        fs::remove(tmp_path, ec);
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        throw;
+    }
+
+    cleanup_temp_dir(content.temp_dir, processor_tag());
+
+    const auto new_size = fs::file_size(tmp_path, ec);
+    const auto old_size = fs::file_size(src_path, ec);
+    if (ec || new_size >= old_size) {
+        Logger::log(LogLevel::Debug, "Recompressed stream is not smaller, keeping original: " +
+                    src_path.filename().string(), processor_tag());
+        fs::remove(tmp_path, ec);
+        return {};
+    }
+    return tmp_path;
+}
+
+std::string CompressedStreamProcessor::get_raw_checksum(const std::filesystem::path& /*file_path*/) const {
+    return "";
+}
+
+} // namespace chisel
@@ -5,28 +5,116 @@
 #include "../../include/flac_processor.hpp"
 #include "../../include/logger.hpp"
 #include <FLAC/all.h>
+#include <algorithm>
+#include <array>
+#include <bit>
+#include <cctype>
+#include <cstdint>
+#include <deque>
+#include <fstream>
+#include <iomanip>
+#include <optional>
+#include <set>
 #include <stdexcept>
 #include <cstdlib>
 #include <iostream>
 #include <sstream>
 #include <taglib/tag.h>
 #include "audio_metadata_util.hpp"
+#include "best_candidate.hpp"
 #include "file_type.hpp"
 #include "file_utils.hpp"
 #include "random_utils.hpp"
 
 namespace chisel {
 
+namespace fs = std::filesystem;
+
+namespace {
+
+using Bytes = std::vector<unsigned char>;
+
+constexpr const char* kDefaultApodization = "tukey(0.5);partial_tukey(2);punchout_tukey(3);gauss(0.2)";
+
+/**
+ * @brief Encoder parameters that the search varies.
+ *
+ * Everything else stays at the fixed configuration: level 8, mid-side
+ * stereo, max LPC order 16 and exhaustive model search.
+ */
+struct EncoderSettings {
+    unsigned blocksize = 0;               ///< 0 lets libFLAC choose (4096)
+    const char* apodization = kDefaultApodization;
+    unsigned qlp_precision = 0;           ///< 0 lets libFLAC choose
+    bool precision_search = false;        ///< try every QLP precision per subframe
+    unsigned max_partition_order = 6;
+
+    bool operator==(const EncoderSettings&) const = default;
+};
+
+// tried one axis at a time, each starting from the best settings so far
+constexpr std::array<unsigned, 4> kSearchBlocksizes = { 4096, 2048, 4608, 8192 };
+constexpr std::array<const char*, 3> kSearchApodizations = {
+    kDefaultApodization,
+    "subdivide_tukey(3)",
+    "subdivide_tukey(5);gauss(0.1);welch;flattop"
+};
+constexpr std::array<std::pair<unsigned, bool>, 3> kSearchPrecisions = {{ {0, false}, {15, false}, {0, true} }};
+
+// tree levels of the variable-blocksize encoder; each one halves the previous
+constexpr std::array<unsigned, 4> kVariableBlocksizes = { 8192, 4096, 2048, 1024 };
+
+/**
+ * @brief Metadata blocks copied from the source file.
+ */
+struct MetadataCopy {
+    std::vector<FLAC__StreamMetadata*> blocks;
+
+    MetadataCopy() = default;
+    MetadataCopy(const MetadataCopy&) = delete;
+    MetadataCopy& operator=(const MetadataCopy&) = delete;
+    ~MetadataCopy() {
+        for (FLAC__StreamMetadata* block : blocks) FLAC__metadata_object_delete(block);
+    }
+};
+
 struct TranscodeContext {
     FLAC__StreamEncoder* encoder = nullptr;
-    std::filesystem::path output;
+    fs::path output;
+    const EncoderSettings* settings = nullptr;
     bool encoder_initialized = false;
-    bool preserve_metadata = true;
     FLAC__StreamMetadata** metadata_blocks = nullptr;
     unsigned num_blocks = 0;
     bool failed = false;
 };
 
+/**
+ * @brief Applies the fixed configuration plus the searched parameters.
+ */
+void apply_settings(FLAC__StreamEncoder* encoder, const EncoderSettings& settings) {
+    FLAC__stream_encoder_set_compression_level(encoder, 8);
+    FLAC__stream_encoder_set_blocksize(encoder, settings.blocksize);
+    FLAC__stream_encoder_set_do_mid_side_stereo(encoder, true);
+    FLAC__stream_encoder_set_loose_mid_side_stereo(encoder, false);
+    FLAC__stream_encoder_set_apodization(encoder, settings.apodization);
+    FLAC__stream_encoder_set_max_lpc_order(encoder, 16);
+    FLAC__stream_encoder_set_qlp_coeff_precision(encoder, settings.qlp_precision);
+    FLAC__stream_encoder_set_do_qlp_coeff_prec_search(encoder, settings.precision_search);
+    FLAC__stream_encoder_set_min_residual_partition_order(encoder, 0);
+    FLAC__stream_encoder_set_max_residual_partition_order(encoder, settings.max_partition_order);
+    FLAC__stream_encoder_set_do_exhaustive_model_search(encoder, true);
+    FLAC__stream_encoder_set_streamable_subset(encoder, false);
+}
+
+std::string describe(const EncoderSettings& settings) {
+    std::string precision = settings.precision_search ? "search"
+                          : settings.qlp_precision ? std::to_string(settings.qlp_precision) : "auto";
+    return "blocksize " + (settings.blocksize ? std::to_string(settings.blocksize) : std::string("auto")) +
+           ", apodization " + settings.apodization + ", precision " + precision;
+}
+
+} // namespace
+
 /**
  * @brief FLAC decoder write callback.
  * This function is called by the decoder for each decoded audio frame. It passes
@@ -47,18 +135,9 @@ static FLAC__StreamDecoderWriteStatus write_callback(
         FLAC__stream_encoder_set_channels(ctx->encoder, frame->header.channels);
         FLAC__stream_encoder_set_bits_per_sample(ctx->encoder, frame->header.bits_per_sample);
         FLAC__stream_encoder_set_sample_rate(ctx->encoder, frame->header.sample_rate);
-        FLAC__stream_encoder_set_compression_level(ctx->encoder, 8);
-        FLAC__stream_encoder_set_blocksize(ctx->encoder, 0);
-        FLAC__stream_encoder_set_do_mid_side_stereo(ctx->encoder, true);
-        FLAC__stream_encoder_set_loose_mid_side_stereo(ctx->encoder, false);
-        FLAC__stream_encoder_set_apodization(ctx->encoder, "tukey(0.5);partial_tukey(2);punchout_tukey(3);gauss(0.2)");
-        FLAC__stream_encoder_set_max_lpc_order(ctx->encoder, 16);
-        FLAC__stream_encoder_set_min_residual_partition_order(ctx->encoder, 0);
-        FLAC__stream_encoder_set_max_residual_partition_order(ctx->encoder, 6);
-        FLAC__stream_encoder_set_do_exhaustive_model_search(ctx->encoder, true);
-        FLAC__stream_encoder_set_streamable_subset(ctx->encoder, false);
-
-        if (ctx->preserve_metadata && ctx->metadata_blocks && ctx->num_blocks > 0) {
+        apply_settings(ctx->encoder, *ctx->settings);
+
+        if (ctx->metadata_blocks && ctx->num_blocks > 0) {
             FLAC__stream_encoder_set_metadata(ctx->encoder, ctx->metadata_blocks, ctx->num_blocks);
         }
 
@@ -100,122 +179,763 @@ static void error_callback(const FLAC__StreamDecoder*, FLAC__StreamDecoderErrorS
                 "libFLAC");
 }
 
-void FlacProcessor::recompress(const std::filesystem::path& input,
-                               const std::filesystem::path& output,
-                               const bool preserve_metadata)
-{
-    Logger::log(LogLevel::Info, "Starting FLAC re-encoding: " + input.string(), "flac_processor");
-
-    if (std::filesystem::exists(output)) {
-        std::filesystem::remove(output);
-    }
-
-    TranscodeContext ctx;
-    ctx.output = output;
-    ctx.preserve_metadata = preserve_metadata;
-
-    // metadata copy (optional)
-    FLAC__Metadata_Chain* chain = nullptr;
-    FLAC__Metadata_Iterator* it = nullptr;
-    if (ctx.preserve_metadata) {
-        chain = FLAC__metadata_chain_new();
-        if (chain && FLAC__metadata_chain_read(chain, input.string().c_str())) {
-            it = FLAC__metadata_iterator_new();
+/**
+ * @brief Copies every metadata block of a FLAC file except STREAMINFO and PICTURE.
+ * @param input The FLAC file to read.
+ * @param copy Receives the cloned blocks.
+ */
+static void read_metadata(const fs::path& input, MetadataCopy& copy) {
+    FLAC__Metadata_Chain* chain = FLAC__metadata_chain_new();
+    if (!chain) return;
+    if (FLAC__metadata_chain_read(chain, input.string().c_str())) {
+        if (FLAC__Metadata_Iterator* it = FLAC__metadata_iterator_new()) {
             FLAC__metadata_iterator_init(it, chain);
-            unsigned count = 0;
             do {
                 const FLAC__StreamMetadata* block = FLAC__metadata_iterator_get_block(it);
                 // skip streaminfo (handled by encoder) and picture (handled by finalize_extraction)
                 if (block &&
                     block->type != FLAC__METADATA_TYPE_STREAMINFO &&
                     block->type != FLAC__METADATA_TYPE_PICTURE) {
-                    ++count;
+                    if (FLAC__StreamMetadata* clone = FLAC__metadata_object_clone(block)) {
+                        copy.blocks.push_back(clone);
+                    }
                 }
             } while (FLAC__metadata_iterator_next(it));
+            FLAC__metadata_iterator_delete(it);
+        }
+    }
+    FLAC__metadata_chain_delete(chain);
+}
+
+/**
+ * @brief Applies a FlacMetadataPolicy to the blocks copied from `input`.
+ *
+ * PADDING is replaced by a single block of the policy size, SEEKTABLEs by
+ * one template with evenly spaced points (filled in by the encoder),
+ * VORBIS_COMMENT blocks are merged with exact duplicate fields removed,
+ * and only the first CUESHEET is kept.
+ */
+static void compact_metadata(const fs::path& input, const FlacMetadataPolicy& policy, MetadataCopy& copy) {
+    std::vector<FLAC__StreamMetadata*> kept;
+    FLAC__StreamMetadata* comments = nullptr;
+    std::vector<std::string> fields;
+    std::set<std::string> seen_fields;
+    bool had_seektable = false;
+    bool had_cuesheet = false;
+    unsigned dropped = 0;
+    unsigned duplicates = 0;
 
-            if (count > 0) {
-                ctx.metadata_blocks = static_cast<FLAC__StreamMetadata**>(
-                    std::malloc(sizeof(FLAC__StreamMetadata*) * count));
-                ctx.num_blocks = count;
-                FLAC__metadata_iterator_init(it, chain);
-                unsigned idx = 0;
-                do {
-                    const FLAC__StreamMetadata* block = FLAC__metadata_iterator_get_block(it);
-                    if (block &&
-                        block->type != FLAC__METADATA_TYPE_STREAMINFO &&
-                        block->type != FLAC__METADATA_TYPE_PICTURE) {
-                        ctx.metadata_blocks[idx++] = FLAC__metadata_object_clone(block);
+    for (FLAC__StreamMetadata* block : copy.blocks) {
+        bool keep = true;
+        switch (block->type) {
+            case FLAC__METADATA_TYPE_PADDING:
+                keep = false;
+                break;
+            case FLAC__METADATA_TYPE_SEEKTABLE:
+                had_seektable = true;
+                keep = false;
+                break;
+            case FLAC__METADATA_TYPE_APPLICATION: {
+                // written by flac --keep-foreign-metadata, needed to restore the original WAV/AIFF
+                const std::string_view id(reinterpret_cast<const char*>(block->data.application.id), 4);
+                keep = policy.keep_application || id == "riff" || id == "aiff" || id == "w64 ";
+                break;
+            }
+            case FLAC__METADATA_TYPE_CUESHEET:
+                keep = !had_cuesheet;
+                had_cuesheet = true;
+                break;
+            case FLAC__METADATA_TYPE_VORBIS_COMMENT: {
+                const auto& vc = block->data.vorbis_comment;
+                for (FLAC__uint32 i = 0; i < vc.num_comments; ++i) {
+                    std::string field(reinterpret_cast<const char*>(vc.comments[i].entry), vc.comments[i].length);
+                    // field names are case-insensitive, values are not
+                    std::string key = field;
+                    const auto name_end = std::min(key.find('='), key.size());
+                    std::transform(key.begin(), key.begin() + static_cast<std::ptrdiff_t>(name_end), key.begin(),
+                                   [](unsigned char c) { return static_cast<char>(std::toupper(c)); });
+                    if (seen_fields.insert(key).second) {
+                        fields.push_back(std::move(field));
+                    } else {
+                        ++duplicates;
                     }
-                } while (FLAC__metadata_iterator_next(it));
+                }
+                keep = comments == nullptr;
+                if (keep) comments = block;
+                break;
             }
+            default:
+                break;
+        }
+        if (keep) {
+            kept.push_back(block);
+        } else {
+            FLAC__metadata_object_delete(block);
+            ++dropped;
         }
     }
+    copy.blocks = std::move(kept);
 
-    // encoder/decoder setup
-    ctx.encoder = FLAC__stream_encoder_new();
-    if (!ctx.encoder) {
-        Logger::log(LogLevel::Error, "Can't create encoder", "flac_processor");
-        if (ctx.metadata_blocks) {
-            for (unsigned i = 0; i < ctx.num_blocks; ++i) FLAC__metadata_object_delete(ctx.metadata_blocks[i]);
-            std::free(ctx.metadata_blocks);
+    if (comments) {
+        FLAC__metadata_object_vorbiscomment_resize_comments(comments, 0);
+        for (std::string& field : fields) {
+            FLAC__StreamMetadata_VorbisComment_Entry entry;
+            entry.length = static_cast<FLAC__uint32>(field.size());
+            entry.entry = reinterpret_cast<FLAC__byte*>(field.data());
+            FLAC__metadata_object_vorbiscomment_append_comment(comments, entry, true);
         }
-        if (it) FLAC__metadata_iterator_delete(it);
-        if (chain) FLAC__metadata_chain_delete(chain);
-        throw std::runtime_error("Can't create encoder");
     }
 
+    FLAC__StreamMetadata info;
+    if (had_seektable && policy.seek_interval > 0 &&
+        FLAC__metadata_get_streaminfo(input.string().c_str(), &info) &&
+        info.data.stream_info.total_samples > 0) {
+        if (FLAC__StreamMetadata* table = FLAC__metadata_object_new(FLAC__METADATA_TYPE_SEEKTABLE)) {
+            const auto spacing = std::uint64_t{policy.seek_interval} * info.data.stream_info.sample_rate;
+            if (FLAC__metadata_object_seektable_template_append_spaced_points_by_samples(
+                    table, static_cast<uint32_t>(std::min<std::uint64_t>(spacing, UINT32_MAX)), info.data.stream_info.total_samples) &&
+                FLAC__metadata_object_seektable_template_sort(table, true)) {
+                copy.blocks.push_back(table);
+            } else {
+                FLAC__metadata_object_delete(table);
+            }
+        }
+    }
+
+    Logger::log(LogLevel::Debug,
+                "FLAC: Metadata compacted: " + std::to_string(dropped) + " blocks dropped, " +
+                std::to_string(duplicates) + " duplicate comments removed",
+                "flac_processor");
+}
+
+/**
+ * @brief Appends a PADDING block of the given size (nothing if 0).
+ */
+static void add_padding(MetadataCopy& copy, unsigned padding) {
+    if (padding == 0) return;
+    if (FLAC__StreamMetadata* block = FLAC__metadata_object_new(FLAC__METADATA_TYPE_PADDING)) {
+        block->length = padding;
+        copy.blocks.push_back(block);
+    }
+}
+
+/**
+ * @brief Replaces every PADDING block of a FLAC file with a single one of `padding` bytes at the end.
+ * @return true if the file was rewritten.
+ */
+static bool rewrite_padding(const fs::path& file, unsigned padding) {
+    FLAC__Metadata_Chain* chain = FLAC__metadata_chain_new();
+    if (!chain) return false;
+    bool ok = FLAC__metadata_chain_read(chain, file.string().c_str());
+    FLAC__Metadata_Iterator* it = ok ? FLAC__metadata_iterator_new() : nullptr;
+    if (it) {
+        FLAC__metadata_iterator_init(it, chain);
+        // the first block is STREAMINFO
+        while (FLAC__metadata_iterator_next(it)) {
+            if (FLAC__metadata_iterator_get_block_type(it) == FLAC__METADATA_TYPE_PADDING) {
+                FLAC__metadata_iterator_delete_block(it, false);
+            }
+        }
+        if (padding > 0) {
+            FLAC__StreamMetadata* block = FLAC__metadata_object_new(FLAC__METADATA_TYPE_PADDING);
+            if (block) block->length = padding;
+            if (!block || !FLAC__metadata_iterator_insert_block_after(it, block)) {
+                if (block) FLAC__metadata_object_delete(block);
+                ok = false;
+            }
+        }
+        // without use_padding, a smaller metadata section rewrites the whole file
+        ok = ok && FLAC__metadata_chain_write(chain, false, false);
+        FLAC__metadata_iterator_delete(it);
+    } else {
+        ok = false;
+    }
+    FLAC__metadata_chain_delete(chain);
+    return ok;
+}
+
+/**
+ * @brief Runs a decoder over a whole FLAC file.
+ * @param input The FLAC file to decode.
+ * @param write_cb Receives every decoded frame.
+ * @param client_data Passed to write_cb.
+ * @param check_md5 If true, the decoded audio must match the STREAMINFO MD5.
+ * @return true if the file was decoded to the end (and matched its MD5).
+ * @throws std::runtime_error if the decoder cannot be created or initialized.
+ */
+static bool run_decoder(const fs::path& input,
+                        FLAC__StreamDecoderWriteCallback write_cb,
+                        void* client_data,
+                        bool check_md5 = false)
+{
     FLAC__StreamDecoder* decoder = FLAC__stream_decoder_new();
     if (!decoder) {
         Logger::log(LogLevel::Error, "Can't create decoder", "flac_processor");
-        FLAC__stream_encoder_delete(ctx.encoder);
-        if (ctx.metadata_blocks) {
-            for (unsigned i = 0; i < ctx.num_blocks; ++i) FLAC__metadata_object_delete(ctx.metadata_blocks[i]);
-            std::free(ctx.metadata_blocks);
-        }
-        if (it) FLAC__metadata_iterator_delete(it);
-        if (chain) FLAC__metadata_chain_delete(chain);
         throw std::runtime_error("Can't create decoder");
     }
+    FLAC__stream_decoder_set_md5_checking(decoder, check_md5);
 
     const auto ist = FLAC__stream_decoder_init_file(
-        decoder, input.string().c_str(), write_callback, metadata_callback, error_callback, &ctx);
+        decoder, input.string().c_str(), write_cb, metadata_callback, error_callback, client_data);
     if (ist != FLAC__STREAM_DECODER_INIT_STATUS_OK) {
         Logger::log(LogLevel::Error, "decoder init failed", "flac_processor");
         FLAC__stream_decoder_delete(decoder);
-        FLAC__stream_encoder_delete(ctx.encoder);
-        if (ctx.metadata_blocks) {
-            for (unsigned i = 0; i < ctx.num_blocks; ++i) FLAC__metadata_object_delete(ctx.metadata_blocks[i]);
-            std::free(ctx.metadata_blocks);
-        }
-        if (it) FLAC__metadata_iterator_delete(it);
-        if (chain) FLAC__metadata_chain_delete(chain);
         throw std::runtime_error("decoder init failed");
     }
 
-    // transcode
     const bool ok = FLAC__stream_decoder_process_until_end_of_stream(decoder);
-    const bool failed = (!ok) || ctx.failed;
-
-    FLAC__stream_decoder_finish(decoder);
+    // finish() reports an MD5 mismatch when checking is enabled
+    const bool md5_ok = FLAC__stream_decoder_finish(decoder);
     FLAC__stream_decoder_delete(decoder);
+    return ok && md5_ok;
+}
 
-    if (ctx.encoder_initialized) {
-        FLAC__stream_encoder_finish(ctx.encoder);
+/**
+ * @brief Decoder write callback that drops the audio.
+ */
+static FLAC__StreamDecoderWriteStatus discard_callback(
+    const FLAC__StreamDecoder*, const FLAC__Frame*, const FLAC__int32* const[], void*)
+{
+    return FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE;
+}
+
+/**
+ * @brief Checks that a FLAC file decodes to the MD5 stored in its STREAMINFO.
+ */
+static bool verify_md5(const fs::path& file) {
+    return run_decoder(file, discard_callback, nullptr, true);
+}
+
+/**
+ * @brief Decodes a FLAC file and re-encodes it with the given settings.
+ * @param input Path to the source FLAC file.
+ * @param output Path to write the encoded file.
+ * @param settings The encoder parameters.
+ * @param metadata Blocks to copy into the new file; cloned, so the seektable template is not consumed.
+ * @return true on success.
+ * @throws std::runtime_error if libFLAC objects cannot be created.
+ */
+static bool transcode(const fs::path& input,
+                      const fs::path& output,
+                      const EncoderSettings& settings,
+                      const MetadataCopy& metadata)
+{
+    MetadataCopy blocks;
+    for (const FLAC__StreamMetadata* block : metadata.blocks) {
+        if (FLAC__StreamMetadata* clone = FLAC__metadata_object_clone(block)) blocks.blocks.push_back(clone);
+    }
+
+    TranscodeContext ctx;
+    ctx.output = output;
+    ctx.settings = &settings;
+    if (!blocks.blocks.empty()) {
+        ctx.metadata_blocks = blocks.blocks.data();
+        ctx.num_blocks = static_cast<unsigned>(blocks.blocks.size());
+    }
+
+    ctx.encoder = FLAC__stream_encoder_new();
+    if (!ctx.encoder) {
+        Logger::log(LogLevel::Error, "Can't create encoder", "flac_processor");
+        throw std::runtime_error("Can't create encoder");
+    }
+
+    bool ok = false;
+    try {
+        ok = run_decoder(input, write_callback, &ctx);
+    } catch (...) {
+        FLAC__stream_encoder_delete(ctx.encoder);
+        throw;
+    }
+
+    if (ctx.encoder_initialized && !FLAC__stream_encoder_finish(ctx.encoder)) {
+        ok = false;
     }
     FLAC__stream_encoder_delete(ctx.encoder);
+    return ok && !ctx.failed;
+}
+
+namespace {
+
+/**
+ * @brief One frame produced by a tree-level encoder.
+ */
+struct EncodedFrame {
+    Bytes bytes;
+    unsigned samples = 0;
+};
+
+/**
+ * @brief A fixed-blocksize encoder whose frames are candidates for the variable stream.
+ */
+struct VariableLevel {
+    FLAC__StreamEncoder* encoder = nullptr;
+    unsigned blocksize = 0;
+    std::deque<EncodedFrame> frames;
+};
+
+/**
+ * @brief Where a frame of the variable stream landed, for the seektable.
+ */
+struct FrameRecord {
+    std::uint64_t first_sample = 0;
+    std::uint64_t offset = 0;       ///< from the first frame
+    unsigned samples = 0;
+};
+
+struct VariableContext {
+    std::array<VariableLevel, kVariableBlocksizes.size()> levels;
+    const EncoderSettings* settings = nullptr;
+    std::ofstream* out = nullptr;
+    std::vector<FrameRecord> written;
+    std::uint64_t next_sample = 0;
+    std::uint64_t next_offset = 0;
+    unsigned min_framesize = 0;
+    unsigned max_framesize = 0;
+    bool initialized = false;
+    bool failed = false;
+
+    VariableContext() = default;
+    VariableContext(const VariableContext&) = delete;
+    VariableContext& operator=(const VariableContext&) = delete;
+    ~VariableContext() {
+        for (VariableLevel& level : levels) {
+            if (level.encoder) FLAC__stream_encoder_delete(level.encoder);
+        }
+    }
+};
+
+std::uint8_t crc8(const unsigned char* data, std::size_t size) {
+    std::uint8_t crc = 0;
+    for (std::size_t i = 0; i < size; ++i) {
+        crc ^= data[i];
+        for (int bit = 0; bit < 8; ++bit) {
+            crc = static_cast<std::uint8_t>((crc & 0x80) ? (crc << 1) ^ 0x07 : crc << 1);
+        }
+    }
+    return crc;
+}
+
+std::uint16_t crc16(const unsigned char* data, std::size_t size) {
+    std::uint16_t crc = 0;
+    for (std::size_t i = 0; i < size; ++i) {
+        crc ^= static_cast<std::uint16_t>(data[i] << 8);
+        for (int bit = 0; bit < 8; ++bit) {
+            crc = static_cast<std::uint16_t>((crc & 0x8000) ? (crc << 1) ^ 0x8005 : crc << 1);
+        }
+    }
+    return crc;
+}
+
+/**
+ * @brief Appends a number in the UTF-8-like coding of FLAC frame headers (up to 36 bits).
+ */
+void put_coded_number(Bytes& out, std::uint64_t value) {
+    if (value < 0x80) {
+        out.push_back(static_cast<unsigned char>(value));
+        return;
+    }
+    int length = 2;
+    while (length < 7 && value >= (std::uint64_t{1} << (5 * length + 1))) ++length;
+    out.push_back(static_cast<unsigned char>((0xFF00u >> length) | (length < 7 ? value >> (6 * (length - 1)) : 0)));
+    for (int i = length - 2; i >= 0; --i) {
+        out.push_back(static_cast<unsigned char>(0x80 | ((value >> (6 * i)) & 0x3F)));
+    }
+}
+
+/**
+ * @brief Turns a fixed-blocksize frame into a variable-blocksize one.
+ *
+ * The frame number in the header becomes the number of its first sample;
+ * both CRCs are recomputed, the subframes are copied untouched.
+ */
+Bytes to_variable_frame(const Bytes& frame, std::uint64_t first_sample) {
+    if (frame.size() < 8 || frame[0] != 0xFF || (frame[1] & 0xFE) != 0xF8) {
+        throw std::runtime_error("FlacProcessor: unexpected frame header");
+    }
+    const unsigned lead = frame[4];
+    const int coded = lead < 0x80 ? 1 : std::countl_one(static_cast<unsigned char>(lead));
+    if (coded > 7 || (lead >= 0x80 && coded == 1)) {
+        throw std::runtime_error("FlacProcessor: bad frame number");
+    }
+    std::size_t end = 4 + static_cast<std::size_t>(coded);
+    const unsigned blocksize_code = frame[2] >> 4;
+    const unsigned rate_code = frame[2] & 0x0F;
+    if (blocksize_code == 6) end += 1;
+    if (blocksize_code == 7) end += 2;
+    if (rate_code == 12) end += 1;
+    if (rate_code == 13 || rate_code == 14) end += 2;
+    if (end + 3 > frame.size()) throw std::runtime_error("FlacProcessor: truncated frame");
+
+    Bytes out = { 0xFF, 0xF9, frame[2], frame[3] };
+    put_coded_number(out, first_sample);
+    out.insert(out.end(), frame.begin() + 4 + coded, frame.begin() + static_cast<std::ptrdiff_t>(end));
+    out.push_back(crc8(out.data(), out.size()));
+    out.insert(out.end(), frame.begin() + static_cast<std::ptrdiff_t>(end) + 1, frame.end() - 2);
+    const std::uint16_t crc = crc16(out.data(), out.size());
+    out.push_back(static_cast<unsigned char>(crc >> 8));
+    out.push_back(static_cast<unsigned char>(crc & 0xFF));
+    return out;
+}
+
+/**
+ * @brief Picks the cheapest way to code one node of the blocksize tree.
+ *
+ * A node is either the frame of its own level or the best coding of its
+ * two halves one level down; ties keep the larger frame.
+ */
+std::size_t plan_node(const VariableContext& ctx,
+                      const std::array<std::size_t, kVariableBlocksizes.size()>& counts,
+                      std::size_t level, std::size_t index,
+                      std::vector<std::pair<std::size_t, std::size_t>>& chosen)
+{
+    const std::size_t whole = ctx.levels[level].frames[index].bytes.size();
+    if (level + 1 < ctx.levels.size()) {
+        std::vector<std::pair<std::size_t, std::size_t>> halves;
+        std::size_t split = 0;
+        for (std::size_t child = 2 * index; child < 2 * index + 2 && child < counts[level + 1]; ++child) {
+            split += plan_node(ctx, counts, level + 1, child, halves);
+        }
+        if (split < whole) {
+            chosen.insert(chosen.end(), halves.begin(), halves.end());
+            return split;
+        }
+    }
+    chosen.emplace_back(level, index);
+    return whole;
+}
+
+/**
+ * @brief Writes every superblock that all levels have finished encoding.
+ * @param final If true, the encoders are done and the last, partial superblock is written too.
+ */
+void emit_superblocks(VariableContext& ctx, bool final) {
+    std::array<std::size_t, kVariableBlocksizes.size()> counts{};
+    while (!ctx.levels[0].frames.empty()) {
+        for (std::size_t l = 0; l < ctx.levels.size(); ++l) {
+            const std::size_t per_superblock = kVariableBlocksizes[0] / kVariableBlocksizes[l];
+            if (!final && ctx.levels[l].frames.size() < per_superblock) return;
+            counts[l] = std::min(per_superblock, ctx.levels[l].frames.size());
+        }
+
+        std::vector<std::pair<std::size_t, std::size_t>> chosen;
+        plan_node(ctx, counts, 0, 0, chosen);
+        for (const auto& [level, index] : chosen) {
+            const EncodedFrame& frame = ctx.levels[level].frames[index];
+            const Bytes bytes = to_variable_frame(frame.bytes, ctx.next_sample);
+            ctx.out->write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+            ctx.written.push_back({ctx.next_sample, ctx.next_offset, frame.samples});
+            ctx.next_sample += frame.samples;
+            ctx.next_offset += bytes.size();
+            const auto size = static_cast<unsigned>(bytes.size());
+            ctx.min_framesize = ctx.min_framesize ? std::min(ctx.min_framesize, size) : size;
+            ctx.max_framesize = std::max(ctx.max_framesize, size);
+        }
+        for (std::size_t l = 0; l < ctx.levels.size(); ++l) {
+            ctx.levels[l].frames.erase(ctx.levels[l].frames.begin(),
+                                       ctx.levels[l].frames.begin() + static_cast<std::ptrdiff_t>(counts[l]));
+        }
+    }
+}
+
+} // namespace
+
+/**
+ * @brief Encoder write callback of the variable-blocksize tree levels.
+ * Keeps each frame in memory until its superblock can be decided; metadata is discarded.
+ */
+static FLAC__StreamEncoderWriteStatus level_write_callback(
+    const FLAC__StreamEncoder*,
+    const FLAC__byte buffer[],
+    size_t bytes,
+    uint32_t samples,
+    uint32_t,
+    void* client_data)
+{
+    // metadata is written with samples == 0
+    if (samples > 0) {
+        auto* level = static_cast<VariableLevel*>(client_data);
+        level->frames.push_back({Bytes(buffer, buffer + bytes), samples});
+    }
+    return FLAC__STREAM_ENCODER_WRITE_STATUS_OK;
+}
+
+/**
+ * @brief FLAC decoder write callback of the variable-blocksize encoder.
+ * Feeds every tree level with the decoded audio and writes the superblocks that are complete.
+ */
+static FLAC__StreamDecoderWriteStatus variable_write_callback(
+    const FLAC__StreamDecoder*,
+    const FLAC__Frame* frame,
+    const FLAC__int32* const buffer[],
+    void* client_data)
+{
+    auto* ctx = static_cast<VariableContext*>(client_data);
+    try {
+        if (!ctx->initialized) {
+            for (std::size_t l = 0; l < ctx->levels.size(); ++l) {
+                VariableLevel& level = ctx->levels[l];
+                level.blocksize = kVariableBlocksizes[l];
+                level.encoder = FLAC__stream_encoder_new();
+                if (!level.encoder) throw std::runtime_error("Can't create encoder");
+                FLAC__stream_encoder_set_channels(level.encoder, frame->header.channels);
+                FLAC__stream_encoder_set_bits_per_sample(level.encoder, frame->header.bits_per_sample);
+                FLAC__stream_encoder_set_sample_rate(level.encoder, frame->header.sample_rate);
+                EncoderSettings settings = *ctx->settings;
+                settings.blocksize = level.blocksize;
+                apply_settings(level.encoder, settings);
+                FLAC__stream_encoder_set_do_md5(level.encoder, false);
+
+                const auto st = FLAC__stream_encoder_init_stream(
+                    level.encoder, level_write_callback, nullptr, nullptr, nullptr, &level);
+                if (st != FLAC__STREAM_ENCODER_INIT_STATUS_OK) {
+                    throw std::runtime_error(std::string("FLAC init error: ") + FLAC__StreamEncoderInitStatusString[st]);
+                }
+            }
+            ctx->initialized = true;
+        }
+
+        for (VariableLevel& level : ctx->levels) {
+            if (!FLAC__stream_encoder_process(level.encoder, buffer, frame->header.blocksize)) {
+                throw std::runtime_error("encoder process failed");
+            }
+        }
+        emit_superblocks(*ctx, false);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, std::string("FLAC variable blocksize: ") + e.what(), "flac_processor");
+        ctx->failed = true;
+        return FLAC__STREAM_DECODER_WRITE_STATUS_ABORT;
+    }
+    return FLAC__STREAM_DECODER_WRITE_STATUS_CONTINUE;
+}
+
+/**
+ * @brief Points every seek point of a SEEKTABLE body at the variable-blocksize frame holding its sample.
+ *
+ * Points that collapse onto the same frame become placeholders, which go last.
+ */
+static void relocate_seektable(unsigned char* body, std::size_t size, const std::vector<FrameRecord>& frames) {
+    constexpr std::uint64_t kPlaceholder = ~std::uint64_t{0};
+    auto get = [](const unsigned char* p, int n) {
+        std::uint64_t v = 0;
+        for (int i = 0; i < n; ++i) v = (v << 8) | p[i];
+        return v;
+    };
+    auto put = [](unsigned char* p, int n, std::uint64_t v) {
+        for (int i = n - 1; i >= 0; --i, v >>= 8) p[i] = static_cast<unsigned char>(v & 0xFF);
+    };
+
+    const std::size_t count = size / 18;
+    std::vector<FrameRecord> points;
+    for (std::size_t i = 0; i < count; ++i) {
+        const std::uint64_t target = get(body + 18 * i, 8);
+        if (target == kPlaceholder) continue;
+        const auto it = std::ranges::upper_bound(frames, target, {}, &FrameRecord::first_sample);
+        if (it == frames.begin()) continue;
+        const FrameRecord& frame = *std::prev(it);
+        if (target >= frame.first_sample + frame.samples) continue;
+        if (points.empty() || points.back().first_sample != frame.first_sample) points.push_back(frame);
+    }
+    std::ranges::sort(points, {}, &FrameRecord::first_sample);
+    for (std::size_t i = 0; i < count; ++i) {
+        unsigned char* point = body + 18 * i;
+        if (i < points.size()) {
+            put(point, 8, points[i].first_sample);
+            put(point + 8, 8, points[i].offset);
+            put(point + 16, 2, points[i].samples);
+        } else {
+            put(point, 8, kPlaceholder);
+            put(point + 8, 8, 0);
+            put(point + 16, 2, 0);
+        }
+    }
+}
+
+/**
+ * @brief Encodes a FLAC file with a variable blocksize, in the style of flake.
+ *
+ * Every superblock of kVariableBlocksizes[0] samples is encoded at each
+ * blocksize of the tree, and the cheapest split into frames is kept. The
+ * metadata (STREAMINFO MD5, tags, seektable size) comes from `base`, a
+ * fixed-blocksize encode of the same audio; STREAMINFO block and frame
+ * sizes and the seek points are updated afterwards.
+ *
+ * @param input Path to the source FLAC file.
+ * @param base A fixed-blocksize encode of `input`, written by transcode().
+ * @param output Path to write the variable-blocksize file.
+ * @param settings Apodization and precision for every level.
+ * @return true if the new file was written and decodes to the MD5 of `base`.
+ */
+static bool encode_variable(const fs::path& input,
+                            const fs::path& base,
+                            const fs::path& output,
+                            const EncoderSettings& settings)
+{
+    // the metadata of the base file, with the offsets of the bodies to patch
+    std::ifstream in(base, std::ios::binary);
+    Bytes header(4);
+    if (!in.read(reinterpret_cast<char*>(header.data()), 4) || std::string_view(reinterpret_cast<const char*>(header.data()), 4) != "fLaC") {
+        return false;
+    }
+    std::size_t streaminfo = 0;
+    std::vector<std::pair<std::size_t, std::size_t>> seektables;
+    for (bool last = false; !last;) {
+        unsigned char block[4];
+        if (!in.read(reinterpret_cast<char*>(block), 4)) return false;
+        last = (block[0] & 0x80) != 0;
+        const std::size_t length = (std::size_t{block[1]} << 16) | (std::size_t{block[2]} << 8) | block[3];
+        header.insert(header.end(), block, block + 4);
+        const std::size_t body = header.size();
+        header.resize(body + length);
+        if (!in.read(reinterpret_cast<char*>(header.data() + body), static_cast<std::streamsize>(length))) return false;
+        const unsigned type = block[0] & 0x7F;
+        if (type == FLAC__METADATA_TYPE_STREAMINFO && length >= 34) streaminfo = body;
+        if (type == FLAC__METADATA_TYPE_SEEKTABLE) seektables.emplace_back(body, length);
+    }
+    in.close();
+    if (streaminfo != 8) return false;
+
+    std::ofstream out(output, std::ios::binary | std::ios::trunc);
+    if (!out) return false;
+    out.write(reinterpret_cast<const char*>(header.data()), static_cast<std::streamsize>(header.size()));
 
-    if (ctx.metadata_blocks) {
-        for (unsigned i = 0; i < ctx.num_blocks; ++i) FLAC__metadata_object_delete(ctx.metadata_blocks[i]);
-        std::free(ctx.metadata_blocks);
+    VariableContext ctx;
+    ctx.settings = &settings;
+    ctx.out = &out;
+    bool ok = run_decoder(input, variable_write_callback, &ctx) && !ctx.failed && ctx.initialized;
+    if (ok) {
+        for (VariableLevel& level : ctx.levels) {
+            ok = FLAC__stream_encoder_finish(level.encoder) && ok;
+        }
+    }
+    if (ok) {
+        try {
+            emit_superblocks(ctx, true);
+        } catch (const std::exception& e) {
+            Logger::log(LogLevel::Error, std::string("FLAC variable blocksize: ") + e.what(), "flac_processor");
+            ok = false;
+        }
+    }
+    ok = ok && !ctx.written.empty() && std::ranges::all_of(ctx.levels, [](const VariableLevel& l) { return l.frames.empty(); });
+    if (!ok) return false;
+
+    // STREAMINFO: the min blocksize leaves out the last frame, unless it is the only one
+    unsigned min_blocksize = 0;
+    unsigned max_blocksize = 0;
+    for (std::size_t i = 0; i < ctx.written.size(); ++i) {
+        const unsigned samples = ctx.written[i].samples;
+        if (i + 1 < ctx.written.size() || i == 0) {
+            min_blocksize = min_blocksize ? std::min(min_blocksize, samples) : samples;
+        }
+        max_blocksize = std::max(max_blocksize, samples);
+    }
+    unsigned char* info = header.data() + streaminfo;
+    info[0] = static_cast<unsigned char>(min_blocksize >> 8);
+    info[1] = static_cast<unsigned char>(min_blocksize & 0xFF);
+    info[2] = static_cast<unsigned char>(max_blocksize >> 8);
+    info[3] = static_cast<unsigned char>(max_blocksize & 0xFF);
+    for (int i = 0; i < 3; ++i) {
+        info[4 + i] = static_cast<unsigned char>((ctx.min_framesize >> (16 - 8 * i)) & 0xFF);
+        info[7 + i] = static_cast<unsigned char>((ctx.max_framesize >> (16 - 8 * i)) & 0xFF);
+    }
+    for (const auto& [body, length] : seektables) {
+        relocate_seektable(header.data() + body, length, ctx.written);
     }
-    if (it) FLAC__metadata_iterator_delete(it);
-    if (chain) FLAC__metadata_chain_delete(chain);
 
-    if (failed) {
+    out.seekp(0);
+    out.write(reinterpret_cast<const char*>(header.data()), static_cast<std::streamsize>(header.size()));
+    out.close();
+    if (!out) return false;
+
+    // the frames were rewritten by hand: decode them against the MD5 of the base file
+    return verify_md5(output);
+}
+
+void FlacProcessor::recompress(const std::filesystem::path& input,
+                               const std::filesystem::path& output,
+                               const bool preserve_metadata)
+{
+    Logger::log(LogLevel::Info, "Starting FLAC re-encoding: " + input.string(), "flac_processor");
+
+    if (std::filesystem::exists(output)) {
+        std::filesystem::remove(output);
+    }
+
+    // metadata copy (optional), compacted by the policy
+    MetadataCopy metadata;
+    if (preserve_metadata) {
+        read_metadata(input, metadata);
+        compact_metadata(input, metadata_policy_, metadata);
+    }
+    add_padding(metadata, metadata_policy_.padding);
+
+    if (effort_ == FlacEffort::Normal) {
+        if (!transcode(input, output, EncoderSettings{}, metadata)) {
+            Logger::log(LogLevel::Error, "decoding or encoding failed", "flac_processor");
+            throw std::runtime_error("FLAC transcoding failed");
+        }
+        Logger::log(LogLevel::Info, "FLAC re-encoding completed: " + output.string(), "flac_processor");
+        return;
+    }
+
+    std::optional<EncoderSettings> best;
+    BestCandidate best_file(output, ".flac");
+    unsigned encodes = 0;
+    auto keep_if_smaller = [&](const fs::path& candidate, bool ok) {
+        if (ok) return best_file.offer(candidate);
+        best_file.discard(candidate);
+        return false;
+    };
+    auto try_settings = [&](const EncoderSettings& settings) {
+        if (best && settings == *best) return;
+        const fs::path candidate = best_file.next_path();
+        bool ok = false;
+        try {
+            ok = transcode(input, candidate, settings, metadata);
+        } catch (const std::exception& e) {
+            Logger::log(LogLevel::Warning, std::string("FLAC: Candidate failed: ") + e.what(), "flac_processor");
+        }
+        ++encodes;
+        if (keep_if_smaller(candidate, ok)) best = settings;
+        Logger::log(LogLevel::Debug, "FLAC: " + describe(settings) + (ok ? "" : " failed"), "flac_processor");
+    };
+
+    EncoderSettings settings;
+    settings.max_partition_order = 8;
+    for (const unsigned blocksize : kSearchBlocksizes) {
+        settings.blocksize = blocksize;
+        try_settings(settings);
+    }
+    if (!best) {
         Logger::log(LogLevel::Error, "decoding or encoding failed", "flac_processor");
         throw std::runtime_error("FLAC transcoding failed");
     }
+    settings = *best;
+    for (const char* apodization : kSearchApodizations) {
+        settings.apodization = apodization;
+        try_settings(settings);
+    }
+    settings = *best;
+    for (const auto& [precision, search] : kSearchPrecisions) {
+        settings.qlp_precision = precision;
+        settings.precision_search = search;
+        try_settings(settings);
+    }
+
+    std::string chosen = describe(*best);
+    if (effort_ == FlacEffort::Extreme) {
+        const fs::path candidate = best_file.next_path();
+        bool ok = false;
+        try {
+            ok = encode_variable(input, best_file.path(), candidate, *best);
+        } catch (const std::exception& e) {
+            Logger::log(LogLevel::Warning, std::string("FLAC: Variable blocksize failed: ") + e.what(), "flac_processor");
+        }
+        ++encodes;
+        if (keep_if_smaller(candidate, ok)) chosen = "variable blocksize, apodization " + std::string(best->apodization);
+    }
 
+    best_file.commit();
+    Logger::log(LogLevel::Debug, "FLAC: Best of " + std::to_string(encodes) + " encodes: " + chosen, "flac_processor");
     Logger::log(LogLevel::Info, "FLAC re-encoding completed: " + output.string(), "flac_processor");
 }
 /**
@@ -304,29 +1024,34 @@ std::filesystem::path FlacProcessor::finalize_extraction(const ExtractedContent
         return {};
     }
 
-    // 5. cleanup
+    // 5. taglib pads the rewritten metadata on its own: bring the padding back to the policy size
+    if (!rewrite_padding(final_temp_path, metadata_policy_.padding)) {
+        Logger::log(LogLevel::Warning, "FLAC: Could not compact padding of: " + final_temp_path.string(), "flac_processor");
+    }
+
+    // 6. cleanup
     cleanup_temp_dir(content.temp_dir);
 
-    // 6. return path to the finalized file.
+    // 7. return path to the finalized file.
     // processorexecutor will handle replacing the original.
     return final_temp_path;
 }
 
 
 std::string FlacProcessor::get_raw_checksum(const std::filesystem::path& file_path) const {
-    FLAC__StreamMetadata* metadata = nullptr;
+    // filled in place: libFLAC does not allocate the block here
+    FLAC__StreamMetadata metadata;
 
-    if (!FLAC__metadata_get_streaminfo(file_path.string().c_str(), metadata)) {
+    if (!FLAC__metadata_get_streaminfo(file_path.string().c_str(), &metadata)) {
         throw std::runtime_error("Failed to read STREAMINFO from FLAC file: " + file_path.string());
     }
 
     std::ostringstream oss;
     for (int i = 0; i < 16; ++i) {
         oss << std::hex << std::setw(2) << std::setfill('0')
-            << static_cast<int>(metadata->data.stream_info.md5sum[i]);
+            << static_cast<int>(metadata.data.stream_info.md5sum[i]);
     }
 
-    FLAC__metadata_object_delete(metadata);
     return oss.str();
 }
 
@@ -409,6 +1134,22 @@ std::vector<int32_t> decode_flac_pcm(const std::filesystem::path& file,
 
 bool FlacProcessor::raw_equal(const std::filesystem::path& a,
                               const std::filesystem::path& b) const {
+    // matching STREAMINFO MD5s are enough once b is known to decode to its own
+    constexpr std::string_view kUnsetMd5 = "00000000000000000000000000000000";
+    std::string md5_a;
+    std::string md5_b;
+    try {
+        md5_a = get_raw_checksum(a);
+        md5_b = get_raw_checksum(b);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("FLAC: MD5 comparison unavailable: ") + e.what(), "flac_processor");
+    }
+    const bool md5_a_set = !md5_a.empty() && md5_a != kUnsetMd5;
+    const bool md5_b_set = !md5_b.empty() && md5_b != kUnsetMd5;
+    if (md5_a_set && md5_b_set) {
+        return md5_a == md5_b && verify_md5(b);
+    }
+
     unsigned ra, ca, bpsa;
     unsigned rb, cb, bpsb;
     const auto pcmA = decode_flac_pcm(a, ra, ca, bpsa);
@@ -418,4 +1159,4 @@ bool FlacProcessor::raw_equal(const std::filesystem::path& a,
     return pcmA == pcmB;
 }
 
-} // namespace chisel
\ No newline at end of file
+} // namespace chisel
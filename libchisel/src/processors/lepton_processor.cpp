@@ -0,0 +1,116 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/lepton_processor.hpp"
+#include "../../include/logger.hpp"
+#include "../../include/random_utils.hpp"
+#include "../../include/rust_bridge.hpp"
+#include <filesystem>
+#include <fstream>
+#include <iterator>
+#include <stdexcept>
+#include <string>
+#include <vector>
+
+namespace chisel {
+
+namespace fs = std::filesystem;
+
+static const char* processor_tag() {
+    return "LeptonProcessor";
+}
+
+namespace {
+
+    // Builds an exception from a failed bridge call, releasing its message
+    std::runtime_error bridge_error(const std::string& operation, const int result, char* error_message) {
+        std::string msg = operation + " failed with error code " + std::to_string(result);
+        if (error_message != nullptr) {
+            msg += ": " + std::string(error_message);
+            chisel_free_string(error_message);
+        }
+        return std::runtime_error(msg);
+    }
+
+    std::vector<char> read_all(const fs::path& path) {
+        std::ifstream in(path, std::ios::binary);
+        if (!in) throw std::runtime_error("cannot open " + path.string());
+        return {std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>()};
+    }
+
+} // namespace
+
+void LeptonProcessor::recompress(const fs::path& input,
+                                 const fs::path& output,
+                                 bool /*preserve_metadata*/) {
+    Logger::log(LogLevel::Info, "Archiving JPEG as Lepton: " + input.string(), processor_tag());
+
+    const std::string input_str = input.string();
+    const std::string output_str = output.string();
+
+    char* error_message = nullptr;
+    const int result = chisel_lepton_compress(input_str.c_str(), output_str.c_str(), &error_message);
+    if (result != CHISEL_VORBIS_OK) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        const auto err = bridge_error("Lepton compression", result, error_message);
+        Logger::log(result == CHISEL_VORBIS_ERR_VERIFY ? LogLevel::Error : LogLevel::Warning,
+                    err.what(), processor_tag());
+        throw err;
+    }
+
+    Logger::log(LogLevel::Debug, "Lepton round-trip verified: " + output.string(), processor_tag());
+}
+
+void LeptonProcessor::restore(const fs::path& input, const fs::path& output) {
+    Logger::log(LogLevel::Info, "Restoring JPEG from Lepton: " + input.string(), processor_tag());
+
+    const std::string input_str = input.string();
+    const std::string output_str = output.string();
+
+    char* error_message = nullptr;
+    const int result = chisel_lepton_restore(input_str.c_str(), output_str.c_str(), &error_message);
+    if (result != CHISEL_VORBIS_OK) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        const auto err = bridge_error("Lepton restore", result, error_message);
+        Logger::log(LogLevel::Error, err.what(), processor_tag());
+        throw err;
+    }
+}
+
+fs::path LeptonProcessor::restored_filename(const fs::path& lepton_file) {
+    fs::path name = lepton_file.stem();
+    if (!name.has_extension()) {
+        name += ".jpg";
+    }
+    return name;
+}
+
+bool LeptonProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    const fs::path restored = fs::temp_directory_path() /
+                              (b.stem().string() + "_restore" + RandomUtils::random_suffix() + ".jpg");
+    try {
+        restore(b, restored);
+        const bool equal = read_all(a) == read_all(restored);
+        std::error_code ec;
+        fs::remove(restored, ec);
+        if (!equal) {
+            Logger::log(LogLevel::Error, "Restored JPEG differs from the original: " + a.string(), processor_tag());
+        }
+        return equal;
+    } catch (const std::exception& e) {
+        std::error_code ec;
+        fs::remove(restored, ec);
+        Logger::log(LogLevel::Error, std::string("Lepton verification failed: ") + e.what(), processor_tag());
+        return false;
+    }
+}
+
+std::string LeptonProcessor::get_raw_checksum(const fs::path&) const {
+    // verification restores the JPEG and compares bytes, see raw_equal
+    return "";
+}
+
+} // namespace chisel
@@ -3,85 +3,1351 @@
 //
 
 #include "../../include/mkv_processor.hpp"
+#include "../../include/file_type.hpp"
 #include "../../include/logger.hpp"
+#include "../../include/random_utils.hpp"
+#include "file_utils.hpp"
+#include <algorithm>
+#include <any>
+#include <array>
+#include <cstdint>
+#include <fstream>
+#include <functional>
+#include <iterator>
+#include <map>
+#include <optional>
 #include <vector>
 #include <string>
 #include <stdexcept>
 #include <system_error>
 #include <filesystem>
-
-// forward declaration of mkclean API
-extern "C" int mkclean_optimize(int argc, char* argv[]);
+#include <zlib.h>
 
 namespace chisel {
 
+namespace fs = std::filesystem;
+
+namespace {
+
+    const char* processor_tag() {
+        return "mkv_processor";
+    }
+
+    constexpr std::size_t COPY_BLOCK = 1 << 20;
+    // elements read into memory; anything bigger is not a plausible index or attachment list
+    constexpr std::uint64_t MAX_INDEX_SIZE = 256ull << 20;
+    constexpr std::uint64_t MAX_ATTACHMENTS_SIZE = 1ull << 30;
+
+    // Matroska element IDs, marker bits included
+    constexpr std::uint32_t ID_EBML = 0x1A45DFA3;
+    constexpr std::uint32_t ID_SEGMENT = 0x18538067;
+    constexpr std::uint32_t ID_SEEK_HEAD = 0x114D9B74;
+    constexpr std::uint32_t ID_SEEK = 0x4DBB;
+    constexpr std::uint32_t ID_SEEK_POSITION = 0x53AC;
+    constexpr std::uint32_t ID_CUES = 0x1C53BB6B;
+    constexpr std::uint32_t ID_CUE_POINT = 0xBB;
+    constexpr std::uint32_t ID_CUE_TRACK_POSITIONS = 0xB7;
+    constexpr std::uint32_t ID_CUE_CLUSTER_POSITION = 0xF1;
+    constexpr std::uint32_t ID_CUE_CODEC_STATE = 0xEA;
+    constexpr std::uint32_t ID_CUE_REFERENCE = 0xDB;
+    constexpr std::uint32_t ID_CUE_REF_CLUSTER = 0x97;
+    constexpr std::uint32_t ID_CUE_REF_CODEC_STATE = 0xEB;
+    constexpr std::uint32_t ID_CLUSTER = 0x1F43B675;
+    constexpr std::uint32_t ID_CLUSTER_POSITION = 0xA7;
+    constexpr std::uint32_t ID_ATTACHMENTS = 0x1941A469;
+    constexpr std::uint32_t ID_ATTACHED_FILE = 0x61A7;
+    constexpr std::uint32_t ID_FILE_NAME = 0x466E;
+    constexpr std::uint32_t ID_FILE_DATA = 0x465C;
+    constexpr std::uint32_t ID_CRC32 = 0xBF;
+    constexpr std::uint32_t ID_VOID = 0xEC;
+
+    // children that may follow each other inside a Cluster of unknown size
+    constexpr std::array<std::uint32_t, 9> kClusterChildren = {
+        0xE7, 0x5854, ID_CLUSTER_POSITION, 0xAB, 0xA3, 0xA0, 0xAF, ID_VOID, ID_CRC32
+    };
+
+    using Bytes = std::vector<unsigned char>;
+
+    /**
+     * @brief An EBML element header.
+     */
+    struct ElementHeader {
+        std::uint32_t id = 0;
+        std::uint64_t header = 0;          ///< Length of the ID and size fields
+        std::optional<std::uint64_t> size; ///< Data size, unset for "unknown"
+    };
+
+    /**
+     * @brief A child of the Segment, located in the file.
+     */
+    struct Element {
+        std::uint32_t id = 0;
+        std::uint64_t offset = 0; ///< Start of the element header
+        std::uint64_t header = 0;
+        std::uint64_t size = 0;   ///< Data size, resolved for unknown-size clusters
+
+        [[nodiscard]] std::uint64_t end() const { return offset + header + size; }
+    };
+
+    /**
+     * @brief The first Segment of a file, split into its top-level children.
+     */
+    struct MkvLayout {
+        std::uint64_t segment_offset = 0;
+        std::uint64_t segment_header = 0;
+        bool segment_unknown_size = false;
+        std::uint64_t segment_end = 0;
+        std::uint64_t file_size = 0;
+        std::vector<Element> children;
+        std::optional<std::size_t> attachments;
+
+        /// Positions stored in SeekHead, Cues and Clusters are relative to this offset.
+        [[nodiscard]] std::uint64_t data_start() const { return segment_offset + segment_header; }
+    };
+
+    /**
+     * @brief An attachment written to the temporary directory.
+     */
+    struct ExtractedAttachment {
+        std::size_t index = 0;      ///< Position among the AttachedFile elements
+        std::uint64_t original_size = 0;
+        uLong original_crc = 0;
+        fs::path file;
+    };
+
+    /**
+     * @brief State handed from prepare_extraction() to finalize_extraction().
+     */
+    struct MkvState {
+        std::size_t attached_files = 0;
+        std::vector<ExtractedAttachment> attachments;
+    };
+
+    /**
+     * @brief Decodes an EBML ID or size from the bytes `p`, `available` long.
+     * @return The value with the length marker kept (IDs) or stripped (sizes), and its length.
+     */
+    std::pair<std::uint64_t, std::uint64_t> read_vint(const unsigned char* p, std::uint64_t available,
+                                                      std::uint64_t max_length, bool keep_marker) {
+        if (available == 0 || p[0] == 0) throw std::runtime_error("MkvProcessor: invalid EBML number");
+        std::uint64_t length = 1;
+        while (!(p[0] & (0x80 >> (length - 1)))) ++length;
+        if (length > max_length || length > available) throw std::runtime_error("MkvProcessor: invalid EBML number");
+        std::uint64_t value = keep_marker ? p[0] : p[0] & (0xFF >> length);
+        for (std::uint64_t i = 1; i < length; ++i) value = value << 8 | p[i];
+        return {value, length};
+    }
+
+    ElementHeader parse_header(const unsigned char* p, std::uint64_t available) {
+        ElementHeader h;
+        const auto [id, id_length] = read_vint(p, available, 4, true);
+        const auto [size, size_length] = read_vint(p + id_length, available - id_length, 8, false);
+        h.id = static_cast<std::uint32_t>(id);
+        h.header = id_length + size_length;
+        // all value bits set means "unknown size"
+        if (size != (std::uint64_t{1} << (7 * size_length)) - 1) h.size = size;
+        return h;
+    }
+
+    ElementHeader read_header(std::ifstream& in, std::uint64_t pos, std::uint64_t end) {
+        unsigned char raw[12];
+        const std::uint64_t available = std::min<std::uint64_t>(sizeof(raw), end - pos);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(pos));
+        if (!in.read(reinterpret_cast<char*>(raw), static_cast<std::streamsize>(available))) {
+            throw std::runtime_error("MkvProcessor: read failed");
+        }
+        return parse_header(raw, available);
+    }
+
+    /**
+     * @brief Reads the element header at `pos` of an in-memory master element.
+     * @throws std::runtime_error if the element has an unknown size or overruns `end`.
+     */
+    ElementHeader header_at(const Bytes& data, std::size_t pos, std::size_t end) {
+        ElementHeader h = parse_header(data.data() + pos, end - pos);
+        if (!h.size || *h.size > end - pos - h.header) {
+            throw std::runtime_error("MkvProcessor: element size out of range");
+        }
+        return h;
+    }
+
+    void put_id(Bytes& out, std::uint32_t id) {
+        int length = 1;
+        while (length < 4 && (id >> (8 * length)) != 0) ++length;
+        for (int i = length - 1; i >= 0; --i) out.push_back(static_cast<unsigned char>(id >> (8 * i)));
+    }
+
+    void put_size(Bytes& out, std::uint64_t size, std::uint64_t length = 0) {
+        if (length == 0) {
+            length = 1;
+            while (length < 8 && size >= (std::uint64_t{1} << (7 * length)) - 1) ++length;
+        }
+        const std::uint64_t coded = size | std::uint64_t{1} << (7 * length);
+        for (std::uint64_t i = length; i-- > 0;) out.push_back(static_cast<unsigned char>(coded >> (8 * i)));
+    }
+
+    std::uint64_t read_uint(const Bytes& data, std::size_t pos, std::uint64_t length) {
+        std::uint64_t value = 0;
+        for (std::uint64_t i = 0; i < length; ++i) value = value << 8 | data[pos + i];
+        return value;
+    }
+
+    void write_uint(Bytes& data, std::size_t pos, std::uint64_t length, std::uint64_t value) {
+        for (std::uint64_t i = length; i-- > 0; value >>= 8) data[pos + i] = static_cast<unsigned char>(value);
+    }
+
+    /**
+     * @brief Recomputes the CRC-32 element of a master, if it carries one.
+     *
+     * The CRC-32 must be the first child and covers every following byte of
+     * the master's data, stored little-endian.
+     */
+    void update_crc(Bytes& data, std::size_t begin, std::size_t end) {
+        if (begin == end) return;
+        const ElementHeader h = header_at(data, begin, end);
+        if (h.id != ID_CRC32 || *h.size != 4) return;
+        const std::size_t covered = begin + h.header + 4;
+        std::uint32_t crc = crc32(0L, Z_NULL, 0);
+        crc = crc32(crc, data.data() + covered, static_cast<uInt>(end - covered));
+        for (int i = 0; i < 4; ++i) data[begin + h.header + i] = static_cast<unsigned char>(crc >> (8 * i));
+    }
+
+    bool is_position(std::uint32_t id) {
+        return id == ID_SEEK_POSITION || id == ID_CUE_CLUSTER_POSITION || id == ID_CUE_CODEC_STATE ||
+               id == ID_CUE_REF_CLUSTER || id == ID_CUE_REF_CODEC_STATE || id == ID_CLUSTER_POSITION;
+    }
+
+    bool holds_positions(std::uint32_t id) {
+        return id == ID_SEEK || id == ID_CUE_POINT || id == ID_CUE_TRACK_POSITIONS || id == ID_CUE_REFERENCE;
+    }
+
+    /**
+     * @brief Moves every segment position past `after` by `delta` bytes, in place.
+     *
+     * `delta` is negative, so each value keeps its encoded width and the
+     * master keeps its size.
+     */
+    void shift_positions(Bytes& data, std::size_t begin, std::size_t end, std::uint64_t after, std::int64_t delta) {
+        for (std::size_t pos = begin; pos < end;) {
+            const ElementHeader h = header_at(data, pos, end);
+            const std::size_t body = pos + h.header;
+            if (is_position(h.id) && *h.size >= 1 && *h.size <= 8) {
+                const std::uint64_t value = read_uint(data, body, *h.size);
+                if (value > after) write_uint(data, body, *h.size, value + delta);
+            } else if (holds_positions(h.id)) {
+                shift_positions(data, body, body + *h.size, after, delta);
+            }
+            pos = body + *h.size;
+        }
+        update_crc(data, begin, end);
+    }
+
+    bool is_cluster_child(std::uint32_t id) {
+        return std::find(kClusterChildren.begin(), kClusterChildren.end(), id) != kClusterChildren.end();
+    }
+
+    /**
+     * @brief Finds where a Cluster of unknown size ends: at the first element that cannot belong to it.
+     */
+    std::uint64_t unknown_cluster_end(std::ifstream& in, std::uint64_t pos, std::uint64_t limit) {
+        while (pos < limit) {
+            const ElementHeader h = read_header(in, pos, limit);
+            if (!is_cluster_child(h.id)) break;
+            if (!h.size || *h.size > limit - pos - h.header) {
+                throw std::runtime_error("MkvProcessor: cluster child size out of range");
+            }
+            pos += h.header + *h.size;
+        }
+        return pos;
+    }
+
+    bool cluster_has_position(std::ifstream& in, const Element& cluster) {
+        for (std::uint64_t pos = cluster.offset + cluster.header; pos < cluster.end();) {
+            const ElementHeader h = read_header(in, pos, cluster.end());
+            if (h.id == ID_CLUSTER_POSITION) return true;
+            if (!h.size) throw std::runtime_error("MkvProcessor: unknown-size element inside a cluster");
+            pos += h.header + *h.size;
+        }
+        return false;
+    }
+
+    /**
+     * @brief Locates the first Segment of a file and lists its top-level children.
+     * @throws std::runtime_error for malformed files.
+     */
+    MkvLayout parse_mkv(std::ifstream& in, const fs::path& path) {
+        MkvLayout layout;
+        layout.file_size = fs::file_size(path);
+
+        const ElementHeader ebml = read_header(in, 0, layout.file_size);
+        if (ebml.id != ID_EBML || !ebml.size) throw std::runtime_error("MkvProcessor: missing EBML header");
+        std::uint64_t pos = ebml.header + *ebml.size;
+        for (;;) {
+            if (pos >= layout.file_size) throw std::runtime_error("MkvProcessor: no Segment");
+            const ElementHeader h = read_header(in, pos, layout.file_size);
+            if (h.id == ID_SEGMENT) {
+                layout.segment_offset = pos;
+                layout.segment_header = h.header;
+                layout.segment_unknown_size = !h.size;
+                layout.segment_end = h.size ? pos + h.header + *h.size : layout.file_size;
+                break;
+            }
+            if (!h.size) throw std::runtime_error("MkvProcessor: unknown-size element before the Segment");
+            pos += h.header + *h.size;
+        }
+        if (layout.segment_end > layout.file_size) throw std::runtime_error("MkvProcessor: truncated Segment");
+
+        for (pos = layout.data_start(); pos < layout.segment_end;) {
+            const ElementHeader h = read_header(in, pos, layout.segment_end);
+            // a chained segment starts where a live-streamed one ends
+            if (layout.segment_unknown_size && (h.id == ID_EBML || h.id == ID_SEGMENT)) {
+                layout.segment_end = pos;
+                break;
+            }
+            Element element{h.id, pos, h.header, 0};
+            if (h.size) {
+                element.size = *h.size;
+            } else if (h.id == ID_CLUSTER) {
+                element.size = unknown_cluster_end(in, pos + h.header, layout.segment_end) - pos - h.header;
+            } else {
+                throw std::runtime_error("MkvProcessor: unknown-size element in the Segment");
+            }
+            if (element.end() > layout.segment_end) throw std::runtime_error("MkvProcessor: element runs past the Segment");
+
+            if (h.id == ID_ATTACHMENTS) {
+                if (layout.attachments) throw std::runtime_error("MkvProcessor: more than one Attachments element");
+                layout.attachments = layout.children.size();
+            }
+            layout.children.push_back(element);
+            pos = element.end();
+        }
+        return layout;
+    }
+
+    Bytes read_element(std::ifstream& in, const Element& element, std::uint64_t limit) {
+        if (element.header + element.size > limit) throw std::runtime_error("MkvProcessor: element too large");
+        Bytes data(element.header + element.size);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(element.offset));
+        if (!in.read(reinterpret_cast<char*>(data.data()), static_cast<std::streamsize>(data.size()))) {
+            throw std::runtime_error("MkvProcessor: read failed");
+        }
+        return data;
+    }
+
+    /**
+     * @brief Lists the children of an in-memory master as (header offset, header) pairs.
+     */
+    std::vector<std::pair<std::size_t, ElementHeader>> children_of(const Bytes& data, std::size_t begin, std::size_t end) {
+        std::vector<std::pair<std::size_t, ElementHeader>> children;
+        for (std::size_t pos = begin; pos < end;) {
+            const ElementHeader h = header_at(data, pos, end);
+            children.emplace_back(pos, h);
+            pos += h.header + *h.size;
+        }
+        return children;
+    }
+
+    /**
+     * @brief Turns a stored file name into a safe name inside the temporary directory.
+     */
+    std::string sanitize_file_name(std::string name) {
+        std::ranges::replace_if(name, [](char c) {
+            return c == '/' || c == '\\' || c == ':' || static_cast<unsigned char>(c) < 0x20;
+        }, '_');
+        return name.empty() || name == "." || name == ".." ? "attachment" : name;
+    }
+
+    void copy_range(std::ifstream& in, std::uint64_t offset, std::uint64_t size, std::ofstream& out) {
+        std::vector<char> buf(COPY_BLOCK);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        while (size > 0) {
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(size, buf.size()));
+            if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                throw std::runtime_error("MkvProcessor: read failed");
+            }
+            out.write(buf.data(), static_cast<std::streamsize>(n));
+            size -= n;
+        }
+    }
+
+    Bytes read_file(const fs::path& path) {
+        std::ifstream in(path, std::ios::binary);
+        if (!in) throw std::runtime_error("MkvProcessor: cannot open " + path.string());
+        return {std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>()};
+    }
+
+    /**
+     * @brief Rebuilds the Attachments element with the processed files, dropping Void elements.
+     */
+    Bytes rebuild_attachments(const Bytes& original, const MkvState& state) {
+        const ElementHeader top = header_at(original, 0, original.size());
+        Bytes body;
+        std::size_t index = 0;
+        for (const auto& [pos, h] : children_of(original, top.header, original.size())) {
+            if (h.id == ID_VOID) continue;
+            if (h.id != ID_ATTACHED_FILE) {
+                body.insert(body.end(), original.begin() + static_cast<std::ptrdiff_t>(pos),
+                            original.begin() + static_cast<std::ptrdiff_t>(pos + h.header + *h.size));
+                continue;
+            }
+
+            const auto it = std::ranges::find(state.attachments, index++, &ExtractedAttachment::index);
+            Bytes file;
+            for (const auto& [child_pos, child] : children_of(original, pos + h.header, pos + h.header + *h.size)) {
+                if (child.id == ID_VOID) continue;
+                if (child.id == ID_FILE_DATA && it != state.attachments.end()) {
+                    const Bytes data = read_file(it->file);
+                    put_id(file, ID_FILE_DATA);
+                    put_size(file, data.size());
+                    file.insert(file.end(), data.begin(), data.end());
+                } else {
+                    file.insert(file.end(), original.begin() + static_cast<std::ptrdiff_t>(child_pos),
+                                original.begin() + static_cast<std::ptrdiff_t>(child_pos + child.header + *child.size));
+                }
+            }
+            update_crc(file, 0, file.size());
+            put_id(body, ID_ATTACHED_FILE);
+            put_size(body, file.size());
+            body.insert(body.end(), file.begin(), file.end());
+        }
+        if (index != state.attached_files) throw std::runtime_error("MkvProcessor: attachments changed since extraction");
+        update_crc(body, 0, body.size());
+
+        Bytes out;
+        put_id(out, ID_ATTACHMENTS);
+        put_size(out, body.size());
+        out.insert(out.end(), body.begin(), body.end());
+        return out;
+    }
+
+    // --- optimizer ---
+
+    constexpr std::uint32_t ID_INFO = 0x1549A966;
+    constexpr std::uint32_t ID_TRACKS = 0x1654AE6B;
+    constexpr std::uint32_t ID_CHAPTERS = 0x1043A770;
+    constexpr std::uint32_t ID_TAGS = 0x1254C367;
+    constexpr std::uint32_t ID_SEEK_ID = 0x53AB;
+    constexpr std::uint32_t ID_FILE_MIME_TYPE = 0x4660;
+    constexpr std::uint32_t ID_CLUSTER_TIMESTAMP = 0xE7;
+    constexpr std::uint32_t ID_CLUSTER_PREV_SIZE = 0xAB;
+    constexpr std::uint32_t ID_SIMPLE_BLOCK = 0xA3;
+    constexpr std::uint32_t ID_BLOCK_GROUP = 0xA0;
+    constexpr std::uint32_t ID_BLOCK = 0xA1;
+    constexpr std::uint32_t ID_CUE_TIME = 0xB3;
+    constexpr std::uint32_t ID_CUE_TRACK = 0xF7;
+    constexpr std::uint32_t ID_CUE_RELATIVE_POSITION = 0xF0;
+    constexpr std::uint32_t ID_CUE_DURATION = 0xB2;
+    constexpr std::uint32_t ID_CUE_BLOCK_NUMBER = 0x5378;
+    constexpr std::uint32_t ID_CUE_REF_TIME = 0x96;
+    constexpr std::uint32_t ID_CUE_REF_NUMBER = 0x535F;
+
+    // master elements below Info, Tracks, Chapters, Tags and Attachments
+    constexpr std::array<std::uint32_t, 30> kMasters = {
+        0x6924,                                                         // ChapterTranslate
+        0xAE, 0xE0, 0xE1, 0xE2, 0xE3, 0xE4, 0xE9, 0x6D80, 0x6240,       // TrackEntry ... ContentEncoding
+        0x5034, 0x5035, 0x47E7, 0x55B0, 0x55D0, 0x7670, 0x41E4, 0x6624, // ContentCompression ... TrackTranslate
+        0x45B9, 0xB6, 0x8F, 0x80, 0x6944, 0x6911, 0x4520,               // EditionEntry ... EditionDisplay
+        0x7373, 0x63C0, 0x67C8,                                         // Tag, Targets, SimpleTag
+        ID_ATTACHED_FILE, ID_CUE_REFERENCE
+    };
+
+    constexpr unsigned char LACING_MASK = 0x06;
+    constexpr unsigned char LACING_XIPH = 0x02;
+    constexpr unsigned char LACING_FIXED = 0x04;
+    constexpr unsigned char LACING_EBML = 0x06;
+
+    bool is_master(std::uint32_t id) {
+        return std::find(kMasters.begin(), kMasters.end(), id) != kMasters.end();
+    }
+
+    std::uint64_t id_length(std::uint32_t id) {
+        std::uint64_t length = 1;
+        while (length < 4 && (id >> (8 * length)) != 0) ++length;
+        return length;
+    }
+
+    std::uint64_t size_length(std::uint64_t size) {
+        std::uint64_t length = 1;
+        while (length < 8 && size >= (std::uint64_t{1} << (7 * length)) - 1) ++length;
+        return length;
+    }
+
+    void put_element(Bytes& out, std::uint32_t id, const unsigned char* data, std::uint64_t size) {
+        put_id(out, id);
+        put_size(out, size);
+        out.insert(out.end(), data, data + size);
+    }
+
+    void put_element(Bytes& out, std::uint32_t id, const Bytes& body) {
+        put_element(out, id, body.data(), body.size());
+    }
+
+    void put_uint_element(Bytes& out, std::uint32_t id, std::uint64_t value) {
+        std::uint64_t length = 1;
+        while (length < 8 && (value >> (8 * length)) != 0) ++length;
+        put_id(out, id);
+        put_size(out, length);
+        for (std::uint64_t i = length; i-- > 0;) out.push_back(static_cast<unsigned char>(value >> (8 * i)));
+    }
+
+    std::uint64_t uint_at(const Bytes& data, std::size_t body, const ElementHeader& h) {
+        if (*h.size > 8) throw std::runtime_error("MkvProcessor: integer element too long");
+        return read_uint(data, body, *h.size);
+    }
+
+    /**
+     * @brief Copies the children of a master, dropping Void and CRC-32 elements at every level.
+     */
+    void strip_children(const Bytes& data, std::size_t begin, std::size_t end, Bytes& out) {
+        for (const auto& [pos, h] : children_of(data, begin, end)) {
+            if (h.id == ID_VOID || h.id == ID_CRC32) continue;
+            const std::size_t body = pos + h.header;
+            if (is_master(h.id)) {
+                Bytes inner;
+                strip_children(data, body, body + *h.size, inner);
+                put_element(out, h.id, inner);
+            } else {
+                put_element(out, h.id, data.data() + body, *h.size);
+            }
+        }
+    }
+
+    Bytes strip_element(const Bytes& element) {
+        const ElementHeader h = header_at(element, 0, element.size());
+        Bytes body;
+        strip_children(element, h.header, element.size(), body);
+        Bytes out;
+        put_element(out, h.id, body);
+        return out;
+    }
+
+    /**
+     * @brief Drops attached images (cover art); fonts and other files are kept.
+     * @return The filtered element, or an empty buffer if nothing is left.
+     */
+    Bytes drop_cover_art(const Bytes& attachments) {
+        const ElementHeader top = header_at(attachments, 0, attachments.size());
+        Bytes body;
+        for (const auto& [pos, h] : children_of(attachments, top.header, attachments.size())) {
+            bool image = false;
+            for (const auto& [child_pos, child] : children_of(attachments, pos + h.header, pos + h.header + *h.size)) {
+                if (h.id != ID_ATTACHED_FILE || child.id != ID_FILE_MIME_TYPE) continue;
+                const auto mime = attachments.begin() + static_cast<std::ptrdiff_t>(child_pos + child.header);
+                image = std::string(mime, mime + static_cast<std::ptrdiff_t>(*child.size)).starts_with("image/");
+            }
+            if (!image) {
+                body.insert(body.end(), attachments.begin() + static_cast<std::ptrdiff_t>(pos),
+                            attachments.begin() + static_cast<std::ptrdiff_t>(pos + h.header + *h.size));
+            }
+        }
+        Bytes out;
+        if (!body.empty()) put_element(out, ID_ATTACHMENTS, body);
+        return out;
+    }
+
+    /**
+     * @brief The frames of a Block or SimpleBlock.
+     */
+    struct BlockLacing {
+        std::size_t head = 0;               ///< Track number, timecode and flags
+        unsigned char lacing = 0;           ///< Lacing bits of the flags byte
+        std::size_t data = 0;               ///< Start of the frame data
+        std::vector<std::uint64_t> frames;  ///< Frame sizes
+    };
+
+    /**
+     * @brief Reads the lacing of a block from its first `available` bytes.
+     *
+     * A block without lacing only needs its head; the frames of a laced one
+     * are listed only when the whole block is available.
+     */
+    BlockLacing parse_lacing(const unsigned char* p, std::uint64_t available, std::uint64_t block_size) {
+        BlockLacing block;
+        block.head = read_vint(p, available, 8, false).second + 3;
+        if (block.head > available) throw std::runtime_error("MkvProcessor: truncated block header");
+        block.lacing = p[block.head - 1] & LACING_MASK;
+        block.data = block.head;
+        if (block.lacing == 0) {
+            block.frames.push_back(block_size - block.head);
+            return block;
+        }
+        if (available < block_size) return block; // frames unknown until the whole block is read
+        if (block.head >= block_size) throw std::runtime_error("MkvProcessor: truncated lacing");
+
+        std::size_t pos = block.head;
+        const std::size_t count = p[pos++] + std::size_t{1};
+        std::uint64_t total = 0;
+        if (block.lacing == LACING_XIPH) {
+            for (std::size_t i = 0; i + 1 < count; ++i) {
+                std::uint64_t size = 0;
+                unsigned char byte = 0;
+                do {
+                    if (pos >= block_size) throw std::runtime_error("MkvProcessor: truncated lacing");
+                    byte = p[pos++];
+                    size += byte;
+                } while (byte == 0xFF);
+                block.frames.push_back(size);
+                total += size;
+            }
+        } else if (block.lacing == LACING_EBML) {
+            std::int64_t size = 0;
+            for (std::size_t i = 0; i + 1 < count; ++i) {
+                const auto [raw, length] = read_vint(p + pos, block_size - pos, 8, false);
+                pos += length;
+                // sizes after the first are stored as differences, biased to stay unsigned
+                size = i == 0 ? static_cast<std::int64_t>(raw)
+                              : size + static_cast<std::int64_t>(raw) - ((std::int64_t{1} << (7 * length - 1)) - 1);
+                if (size < 0) throw std::runtime_error("MkvProcessor: negative lace size");
+                block.frames.push_back(static_cast<std::uint64_t>(size));
+                total += static_cast<std::uint64_t>(size);
+            }
+        }
+        block.data = pos;
+
+        const std::uint64_t remaining = block_size - pos;
+        if (block.lacing == LACING_FIXED) {
+            if (remaining % count != 0) throw std::runtime_error("MkvProcessor: uneven fixed lacing");
+            block.frames.assign(count, remaining / count);
+        } else {
+            if (total > remaining) throw std::runtime_error("MkvProcessor: lace sizes exceed the block");
+            block.frames.push_back(remaining - total);
+        }
+        return block;
+    }
+
+    /**
+     * @brief Encodes the frame count and sizes of a laced block.
+     */
+    Bytes lace_header(unsigned char lacing, const std::vector<std::uint64_t>& frames) {
+        Bytes out{static_cast<unsigned char>(frames.size() - 1)};
+        for (std::size_t i = 0; i + 1 < frames.size(); ++i) {
+            if (lacing == LACING_XIPH) {
+                std::uint64_t size = frames[i];
+                for (; size >= 0xFF; size -= 0xFF) out.push_back(0xFF);
+                out.push_back(static_cast<unsigned char>(size));
+            } else if (lacing == LACING_EBML && i == 0) {
+                put_size(out, frames[0]);
+            } else if (lacing == LACING_EBML) {
+                const std::int64_t diff = static_cast<std::int64_t>(frames[i]) - static_cast<std::int64_t>(frames[i - 1]);
+                std::uint64_t length = 1;
+                while (length < 8 && (diff > (std::int64_t{1} << (7 * length - 1)) - 1 ||
+                                      diff < -((std::int64_t{1} << (7 * length - 1)) - 1))) {
+                    ++length;
+                }
+                put_size(out, static_cast<std::uint64_t>(diff + (std::int64_t{1} << (7 * length - 1)) - 1), length);
+            }
+        }
+        return out;
+    }
+
+    /**
+     * @brief Rewrites a laced block with the lacing that gives the smallest header.
+     * @return The new block data, or std::nullopt if the block is not laced.
+     */
+    std::optional<Bytes> relace_block(std::ifstream& in, std::uint64_t offset, std::uint64_t size) {
+        unsigned char head[12];
+        const std::uint64_t available = std::min<std::uint64_t>(sizeof(head), size);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        if (!in.read(reinterpret_cast<char*>(head), static_cast<std::streamsize>(available))) {
+            throw std::runtime_error("MkvProcessor: read failed");
+        }
+        if (parse_lacing(head, available, size).lacing == 0) return std::nullopt;
+
+        const Bytes block = read_element(in, {ID_BLOCK, offset, 0, size}, MAX_INDEX_SIZE);
+        const BlockLacing lacing = parse_lacing(block.data(), block.size(), block.size());
+
+        Bytes out(block.begin(), block.begin() + static_cast<std::ptrdiff_t>(lacing.head));
+        out.back() &= static_cast<unsigned char>(~LACING_MASK);
+        if (lacing.frames.size() > 1) {
+            // fixed lacing only fits equal frames; ties keep the original choice
+            const bool equal = std::ranges::all_of(lacing.frames, [&](std::uint64_t f) { return f == lacing.frames.front(); });
+            std::vector<unsigned char> choices{lacing.lacing};
+            for (const unsigned char choice : {LACING_FIXED, LACING_XIPH, LACING_EBML}) {
+                if (choice != lacing.lacing) choices.push_back(choice);
+            }
+            Bytes best;
+            unsigned char best_lacing = 0;
+            for (const unsigned char choice : choices) {
+                if (choice == LACING_FIXED && !equal) continue;
+                Bytes header = lace_header(choice, lacing.frames);
+                if (best_lacing == 0 || header.size() < best.size()) {
+                    best = std::move(header);
+                    best_lacing = choice;
+                }
+            }
+            out.back() |= best_lacing;
+            out.insert(out.end(), best.begin(), best.end());
+        }
+        out.insert(out.end(), block.begin() + static_cast<std::ptrdiff_t>(lacing.data), block.end());
+        return out;
+    }
+
+    /**
+     * @brief Where a Cluster goes in the output.
+     */
+    struct ClusterPlan {
+        Element element;
+        std::uint64_t new_size = 0;                                 ///< Data size once rewritten
+        std::uint64_t new_position = 0;                             ///< Segment position in the output
+        std::vector<std::pair<std::uint64_t, std::uint64_t>> moved; ///< Old to new relative positions the Cues need
+
+        [[nodiscard]] std::uint64_t new_total() const { return id_length(ID_CLUSTER) + size_length(new_size) + new_size; }
+    };
+
+    std::uint64_t emit_header(std::ofstream* out, std::uint32_t id, std::uint64_t size) {
+        Bytes header;
+        put_id(header, id);
+        put_size(header, size);
+        if (out) out->write(reinterpret_cast<const char*>(header.data()), static_cast<std::streamsize>(header.size()));
+        return header.size();
+    }
+
+    std::uint64_t emit_bytes(std::ofstream* out, std::uint32_t id, const Bytes& body) {
+        const std::uint64_t header = emit_header(out, id, body.size());
+        if (out) out->write(reinterpret_cast<const char*>(body.data()), static_cast<std::streamsize>(body.size()));
+        return header + body.size();
+    }
+
+    std::uint64_t emit_copy(std::ifstream& in, std::ofstream* out, std::uint32_t id, std::uint64_t offset, std::uint64_t size) {
+        const std::uint64_t header = emit_header(out, id, size);
+        if (out) copy_range(in, offset, size, *out);
+        return header + size;
+    }
+
+    /**
+     * @brief Writes (or, without `out`, measures) the data of a BlockGroup without Void and CRC-32, relacing its Block.
+     */
+    std::uint64_t rewrite_block_group(std::ifstream& in, const Element& group, std::ofstream* out) {
+        std::uint64_t written = 0;
+        for (std::uint64_t pos = group.offset + group.header; pos < group.end();) {
+            const ElementHeader h = read_header(in, pos, group.end());
+            if (!h.size || *h.size > group.end() - pos - h.header) throw std::runtime_error("MkvProcessor: bad block group");
+            const std::uint64_t body = pos + h.header;
+            if (h.id == ID_BLOCK) {
+                if (auto block = relace_block(in, body, *h.size)) {
+                    written += emit_bytes(out, h.id, *block);
+                } else {
+                    written += emit_copy(in, out, h.id, body, *h.size);
+                }
+            } else if (h.id != ID_VOID && h.id != ID_CRC32) {
+                written += emit_copy(in, out, h.id, body, *h.size);
+            }
+            pos = body + *h.size;
+        }
+        return written;
+    }
+
+    /**
+     * @brief Writes (or, without `out`, measures) the data of a Cluster in its smallest form.
+     *
+     * Void, CRC-32, Position and PrevSize are dropped: the last two only
+     * repeat what the layout already says, and would be stale after it
+     * changes. When measuring, the new relative positions of the children
+     * starting at `wanted` are stored in the plan.
+     */
+    std::uint64_t rewrite_cluster(std::ifstream& in, ClusterPlan& plan, const std::vector<std::uint64_t>& wanted,
+                                  std::ofstream* out) {
+        const Element& cluster = plan.element;
+        const std::uint64_t data_start = cluster.offset + cluster.header;
+        std::uint64_t written = 0;
+        for (std::uint64_t pos = data_start; pos < cluster.end();) {
+            const ElementHeader h = read_header(in, pos, cluster.end());
+            if (!h.size || *h.size > cluster.end() - pos - h.header) throw std::runtime_error("MkvProcessor: bad cluster child");
+            const std::uint64_t body = pos + h.header;
+            if (!out && std::binary_search(wanted.begin(), wanted.end(), pos - data_start)) {
+                plan.moved.emplace_back(pos - data_start, written);
+            }
+
+            if (h.id == ID_SIMPLE_BLOCK) {
+                if (auto block = relace_block(in, body, *h.size)) {
+                    written += emit_bytes(out, h.id, *block);
+                } else {
+                    written += emit_copy(in, out, h.id, body, *h.size);
+                }
+            } else if (h.id == ID_BLOCK_GROUP) {
+                const Element group{h.id, pos, h.header, *h.size};
+                const std::uint64_t size = rewrite_block_group(in, group, nullptr);
+                written += emit_header(out, h.id, size) + size;
+                if (out) rewrite_block_group(in, group, out);
+            } else if (h.id != ID_VOID && h.id != ID_CRC32 && h.id != ID_CLUSTER_POSITION && h.id != ID_CLUSTER_PREV_SIZE) {
+                written += emit_copy(in, out, h.id, body, *h.size);
+            }
+            pos = body + *h.size;
+        }
+        return written;
+    }
+
+    /**
+     * @brief Maps positions of the input Segment to the output, for the Cues.
+     */
+    struct CueMapper {
+        std::function<std::optional<std::uint64_t>(std::uint64_t)> cluster;                 ///< Cluster start
+        std::function<std::optional<std::uint64_t>(std::uint64_t, std::uint64_t)> relative; ///< (cluster, relative position)
+        std::function<std::optional<std::uint64_t>(std::uint64_t)> position;                ///< Any segment position
+    };
+
+    std::optional<Bytes> rebuild_cue_reference(const Bytes& data, std::size_t begin, std::size_t end, const CueMapper& map) {
+        Bytes out;
+        for (const auto& [pos, h] : children_of(data, begin, end)) {
+            const std::size_t body = pos + h.header;
+            if (h.id == ID_CUE_REF_CLUSTER) {
+                const auto cluster = map.cluster(uint_at(data, body, h));
+                if (!cluster) return std::nullopt;
+                put_uint_element(out, h.id, *cluster);
+            } else if (h.id == ID_CUE_REF_CODEC_STATE) {
+                const std::uint64_t value = uint_at(data, body, h);
+                if (const auto state = value == 0 ? std::optional<std::uint64_t>{0} : map.position(value)) {
+                    put_uint_element(out, h.id, *state);
+                }
+            } else if (h.id == ID_CUE_REF_TIME || h.id == ID_CUE_REF_NUMBER) {
+                put_uint_element(out, h.id, uint_at(data, body, h));
+            } else if (h.id != ID_VOID && h.id != ID_CRC32) {
+                put_element(out, h.id, data.data() + body, *h.size);
+            }
+        }
+        return out;
+    }
+
+    std::optional<Bytes> rebuild_cue_track_positions(const Bytes& data, std::size_t begin, std::size_t end,
+                                                     const CueMapper& map) {
+        std::optional<std::uint64_t> old_cluster;
+        for (const auto& [pos, h] : children_of(data, begin, end)) {
+            if (h.id == ID_CUE_CLUSTER_POSITION) old_cluster = uint_at(data, pos + h.header, h);
+        }
+        const auto cluster = old_cluster ? map.cluster(*old_cluster) : std::nullopt;
+        if (!cluster) return std::nullopt;
+
+        Bytes out;
+        for (const auto& [pos, h] : children_of(data, begin, end)) {
+            const std::size_t body = pos + h.header;
+            if (h.id == ID_CUE_CLUSTER_POSITION) {
+                put_uint_element(out, h.id, *cluster);
+            } else if (h.id == ID_CUE_RELATIVE_POSITION) {
+                if (const auto relative = map.relative(*old_cluster, uint_at(data, body, h))) {
+                    put_uint_element(out, h.id, *relative);
+                }
+            } else if (h.id == ID_CUE_CODEC_STATE) {
+                const std::uint64_t value = uint_at(data, body, h);
+                if (const auto state = value == 0 ? std::optional<std::uint64_t>{0} : map.position(value)) {
+                    put_uint_element(out, h.id, *state);
+                }
+            } else if (h.id == ID_CUE_REFERENCE) {
+                if (const auto reference = rebuild_cue_reference(data, body, body + *h.size, map)) {
+                    put_element(out, h.id, *reference);
+                }
+            } else if (h.id == ID_CUE_TRACK || h.id == ID_CUE_DURATION || h.id == ID_CUE_BLOCK_NUMBER) {
+                put_uint_element(out, h.id, uint_at(data, body, h));
+            } else if (h.id != ID_VOID && h.id != ID_CRC32) {
+                put_element(out, h.id, data.data() + body, *h.size);
+            }
+        }
+        return out;
+    }
+
+    /**
+     * @brief Merges the Cues elements of the input into one, with positions mapped and integers at minimal width.
+     * @return The new element, or an empty buffer if no cue point is left.
+     */
+    Bytes rebuild_cues(const std::vector<Bytes>& cues, const CueMapper& map) {
+        Bytes points;
+        for (const Bytes& element : cues) {
+            const ElementHeader top = header_at(element, 0, element.size());
+            for (const auto& [pos, h] : children_of(element, top.header, element.size())) {
+                if (h.id != ID_CUE_POINT) continue;
+                Bytes point;
+                bool positioned = false;
+                for (const auto& [child_pos, child] : children_of(element, pos + h.header, pos + h.header + *h.size)) {
+                    const std::size_t body = child_pos + child.header;
+                    if (child.id == ID_CUE_TIME) {
+                        put_uint_element(point, child.id, uint_at(element, body, child));
+                    } else if (child.id == ID_CUE_TRACK_POSITIONS) {
+                        if (const auto positions = rebuild_cue_track_positions(element, body, body + *child.size, map)) {
+                            put_element(point, child.id, *positions);
+                            positioned = true;
+                        }
+                    } else if (child.id != ID_VOID && child.id != ID_CRC32) {
+                        put_element(point, child.id, element.data() + body, *child.size);
+                    }
+                }
+                if (positioned) put_element(points, ID_CUE_POINT, point);
+            }
+        }
+        Bytes out;
+        if (!points.empty()) put_element(out, ID_CUES, points);
+        return out;
+    }
+
+    /**
+     * @brief A top-level element other than a Cluster, held in memory.
+     */
+    struct Section {
+        int rank = 0; ///< Output order: Info, Tracks, Chapters, Attachments, Tags, others
+        std::uint32_t id = 0;
+        Bytes bytes;
+    };
+
+    int section_rank(std::uint32_t id) {
+        switch (id) {
+            case ID_INFO: return 0;
+            case ID_TRACKS: return 1;
+            case ID_CHAPTERS: return 2;
+            case ID_ATTACHMENTS: return 3;
+            case ID_TAGS: return 4;
+            default: return 5;
+        }
+    }
+
+    Bytes build_seek_head(const std::vector<std::pair<std::uint32_t, std::uint64_t>>& entries) {
+        Bytes body;
+        for (const auto& [id, position] : entries) {
+            Bytes seek;
+            Bytes seek_id;
+            put_id(seek_id, id);
+            put_element(seek, ID_SEEK_ID, seek_id);
+            put_uint_element(seek, ID_SEEK_POSITION, position);
+            put_element(body, ID_SEEK, seek);
+        }
+        Bytes out;
+        put_element(out, ID_SEEK_HEAD, body);
+        return out;
+    }
+
+    /**
+     * @brief Frame count and CRC-32 of the frames of each track, keyed by track number.
+     *
+     * Each frame is hashed with its timestamp, block flags (lacing aside)
+     * and size, so timing and keyframe marks must match as well.
+     */
+    std::map<std::uint64_t, std::pair<std::size_t, uLong>> track_frame_hashes(std::ifstream& in, const MkvLayout& layout) {
+        std::map<std::uint64_t, std::pair<std::size_t, uLong>> tracks;
+        std::vector<char> buf;
+
+        auto hash_block = [&](std::uint64_t offset, std::uint64_t size, std::uint64_t cluster_time) {
+            unsigned char head[12];
+            const std::uint64_t available = std::min<std::uint64_t>(sizeof(head), size);
+            in.clear();
+            in.seekg(static_cast<std::streamoff>(offset));
+            if (!in.read(reinterpret_cast<char*>(head), static_cast<std::streamsize>(available))) {
+                throw std::runtime_error("MkvProcessor: read failed");
+            }
+            BlockLacing lacing = parse_lacing(head, available, size);
+            Bytes block;
+            if (lacing.lacing != 0) {
+                block = read_element(in, {ID_BLOCK, offset, 0, size}, MAX_INDEX_SIZE);
+                lacing = parse_lacing(block.data(), block.size(), block.size());
+            }
+            const auto track = read_vint(head, available, 8, false).first;
+            const auto timecode = static_cast<std::int16_t>(head[lacing.head - 3] << 8 | head[lacing.head - 2]);
+            const std::uint64_t time = cluster_time + static_cast<std::uint64_t>(std::int64_t{timecode});
+
+            auto& [count, crc] = tracks.try_emplace(track, 0, crc32(0L, Z_NULL, 0)).first->second;
+            std::uint64_t frame_offset = lacing.data;
+            for (const std::uint64_t frame : lacing.frames) {
+                unsigned char meta[17];
+                for (int i = 0; i < 8; ++i) meta[i] = static_cast<unsigned char>(time >> (8 * i));
+                meta[8] = head[lacing.head - 1] & static_cast<unsigned char>(~LACING_MASK);
+                for (int i = 0; i < 8; ++i) meta[9 + i] = static_cast<unsigned char>(frame >> (8 * i));
+                crc = crc32(crc, meta, sizeof(meta));
+                if (!block.empty()) {
+                    crc = crc32(crc, block.data() + frame_offset, static_cast<uInt>(frame));
+                } else {
+                    in.clear();
+                    in.seekg(static_cast<std::streamoff>(offset + frame_offset));
+                    for (std::uint64_t left = frame; left > 0;) {
+                        const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(left, COPY_BLOCK));
+                        buf.resize(n);
+                        if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                            throw std::runtime_error("MkvProcessor: read failed");
+                        }
+                        crc = crc32(crc, reinterpret_cast<const Bytef*>(buf.data()), static_cast<uInt>(n));
+                        left -= n;
+                    }
+                }
+                frame_offset += frame;
+                ++count;
+            }
+        };
+
+        for (const Element& cluster : layout.children) {
+            if (cluster.id != ID_CLUSTER) continue;
+            std::uint64_t cluster_time = 0;
+            for (std::uint64_t pos = cluster.offset + cluster.header; pos < cluster.end();) {
+                const ElementHeader h = read_header(in, pos, cluster.end());
+                if (!h.size || *h.size > cluster.end() - pos - h.header) throw std::runtime_error("MkvProcessor: bad cluster child");
+                const std::uint64_t body = pos + h.header;
+                if (h.id == ID_CLUSTER_TIMESTAMP) {
+                    cluster_time = read_uint(read_element(in, {h.id, body, 0, *h.size}, 8), 0, *h.size);
+                } else if (h.id == ID_SIMPLE_BLOCK) {
+                    hash_block(body, *h.size, cluster_time);
+                } else if (h.id == ID_BLOCK_GROUP) {
+                    for (std::uint64_t child = body; child < body + *h.size;) {
+                        const ElementHeader c = read_header(in, child, body + *h.size);
+                        if (!c.size || *c.size > body + *h.size - child - c.header) throw std::runtime_error("MkvProcessor: bad block group");
+                        if (c.id == ID_BLOCK) hash_block(child + c.header, *c.size, cluster_time);
+                        child += c.header + *c.size;
+                    }
+                }
+                pos = body + *h.size;
+            }
+        }
+        return tracks;
+    }
+
+} // namespace
+
 void MkvProcessor::recompress(const std::filesystem::path& input,
                               const std::filesystem::path& output,
                               bool preserve_metadata) {
-    Logger::log(LogLevel::Info, "Starting Matroska optimization: " + input.string(), "mkv_processor");
+    Logger::log(LogLevel::Info, "Starting Matroska optimization: " + input.string(), processor_tag());
 
-    namespace fs = std::filesystem;
-    std::error_code ec;
+    std::ifstream in(input, std::ios::binary);
+    if (!in) {
+        throw std::runtime_error("MkvProcessor: cannot open " + input.string());
+    }
 
-    const fs::path temp_input = fs::temp_directory_path() / (input.filename().string() + ".tmp.mkv");
-    fs::copy_file(input, temp_input, fs::copy_options::overwrite_existing, ec);
+    auto keep_as_is = [&](const std::string& reason) {
+        Logger::log(LogLevel::Warning, "MKV: Left as-is: " + reason, processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+    };
 
-    if (ec) {
-        Logger::log(LogLevel::Error, "Failed to create temporary copy: " + ec.message(), "mkv_processor");
-        throw std::runtime_error("MkvProcessor: temp copy failed");
-    }
+    MkvLayout layout;
+    std::vector<Section> sections;
+    std::vector<Bytes> cues;
+    std::vector<ClusterPlan> clusters;
+    try {
+        layout = parse_mkv(in, input);
+        for (const Element& element : layout.children) {
+            switch (element.id) {
+                case ID_VOID:
+                case ID_CRC32:
+                case ID_SEEK_HEAD:
+                    // rebuilt below
+                    break;
+                case ID_CLUSTER:
+                    clusters.emplace_back().element = element;
+                    break;
+                case ID_CUES:
+                    cues.push_back(read_element(in, element, MAX_INDEX_SIZE));
+                    break;
+                default: {
+                    if (element.id == ID_TAGS && !preserve_metadata) break;
+                    Bytes bytes = read_element(in, element, element.id == ID_ATTACHMENTS ? MAX_ATTACHMENTS_SIZE : MAX_INDEX_SIZE);
+                    if (section_rank(element.id) < 5) bytes = strip_element(bytes);
+                    if (element.id == ID_ATTACHMENTS && !preserve_metadata) bytes = drop_cover_art(bytes);
+                    if (!bytes.empty()) sections.push_back({section_rank(element.id), element.id, std::move(bytes)});
+                    break;
+                }
+            }
+        }
+        std::ranges::stable_sort(sections, {}, &Section::rank);
+
+        // positions are looked up by the old segment position of each cluster
+        auto find_cluster = [&](std::uint64_t position) -> ClusterPlan* {
+            const auto it = std::ranges::upper_bound(clusters, position + layout.data_start(), {},
+                                                     [](const ClusterPlan& c) { return c.element.offset; });
+            if (it == clusters.begin() || position + layout.data_start() >= std::prev(it)->element.end()) return nullptr;
+            return &*std::prev(it);
+        };
+
+        std::vector<std::vector<std::uint64_t>> wanted(clusters.size());
+        const CueMapper collect{
+            [](std::uint64_t position) { return std::optional<std::uint64_t>{position}; },
+            [&](std::uint64_t cluster, std::uint64_t relative) {
+                if (ClusterPlan* plan = find_cluster(cluster)) wanted[plan - clusters.data()].push_back(relative);
+                return std::optional<std::uint64_t>{relative};
+            },
+            [&](std::uint64_t position) {
+                if (ClusterPlan* plan = find_cluster(position)) {
+                    const std::uint64_t start = plan->element.offset + plan->element.header - layout.data_start();
+                    if (position >= start) wanted[plan - clusters.data()].push_back(position - start);
+                }
+                return std::optional<std::uint64_t>{position};
+            }
+        };
+        rebuild_cues(cues, collect);
+        for (std::size_t i = 0; i < clusters.size(); ++i) {
+            std::ranges::sort(wanted[i]);
+            clusters[i].new_size = rewrite_cluster(in, clusters[i], wanted[i], nullptr);
+        }
+
+        auto moved = [](const ClusterPlan& plan, std::uint64_t relative) -> std::optional<std::uint64_t> {
+            const auto it = std::ranges::lower_bound(plan.moved, relative, {}, &std::pair<std::uint64_t, std::uint64_t>::first);
+            if (it == plan.moved.end() || it->first != relative) return std::nullopt;
+            return it->second;
+        };
+        const CueMapper remap{
+            [&](std::uint64_t position) -> std::optional<std::uint64_t> {
+                const ClusterPlan* plan = find_cluster(position);
+                if (!plan || plan->element.offset != position + layout.data_start()) return std::nullopt;
+                return plan->new_position;
+            },
+            [&](std::uint64_t cluster, std::uint64_t relative) -> std::optional<std::uint64_t> {
+                const ClusterPlan* plan = find_cluster(cluster);
+                return plan ? moved(*plan, relative) : std::nullopt;
+            },
+            [&](std::uint64_t position) -> std::optional<std::uint64_t> {
+                const ClusterPlan* plan = find_cluster(position);
+                if (!plan) return std::nullopt;
+                if (plan->element.offset == position + layout.data_start()) return plan->new_position;
+                const std::uint64_t start = plan->element.offset + plan->element.header - layout.data_start();
+                if (position < start) return std::nullopt;
+                const auto relative = moved(*plan, position - start);
+                if (!relative) return std::nullopt;
+                return plan->new_position + plan->new_total() - plan->new_size + *relative;
+            }
+        };
 
-    std::vector<std::string> args;
-    args.emplace_back("mkclean");
+        // SeekHead and Cues come before the clusters they point to: grow them until they fit
+        std::uint64_t seek_head_size = 0;
+        std::uint64_t cues_size = 0;
+        Bytes seek_head;
+        Bytes new_cues;
+        for (int round = 0;; ++round) {
+            if (round == 16) throw std::runtime_error("MkvProcessor: index layout does not settle");
+            std::vector<std::pair<std::uint32_t, std::uint64_t>> entries;
+            std::uint64_t pos = seek_head_size;
+            for (const Section& section : sections) {
+                entries.emplace_back(section.id, pos);
+                pos += section.bytes.size();
+            }
+            if (!cues.empty()) entries.emplace_back(ID_CUES, pos);
+            pos += cues_size;
+            for (ClusterPlan& plan : clusters) {
+                plan.new_position = pos;
+                pos += plan.new_total();
+            }
 
-    if (preserve_metadata) {
-        args.emplace_back("--optimize");
-        args.emplace_back("--keep-cues");
-    } else {
-        args.emplace_back("--optimize");
-        args.emplace_back("--unsafe");
+            new_cues = cues.empty() ? Bytes{} : rebuild_cues(cues, remap);
+            if (new_cues.empty() && !cues.empty()) entries.pop_back();
+            seek_head = build_seek_head(entries);
+            if (seek_head.size() == seek_head_size && new_cues.size() == cues_size) break;
+            seek_head_size = seek_head.size();
+            cues_size = new_cues.size();
+        }
+
+        std::uint64_t segment_size = seek_head.size() + new_cues.size();
+        for (const Section& section : sections) segment_size += section.bytes.size();
+        for (const ClusterPlan& plan : clusters) segment_size += plan.new_total();
+
+        std::ofstream out(output, std::ios::binary | std::ios::trunc);
+        if (!out) {
+            throw std::runtime_error("MkvProcessor: cannot create " + output.string());
+        }
+        copy_range(in, 0, layout.segment_offset, out);
+        emit_header(&out, ID_SEGMENT, segment_size);
+        out.write(reinterpret_cast<const char*>(seek_head.data()), static_cast<std::streamsize>(seek_head.size()));
+        for (const Section& section : sections) {
+            out.write(reinterpret_cast<const char*>(section.bytes.data()), static_cast<std::streamsize>(section.bytes.size()));
+        }
+        out.write(reinterpret_cast<const char*>(new_cues.data()), static_cast<std::streamsize>(new_cues.size()));
+        for (ClusterPlan& plan : clusters) {
+            emit_header(&out, ID_CLUSTER, plan.new_size);
+            if (rewrite_cluster(in, plan, {}, &out) != plan.new_size) {
+                throw std::runtime_error("MkvProcessor: cluster size changed while writing");
+            }
+        }
+        copy_range(in, layout.segment_end, layout.file_size - layout.segment_end, out);
+
+        out.close();
+        if (!out) {
+            throw std::runtime_error("MkvProcessor: write failed for " + output.string());
+        }
+        Logger::log(LogLevel::Debug, "MKV: " + std::to_string(clusters.size()) + " clusters rewritten, SeekHead and Cues rebuilt",
+                    processor_tag());
+    } catch (const std::exception& e) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        keep_as_is(e.what());
     }
-    args.emplace_back("--quiet");
-    args.push_back(temp_input.string());
-    args.push_back(output.string());
+}
 
-    std::vector<char*> argv;
-    argv.reserve(args.size());
-    for (auto& s : args) {
-        argv.push_back(s.data());
+std::optional<ExtractedContent> MkvProcessor::prepare_extraction(const fs::path& input_path) {
+    Logger::log(LogLevel::Info, "MKV: Preparing attachment extraction for: " + input_path.string(), processor_tag());
+
+    std::ifstream in(input_path, std::ios::binary);
+    if (!in) {
+        Logger::log(LogLevel::Error, "MKV: Cannot open " + input_path.string(), processor_tag());
+        return std::nullopt;
     }
 
-    int return_code = -1;
+    ExtractedContent content;
+    content.original_path = input_path;
+    content.format = ContainerFormat::Mkv;
+    content.temp_dir = make_temp_dir_for(input_path, "mkv-processor");
+
+    MkvState state;
     try {
-        return_code = mkclean_optimize(static_cast<int>(argv.size()), argv.data());
+        const MkvLayout layout = parse_mkv(in, input_path);
+        if (!layout.attachments) {
+            Logger::log(LogLevel::Debug, "MKV: No attachments found.", processor_tag());
+            cleanup_temp_dir(content.temp_dir, processor_tag());
+            return std::nullopt;
+        }
+
+        const Bytes attachments = read_element(in, layout.children[*layout.attachments], MAX_ATTACHMENTS_SIZE);
+        const ElementHeader top = header_at(attachments, 0, attachments.size());
+        for (const auto& [pos, h] : children_of(attachments, top.header, attachments.size())) {
+            if (h.id != ID_ATTACHED_FILE) continue;
+            const std::size_t index = state.attached_files++;
+
+            std::string name;
+            std::optional<std::pair<std::size_t, std::uint64_t>> data;
+            for (const auto& [child_pos, child] : children_of(attachments, pos + h.header, pos + h.header + *h.size)) {
+                const std::size_t body = child_pos + child.header;
+                if (child.id == ID_FILE_NAME) {
+                    name.assign(attachments.begin() + static_cast<std::ptrdiff_t>(body),
+                                attachments.begin() + static_cast<std::ptrdiff_t>(body + *child.size));
+                    name.erase(std::find(name.begin(), name.end(), '\0'), name.end());
+                } else if (child.id == ID_FILE_DATA) {
+                    data.emplace(body, *child.size);
+                }
+            }
+            if (!data || data->second == 0) continue;
+
+            ExtractedAttachment attachment;
+            attachment.index = index;
+            attachment.original_size = data->second;
+            attachment.original_crc = crc32(crc32(0L, Z_NULL, 0), attachments.data() + data->first,
+                                             static_cast<uInt>(data->second));
+            attachment.file = content.temp_dir / (std::to_string(index) + "_" + sanitize_file_name(name));
+
+            std::ofstream out(attachment.file, std::ios::binary | std::ios::trunc);
+            out.write(reinterpret_cast<const char*>(attachments.data() + data->first),
+                      static_cast<std::streamsize>(data->second));
+            out.close();
+            if (!out) throw std::runtime_error("MkvProcessor: cannot write " + attachment.file.string());
+
+            content.extracted_files.push_back(attachment.file);
+            state.attachments.push_back(std::move(attachment));
+        }
     } catch (const std::exception& e) {
-        Logger::log(LogLevel::Error, "mkclean_optimize exception: " + std::string(e.what()), "mkv_processor");
-        fs::remove(temp_input, ec);
-        throw;
-    } catch (...) {
-        Logger::log(LogLevel::Error, "mkclean_optimize unknown exception", "mkv_processor");
-        fs::remove(temp_input, ec);
-        throw;
+        Logger::log(LogLevel::Warning, "MKV: Attachments left as-is: " + std::string(e.what()), processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return std::nullopt;
     }
 
-    fs::remove(temp_input, ec);
-
-    if (return_code != 0) {
-        Logger::log(LogLevel::Error, "mkclean failed with exit code " + std::to_string(return_code), "mkv_processor");
-        throw std::runtime_error("MkvProcessor: mkclean failed");
+    if (state.attachments.empty()) {
+        Logger::log(LogLevel::Debug, "MKV: Attachments hold no data.", processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return std::nullopt;
     }
 
-    Logger::log(LogLevel::Info, "Matroska optimization completed: " + output.string(), "mkv_processor");
+    Logger::log(LogLevel::Debug, "MKV: Extracted " + std::to_string(state.attachments.size()) + " attachments", processor_tag());
+    content.extras = std::make_any<MkvState>(std::move(state));
+    return content;
 }
 
-std::optional<ExtractedContent> MkvProcessor::prepare_extraction(const std::filesystem::path&) {
-    // TODO: implement track extraction in the future
-    return std::nullopt;
+std::filesystem::path MkvProcessor::finalize_extraction(const ExtractedContent &content) {
+    Logger::log(LogLevel::Info, "MKV: Finalizing (re-muxing attachments) for: " + content.original_path.string(), processor_tag());
+
+    const MkvState* state = std::any_cast<MkvState>(&content.extras);
+    if (!state) {
+        Logger::log(LogLevel::Error, "MKV: Failed to retrieve extraction state.", processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return {};
+    }
+
+    const fs::path final_temp_path = fs::temp_directory_path() /
+                                     (content.original_path.stem().string() + "_final" + RandomUtils::random_suffix() + content.original_path.extension().string());
+    try {
+        std::ifstream in(content.original_path, std::ios::binary);
+        if (!in) throw std::runtime_error("MkvProcessor: cannot open " + content.original_path.string());
+
+        const MkvLayout layout = parse_mkv(in, content.original_path);
+        if (!layout.attachments) throw std::runtime_error("MkvProcessor: attachments changed since extraction");
+        const Element& old_attachments = layout.children[*layout.attachments];
+        const Bytes original = read_element(in, old_attachments, MAX_ATTACHMENTS_SIZE);
+
+        // the recompressed file must still carry the attachments that were extracted
+        const ElementHeader top = header_at(original, 0, original.size());
+        std::size_t index = 0;
+        for (const auto& [pos, h] : children_of(original, top.header, original.size())) {
+            if (h.id != ID_ATTACHED_FILE) continue;
+            const auto it = std::ranges::find(state->attachments, index++, &ExtractedAttachment::index);
+            if (it == state->attachments.end()) continue;
+            for (const auto& [child_pos, child] : children_of(original, pos + h.header, pos + h.header + *h.size)) {
+                if (child.id != ID_FILE_DATA) continue;
+                const uLong crc = crc32(crc32(0L, Z_NULL, 0), original.data() + child_pos + child.header,
+                                        static_cast<uInt>(*child.size));
+                if (*child.size != it->original_size || crc != it->original_crc) {
+                    throw std::runtime_error("MkvProcessor: attachments changed since extraction");
+                }
+            }
+        }
+
+        const Bytes attachments = rebuild_attachments(original, *state);
+        const std::int64_t delta = static_cast<std::int64_t>(attachments.size()) -
+                                   static_cast<std::int64_t>(original.size());
+        if (delta >= 0) {
+            Logger::log(LogLevel::Debug, "MKV: Attachments did not shrink.", processor_tag());
+            cleanup_temp_dir(content.temp_dir, processor_tag());
+            return {};
+        }
+        // positions after the old Attachments element move back by -delta bytes
+        const std::uint64_t after = old_attachments.offset - layout.data_start();
+
+        std::ofstream out(final_temp_path, std::ios::binary | std::ios::trunc);
+        if (!out) throw std::runtime_error("MkvProcessor: cannot create " + final_temp_path.string());
+
+        copy_range(in, 0, layout.segment_offset, out);
+        Bytes segment_header = read_element(in, {ID_SEGMENT, layout.segment_offset, layout.segment_header, 0},
+                                            layout.segment_header);
+        if (!layout.segment_unknown_size) {
+            const auto [id, id_length] = read_vint(segment_header.data(), segment_header.size(), 4, true);
+            const std::uint64_t segment_size = layout.segment_end - layout.data_start() + delta;
+            segment_header.resize(id_length);
+            put_size(segment_header, segment_size, layout.segment_header - id_length);
+        }
+        out.write(reinterpret_cast<const char*>(segment_header.data()), static_cast<std::streamsize>(segment_header.size()));
+
+        for (const Element& element : layout.children) {
+            const bool patch = element.id == ID_SEEK_HEAD || element.id == ID_CUES ||
+                               (element.id == ID_CLUSTER && element.offset > old_attachments.offset &&
+                                cluster_has_position(in, element));
+            if (element.id == ID_ATTACHMENTS) {
+                out.write(reinterpret_cast<const char*>(attachments.data()), static_cast<std::streamsize>(attachments.size()));
+            } else if (patch) {
+                Bytes data = read_element(in, element, MAX_INDEX_SIZE);
+                shift_positions(data, element.header, data.size(), after, delta);
+                out.write(reinterpret_cast<const char*>(data.data()), static_cast<std::streamsize>(data.size()));
+            } else {
+                copy_range(in, element.offset, element.end() - element.offset, out);
+            }
+        }
+        copy_range(in, layout.segment_end, layout.file_size - layout.segment_end, out);
+
+        out.close();
+        if (!out) throw std::runtime_error("MkvProcessor: write failed for " + final_temp_path.string());
+        Logger::log(LogLevel::Debug, "MKV: Attachments shrunk by " + std::to_string(-delta) + " bytes", processor_tag());
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Warning, "MKV: Attachments left as-is: " + std::string(e.what()), processor_tag());
+        std::error_code ec;
+        fs::remove(final_temp_path, ec);
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return {};
+    }
+
+    cleanup_temp_dir(content.temp_dir, processor_tag());
+    return final_temp_path;
 }
 
-std::filesystem::path MkvProcessor::finalize_extraction(const ExtractedContent &) {
-    return {};
-    // TODO: implement container rebuild after track modifications
+bool MkvProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    std::ifstream in_a(a, std::ios::binary);
+    std::ifstream in_b(b, std::ios::binary);
+    if (!in_a || !in_b) return false;
+
+    try {
+        const MkvLayout mkv_a = parse_mkv(in_a, a);
+        const MkvLayout mkv_b = parse_mkv(in_b, b);
+        return track_frame_hashes(in_a, mkv_a) == track_frame_hashes(in_b, mkv_b);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("MKV: Frame comparison unavailable: ") + e.what(), processor_tag());
+        return files_equal(a, b);
+    }
 }
 
 std::string MkvProcessor::get_raw_checksum(const std::filesystem::path&) const {
@@ -89,4 +1355,4 @@ std::string MkvProcessor::get_raw_checksum(const std::filesystem::path&) const {
     return "";
 }
 
-} // namespace chisel
\ No newline at end of file
+} // namespace chisel
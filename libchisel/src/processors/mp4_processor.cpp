@@ -7,8 +7,18 @@
 #include "../../include/audio_metadata_util.hpp"
 #include "../../include/file_utils.hpp"
 #include "../../include/random_utils.hpp"
+#include <algorithm>
+#include <array>
+#include <cstdint>
+#include <fstream>
+#include <optional>
 #include <stdexcept>
 #include <filesystem>
+#include <string>
+#include <string_view>
+#include <utility>
+#include <vector>
+#include <zlib.h>
 #include "file_type.hpp"
 
 namespace chisel {
@@ -18,13 +28,542 @@ static const char* processor_tag() {
     return "Mp4Processor";
 }
 
+namespace {
+
+    constexpr std::size_t COPY_BLOCK = 1 << 20;
+    constexpr std::uint64_t MAX_BOX_HEADER = 16;
+    // moov is parsed in memory; anything bigger is not a real movie header
+    constexpr std::uint64_t MAX_MOOV_SIZE = 256ull << 20;
+
+    using Bytes = std::vector<unsigned char>;
+
+    /**
+     * @brief A box of the moov tree, held in memory.
+     */
+    struct Box {
+        std::string type;
+        bool container = false;
+        Bytes prefix;             ///< Version/flags of a full-box container ('meta')
+        Bytes payload;            ///< Contents of a leaf box
+        std::vector<Box> children;
+
+        [[nodiscard]] std::uint64_t size() const {
+            std::uint64_t body = prefix.size() + payload.size();
+            for (const auto& child : children) body += child.size();
+            return body + (body + 8 > 0xFFFFFFFFu ? 16 : 8);
+        }
+    };
+
+    /**
+     * @brief A top-level box, copied from the input unless it is moov.
+     */
+    struct TopBox {
+        std::string type;
+        std::uint64_t offset = 0; ///< Start of the box header in the input
+        std::uint64_t size = 0;   ///< Whole box, header included
+    };
+
+    /**
+     * @brief The input split into top-level boxes, with moov parsed.
+     */
+    struct Mp4Layout {
+        std::vector<TopBox> boxes;
+        std::optional<std::size_t> moov_index;
+        Box moov;
+    };
+
+    std::uint32_t be32_at(const unsigned char* p) {
+        return static_cast<std::uint32_t>(p[0]) << 24 | static_cast<std::uint32_t>(p[1]) << 16 |
+               static_cast<std::uint32_t>(p[2]) << 8 | p[3];
+    }
+
+    std::uint64_t be64_at(const unsigned char* p) {
+        return std::uint64_t{be32_at(p)} << 32 | be32_at(p + 4);
+    }
+
+    void put_be32(Bytes& out, std::uint32_t v) {
+        for (int i = 3; i >= 0; --i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    void put_be64(Bytes& out, std::uint64_t v) {
+        put_be32(out, static_cast<std::uint32_t>(v >> 32));
+        put_be32(out, static_cast<std::uint32_t>(v));
+    }
+
+    std::uint32_t read_u32(const Bytes& payload, std::size_t pos) {
+        if (pos + 4 > payload.size()) throw std::runtime_error("Mp4Processor: sample table truncated");
+        return be32_at(payload.data() + pos);
+    }
+
+    bool is_container(const std::string& type) {
+        static constexpr std::array<std::string_view, 10> kContainers = {
+            "moov", "trak", "mdia", "minf", "stbl", "edts", "dinf", "udta", "meta", "mvex"
+        };
+        return std::find(kContainers.begin(), kContainers.end(), type) != kContainers.end();
+    }
+
+    bool is_free_space(const std::string& type) {
+        return type == "free" || type == "skip" || type == "wide";
+    }
+
+    struct BoxHeader {
+        std::string type;
+        std::uint64_t header_size;
+        std::uint64_t size; ///< Whole box, header included
+    };
+
+    /**
+     * @brief Reads the box header at `p`, with `available` bytes left in the parent.
+     */
+    BoxHeader box_header(const unsigned char* p, std::uint64_t available) {
+        if (available < 8) throw std::runtime_error("Mp4Processor: truncated box header");
+        BoxHeader header{std::string(reinterpret_cast<const char*>(p + 4), 4), 8, be32_at(p)};
+        if (header.size == 1) {
+            if (available < 16) throw std::runtime_error("Mp4Processor: truncated box header");
+            header.size = be64_at(p + 8);
+            header.header_size = 16;
+        } else if (header.size == 0) {
+            header.size = available; // runs to the end of the file
+        }
+        if (header.size < header.header_size || header.size > available) {
+            throw std::runtime_error("Mp4Processor: box '" + header.type + "' size out of range");
+        }
+        return header;
+    }
+
+    std::vector<Box> parse_boxes(const Bytes& data, std::uint64_t begin, std::uint64_t end) {
+        std::vector<Box> boxes;
+        std::uint64_t pos = begin;
+        while (pos < end) {
+            // QuickTime may close a container with a 32-bit zero terminator
+            if (end - pos == 4 && be32_at(data.data() + pos) == 0) break;
+            const BoxHeader header = box_header(data.data() + pos, end - pos);
+            Box box;
+            box.type = header.type;
+            std::uint64_t body = pos + header.header_size;
+            if (is_container(header.type)) {
+                box.container = true;
+                // iTunes 'meta' is a full box, QuickTime 'meta' is not
+                if (header.type == "meta" && pos + header.size - body >= 4 && be32_at(data.data() + body) == 0) {
+                    box.prefix.assign(data.begin() + static_cast<std::ptrdiff_t>(body),
+                                      data.begin() + static_cast<std::ptrdiff_t>(body + 4));
+                    body += 4;
+                }
+                box.children = parse_boxes(data, body, pos + header.size);
+            } else {
+                box.payload.assign(data.begin() + static_cast<std::ptrdiff_t>(body),
+                                   data.begin() + static_cast<std::ptrdiff_t>(pos + header.size));
+            }
+            boxes.push_back(std::move(box));
+            pos += header.size;
+        }
+        return boxes;
+    }
+
+    void serialize(const Box& box, Bytes& out) {
+        const std::uint64_t size = box.size();
+        if (size > 0xFFFFFFFFu) {
+            put_be32(out, 1);
+            out.insert(out.end(), box.type.begin(), box.type.end());
+            put_be64(out, size);
+        } else {
+            put_be32(out, static_cast<std::uint32_t>(size));
+            out.insert(out.end(), box.type.begin(), box.type.end());
+        }
+        out.insert(out.end(), box.prefix.begin(), box.prefix.end());
+        out.insert(out.end(), box.payload.begin(), box.payload.end());
+        for (const auto& child : box.children) serialize(child, out);
+    }
+
+    template <typename B>
+    B* find_child(B& box, std::string_view type) {
+        const auto it = std::find_if(box.children.begin(), box.children.end(),
+                                     [&](const Box& child) { return child.type == type; });
+        return it == box.children.end() ? nullptr : &*it;
+    }
+
+    template <typename B>
+    B* sample_table(B& trak) {
+        auto* mdia = find_child(trak, "mdia");
+        auto* minf = mdia ? find_child(*mdia, "minf") : nullptr;
+        return minf ? find_child(*minf, "stbl") : nullptr;
+    }
+
+    bool contains_box(const Box& box, std::string_view type) {
+        return box.type == type || std::any_of(box.children.begin(), box.children.end(),
+                                               [&](const Box& child) { return contains_box(child, type); });
+    }
+
+    /**
+     * @brief Drops free space (and, without metadata, user data) from a box tree.
+     * @return False if the box itself should go.
+     */
+    bool strip_box(Box& box, bool preserve_metadata, std::size_t& dropped) {
+        if (is_free_space(box.type) || (!preserve_metadata && (box.type == "udta" || box.type == "meta"))) {
+            ++dropped;
+            return false;
+        }
+        std::erase_if(box.children, [&](Box& child) { return !strip_box(child, preserve_metadata, dropped); });
+        if (box.type == "udta" && box.children.empty()) {
+            ++dropped;
+            return false;
+        }
+        return true;
+    }
+
+    /**
+     * @brief Merges runs of equal deltas in an 'stts' table.
+     */
+    void compact_stts(Box& box) {
+        const std::uint32_t count = read_u32(box.payload, 4);
+        std::vector<std::pair<std::uint32_t, std::uint32_t>> entries;
+        for (std::uint32_t i = 0; i < count; ++i) {
+            const std::uint32_t samples = read_u32(box.payload, 8 + 8 * std::size_t{i});
+            const std::uint32_t delta = read_u32(box.payload, 12 + 8 * std::size_t{i});
+            if (samples == 0) continue;
+            if (!entries.empty() && entries.back().second == delta && entries.back().first <= 0xFFFFFFFFu - samples) {
+                entries.back().first += samples;
+            } else {
+                entries.emplace_back(samples, delta);
+            }
+        }
+        Bytes out(box.payload.begin(), box.payload.begin() + 4);
+        put_be32(out, static_cast<std::uint32_t>(entries.size()));
+        for (const auto& [samples, delta] : entries) {
+            put_be32(out, samples);
+            put_be32(out, delta);
+        }
+        box.payload = std::move(out);
+    }
+
+    /**
+     * @brief Drops 'stsc' entries that repeat the previous one.
+     */
+    void compact_stsc(Box& box) {
+        const std::uint32_t count = read_u32(box.payload, 4);
+        Bytes entries;
+        for (std::uint32_t i = 0; i < count; ++i) {
+            const std::size_t at = 8 + 12 * std::size_t{i};
+            const std::uint32_t per_chunk = read_u32(box.payload, at + 4);
+            const std::uint32_t description = read_u32(box.payload, at + 8);
+            if (!entries.empty() && read_u32(entries, entries.size() - 8) == per_chunk &&
+                read_u32(entries, entries.size() - 4) == description) {
+                continue;
+            }
+            entries.insert(entries.end(), box.payload.begin() + static_cast<std::ptrdiff_t>(at),
+                           box.payload.begin() + static_cast<std::ptrdiff_t>(at + 12));
+        }
+        Bytes out(box.payload.begin(), box.payload.begin() + 4);
+        put_be32(out, static_cast<std::uint32_t>(entries.size() / 12));
+        out.insert(out.end(), entries.begin(), entries.end());
+        box.payload = std::move(out);
+    }
+
+    /**
+     * @brief Replaces an 'stsz' table of identical sizes by its constant size.
+     */
+    void compact_stsz(Box& box) {
+        if (read_u32(box.payload, 4) != 0) return; // already constant
+        const std::uint32_t count = read_u32(box.payload, 8);
+        if (count == 0) return;
+        const std::uint32_t first = read_u32(box.payload, 12);
+        for (std::uint32_t i = 1; i < count; ++i) {
+            if (read_u32(box.payload, 12 + 4 * std::size_t{i}) != first) return;
+        }
+        Bytes out(box.payload.begin(), box.payload.begin() + 4);
+        put_be32(out, first);
+        put_be32(out, count);
+        box.payload = std::move(out);
+    }
+
+    std::vector<std::uint64_t> chunk_offsets(const Box& box) {
+        const bool wide = box.type == "co64";
+        const std::uint32_t count = read_u32(box.payload, 4);
+        std::vector<std::uint64_t> offsets(count);
+        for (std::uint32_t i = 0; i < count; ++i) {
+            const std::size_t at = 8 + (wide ? 8 : 4) * std::size_t{i};
+            offsets[i] = wide ? std::uint64_t{read_u32(box.payload, at)} << 32 | read_u32(box.payload, at + 4)
+                              : read_u32(box.payload, at);
+        }
+        return offsets;
+    }
+
+    void write_chunk_offsets(Box& box, const std::vector<std::uint64_t>& offsets, bool wide) {
+        Bytes out(box.payload.begin(), box.payload.begin() + 4);
+        put_be32(out, static_cast<std::uint32_t>(offsets.size()));
+        for (const std::uint64_t offset : offsets) {
+            if (wide) {
+                put_be64(out, offset);
+            } else {
+                put_be32(out, static_cast<std::uint32_t>(offset));
+            }
+        }
+        box.type = wide ? "co64" : "stco";
+        box.payload = std::move(out);
+    }
+
+    /**
+     * @brief Lists the top-level boxes of a file and parses its moov box.
+     * @throws std::runtime_error for malformed and fragmented files.
+     */
+    Mp4Layout parse_mp4(std::ifstream& in, const fs::path& path) {
+        Mp4Layout layout;
+        const std::uint64_t file_size = fs::file_size(path);
+        std::uint64_t pos = 0;
+        while (pos < file_size) {
+            unsigned char raw[MAX_BOX_HEADER];
+            const std::uint64_t available = std::min<std::uint64_t>(MAX_BOX_HEADER, file_size - pos);
+            in.clear();
+            in.seekg(static_cast<std::streamoff>(pos));
+            if (!in.read(reinterpret_cast<char*>(raw), static_cast<std::streamsize>(available))) {
+                throw std::runtime_error("Mp4Processor: read failed");
+            }
+            const BoxHeader header = box_header(raw, file_size - pos);
+            layout.boxes.push_back({header.type, pos, header.size});
+
+            if (header.type == "moov") {
+                if (layout.moov_index) throw std::runtime_error("Mp4Processor: more than one moov box");
+                if (header.size > MAX_MOOV_SIZE) throw std::runtime_error("Mp4Processor: moov box too large");
+                Bytes data(header.size);
+                in.clear();
+                in.seekg(static_cast<std::streamoff>(pos));
+                if (!in.read(reinterpret_cast<char*>(data.data()), static_cast<std::streamsize>(data.size()))) {
+                    throw std::runtime_error("Mp4Processor: read failed in moov box");
+                }
+                layout.moov = std::move(parse_boxes(data, 0, data.size()).front());
+                layout.moov_index = layout.boxes.size() - 1;
+            } else if (header.type == "moof" || header.type == "mfra" || header.type == "sidx") {
+                throw std::runtime_error("Mp4Processor: fragmented file");
+            } else if (header.type == "meta") {
+                // HEIF-style item locations are absolute offsets that are not relocated here
+                throw std::runtime_error("Mp4Processor: top-level meta box");
+            }
+            pos += header.size;
+        }
+        if (!layout.moov_index) throw std::runtime_error("Mp4Processor: no moov box");
+        return layout;
+    }
+
+    /**
+     * @brief Reads the sample sizes of a track from 'stsz' or 'stz2'.
+     */
+    std::vector<std::uint32_t> sample_sizes(const Box& stbl) {
+        std::vector<std::uint32_t> sizes;
+        if (const Box* stsz = find_child(stbl, "stsz")) {
+            const std::uint32_t fixed = read_u32(stsz->payload, 4);
+            const std::uint32_t count = read_u32(stsz->payload, 8);
+            sizes.resize(count, fixed);
+            for (std::uint32_t i = 0; fixed == 0 && i < count; ++i) {
+                sizes[i] = read_u32(stsz->payload, 12 + 4 * std::size_t{i});
+            }
+        } else if (const Box* stz2 = find_child(stbl, "stz2")) {
+            const Bytes& p = stz2->payload;
+            const unsigned field = p.size() > 7 ? p[7] : 0;
+            const std::uint32_t count = read_u32(p, 8);
+            if ((field != 4 && field != 8 && field != 16) || p.size() < 12 + (std::size_t{count} * field + 7) / 8) {
+                throw std::runtime_error("Mp4Processor: bad stz2 box");
+            }
+            sizes.resize(count);
+            for (std::uint32_t i = 0; i < count; ++i) {
+                const std::size_t at = 12 + std::size_t{i} * field / 8;
+                sizes[i] = field == 16 ? static_cast<std::uint32_t>(p[at] << 8 | p[at + 1])
+                         : field == 8  ? p[at]
+                                       : (i % 2 == 0 ? p[at] >> 4 : p[at] & 0x0F);
+            }
+        } else {
+            throw std::runtime_error("Mp4Processor: track without sample sizes");
+        }
+        return sizes;
+    }
+
+    /**
+     * @brief Sample count and CRC-32 of the sample data of every track, in track order.
+     */
+    std::vector<std::pair<std::size_t, uLong>> track_sample_hashes(std::ifstream& in, const Box& moov) {
+        std::vector<std::pair<std::size_t, uLong>> tracks;
+        for (const Box& trak : moov.children) {
+            if (trak.type != "trak") continue;
+            const Box* stbl = sample_table(trak);
+            if (!stbl) throw std::runtime_error("Mp4Processor: track without sample table");
+            const Box* stco = find_child(*stbl, "stco");
+            if (!stco) stco = find_child(*stbl, "co64");
+            const Box* stsc = find_child(*stbl, "stsc");
+            if (!stco || !stsc) throw std::runtime_error("Mp4Processor: incomplete sample table");
+
+            const auto sizes = sample_sizes(*stbl);
+            const auto offsets = chunk_offsets(*stco);
+            const std::uint32_t entries = read_u32(stsc->payload, 4);
+
+            uLong crc = crc32(0L, Z_NULL, 0);
+            std::vector<char> buf;
+            std::size_t sample = 0;
+            std::uint32_t entry = 0;
+            for (std::size_t chunk = 0; chunk < offsets.size() && sample < sizes.size() && entries > 0; ++chunk) {
+                // entries are keyed by their 1-based first chunk
+                while (entry + 1 < entries && read_u32(stsc->payload, 8 + 12 * std::size_t{entry + 1}) <= chunk + 1) {
+                    ++entry;
+                }
+                const std::uint32_t per_chunk = read_u32(stsc->payload, 8 + 12 * std::size_t{entry} + 4);
+                std::uint64_t bytes = 0;
+                for (std::uint32_t i = 0; i < per_chunk && sample < sizes.size(); ++i) bytes += sizes[sample++];
+
+                in.clear();
+                in.seekg(static_cast<std::streamoff>(offsets[chunk]));
+                while (bytes > 0) {
+                    const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(bytes, COPY_BLOCK));
+                    buf.resize(n);
+                    if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                        throw std::runtime_error("Mp4Processor: sample data out of range");
+                    }
+                    crc = crc32(crc, reinterpret_cast<const Bytef*>(buf.data()), static_cast<uInt>(n));
+                    bytes -= n;
+                }
+            }
+            if (sample != sizes.size()) throw std::runtime_error("Mp4Processor: chunks do not hold every sample");
+            tracks.emplace_back(sizes.size(), crc);
+        }
+        return tracks;
+    }
+
+    void copy_range(std::ifstream& in, std::uint64_t offset, std::uint64_t size, std::ofstream& out) {
+        std::vector<char> buf(COPY_BLOCK);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        while (size > 0) {
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(size, buf.size()));
+            if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                throw std::runtime_error("Mp4Processor: read failed");
+            }
+            out.write(buf.data(), static_cast<std::streamsize>(n));
+            size -= n;
+        }
+    }
+
+} // namespace
+
 void Mp4Processor::recompress(const fs::path& input,
                               const fs::path& output,
-                              bool /*preserve_metadata*/) {
-    Logger::log(LogLevel::Error, "Recompress called on Mp4Processor placeholder.", processor_tag());
-    std::error_code ec;
-    fs::copy_file(input, output, fs::copy_options::overwrite_existing, ec);
-    if (ec) throw std::runtime_error("Placeholder recompress failed to copy file.");
+                              bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "MP4: Optimizing boxes of: " + input.string(), processor_tag());
+
+    std::ifstream in(input, std::ios::binary);
+    if (!in) {
+        throw std::runtime_error("Mp4Processor: cannot open " + input.string());
+    }
+
+    auto keep_as_is = [&](const std::string& reason) {
+        Logger::log(LogLevel::Warning, "MP4: Left as-is: " + reason, processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+    };
+
+    Mp4Layout layout;
+    std::vector<Box*> offset_tables;
+    std::vector<std::vector<std::uint64_t>> old_offsets;
+    std::size_t dropped = 0;
+    try {
+        layout = parse_mp4(in, input);
+        // sample auxiliary offsets (encryption) are absolute as well, but not relocated here
+        if (contains_box(layout.moov, "saio")) {
+            keep_as_is("'saio' box present");
+            return;
+        }
+
+        std::erase_if(layout.moov.children, [&](Box& child) { return !strip_box(child, preserve_metadata, dropped); });
+        for (Box& trak : layout.moov.children) {
+            Box* stbl = trak.type == "trak" ? sample_table(trak) : nullptr;
+            if (!stbl) continue;
+            for (Box& table : stbl->children) {
+                if (table.type == "stts") {
+                    compact_stts(table);
+                } else if (table.type == "stsc") {
+                    compact_stsc(table);
+                } else if (table.type == "stsz") {
+                    compact_stsz(table);
+                } else if (table.type == "stco" || table.type == "co64") {
+                    old_offsets.push_back(chunk_offsets(table));
+                    offset_tables.push_back(&table);
+                }
+            }
+        }
+    } catch (const std::exception& e) {
+        keep_as_is(e.what());
+        return;
+    }
+
+    // faststart order: ftyp, moov, then the remaining boxes as they were
+    std::vector<const TopBox*> order;
+    for (const auto& box : layout.boxes) {
+        if (box.type == "ftyp") order.push_back(&box);
+    }
+    order.push_back(&layout.boxes[*layout.moov_index]);
+    for (const auto& box : layout.boxes) {
+        if (box.type == "ftyp" || box.type == "moov") continue;
+        if (is_free_space(box.type) || (!preserve_metadata && box.type == "udta")) {
+            ++dropped;
+            continue;
+        }
+        order.push_back(&box);
+    }
+
+    // the size of moov depends on stco vs. co64, which depends on where the data lands
+    std::vector<bool> wide(offset_tables.size(), false);
+    std::vector<std::vector<std::uint64_t>> new_offsets(offset_tables.size());
+    for (bool settled = false; !settled;) {
+        for (std::size_t t = 0; t < offset_tables.size(); ++t) {
+            write_chunk_offsets(*offset_tables[t], old_offsets[t], wide[t]);
+        }
+        std::vector<std::uint64_t> placed;
+        std::uint64_t pos = 0;
+        for (const TopBox* box : order) {
+            placed.push_back(pos);
+            pos += box->type == "moov" ? layout.moov.size() : box->size;
+        }
+
+        settled = true;
+        for (std::size_t t = 0; t < offset_tables.size(); ++t) {
+            new_offsets[t].clear();
+            for (const std::uint64_t offset : old_offsets[t]) {
+                std::size_t i = 0;
+                while (i < order.size() && (order[i]->type == "moov" || offset < order[i]->offset ||
+                                            offset >= order[i]->offset + order[i]->size)) {
+                    ++i;
+                }
+                if (i == order.size()) {
+                    keep_as_is("chunk offset outside the data boxes");
+                    return;
+                }
+                new_offsets[t].push_back(offset - order[i]->offset + placed[i]);
+            }
+            if (!wide[t] && std::any_of(new_offsets[t].begin(), new_offsets[t].end(),
+                                        [](std::uint64_t o) { return o > 0xFFFFFFFFu; })) {
+                wide[t] = true;
+                settled = false;
+            }
+        }
+    }
+    for (std::size_t t = 0; t < offset_tables.size(); ++t) {
+        write_chunk_offsets(*offset_tables[t], new_offsets[t], wide[t]);
+    }
+
+    Bytes moov;
+    serialize(layout.moov, moov);
+
+    std::ofstream out(output, std::ios::binary | std::ios::trunc);
+    if (!out) {
+        throw std::runtime_error("Mp4Processor: cannot create " + output.string());
+    }
+    for (const TopBox* box : order) {
+        if (box->type == "moov") {
+            out.write(reinterpret_cast<const char*>(moov.data()), static_cast<std::streamsize>(moov.size()));
+        } else {
+            copy_range(in, box->offset, box->size, out);
+        }
+    }
+    out.close();
+    if (!out) {
+        throw std::runtime_error("Mp4Processor: write failed for " + output.string());
+    }
+    Logger::log(LogLevel::Debug, "MP4: " + std::to_string(dropped) + " boxes dropped, moov written first", processor_tag());
 }
 
 std::optional<ExtractedContent> Mp4Processor::prepare_extraction(const fs::path& input_path) {
@@ -83,4 +622,19 @@ std::filesystem::path Mp4Processor::finalize_extraction(const ExtractedContent &
     return final_temp_path;
 }
 
-} // namespace chisel
\ No newline at end of file
+bool Mp4Processor::raw_equal(const fs::path& a, const fs::path& b) const {
+    std::ifstream in_a(a, std::ios::binary);
+    std::ifstream in_b(b, std::ios::binary);
+    if (!in_a || !in_b) return false;
+
+    try {
+        const Mp4Layout mp4_a = parse_mp4(in_a, a);
+        const Mp4Layout mp4_b = parse_mp4(in_b, b);
+        return track_sample_hashes(in_a, mp4_a.moov) == track_sample_hashes(in_b, mp4_b.moov);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("MP4: Sample comparison unavailable: ") + e.what(), processor_tag());
+        return files_equal(a, b);
+    }
+}
+
+} // namespace chisel
@@ -6,9 +6,20 @@
 #include "../../include/logger.hpp"
 #include "../../include/audio_metadata_util.hpp"
 #include "../../include/file_utils.hpp"
+#include "../../include/mime_detector.hpp"
 #include "../../include/random_utils.hpp"
+#include <algorithm>
+#include <array>
+#include <cstdint>
+#include <cstring>
+#include <fstream>
+#include <iterator>
+#include <optional>
 #include <stdexcept>
 #include <filesystem>
+#include <string>
+#include <string_view>
+#include <vector>
 #include "file_type.hpp"
 
 namespace chisel {
@@ -18,17 +29,568 @@ static const char* processor_tag() {
     return "MpegProcessor";
 }
 
+namespace {
+
+    constexpr std::array<int, 15> BITRATES_KBPS = {0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320};
+    constexpr std::array<int, 3> SAMPLE_RATES = {44100, 48000, 32000};
+    constexpr std::size_t HEADER_SIZE = 4;
+    constexpr std::size_t CRC_SIZE = 2;
+    constexpr std::size_t MAX_MAIN_DATA_BEGIN = 511;
+    constexpr std::size_t GRANULE_INFO_BITS = 59;
+    constexpr std::size_t XING_TOC_SIZE = 100;
+    constexpr std::size_t LAME_TAG_SIZE = 36;
+
+    constexpr std::uint32_t XING_FRAMES = 0x1;
+    constexpr std::uint32_t XING_BYTES = 0x2;
+    constexpr std::uint32_t XING_TOC = 0x4;
+    constexpr std::uint32_t XING_QUALITY = 0x8;
+
+    using Bytes = std::vector<unsigned char>;
+
+    /**
+     * @brief An MPEG-1 Layer III frame header.
+     */
+    struct FrameHeader {
+        std::array<unsigned char, 4> b{};
+
+        [[nodiscard]] bool has_crc() const { return (b[1] & 0x01) == 0; }
+        [[nodiscard]] int bitrate_index() const { return b[2] >> 4; }
+        [[nodiscard]] int sample_rate_index() const { return (b[2] >> 2) & 0x03; }
+        [[nodiscard]] bool padding() const { return (b[2] & 0x02) != 0; }
+        [[nodiscard]] bool mono() const { return (b[3] >> 6) == 3; }
+        [[nodiscard]] std::size_t side_info_size() const { return mono() ? 17 : 32; }
+        [[nodiscard]] std::size_t overhead() const { return HEADER_SIZE + (has_crc() ? CRC_SIZE : 0) + side_info_size(); }
+    };
+
+    std::size_t frame_size(int bitrate_index, int sample_rate_index, bool padding) {
+        return 144000u * BITRATES_KBPS[bitrate_index] / SAMPLE_RATES[sample_rate_index] + (padding ? 1 : 0);
+    }
+
+    std::size_t frame_size(const FrameHeader& h) {
+        return frame_size(h.bitrate_index(), h.sample_rate_index(), h.padding());
+    }
+
+    /**
+     * @brief Parses a header if `p` holds an MPEG-1 Layer III sync with a usable bitrate and sample rate.
+     */
+    std::optional<FrameHeader> parse_header(const unsigned char* p) {
+        if (p[0] != 0xFF || (p[1] & 0xFE) != 0xFA) return std::nullopt;
+        FrameHeader h;
+        std::copy_n(p, 4, h.b.begin());
+        // free format (0) and the forbidden index (15) cannot be repacked
+        if (h.bitrate_index() == 0 || h.bitrate_index() == 15) return std::nullopt;
+        if (h.sample_rate_index() == 3) return std::nullopt;
+        if ((p[3] & 0x03) == 2) return std::nullopt; // reserved emphasis
+        return h;
+    }
+
+    std::uint32_t read_bits(const Bytes& data, std::size_t bit_pos, int count) {
+        std::uint32_t v = 0;
+        for (int i = 0; i < count; ++i, ++bit_pos) {
+            v = (v << 1) | ((data[bit_pos >> 3] >> (7 - (bit_pos & 7))) & 1);
+        }
+        return v;
+    }
+
+    std::uint32_t be32_at(const Bytes& data, std::size_t pos) {
+        return static_cast<std::uint32_t>(data[pos]) << 24 | static_cast<std::uint32_t>(data[pos + 1]) << 16 |
+               static_cast<std::uint32_t>(data[pos + 2]) << 8 | data[pos + 3];
+    }
+
+    void put_be32(Bytes& out, std::size_t pos, std::uint32_t v) {
+        for (int i = 0; i < 4; ++i) out[pos + i] = static_cast<unsigned char>(v >> (24 - 8 * i));
+    }
+
+    /**
+     * @brief The frame CRC: CRC-16 (0x8005, initial 0xFFFF) over header bytes 2-3 and the side info.
+     */
+    std::uint16_t frame_crc(const FrameHeader& h, const Bytes& side_info) {
+        std::uint32_t crc = 0xFFFF;
+        auto update = [&crc](unsigned char byte) {
+            crc ^= static_cast<std::uint32_t>(byte) << 8;
+            for (int i = 0; i < 8; ++i) crc = (crc & 0x8000) ? ((crc << 1) ^ 0x8005) & 0xFFFF : (crc << 1) & 0xFFFF;
+        };
+        update(h.b[2]);
+        update(h.b[3]);
+        for (const unsigned char c : side_info) update(c);
+        return static_cast<std::uint16_t>(crc);
+    }
+
+    /**
+     * @brief The CRC used by the LAME tag: reflected CRC-16 (0xA001), initial 0.
+     */
+    std::uint16_t lame_crc(const unsigned char* data, std::size_t size, std::uint16_t crc = 0) {
+        for (std::size_t i = 0; i < size; ++i) {
+            crc ^= data[i];
+            for (int b = 0; b < 8; ++b) crc = (crc & 1) ? (crc >> 1) ^ 0xA001 : crc >> 1;
+        }
+        return crc;
+    }
+
+    /**
+     * @brief One audio frame, reduced to what a decoder reads from it.
+     */
+    struct AudioFrame {
+        FrameHeader header;
+        Bytes side_info;      ///< As stored; main_data_begin is rewritten on output
+        Bytes main_data;      ///< The granule data, ancillary bits after it cleared
+    };
+
+    /**
+     * @brief The Xing/Info tag fields worth carrying over.
+     */
+    struct InfoTag {
+        std::optional<std::uint32_t> quality;
+        std::optional<std::array<unsigned char, LAME_TAG_SIZE>> lame; ///< LAME extension (encoder delay/padding, ReplayGain)
+    };
+
+    struct ParsedMp3 {
+        Bytes leading_tags;           ///< ID3v2 tag(s) before the first frame
+        std::optional<InfoTag> info;  ///< Set if the stream starts with a Xing/Info/VBRI frame
+        std::vector<AudioFrame> frames;
+        Bytes trailing;               ///< Unrecognized data after the last frame
+        Bytes trailing_tags;          ///< APEv2 and ID3v1 tags at the end of the file
+        std::size_t junk_bytes = 0;   ///< Bytes dropped between frames
+    };
+
+    Bytes read_file(const fs::path& path) {
+        std::ifstream in(path, std::ios::binary);
+        if (!in) throw std::runtime_error("MpegProcessor: cannot open " + path.string());
+        return {std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>()};
+    }
+
+    bool has_magic(const Bytes& data, std::size_t pos, std::string_view magic) {
+        return pos + magic.size() <= data.size() &&
+               std::memcmp(data.data() + pos, magic.data(), magic.size()) == 0;
+    }
+
+    /**
+     * @brief Reads the Xing/Info or VBRI tag if the frame at `pos` carries one.
+     */
+    std::optional<InfoTag> read_info_tag(const Bytes& data, std::size_t pos, const FrameHeader& h) {
+        const std::size_t end = pos + frame_size(h);
+        if (has_magic(data, pos + HEADER_SIZE + 32, "VBRI")) {
+            return InfoTag{};
+        }
+        std::size_t x = pos + HEADER_SIZE + h.side_info_size();
+        if (!has_magic(data, x, "Xing") && !has_magic(data, x, "Info")) return std::nullopt;
+
+        InfoTag tag;
+        if (x + 8 > end) return tag;
+        const std::uint32_t flags = be32_at(data, x + 4);
+        x += 8;
+        if (flags & XING_FRAMES) x += 4;
+        if (flags & XING_BYTES) x += 4;
+        if (flags & XING_TOC) x += XING_TOC_SIZE;
+        if (flags & XING_QUALITY) {
+            if (x + 4 > end) return tag;
+            tag.quality = be32_at(data, x);
+            x += 4;
+        }
+        if (x + LAME_TAG_SIZE <= end &&
+            (has_magic(data, x, "LAME") || has_magic(data, x, "Lavf") || has_magic(data, x, "Lavc"))) {
+            std::array<unsigned char, LAME_TAG_SIZE> lame{};
+            std::copy_n(data.begin() + static_cast<std::ptrdiff_t>(x), LAME_TAG_SIZE, lame.begin());
+            tag.lame = lame;
+        }
+        return tag;
+    }
+
+    /**
+     * @brief Splits an MP3 file into tags, frames and the granule data of each frame.
+     * @throws std::runtime_error if the stream is not a repackable MPEG-1 Layer III stream.
+     */
+    ParsedMp3 parse_mp3(const Bytes& data) {
+        ParsedMp3 mp3;
+
+        std::size_t pos = 0;
+        while (has_magic(data, pos, "ID3") && pos + 10 <= data.size()) {
+            const std::size_t size = 10 + ((data[pos + 6] & 0x7F) << 21 | (data[pos + 7] & 0x7F) << 14 |
+                                           (data[pos + 8] & 0x7F) << 7 | (data[pos + 9] & 0x7F)) +
+                                     ((data[pos + 5] & 0x10) ? 10 : 0);
+            const std::size_t tag_end = std::min(data.size(), pos + size);
+            mp3.leading_tags.insert(mp3.leading_tags.end(), data.begin() + static_cast<std::ptrdiff_t>(pos),
+                                    data.begin() + static_cast<std::ptrdiff_t>(tag_end));
+            pos = tag_end;
+        }
+
+        // trailing tags: APEv2 (footer-located) and ID3v1, in either order
+        std::size_t end = data.size();
+        for (bool found = true; found;) {
+            found = false;
+            if (end >= pos + 128 && has_magic(data, end - 128, "TAG")) {
+                end -= 128;
+                found = true;
+            }
+            if (end >= pos + 32 && has_magic(data, end - 32, "APETAGEX")) {
+                const std::size_t f = end - 32;
+                std::size_t size = data[f + 12] | data[f + 13] << 8 | data[f + 14] << 16 |
+                                   static_cast<std::size_t>(data[f + 15]) << 24;
+                if (data[f + 23] & 0x80) size += 32; // header present
+                if (size <= end - pos) {
+                    end -= size;
+                    found = true;
+                }
+            }
+        }
+        mp3.trailing_tags.assign(data.begin() + static_cast<std::ptrdiff_t>(end), data.end());
+
+        std::optional<FrameHeader> first;
+        auto frame_at = [&](std::size_t p) -> std::optional<FrameHeader> {
+            if (p + HEADER_SIZE > end) return std::nullopt;
+            auto h = parse_header(data.data() + p);
+            if (!h) return std::nullopt;
+            if (p + frame_size(*h) > end) return std::nullopt;
+            return h;
+        };
+        // a sync is only trusted when another frame (or the end of the audio) follows it
+        auto confirmed_frame_at = [&](std::size_t p) -> std::optional<FrameHeader> {
+            auto h = frame_at(p);
+            if (!h) return std::nullopt;
+            const std::size_t next = p + frame_size(*h);
+            if (next != end && !frame_at(next)) return std::nullopt;
+            return h;
+        };
+        // the first frame must be confirmed, later ones only need a valid header;
+        // a real frame with other stream parameters cannot be repacked with the rest
+        auto stream_frame_at = [&](std::size_t p) -> std::optional<FrameHeader> {
+            auto h = first ? frame_at(p) : confirmed_frame_at(p);
+            if (!h || !first) return h;
+            if (h->sample_rate_index() == first->sample_rate_index() && h->mono() == first->mono()) return h;
+            if (confirmed_frame_at(p)) {
+                throw std::runtime_error("MpegProcessor: stream parameters change at byte " + std::to_string(p));
+            }
+            return std::nullopt;
+        };
+
+        std::vector<std::pair<std::size_t, FrameHeader>> frames;
+        while (pos < end) {
+            auto h = stream_frame_at(pos);
+            if (!h) {
+                std::size_t q = pos + 1;
+                while (q < end && !stream_frame_at(q)) ++q;
+                if (q >= end) break;
+                if (!frames.empty()) mp3.junk_bytes += q - pos;
+                pos = q;
+                continue;
+            }
+            if (!first) first = h;
+            frames.emplace_back(pos, *h);
+            pos += frame_size(*h);
+        }
+        if (frames.empty()) {
+            throw std::runtime_error("MpegProcessor: no MPEG-1 Layer III frames found");
+        }
+
+        // anything left that starts with a sync is a truncated frame, which cannot survive repacking
+        if (pos + 2 <= end && data[pos] == 0xFF && (data[pos + 1] & 0xE0) == 0xE0) {
+            mp3.junk_bytes += end - pos;
+        } else {
+            mp3.trailing.assign(data.begin() + static_cast<std::ptrdiff_t>(pos),
+                                data.begin() + static_cast<std::ptrdiff_t>(end));
+        }
+
+        std::size_t first_audio = 0;
+        if (auto info = read_info_tag(data, frames[0].first, frames[0].second)) {
+            mp3.info = std::move(info);
+            first_audio = 1;
+        }
+
+        // concatenated frame payloads: the byte space main_data_begin points into
+        Bytes reservoir;
+        for (std::size_t i = first_audio; i < frames.size(); ++i) {
+            const auto& [p, h] = frames[i];
+            const std::size_t payload = p + h.overhead();
+            const std::size_t payload_start = reservoir.size();
+            reservoir.insert(reservoir.end(), data.begin() + static_cast<std::ptrdiff_t>(payload),
+                             data.begin() + static_cast<std::ptrdiff_t>(p + frame_size(h)));
+
+            AudioFrame frame;
+            frame.header = h;
+            const std::size_t side = p + HEADER_SIZE + (h.has_crc() ? CRC_SIZE : 0);
+            frame.side_info.assign(data.begin() + static_cast<std::ptrdiff_t>(side),
+                                   data.begin() + static_cast<std::ptrdiff_t>(side + h.side_info_size()));
+
+            const std::size_t channels = h.mono() ? 1 : 2;
+            const std::size_t granules_at = 9 + (h.mono() ? 5 : 3) + 4 * channels;
+            std::size_t bits = 0;
+            for (std::size_t g = 0; g < 2 * channels; ++g) {
+                bits += read_bits(frame.side_info, granules_at + g * GRANULE_INFO_BITS, 12);
+            }
+            const std::size_t begin = read_bits(frame.side_info, 0, 9);
+            if (begin > payload_start) {
+                throw std::runtime_error("MpegProcessor: frame data starts before the first frame");
+            }
+            const std::size_t start = payload_start - begin;
+            const std::size_t bytes = (bits + 7) / 8;
+            if (start + bytes > reservoir.size()) {
+                throw std::runtime_error("MpegProcessor: frame data overruns its frame");
+            }
+            frame.main_data.assign(reservoir.begin() + static_cast<std::ptrdiff_t>(start),
+                                   reservoir.begin() + static_cast<std::ptrdiff_t>(start + bytes));
+            if (bits % 8 != 0) {
+                frame.main_data.back() &= static_cast<unsigned char>(0xFF << (8 - bits % 8));
+            }
+            mp3.frames.push_back(std::move(frame));
+        }
+        if (mp3.frames.empty()) {
+            throw std::runtime_error("MpegProcessor: no audio frames found");
+        }
+        return mp3;
+    }
+
+    /**
+     * @brief A frame size choice for one sample rate, ordered by size.
+     */
+    struct SizeOption {
+        std::size_t size;
+        int bitrate_index;
+        bool padding;
+    };
+
+    std::vector<SizeOption> size_options(int sample_rate_index) {
+        std::vector<SizeOption> options;
+        for (int i = 1; i < static_cast<int>(BITRATES_KBPS.size()); ++i) {
+            options.push_back({frame_size(i, sample_rate_index, false), i, false});
+            options.push_back({frame_size(i, sample_rate_index, true), i, true});
+        }
+        std::sort(options.begin(), options.end(), [](const SizeOption& a, const SizeOption& b) {
+            return a.size < b.size;
+        });
+        return options;
+    }
+
+    FrameHeader with_size(FrameHeader h, const SizeOption& option) {
+        h.b[2] = static_cast<unsigned char>((option.bitrate_index << 4) | (h.b[2] & 0x0D) | (option.padding ? 0x02 : 0));
+        return h;
+    }
+
+    /**
+     * @brief Writes the frames back at the smallest bitrate each one needs.
+     *
+     * Granule data is laid out back to back through the bit reservoir, so
+     * every frame starts where the previous one's data ended (at most 511
+     * bytes back). A backward pass first computes how much reservoir each
+     * frame must leave to the rest of the stream, so that a frame bigger than
+     * the largest bitrate still finds enough room before it.
+     *
+     * @param sizes Receives the size and bitrate of each written frame.
+     * @throws std::runtime_error if the data cannot be laid out.
+     */
+    Bytes repack_frames(const std::vector<AudioFrame>& frames, std::vector<SizeOption>& sizes) {
+        const auto options = size_options(frames.front().header.sample_rate_index());
+        const std::size_t max_size = options.back().size;
+
+        std::vector<std::size_t> needed(frames.size() + 1, 0);
+        for (std::size_t i = frames.size(); i-- > 0;) {
+            const std::size_t max_capacity = max_size - frames[i].header.overhead();
+            const std::size_t want = frames[i].main_data.size() + needed[i + 1];
+            needed[i] = want > max_capacity ? want - max_capacity : 0;
+            if (needed[i] > MAX_MAIN_DATA_BEGIN) {
+                throw std::runtime_error("MpegProcessor: frame data does not fit the bit reservoir");
+            }
+        }
+        if (needed[0] > 0) {
+            throw std::runtime_error("MpegProcessor: first frame does not fit");
+        }
+
+        // granule data is laid out in the payload space first (headers and
+        // side info do not count towards main_data_begin), then the frames
+        // are cut out of it
+        Bytes payload;
+        std::vector<FrameHeader> headers;
+        std::vector<Bytes> sides;
+        std::size_t reservoir = 0; // free payload bytes at the end of the previous frames
+        for (std::size_t i = 0; i < frames.size(); ++i) {
+            const AudioFrame& frame = frames[i];
+            const std::size_t overhead = frame.header.overhead();
+            const std::size_t m = frame.main_data.size();
+
+            const SizeOption* chosen = nullptr;
+            for (const auto& option : options) {
+                const std::size_t capacity = option.size - overhead;
+                if (reservoir + capacity >= m + needed[i + 1]) {
+                    chosen = &option;
+                    break;
+                }
+            }
+            if (!chosen) {
+                throw std::runtime_error("MpegProcessor: no bitrate fits frame " + std::to_string(i));
+            }
+
+            Bytes side = frame.side_info;
+            side[0] = static_cast<unsigned char>(reservoir >> 1);
+            side[1] = static_cast<unsigned char>((side[1] & 0x7F) | ((reservoir & 1) << 7));
+
+            const std::size_t capacity = chosen->size - overhead;
+            const std::size_t data_start = payload.size() - reservoir;
+            payload.resize(payload.size() + capacity, 0);
+            std::copy(frame.main_data.begin(), frame.main_data.end(),
+                      payload.begin() + static_cast<std::ptrdiff_t>(data_start));
+
+            reservoir = std::min(reservoir + capacity - m, MAX_MAIN_DATA_BEGIN);
+            headers.push_back(with_size(frame.header, *chosen));
+            sides.push_back(std::move(side));
+            sizes.push_back(*chosen);
+        }
+
+        Bytes out;
+        std::size_t offset = 0;
+        for (std::size_t i = 0; i < headers.size(); ++i) {
+            const FrameHeader& h = headers[i];
+            out.insert(out.end(), h.b.begin(), h.b.end());
+            if (h.has_crc()) {
+                const std::uint16_t crc = frame_crc(h, sides[i]);
+                out.push_back(static_cast<unsigned char>(crc >> 8));
+                out.push_back(static_cast<unsigned char>(crc));
+            }
+            out.insert(out.end(), sides[i].begin(), sides[i].end());
+            const std::size_t capacity = sizes[i].size - h.overhead();
+            out.insert(out.end(), payload.begin() + static_cast<std::ptrdiff_t>(offset),
+                       payload.begin() + static_cast<std::ptrdiff_t>(offset + capacity));
+            offset += capacity;
+        }
+        return out;
+    }
+
+    /**
+     * @brief Builds a Xing (or Info, if every frame has the same bitrate) frame for the repacked stream.
+     *
+     * The LAME extension of the original tag is kept, with its music
+     * length and CRCs updated.
+     */
+    Bytes build_info_frame(const ParsedMp3& mp3, const Bytes& audio, const std::vector<SizeOption>& sizes) {
+        const InfoTag tag = mp3.info.value_or(InfoTag{});
+        FrameHeader h = mp3.frames.front().header;
+        h.b[1] |= 0x01;  // no CRC
+        h.b[2] &= 0x0C;  // keep the sample rate, clear the private bit
+        h.b[3] &= 0xC3;  // keep the channel mode and emphasis, no mode extension
+
+        std::uint32_t flags = XING_FRAMES | XING_BYTES | XING_TOC;
+        std::size_t tag_size = 8 + 4 + 4 + XING_TOC_SIZE;
+        if (tag.quality || tag.lame) {
+            flags |= XING_QUALITY;
+            tag_size += 4;
+        }
+        if (tag.lame) tag_size += LAME_TAG_SIZE;
+
+        const auto options = size_options(h.sample_rate_index());
+        const auto fits = std::find_if(options.begin(), options.end(), [&](const SizeOption& o) {
+            return !o.padding && o.size >= HEADER_SIZE + h.side_info_size() + tag_size;
+        });
+        if (fits == options.end()) {
+            throw std::runtime_error("MpegProcessor: Xing tag does not fit a frame");
+        }
+        h = with_size(h, *fits);
+
+        Bytes frame(fits->size, 0);
+        std::copy(h.b.begin(), h.b.end(), frame.begin());
+        std::size_t x = HEADER_SIZE + h.side_info_size();
+        const bool constant = std::all_of(sizes.begin(), sizes.end(), [&](const SizeOption& s) {
+            return s.bitrate_index == sizes.front().bitrate_index;
+        });
+        std::memcpy(frame.data() + x, constant ? "Info" : "Xing", 4);
+        put_be32(frame, x + 4, flags);
+        put_be32(frame, x + 8, static_cast<std::uint32_t>(mp3.frames.size()));
+        const std::uint64_t total = frame.size() + audio.size();
+        put_be32(frame, x + 12, static_cast<std::uint32_t>(total));
+
+        // TOC: byte position (in 1/256 of the stream) at each percent of the duration
+        std::vector<std::uint64_t> offsets(sizes.size());
+        std::uint64_t running = frame.size();
+        for (std::size_t i = 0; i < sizes.size(); ++i) {
+            offsets[i] = running;
+            running += sizes[i].size;
+        }
+        for (std::size_t i = 0; i < XING_TOC_SIZE; ++i) {
+            const std::uint64_t at = offsets[i * sizes.size() / XING_TOC_SIZE];
+            frame[x + 16 + i] = static_cast<unsigned char>(std::min<std::uint64_t>(255, at * 256 / total));
+        }
+        x += 16 + XING_TOC_SIZE;
+
+        if (flags & XING_QUALITY) {
+            put_be32(frame, x, tag.quality.value_or(0));
+            x += 4;
+        }
+        if (tag.lame) {
+            std::copy(tag.lame->begin(), tag.lame->end(), frame.begin() + static_cast<std::ptrdiff_t>(x));
+            put_be32(frame, x + 28, static_cast<std::uint32_t>(total));
+            const std::uint16_t music_crc = lame_crc(audio.data(), audio.size());
+            frame[x + 32] = static_cast<unsigned char>(music_crc >> 8);
+            frame[x + 33] = static_cast<unsigned char>(music_crc);
+            // the tag CRC covers the frame up to itself (190 bytes for a stereo frame)
+            const std::uint16_t tag_crc = lame_crc(frame.data(), x + 34);
+            frame[x + 34] = static_cast<unsigned char>(tag_crc >> 8);
+            frame[x + 35] = static_cast<unsigned char>(tag_crc);
+        }
+        return frame;
+    }
+
+    /**
+     * @brief The decoder input of a frame: everything but bitrate, padding, CRC and reservoir offset.
+     */
+    Bytes canonical_frame(const AudioFrame& frame) {
+        Bytes out(frame.header.b.begin(), frame.header.b.end());
+        out[1] |= 0x01;
+        out[2] &= 0x0D;
+        Bytes side = frame.side_info;
+        side[0] = 0;
+        side[1] &= 0x7F;
+        out.insert(out.end(), side.begin(), side.end());
+        out.insert(out.end(), frame.main_data.begin(), frame.main_data.end());
+        return out;
+    }
+
+} // namespace
+
 void MpegProcessor::recompress(const fs::path& input,
                               const fs::path& output,
                               bool preserve_metadata) {
-    Logger::log(LogLevel::Error, "Recompress called on MpegProcessor placeholder.", processor_tag());
+    Logger::log(LogLevel::Info, "MP3: Repacking frames of: " + input.string(), processor_tag());
 
-    std::error_code ec;
-    fs::copy_file(input, output, fs::copy_options::overwrite_existing, ec);
+    if (!MimeDetector::is_mpeg1_layer3(input)) {
+        Logger::log(LogLevel::Info, "MP3: Not an MPEG-1 Layer III stream, left as-is: " + input.filename().string(),
+                    processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
+    }
 
-    if (ec) {
-        throw std::runtime_error("Placeholder recompress failed to copy file.");
+    const Bytes data = read_file(input);
+    ParsedMp3 mp3;
+    Bytes audio;
+    Bytes info;
+    try {
+        mp3 = parse_mp3(data);
+        std::vector<SizeOption> sizes;
+        audio = repack_frames(mp3.frames, sizes);
+        info = build_info_frame(mp3, audio, sizes);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Warning, std::string("MP3: Cannot repack, left as-is: ") + e.what(), processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
     }
+
+    std::ofstream out(output, std::ios::binary | std::ios::trunc);
+    if (!out) {
+        throw std::runtime_error("MpegProcessor: cannot create " + output.string());
+    }
+    auto write = [&out](const Bytes& bytes) {
+        out.write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+    };
+    if (preserve_metadata) write(mp3.leading_tags);
+    write(info);
+    write(audio);
+    write(mp3.trailing);
+    if (preserve_metadata) write(mp3.trailing_tags);
+    out.close();
+    if (!out) {
+        throw std::runtime_error("MpegProcessor: write failed for " + output.string());
+    }
+
+    Logger::log(LogLevel::Debug,
+                "MP3: " + std::to_string(mp3.frames.size()) + " frames repacked, " +
+                std::to_string(mp3.junk_bytes) + " junk bytes dropped",
+                processor_tag());
 }
 
 std::optional<ExtractedContent> MpegProcessor::prepare_extraction(const fs::path& input_path) {
@@ -87,4 +649,22 @@ std::filesystem::path MpegProcessor::finalize_extraction(const ExtractedContent
     return final_temp_path;
 }
 
-} // namespace chisel
\ No newline at end of file
+bool MpegProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    const Bytes data_a = read_file(a);
+    const Bytes data_b = read_file(b);
+    try {
+        const ParsedMp3 mp3_a = parse_mp3(data_a);
+        const ParsedMp3 mp3_b = parse_mp3(data_b);
+        if (mp3_a.frames.size() != mp3_b.frames.size()) return false;
+        for (std::size_t i = 0; i < mp3_a.frames.size(); ++i) {
+            if (canonical_frame(mp3_a.frames[i]) != canonical_frame(mp3_b.frames[i])) return false;
+        }
+        return true;
+    } catch (const std::exception& e) {
+        // streams left as-is by recompress() must still compare equal
+        Logger::log(LogLevel::Debug, std::string("MP3: Frame comparison unavailable: ") + e.what(), processor_tag());
+        return data_a == data_b;
+    }
+}
+
+} // namespace chisel
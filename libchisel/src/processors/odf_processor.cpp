@@ -14,8 +14,7 @@
 #include <vector>
 #include <algorithm>
 #include "file_utils.hpp"
-#include "zlib_container.h"
-#include "zopfli.h"
+#include "../../include/zip_writer.hpp"
 
 namespace chisel {
 
@@ -134,36 +133,6 @@ std::filesystem::path OdfProcessor::finalize_extraction(const ExtractedContent&
     const fs::path tmp_path = fs::temp_directory_path() /
                               (src_path.stem().string() + "_tmp" + RandomUtils::random_suffix() + src_path.extension().string());
 
-    archive* out = archive_write_new();
-    if (!out) {
-        Logger::log(LogLevel::Error, "archive_write_new failed", processor_tag());
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("ODFProcessor: archive_write_new failed");
-    }
-
-    int set_fmt = archive_write_set_format_zip(out);
-    if (set_fmt == ARCHIVE_WARN) {
-        Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-    }
-    if (set_fmt != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to set ZIP format: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("ODFProcessor: set_format_zip failed");
-    }
-    archive_write_set_format_option(out, "zip", "compression", "store");
-
-    int open_w = archive_write_open_filename(out, tmp_path.string().c_str());
-    if (open_w == ARCHIVE_WARN) {
-        Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-    }
-    if (open_w != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to open temp ODF for writing: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("ODFProcessor: open_filename failed");
-    }
-
     // ensure "mimetype" is written first
     std::vector<fs::path> files_ordered;
     auto it = std::find_if(content.extracted_files.begin(), content.extracted_files.end(),
@@ -178,105 +147,24 @@ std::filesystem::path OdfProcessor::finalize_extraction(const ExtractedContent&
     }
 
     try {
+        ZipWriter writer(tmp_path, zip_options_);
         for (const auto& file : files_ordered) {
             fs::path rel = fs::relative(file, content.temp_dir, ec);
             if (ec) rel = fs::path(file).filename();
 
-            std::ifstream ifs(file, std::ios::binary);
-            if (!ifs) {
-                Logger::log(LogLevel::Error, "Failed to open file for reading: " + file.filename().string(), processor_tag());
-                continue;
-            }
-            std::vector<unsigned char> buf((std::istreambuf_iterator<char>(ifs)), std::istreambuf_iterator<char>());
-
-            std::vector<unsigned char> final_data;
-            std::string ext = rel.extension().string();
-
-            if (rel.filename() == "mimetype") {
-                final_data = buf;
-                Logger::log(LogLevel::Debug, "Stored mimetype entry uncompressed", processor_tag());
-                // option is already 'store', do nothing
-            } else if (ext == ".xml") {
-                ZopfliOptions opts;
-                ZopfliInitOptions(&opts);
-                opts.numiterations = 15;
-                opts.blocksplitting = 1;
-                unsigned char* out_data = nullptr;
-                size_t out_size = 0;
-                ZopfliZlibCompress(&opts, buf.data(), buf.size(), &out_data, &out_size);
-
-                std::vector<unsigned char> result(out_data, out_data + out_size);
-                free(out_data);
-                final_data = result;
-
-                Logger::log(LogLevel::Debug, "Recompressed XML with Zopfli: " + rel.string(), processor_tag());
-            } else {
-                final_data = buf;
-                Logger::log(LogLevel::Debug, "Copied entry unchanged: " + rel.string(), processor_tag());
-            }
-
-            archive_entry* entry = archive_entry_new();
-            if (!entry) {
-                Logger::log(LogLevel::Error, "archive_entry_new failed", processor_tag());
-                throw std::runtime_error("ODFProcessor: archive_entry_new failed");
-            }
-
-            archive_entry_set_pathname(entry, rel.generic_string().c_str());
-            archive_entry_set_size(entry, static_cast<la_int64_t>(final_data.size()));
-            archive_entry_set_filetype(entry, AE_IFREG);
-            archive_entry_set_perm(entry, 0644);
-            archive_entry_set_mtime(entry, 0, 0);
-
-            int wh = archive_write_header(out, entry);
-            if (wh == ARCHIVE_WARN) {
-                Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-            }
-            if (wh != ARCHIVE_OK) {
-                Logger::log(LogLevel::Error,
-                            "Failed to write header for: " + rel.string() +
-                            " (" + std::string(archive_error_string(out)) + ")", processor_tag());
-                archive_entry_free(entry);
-                throw std::runtime_error("ODFProcessor: write_header failed");
-            }
-
-            la_ssize_t wrote = archive_write_data(out, final_data.data(), final_data.size());
-            if (wrote < 0) {
-                Logger::log(LogLevel::Error,
-                            "Failed to write data for: " + rel.string() +
-                            " (" + std::string(archive_error_string(out)) + ")", processor_tag());
-                archive_entry_free(entry);
-                throw std::runtime_error("ODFProcessor: write_data failed");
-            }
-
-            archive_write_finish_entry(out); // finish this entry
-            archive_entry_free(entry);
-
+            // the ODF spec requires "mimetype" to be the first entry, uncompressed
+            const bool is_mimetype = rel == "mimetype";
+            writer.add_file_from(rel.generic_string(), file, !is_mimetype);
         }
+        writer.finish();
     } catch (const std::exception& e) {
         // log the error before cleanup
         Logger::log(LogLevel::Error, "Failed to finalize ODF: " + std::string(e.what()) + " for file: " + content.original_path.filename().string(), processor_tag());
-        archive_write_close(out);
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw;
-    } catch (...) {
-        // log unknown error
-        Logger::log(LogLevel::Error, "Failed to finalize ODF: Unknown exception for file: " + content.original_path.filename().string(), processor_tag());
-        archive_write_close(out);
-        archive_write_free(out);
+        fs::remove(tmp_path, ec);
         cleanup_temp_dir(content.temp_dir);
         throw;
     }
 
-    int close_w = archive_write_close(out);
-    if (close_w != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to close archive: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("ODFProcessor: write_close failed");
-    }
-    archive_write_free(out);
-
     cleanup_temp_dir(content.temp_dir);
 
     return tmp_path;
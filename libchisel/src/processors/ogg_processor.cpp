@@ -7,17 +7,17 @@
 #include "../../include/audio_metadata_util.hpp"
 #include "../../include/file_utils.hpp"
 #include "../../include/random_utils.hpp"
+#include "../../include/rust_bridge.hpp"
+#include "../../include/stop_scope.hpp"
 #include <stdexcept>
 #include <filesystem>
 #include <cstdio>
+#include <cstring>
+#include <string_view>
 #include <vector>
 #include <FLAC/all.h>
 #include "file_type.hpp"
 
-extern "C" {
-    int chisel_optimize_vorbis(const char* input, const char* output);
-}
-
 namespace chisel {
 namespace fs = std::filesystem;
 
@@ -26,7 +26,8 @@ static const char* processor_tag() {
 }
 
 namespace {
-    bool is_vorbis_stream(FILE* f) {
+    // Checks whether the first packet of the first Ogg page starts with the given signature
+    bool first_packet_has_signature(FILE* f, const std::string_view signature) {
         if (f == nullptr) return false;
 
         const long start_pos = ftell(f);
@@ -50,17 +51,51 @@ namespace {
             return false;
         }
 
-        unsigned char signature[7];
-        if (fread(signature, 1, 7, f) != 7) {
-            fseek(f, start_pos, SEEK_SET);
-            return false;
-        }
+        std::vector<char> found(signature.size());
+        const bool matches = fread(found.data(), 1, found.size(), f) == found.size() &&
+                             std::string_view(found.data(), found.size()) == signature;
 
-        const bool is_vorbis = (memcmp(signature, "\x01vorbis", 7) == 0);
+        fseek(f, start_pos, SEEK_SET);
+        return matches;
+    }
+
+    bool is_vorbis_stream(FILE* f) {
+        return first_packet_has_signature(f, std::string_view("\x01vorbis", 7));
+    }
+
+    bool is_opus_stream(FILE* f) {
+        return first_packet_has_signature(f, "OpusHead");
+    }
+
+    bool is_flac_stream(FILE* f) {
+        return first_packet_has_signature(f, "\x7f" "FLAC");
+    }
+
+    // Counts the logical bitstreams (BOS pages) of an Ogg file, covering chained and multiplexed files
+    int count_logical_streams(FILE* f) {
+        if (f == nullptr) return 0;
+
+        const long start_pos = ftell(f);
+        fseek(f, 0, SEEK_SET);
+
+        int count = 0;
+        unsigned char header[27];
+        while (fread(header, 1, 27, f) == 27 && memcmp(header, "OggS", 4) == 0) {
+            if ((header[5] & 0x02) != 0) ++count;
+
+            unsigned char lacing[255];
+            const size_t num_segments = header[26];
+            if (fread(lacing, 1, num_segments, f) != num_segments) break;
+
+            long body_size = 0;
+            for (size_t i = 0; i < num_segments; ++i) body_size += lacing[i];
+            if (fseek(f, body_size, SEEK_CUR) != 0) break;
+        }
 
         fseek(f, start_pos, SEEK_SET);
-        return is_vorbis;
+        return count;
     }
+
     // Base struct for IO callbacks to access input file
     struct OggIO {
         FILE* f_in = nullptr;
@@ -263,6 +298,69 @@ namespace {
         return ctx.pcm;
     }
 
+    // Progress state shared with the Rust bridge during a Vorbis or Opus optimization
+    struct BridgeProgress {
+        std::string label;
+        std::string name;
+        int last_decile = -1;
+    };
+
+    int bridge_progress_cb(void* context, const uint64_t bytes_processed, const uint64_t total_bytes) {
+        if (chisel::stop_requested()) return 1;
+
+        auto* progress = static_cast<BridgeProgress*>(context);
+        if (total_bytes > 0) {
+            // optivorbis reads the input twice, so this goes up to 20
+            const int decile = static_cast<int>(bytes_processed * 10 / total_bytes);
+            if (decile != progress->last_decile) {
+                progress->last_decile = decile;
+                Logger::log(LogLevel::Debug, progress->label + " progress on " + progress->name + ": " +
+                            std::to_string(bytes_processed) + " bytes processed", processor_tag());
+            }
+        }
+        return 0;
+    }
+
+    // Turns a failed bridge call into an exception, removing the partial output
+    void check_bridge_result(const int result, char* error_message, const fs::path& output,
+                             const std::string& operation) {
+        if (result == CHISEL_VORBIS_OK) return;
+
+        std::error_code ec;
+        fs::remove(output, ec);
+
+        if (result == CHISEL_VORBIS_ERR_CANCELLED) {
+            chisel_free_string(error_message);
+            throw std::runtime_error("OggProcessor: " + operation + " interrupted");
+        }
+
+        std::string msg = operation + " failed with error code " + std::to_string(result);
+        if (error_message != nullptr) {
+            msg += ": " + std::string(error_message);
+            chisel_free_string(error_message);
+        }
+        Logger::log(LogLevel::Error, msg, processor_tag());
+        throw std::runtime_error(msg);
+    }
+
+    // Interprets the 1/0/negative result of a bridge comparison, logging failures and mismatches
+    bool bridge_compare_result(const int result, char* error_message, const std::string& operation,
+                               const fs::path& candidate) {
+        if (result < 0) {
+            std::string msg = operation + " failed with error code " + std::to_string(result);
+            if (error_message != nullptr) {
+                msg += ": " + std::string(error_message);
+                chisel_free_string(error_message);
+            }
+            Logger::log(LogLevel::Error, msg, processor_tag());
+            return false;
+        }
+        if (result == 0) {
+            Logger::log(LogLevel::Warning, operation + " found differences: " + candidate.string(), processor_tag());
+        }
+        return result == 1;
+    }
+
 } // namespace
 
 void OggProcessor::recompress(const fs::path& input,
@@ -300,9 +398,52 @@ void OggProcessor::recompress(const fs::path& input,
     );
 
     const bool is_ok = (init_stat == FLAC__STREAM_DECODER_INIT_STATUS_OK);
-    bool is_vorbis = is_vorbis_stream(f_in);
+    const bool is_vorbis = is_vorbis_stream(f_in);
+    const bool is_opus = !is_vorbis && is_opus_stream(f_in);
+    const bool is_flac = is_flac_stream(f_in);
+    // chained/multiplexed files and codecs without a dedicated path go through the generic re-packer
+    const bool repack = count_logical_streams(f_in) > 1 || (!is_vorbis && !is_opus && !is_flac);
     if (is_ok) {
-        if (is_vorbis) {
+        if (repack) {
+            FLAC__stream_decoder_delete(decoder);
+            FLAC__stream_encoder_delete(encoder);
+            fclose(f_in);
+
+            const std::string input_str = input.string();
+            const std::string output_str = output.string();
+
+            ChiselVorbisSettings settings = chisel_vorbis_default_settings();
+            if (!preserve_metadata) {
+                settings.comment_fields_action = CHISEL_VORBIS_COMMENTS_DELETE;
+                settings.vendor_string_action = CHISEL_VORBIS_VENDOR_EMPTY;
+            }
+
+            Logger::log(LogLevel::Info, "Re-packing Ogg logical streams...", processor_tag());
+            BridgeProgress progress{"Ogg re-pack", input.filename().string()};
+            char* error_message = nullptr;
+            const int result = chisel_optimize_ogg(input_str.c_str(), output_str.c_str(), &settings,
+                                                   bridge_progress_cb, &progress, &error_message);
+            check_bridge_result(result, error_message, output, "Ogg re-pack");
+        } else if (is_opus) {
+            FLAC__stream_decoder_delete(decoder);
+            FLAC__stream_encoder_delete(encoder);
+            fclose(f_in);
+
+            const std::string input_str = input.string();
+            const std::string output_str = output.string();
+
+            ChiselOpusSettings settings = chisel_opus_default_settings();
+            if (!preserve_metadata) {
+                settings.comment_fields_action = CHISEL_VORBIS_COMMENTS_DELETE;
+            }
+
+            Logger::log(LogLevel::Info, "Repacking Ogg Opus stream...", processor_tag());
+            BridgeProgress progress{"Opus", input.filename().string()};
+            char* error_message = nullptr;
+            const int result = chisel_optimize_opus(input_str.c_str(), output_str.c_str(), &settings,
+                                                    bridge_progress_cb, &progress, &error_message);
+            check_bridge_result(result, error_message, output, "Opus optimization");
+        } else if (is_vorbis) {
             FLAC__stream_decoder_delete(decoder);
             FLAC__stream_encoder_delete(encoder);
             fclose(f_in);
@@ -310,12 +451,18 @@ void OggProcessor::recompress(const fs::path& input,
             const std::string input_str = input.string();
             const std::string output_str = output.string();
 
-            int result = chisel_optimize_vorbis(input_str.c_str(), output_str.c_str());
-            if (result != 0) {
-                const std::string msg = "OptiVorbis failed with error code: " + std::to_string(result);
-                Logger::log(LogLevel::Error, msg, processor_tag());
-                throw std::runtime_error(msg);
+            ChiselVorbisSettings settings = chisel_vorbis_default_settings();
+            if (!preserve_metadata) {
+                settings.comment_fields_action = CHISEL_VORBIS_COMMENTS_DELETE;
+                settings.vendor_string_action = CHISEL_VORBIS_VENDOR_EMPTY;
             }
+
+            BridgeProgress progress{"OptiVorbis", input.filename().string()};
+            char* error_message = nullptr;
+            const int result = chisel_optimize_vorbis_progress(input_str.c_str(), output_str.c_str(),
+                                                               &settings, bridge_progress_cb, &progress,
+                                                               &error_message);
+            check_bridge_result(result, error_message, output, "OptiVorbis");
         } else {
             FILE* f_out = chisel::open_file(output, "wb");
             if (f_out == nullptr) {
@@ -413,6 +560,32 @@ std::string OggProcessor::get_raw_checksum(const std::filesystem::path&) const {
 }
 
     bool OggProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    const std::string a_str = a.string();
+    const std::string b_str = b.string();
+
+    bool opus = false;
+    bool repacked = false;
+    if (FILE* f = chisel::open_file(a, "rb")) {
+        const bool vorbis = is_vorbis_stream(f);
+        opus = !vorbis && is_opus_stream(f);
+        repacked = count_logical_streams(f) > 1 || (!vorbis && !opus && !is_flac_stream(f));
+        fclose(f);
+    }
+
+    // chained/multiplexed files: compare every logical stream
+    if (repacked) {
+        char* error_message = nullptr;
+        const int result = chisel_ogg_streams_equal(a_str.c_str(), b_str.c_str(), &error_message);
+        return bridge_compare_result(result, error_message, "Ogg stream comparison", b);
+    }
+
+    // opus packets are never re-encoded, so compare them directly
+    if (opus) {
+        char* error_message = nullptr;
+        const int result = chisel_opus_streams_equal(a_str.c_str(), b_str.c_str(), &error_message);
+        return bridge_compare_result(result, error_message, "Opus packet comparison", b);
+    }
+
     unsigned ra, ca, bpsa;
     unsigned rb, cb, bpsb;
 
@@ -430,18 +603,10 @@ std::string OggProcessor::get_raw_checksum(const std::filesystem::path&) const {
         return false;
     }
 
-    // neither is flac, assume vorbis
-    FILE* fA = fopen(a.string().c_str(), "rb");
-    FILE* fB = fopen(b.string().c_str(), "rb");
-
-    const bool validA = is_vorbis_stream(fA);
-    const bool validB = is_vorbis_stream(fB);
-
-    if (fA != nullptr) fclose(fA);
-    if (fB != nullptr) fclose(fB);
-
-    // both must be valid vorbis to trust the lossless recompression
-    return validA && validB;
+    // neither flac nor opus, assume vorbis and compare the decoded audio
+    char* error_message = nullptr;
+    const int result = chisel_vorbis_decoded_equal(a_str.c_str(), b_str.c_str(), &error_message);
+    return bridge_compare_result(result, error_message, "Vorbis decode comparison", b);
 }
 
-} // namespace chisel
\ No newline at end of file
+} // namespace chisel
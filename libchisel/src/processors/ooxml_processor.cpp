@@ -14,33 +14,12 @@
 #include <vector>
 #include <algorithm>
 #include "file_utils.hpp"
-#include "zlib_container.h"
-#include "zopfli.h"
+#include "../../include/zip_writer.hpp"
 
 namespace chisel {
 
 namespace fs = std::filesystem;
 
-/**
- * @brief Recompresses a byte vector using Zopfli's Zlib implementation.
- * @param input The raw data to be compressed.
- * @return A vector containing the Zlib-compressed data.
- */
-std::vector<unsigned char> recompress_with_zopfli(const std::vector<unsigned char>& input) {
-        ZopfliOptions opts;
-        ZopfliInitOptions(&opts);
-        opts.numiterations = 15;
-        opts.blocksplitting = 1;
-
-        unsigned char* out_data = nullptr;
-        size_t out_size = 0;
-        ZopfliZlibCompress(&opts, input.data(), input.size(), &out_data, &out_size);
-
-        std::vector<unsigned char> result(out_data, out_data + out_size);
-        free(out_data);
-        return result;
-    }
-
 /**
  * @brief Returns the tag used for logging by this processor.
  * @return A constant string identifier.
@@ -162,37 +141,6 @@ std::filesystem::path OOXMLProcessor::finalize_extraction(const ExtractedContent
     const fs::path tmp_path = fs::temp_directory_path() /
                               (src_path.stem().string() + "_tmp" + RandomUtils::random_suffix() + src_path.extension().string());
 
-    archive* out = archive_write_new();
-    if (!out) {
-        Logger::log(LogLevel::Error, "archive_write_new failed", processor_tag());
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("OOXMLProcessor: archive_write_new failed");
-    }
-
-    // set ZIP format and force deflate compression
-    const int set_fmt = archive_write_set_format_zip(out);
-    if (set_fmt == ARCHIVE_WARN) {
-        Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-    }
-    if (set_fmt != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to set ZIP format: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("OOXMLProcessor: set_format_zip failed");
-    }
-    archive_write_set_options(out, "compression=deflate");
-
-    const int open_w = archive_write_open_filename(out, tmp_path.string().c_str());
-    if (open_w == ARCHIVE_WARN) {
-        Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-    }
-    if (open_w != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to open temp OOXML for writing: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("OOXMLProcessor: open_filename failed");
-    }
-
     // ensure [Content_Types].xml is written first
     std::vector<fs::path> files_ordered;
     auto it = std::find_if(content.extracted_files.begin(), content.extracted_files.end(),
@@ -207,93 +155,24 @@ std::filesystem::path OOXMLProcessor::finalize_extraction(const ExtractedContent
     }
 
     try {
-        // write all entries (recompress images; copy others)
+        // every entry is deflated with Zopfli; images and other incompressible
+        // parts end up stored when deflate does not pay off
+        ZipWriter writer(tmp_path, zip_options_);
         for (const auto& file : files_ordered) {
             fs::path rel = fs::relative(file, content.temp_dir, ec);
             if (ec) rel = fs::path(file).filename();
 
-            std::ifstream ifs(file, std::ios::binary);
-            if (!ifs) {
-                Logger::log(LogLevel::Error, "Failed to open file for reading: " + file.filename().string(), processor_tag());
-                continue;
-            }
-            const std::vector<unsigned char> buf((std::istreambuf_iterator<char>(ifs)), std::istreambuf_iterator<char>());
-
-            std::vector<unsigned char> final_data;
-            const auto ext = rel.extension().string();
-
-            // recompress only PNG/JPG images, leave XML and others untouched
-            if (ext == ".png" || ext == ".jpg" || ext == ".jpeg") {
-                final_data = recompress_with_zopfli(buf);
-                Logger::log(LogLevel::Debug,
-                            "Recompressed image: " + rel.string() + " (" +
-                            std::to_string(buf.size()) + " -> " +
-                            std::to_string(final_data.size()) + " bytes)", processor_tag());
-            } else {
-                final_data = buf;
-                Logger::log(LogLevel::Debug, "Copied entry unchanged: " + rel.string(), processor_tag());
-            }
-
-            archive_entry* entry = archive_entry_new();
-            if (!entry) {
-                Logger::log(LogLevel::Error, "archive_entry_new failed", processor_tag());
-                throw std::runtime_error("OOXMLProcessor: archive_entry_new failed");
-            }
-
-            archive_entry_set_pathname(entry, rel.generic_string().c_str());
-            archive_entry_set_size(entry, static_cast<la_int64_t>(final_data.size()));
-            archive_entry_set_filetype(entry, AE_IFREG);
-            archive_entry_set_perm(entry, 0644);
-            archive_entry_set_mtime(entry, 0, 0); // determinism
-
-            const int wh = archive_write_header(out, entry);
-            if (wh == ARCHIVE_WARN) {
-                Logger::log(LogLevel::Warning, std::string("LIBARCHIVE WARN: ") + archive_error_string(out), processor_tag());
-            }
-            if (wh != ARCHIVE_OK) {
-                Logger::log(LogLevel::Error,
-                            "Failed to write header for: " + rel.string() +
-                            " (" + std::string(archive_error_string(out)) + ")", processor_tag());
-                archive_entry_free(entry);
-                throw std::runtime_error("OOXMLProcessor: write_header failed");
-            }
-
-            const la_ssize_t wrote = archive_write_data(out, final_data.data(), final_data.size());
-            if (wrote < 0) {
-                Logger::log(LogLevel::Error,
-                            "Failed to write data for: " + rel.string() +
-                            " (" + std::string(archive_error_string(out)) + ")", processor_tag());
-                archive_entry_free(entry);
-                throw std::runtime_error("OOXMLProcessor: write_data failed");
-            }
-
-            archive_entry_free(entry);
+            writer.add_file_from(rel.generic_string(), file);
         }
+        writer.finish();
     } catch (const std::exception& e) {
         // log the error before cleanup
         Logger::log(LogLevel::Error, "Failed to finalize OOXML: " + std::string(e.what()) + " for file: " + content.original_path.filename().string(), processor_tag());
-        archive_write_close(out);
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw;
-    } catch (...) {
-        // log unknown error
-        Logger::log(LogLevel::Error, "Failed to finalize OOXML: Unknown exception for file: " + content.original_path.filename().string(), processor_tag());
-        archive_write_close(out);
-        archive_write_free(out);
+        fs::remove(tmp_path, ec);
         cleanup_temp_dir(content.temp_dir);
         throw;
     }
 
-    const int close_w = archive_write_close(out);
-    if (close_w != ARCHIVE_OK) {
-        Logger::log(LogLevel::Error, "Failed to close archive: " + std::string(archive_error_string(out)), processor_tag());
-        archive_write_free(out);
-        cleanup_temp_dir(content.temp_dir);
-        throw std::runtime_error("OOXMLProcessor: write_close failed");
-    }
-    archive_write_free(out);
-
     cleanup_temp_dir(content.temp_dir);
 
     return tmp_path;
@@ -5,11 +5,13 @@
 #include "../../include/pdf_processor.hpp"
 #include "../../include/file_type.hpp"
 #include "../../include/logger.hpp"
+#include "../../include/zip_writer.hpp"
 #include <qpdf/QPDF.hh>
 #include <qpdf/QPDFWriter.hh>
 #include <qpdf/QPDFObjectHandle.hh>
 #include <qpdf/Buffer.hh>
 #include <qpdf/QPDFLogger.hh>
+#include <cstdint>
 #include <fstream>
 #include <memory>
 #include <string>
@@ -190,6 +192,21 @@ std::optional<ExtractedContent> PdfProcessor::prepare_extraction(const std::file
             info.decodable = false;
         }
 
+        if (info.decodable && !info.has_decode_parms && stream_is_single_flate(obj)) {
+            const std::shared_ptr<Buffer> raw = obj.getRawStreamData();
+            const auto analysis = DeflateReconstructor::analyze(
+                std::span<const unsigned char>(raw->getBuffer(), raw->getSize()), DeflateWrapper::Zlib,
+                DeflateSearch::None);
+            info.flate_valid = analysis.valid;
+            info.raw_size = raw->getSize();
+            info.decoded_size = data.size();
+            info.decoded_crc = ZipWriter::crc_of(data);
+            if (!analysis.valid) {
+                Logger::log(LogLevel::Debug, "Stream " + std::to_string(i) +
+                            " has unclean Flate data, it will be copied as-is", "pdf_processor");
+            }
+        }
+
         std::string ext = guess_extension(obj, data);
         std::filesystem::path out_file = content.temp_dir / ("object_" + std::to_string(i) + ext);
 
@@ -241,6 +258,8 @@ std::filesystem::path PdfProcessor::finalize_extraction(const ExtractedContent &
             const QPDFObjectHandle dict = obj.getDict();
             if (dict.isDictionary() && dict.hasKey("/DecodeParms")) continue;
             if (!stream_is_single_flate(obj)) continue;
+            // unclean Flate data cannot be re-encoded without changing what readers decode
+            if (!info.flate_valid) continue;
 
             std::vector<unsigned char> decoded;
             if (!info.file.empty() && std::filesystem::exists(info.file)) {
@@ -260,6 +279,12 @@ std::filesystem::path PdfProcessor::finalize_extraction(const ExtractedContent &
             }
 
             std::vector<unsigned char> recompressed = recompress_with_zopfli(decoded);
+            const bool unchanged = decoded.size() == info.decoded_size && ZipWriter::crc_of(decoded) == info.decoded_crc;
+            if (unchanged && recompressed.size() >= info.raw_size) {
+                Logger::log(LogLevel::Debug, "Stream " + std::to_string(i) + " kept as-is: Zopfli output is not smaller",
+                            "pdf_processor");
+                continue;
+            }
 
             obj.replaceStreamData(
                 std::string(reinterpret_cast<const char*>(recompressed.data()), recompressed.size()),
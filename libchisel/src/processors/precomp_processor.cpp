@@ -0,0 +1,475 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/precomp_processor.hpp"
+#include "../../include/deflate_reconstructor.hpp"
+#include "../../include/logger.hpp"
+#include "../../include/random_utils.hpp"
+#include "../../include/zip_writer.hpp"
+#include <algorithm>
+#include <cstdint>
+#include <cstring>
+#include <filesystem>
+#include <fstream>
+#include <iterator>
+#include <memory>
+#include <optional>
+#include <span>
+#include <stdexcept>
+#include <string>
+#include <utility>
+#include <vector>
+#include <lzma.h>
+
+namespace chisel {
+
+namespace fs = std::filesystem;
+
+static const char* processor_tag() {
+    return "PrecompProcessor";
+}
+
+namespace {
+
+    /*
+     * Archive layout, compressed as a whole with xz:
+     *
+     *   "CHPZ", version, original size (le64), original CRC-32 (le32)
+     *   records until the end, each starting with its Record tag:
+     *     Raw      length (le64), bytes
+     *     Deflate  wrapper, level, window bits, memLevel, strategy (one byte each),
+     *              decompressed length (le64), decompressed bytes
+     *     PngIdat  level, window bits, memLevel, strategy, chunk count (le32),
+     *              chunk data lengths (le32 each), decompressed length (le64),
+     *              decompressed bytes
+     */
+    constexpr std::array<unsigned char, 4> MAGIC = {'C', 'H', 'P', 'Z'};
+    constexpr unsigned char VERSION = 1;
+
+    enum class Record : unsigned char {
+        Raw = 0,
+        Deflate = 1,
+        PngIdat = 2
+    };
+
+    // shorter streams cost more to describe than xz saves on them
+    constexpr std::size_t MIN_STREAM = 32;
+    constexpr std::size_t CHUNK = 64 * 1024;
+    constexpr std::array<unsigned char, 8> PNG_SIGNATURE = {0x89, 'P', 'N', 'G', '\r', '\n', 0x1a, '\n'};
+
+    using Bytes = std::vector<unsigned char>;
+
+    std::uint64_t le_at(std::span<const unsigned char> b, std::size_t o, int n) {
+        std::uint64_t v = 0;
+        for (int i = n - 1; i >= 0; --i) v = v << 8 | b[o + static_cast<std::size_t>(i)];
+        return v;
+    }
+
+    std::uint32_t be32_at(std::span<const unsigned char> b, std::size_t o) {
+        return static_cast<std::uint32_t>(b[o]) << 24 | static_cast<std::uint32_t>(b[o + 1]) << 16 |
+               static_cast<std::uint32_t>(b[o + 2]) << 8 | b[o + 3];
+    }
+
+    void put_le(Bytes& out, std::uint64_t v, int n) {
+        for (int i = 0; i < n; ++i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    void put_be32(Bytes& out, std::uint32_t v) {
+        for (int shift = 24; shift >= 0; shift -= 8) out.push_back(static_cast<unsigned char>(v >> shift));
+    }
+
+    void put_params(Bytes& out, const DeflateParams& p) {
+        out.push_back(static_cast<unsigned char>(p.level));
+        out.push_back(static_cast<unsigned char>(p.window_bits));
+        out.push_back(static_cast<unsigned char>(p.mem_level));
+        out.push_back(static_cast<unsigned char>(p.strategy));
+    }
+
+    /**
+     * @brief Bounds-checked reader over a decompressed archive.
+     */
+    class Reader {
+    public:
+        explicit Reader(std::span<const unsigned char> data) : data_(data) {}
+
+        [[nodiscard]] bool done() const noexcept { return pos_ == data_.size(); }
+
+        std::uint64_t le(int n) {
+            need(static_cast<std::size_t>(n));
+            const auto v = le_at(data_, pos_, n);
+            pos_ += static_cast<std::size_t>(n);
+            return v;
+        }
+
+        std::span<const unsigned char> bytes(std::uint64_t n) {
+            need(n);
+            const auto s = data_.subspan(pos_, static_cast<std::size_t>(n));
+            pos_ += static_cast<std::size_t>(n);
+            return s;
+        }
+
+        DeflateParams params() {
+            DeflateParams p;
+            p.level = static_cast<int>(le(1));
+            p.window_bits = static_cast<int>(le(1));
+            p.mem_level = static_cast<int>(le(1));
+            p.strategy = static_cast<int>(le(1));
+            return p;
+        }
+
+    private:
+        void need(std::uint64_t n) const {
+            if (n > data_.size() - pos_) throw std::runtime_error("truncated precomp archive");
+        }
+
+        std::span<const unsigned char> data_;
+        std::size_t pos_ = 0;
+    };
+
+    /**
+     * @brief Builds the uncompressed archive, expanding every stream zlib can regenerate.
+     */
+    class Expander {
+    public:
+        explicit Expander(std::span<const unsigned char> data) : data_(data) {
+            out_.insert(out_.end(), MAGIC.begin(), MAGIC.end());
+            out_.push_back(VERSION);
+            put_le(out_, data.size(), 8);
+            put_le(out_, ZipWriter::crc_of(data), 4);
+        }
+
+        /**
+         * @return The archive body and the number of expanded streams.
+         */
+        std::pair<Bytes, std::size_t> run() {
+            if (data_.size() >= PNG_SIGNATURE.size() &&
+                std::equal(PNG_SIGNATURE.begin(), PNG_SIGNATURE.end(), data_.begin())) {
+                expand_png();
+            } else {
+                scan();
+            }
+            flush_raw(data_.size());
+            return {std::move(out_), expanded_};
+        }
+
+    private:
+        /**
+         * @brief Looks for zlib streams and ZIP local entries at every offset.
+         */
+        void scan() {
+            std::size_t p = 0;
+            while (p + 2 <= data_.size()) {
+                if (const auto next = try_zip_entry(p)) {
+                    p = *next;
+                } else if (const auto next = try_zlib(p)) {
+                    p = *next;
+                } else {
+                    ++p;
+                }
+            }
+        }
+
+        /**
+         * @brief Expands the deflate data of a ZIP local file header at `p`.
+         * @return Where scanning resumes, or std::nullopt if `p` holds no usable entry.
+         */
+        std::optional<std::size_t> try_zip_entry(std::size_t p) {
+            if (data_.size() - p < 30 || le_at(data_, p, 4) != 0x04034b50) return std::nullopt;
+            const auto flags = le_at(data_, p + 6, 2);
+            const auto method = le_at(data_, p + 8, 2);
+            // encrypted and non-deflate entries have no stream to expand
+            if (method != 8 || (flags & 1) != 0) return std::nullopt;
+            const std::size_t start = p + 30 + le_at(data_, p + 26, 2) + le_at(data_, p + 28, 2);
+            if (start >= data_.size()) return std::nullopt;
+            return try_stream(start, DeflateWrapper::Raw);
+        }
+
+        /**
+         * @brief Expands a zlib stream starting at `p` (PDF FlateDecode, SWF body...).
+         * @return Where scanning resumes, or std::nullopt if `p` holds no stream.
+         */
+        std::optional<std::size_t> try_zlib(std::size_t p) {
+            const unsigned cmf = data_[p];
+            const unsigned flg = data_[p + 1];
+            // deflate, window up to 32K, valid check bits, no preset dictionary
+            if ((cmf & 0x0f) != 8 || (cmf >> 4) > 7 || ((cmf << 8) | flg) % 31 != 0 || (flg & 0x20) != 0) {
+                return std::nullopt;
+            }
+            return try_stream(p, DeflateWrapper::Zlib);
+        }
+
+        std::optional<std::size_t> try_stream(std::size_t start, DeflateWrapper wrapper) {
+            const auto analysis = DeflateReconstructor::analyze(data_.subspan(start), wrapper);
+            if (!analysis.valid || analysis.consumed < MIN_STREAM) return std::nullopt;
+            // a stream zlib cannot regenerate stays compressed, and is not scanned again
+            if (!analysis.reconstructible()) return start + analysis.consumed;
+
+            flush_raw(start);
+            out_.push_back(static_cast<unsigned char>(Record::Deflate));
+            out_.push_back(static_cast<unsigned char>(wrapper));
+            put_params(out_, *analysis.params);
+            put_le(out_, analysis.decompressed.size(), 8);
+            out_.insert(out_.end(), analysis.decompressed.begin(), analysis.decompressed.end());
+            raw_start_ = start + analysis.consumed;
+            ++expanded_;
+            return raw_start_;
+        }
+
+        /**
+         * @brief Expands the IDAT chunks of a PNG, which together hold one zlib stream.
+         *
+         * Only the first run of consecutive IDAT chunks is considered, as
+         * the PNG specification requires; the other chunks are kept raw.
+         */
+        void expand_png() {
+            std::size_t p = PNG_SIGNATURE.size();
+            while (data_.size() - p >= 12) {
+                const std::size_t length = be32_at(data_, p);
+                if (length > data_.size() - p - 12) return;
+                if (std::memcmp(data_.data() + p + 4, "IDAT", 4) == 0) break;
+                p += 12 + length;
+            }
+            if (data_.size() - p < 12) return;
+
+            const std::size_t run_start = p;
+            std::vector<std::uint32_t> sizes;
+            Bytes stream;
+            while (data_.size() - p >= 12 && std::memcmp(data_.data() + p + 4, "IDAT", 4) == 0) {
+                const std::size_t length = be32_at(data_, p);
+                if (length > data_.size() - p - 12) return;
+                sizes.push_back(static_cast<std::uint32_t>(length));
+                stream.insert(stream.end(), data_.begin() + static_cast<std::ptrdiff_t>(p + 8),
+                              data_.begin() + static_cast<std::ptrdiff_t>(p + 8 + length));
+                p += 12 + length;
+            }
+
+            // PNG encoders vary strategy and memLevel, so every combination is tried
+            const auto analysis = DeflateReconstructor::analyze(stream, DeflateWrapper::Zlib, DeflateSearch::Exhaustive);
+            if (!analysis.reconstructible() || analysis.consumed != stream.size()) {
+                Logger::log(LogLevel::Debug, "PNG image data is not reproducible with zlib, kept compressed",
+                            processor_tag());
+                return;
+            }
+            // chunk CRCs are recomputed on restore, so a damaged one must stay raw
+            for (std::size_t q = run_start; q < p; q += 12 + be32_at(data_, q)) {
+                const std::size_t length = be32_at(data_, q);
+                if (ZipWriter::crc_of(data_.subspan(q + 4, length + 4)) != be32_at(data_, q + 8 + length)) return;
+            }
+
+            flush_raw(run_start);
+            out_.push_back(static_cast<unsigned char>(Record::PngIdat));
+            put_params(out_, *analysis.params);
+            put_le(out_, sizes.size(), 4);
+            for (const auto size : sizes) put_le(out_, size, 4);
+            put_le(out_, analysis.decompressed.size(), 8);
+            out_.insert(out_.end(), analysis.decompressed.begin(), analysis.decompressed.end());
+            raw_start_ = p;
+            ++expanded_;
+        }
+
+        void flush_raw(std::size_t end) {
+            if (end <= raw_start_) return;
+            out_.push_back(static_cast<unsigned char>(Record::Raw));
+            put_le(out_, end - raw_start_, 8);
+            out_.insert(out_.end(), data_.begin() + static_cast<std::ptrdiff_t>(raw_start_),
+                        data_.begin() + static_cast<std::ptrdiff_t>(end));
+            raw_start_ = end;
+        }
+
+        std::span<const unsigned char> data_;
+        Bytes out_;
+        std::size_t raw_start_ = 0;
+        std::size_t expanded_ = 0;
+    };
+
+    Bytes reconstruct_or_throw(std::span<const unsigned char> decompressed, const DeflateParams& params,
+                               DeflateWrapper wrapper) {
+        auto stream = DeflateReconstructor::reconstruct(decompressed, params, wrapper);
+        if (!stream) throw std::runtime_error("invalid zlib parameters in precomp archive");
+        return std::move(*stream);
+    }
+
+    /**
+     * @brief Rebuilds the original file from an uncompressed archive.
+     * @throws std::runtime_error if the archive is damaged or the result does not match the stored checksum.
+     */
+    Bytes rebuild(std::span<const unsigned char> body) {
+        Reader in(body);
+        const auto magic = in.bytes(MAGIC.size());
+        if (!std::equal(magic.begin(), magic.end(), MAGIC.begin())) {
+            throw std::runtime_error("not a precomp archive");
+        }
+        if (in.le(1) != VERSION) throw std::runtime_error("unsupported precomp archive version");
+        const auto size = in.le(8);
+        const auto crc = static_cast<std::uint32_t>(in.le(4));
+
+        Bytes out;
+        while (!in.done()) {
+            switch (static_cast<Record>(in.le(1))) {
+                case Record::Raw: {
+                    const auto bytes = in.bytes(in.le(8));
+                    out.insert(out.end(), bytes.begin(), bytes.end());
+                    break;
+                }
+                case Record::Deflate: {
+                    const auto wrapper = static_cast<DeflateWrapper>(in.le(1));
+                    if (wrapper != DeflateWrapper::Raw && wrapper != DeflateWrapper::Zlib) {
+                        throw std::runtime_error("unknown deflate framing in precomp archive");
+                    }
+                    const auto params = in.params();
+                    const auto stream = reconstruct_or_throw(in.bytes(in.le(8)), params, wrapper);
+                    out.insert(out.end(), stream.begin(), stream.end());
+                    break;
+                }
+                case Record::PngIdat: {
+                    const auto params = in.params();
+                    std::vector<std::uint32_t> sizes(static_cast<std::size_t>(in.le(4)));
+                    for (auto& s : sizes) s = static_cast<std::uint32_t>(in.le(4));
+                    const auto stream = reconstruct_or_throw(in.bytes(in.le(8)), params, DeflateWrapper::Zlib);
+
+                    std::size_t offset = 0;
+                    for (const auto length : sizes) {
+                        if (length > stream.size() - offset) throw std::runtime_error("PNG chunk sizes do not match the image data");
+                        Bytes chunk = {'I', 'D', 'A', 'T'};
+                        chunk.insert(chunk.end(), stream.begin() + static_cast<std::ptrdiff_t>(offset),
+                                     stream.begin() + static_cast<std::ptrdiff_t>(offset + length));
+                        put_be32(out, length);
+                        out.insert(out.end(), chunk.begin(), chunk.end());
+                        put_be32(out, ZipWriter::crc_of(chunk));
+                        offset += length;
+                    }
+                    if (offset != stream.size()) throw std::runtime_error("PNG chunk sizes do not match the image data");
+                    break;
+                }
+                default:
+                    throw std::runtime_error("unknown record in precomp archive");
+            }
+        }
+
+        if (out.size() != size || ZipWriter::crc_of(out) != crc) {
+            throw std::runtime_error("restored data does not match the original checksum");
+        }
+        return out;
+    }
+
+    /**
+     * @brief Runs an initialized liblzma coder over a whole buffer.
+     */
+    Bytes run_lzma(lzma_stream& ls, std::span<const unsigned char> in, const char* what) {
+        const std::unique_ptr<lzma_stream, decltype(&lzma_end)> guard(&ls, lzma_end);
+        Bytes out;
+        Bytes buf(CHUNK);
+        ls.next_in = in.data();
+        ls.avail_in = in.size();
+        for (;;) {
+            ls.next_out = buf.data();
+            ls.avail_out = buf.size();
+            const lzma_ret ret = lzma_code(&ls, LZMA_FINISH);
+            out.insert(out.end(), buf.data(), buf.data() + (buf.size() - ls.avail_out));
+            if (ret == LZMA_STREAM_END) return out;
+            if (ret != LZMA_OK) {
+                throw std::runtime_error(std::string(what) + " failed (lzma error " + std::to_string(ret) + ")");
+            }
+        }
+    }
+
+    Bytes xz_compress(std::span<const unsigned char> in) {
+        lzma_stream ls = LZMA_STREAM_INIT;
+        if (lzma_easy_encoder(&ls, 9 | LZMA_PRESET_EXTREME, LZMA_CHECK_CRC64) != LZMA_OK) {
+            throw std::runtime_error("cannot initialize the xz encoder");
+        }
+        return run_lzma(ls, in, "xz encoding");
+    }
+
+    Bytes xz_decompress(std::span<const unsigned char> in) {
+        lzma_stream ls = LZMA_STREAM_INIT;
+        if (lzma_stream_decoder(&ls, UINT64_MAX, 0) != LZMA_OK) {
+            throw std::runtime_error("cannot initialize the xz decoder");
+        }
+        return run_lzma(ls, in, "xz decoding");
+    }
+
+    Bytes read_all(const fs::path& path) {
+        std::ifstream in(path, std::ios::binary);
+        if (!in) throw std::runtime_error("cannot open " + path.string());
+        return {std::istreambuf_iterator<char>(in), std::istreambuf_iterator<char>()};
+    }
+
+    void write_all(const fs::path& path, const Bytes& data) {
+        std::ofstream out(path, std::ios::binary | std::ios::trunc);
+        out.write(reinterpret_cast<const char*>(data.data()), static_cast<std::streamsize>(data.size()));
+        if (!out) throw std::runtime_error("cannot write " + path.string());
+    }
+
+} // namespace
+
+void PrecompProcessor::recompress(const fs::path& input,
+                                  const fs::path& output,
+                                  bool /*preserve_metadata*/) {
+    Logger::log(LogLevel::Info, "Archiving with expanded deflate streams: " + input.string(), processor_tag());
+
+    const Bytes original = read_all(input);
+    auto [body, expanded] = Expander(original).run();
+    Logger::log(LogLevel::Debug, std::to_string(expanded) + " deflate streams expanded in " + input.string(),
+                processor_tag());
+
+    if (rebuild(body) != original) {
+        const std::string msg = "precomp round-trip check failed for " + input.string();
+        Logger::log(LogLevel::Error, msg, processor_tag());
+        throw std::runtime_error(msg);
+    }
+
+    try {
+        write_all(output, xz_compress(body));
+    } catch (const std::exception&) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        throw;
+    }
+}
+
+void PrecompProcessor::restore(const fs::path& input, const fs::path& output) {
+    Logger::log(LogLevel::Info, "Restoring file from precomp archive: " + input.string(), processor_tag());
+
+    try {
+        write_all(output, rebuild(xz_decompress(read_all(input))));
+    } catch (const std::exception& e) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        const std::string msg = "Precomp restore failed: " + std::string(e.what());
+        Logger::log(LogLevel::Error, msg, processor_tag());
+        throw std::runtime_error(msg);
+    }
+}
+
+fs::path PrecompProcessor::restored_filename(const fs::path& archive_file) {
+    return archive_file.stem();
+}
+
+bool PrecompProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    const fs::path restored = fs::temp_directory_path() /
+                              (b.stem().string() + "_restore" + RandomUtils::random_suffix());
+    try {
+        restore(b, restored);
+        const bool equal = read_all(a) == read_all(restored);
+        std::error_code ec;
+        fs::remove(restored, ec);
+        if (!equal) {
+            Logger::log(LogLevel::Error, "Restored file differs from the original: " + a.string(), processor_tag());
+        }
+        return equal;
+    } catch (const std::exception& e) {
+        std::error_code ec;
+        fs::remove(restored, ec);
+        Logger::log(LogLevel::Error, std::string("Precomp verification failed: ") + e.what(), processor_tag());
+        return false;
+    }
+}
+
+std::string PrecompProcessor::get_raw_checksum(const fs::path&) const {
+    // verification restores the file and compares bytes, see raw_equal
+    return "";
+}
+
+} // namespace chisel
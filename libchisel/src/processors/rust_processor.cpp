@@ -0,0 +1,81 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/rust_processor.hpp"
+#include "../../include/logger.hpp"
+#include <stdexcept>
+#include <string>
+
+namespace chisel {
+
+namespace fs = std::filesystem;
+
+namespace {
+
+    // takes ownership of a bridge error message
+    std::string take_error(char* error_message) {
+        if (error_message == nullptr) return {};
+        std::string msg(error_message);
+        chisel_free_string(error_message);
+        return msg;
+    }
+
+} // namespace
+
+RustProcessor::RustProcessor(const ChiselProcessorVTable& vtable)
+    : vtable_(vtable), name_(vtable.name) {
+    mime_types_.assign(vtable.mime_types, vtable.mime_types + vtable.mime_type_count);
+    extensions_.assign(vtable.extensions, vtable.extensions + vtable.extension_count);
+}
+
+void RustProcessor::recompress(const fs::path& input,
+                               const fs::path& output,
+                               const bool preserve_metadata) {
+    const std::string tag(name_);
+    Logger::log(LogLevel::Info, "Starting optimization: " + input.string(), tag);
+
+    const std::string input_str = input.string();
+    const std::string output_str = output.string();
+
+    char* error_message = nullptr;
+    const int result = vtable_.recompress(vtable_.processor, input_str.c_str(), output_str.c_str(),
+                                          preserve_metadata, &error_message);
+    if (result != CHISEL_VORBIS_OK) {
+        std::string msg = tag + " failed with error code " + std::to_string(result);
+        if (const std::string detail = take_error(error_message); !detail.empty()) {
+            msg += ": " + detail;
+        }
+        std::error_code ec;
+        fs::remove(output, ec);
+        Logger::log(LogLevel::Error, msg, tag);
+        throw std::runtime_error(msg);
+    }
+
+    Logger::log(LogLevel::Info, "Optimization finished: " + output.string(), tag);
+}
+
+std::string RustProcessor::get_raw_checksum(const fs::path&) const {
+    // Rust processors compare decoded content pairwise, see raw_equal
+    return "";
+}
+
+bool RustProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    const std::string a_str = a.string();
+    const std::string b_str = b.string();
+
+    char* error_message = nullptr;
+    const int result = vtable_.raw_equal(vtable_.processor, a_str.c_str(), b_str.c_str(), &error_message);
+    if (result < 0) {
+        Logger::log(LogLevel::Warning,
+                    "raw_equal failed with error code " + std::to_string(result) + ": " + take_error(error_message),
+                    std::string(name_));
+        return false;
+    }
+    if (result == 0) {
+        Logger::log(LogLevel::Debug, "raw_equal: content mismatch", std::string(name_));
+    }
+    return result == 1;
+}
+
+} // namespace chisel
@@ -0,0 +1,470 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/tta_processor.hpp"
+#include "../../include/logger.hpp"
+#include "../../include/audio_metadata_util.hpp"
+#include "../../include/file_utils.hpp"
+#include "../../include/md5.hpp"
+#include "../../include/random_utils.hpp"
+#include <libttaR.h>
+#include <zlib.h>
+#include <algorithm>
+#include <cstddef>
+#include <cstdint>
+#include <cstring>
+#include <fstream>
+#include <iomanip>
+#include <sstream>
+#include <stdexcept>
+#include <filesystem>
+#include <string>
+#include <vector>
+#include "file_type.hpp"
+
+namespace chisel {
+namespace fs = std::filesystem;
+
+static const char* processor_tag() {
+    return "TtaProcessor";
+}
+
+namespace {
+
+    constexpr std::uint16_t TTA_FORMAT_SIMPLE = 1;
+    constexpr std::uint16_t TTA_FORMAT_ENCRYPTED = 2;
+    constexpr std::size_t TTA_HEADER_SIZE = 22;
+
+    using Bytes = std::vector<unsigned char>;
+
+    std::uint16_t get_le16(const unsigned char* p) {
+        return static_cast<std::uint16_t>(p[0] | p[1] << 8);
+    }
+
+    std::uint32_t get_le32(const unsigned char* p) {
+        return static_cast<std::uint32_t>(p[0]) | static_cast<std::uint32_t>(p[1]) << 8 |
+               static_cast<std::uint32_t>(p[2]) << 16 | static_cast<std::uint32_t>(p[3]) << 24;
+    }
+
+    void put_le16(Bytes& out, const std::uint16_t value) {
+        out.push_back(static_cast<unsigned char>(value));
+        out.push_back(static_cast<unsigned char>(value >> 8));
+    }
+
+    void put_le32(Bytes& out, const std::uint32_t value) {
+        for (int i = 0; i < 4; ++i) out.push_back(static_cast<unsigned char>(value >> (8 * i)));
+    }
+
+    // TTA checks the header, the seek table and every frame with the zlib CRC-32
+    std::uint32_t crc_of(const unsigned char* data, const std::size_t size) {
+        return static_cast<std::uint32_t>(crc32(crc32(0L, Z_NULL, 0), data, static_cast<uInt>(size)));
+    }
+
+    void read_exact(std::ifstream& in, unsigned char* data, const std::size_t size) {
+        in.read(reinterpret_cast<char*>(data), static_cast<std::streamsize>(size));
+        if (!in) {
+            throw std::runtime_error("TTA: truncated stream");
+        }
+    }
+
+    /**
+     * @brief A TTA1 stream: fixed header, seek table of frame sizes, then the frames.
+     */
+    struct TtaStream {
+        std::uint16_t channels = 0;
+        std::uint16_t bits_per_sample = 0;
+        std::uint32_t sample_rate = 0;
+        std::uint32_t samples = 0;                ///< Samples per channel
+        std::uint64_t frames_offset = 0;          ///< File offset of the first frame
+        std::vector<std::uint32_t> frame_sizes;   ///< Frame sizes, trailing CRC included
+
+        [[nodiscard]] std::uint32_t frame_length() const {
+            return static_cast<std::uint32_t>(256ull * sample_rate / 245);
+        }
+
+        [[nodiscard]] std::size_t samples_in(const std::size_t frame) const {
+            if (frame + 1 < frame_sizes.size()) return frame_length();
+            return samples - static_cast<std::size_t>(frame) * frame_length();
+        }
+
+        [[nodiscard]] unsigned sample_bytes() const {
+            return (bits_per_sample + 7u) / 8u;
+        }
+
+        [[nodiscard]] bool same_format(const TtaStream& other) const {
+            return channels == other.channels && bits_per_sample == other.bits_per_sample &&
+                   sample_rate == other.sample_rate && samples == other.samples;
+        }
+    };
+
+    /**
+     * @brief Parses and checks the header and seek table of the stream at [offset, offset + size).
+     * @throws std::runtime_error if the stream is not a plain TTA1 stream or fails a CRC check.
+     */
+    TtaStream parse_tta(std::ifstream& in, const std::uint64_t offset, const std::uint64_t size) {
+        if (size < TTA_HEADER_SIZE) {
+            throw std::runtime_error("TTA: stream too small");
+        }
+        unsigned char header[TTA_HEADER_SIZE];
+        in.seekg(static_cast<std::streamoff>(offset));
+        read_exact(in, header, sizeof(header));
+        if (std::memcmp(header, "TTA1", 4) != 0) {
+            throw std::runtime_error("TTA: missing TTA1 signature");
+        }
+        if (crc_of(header, 18) != get_le32(header + 18)) {
+            throw std::runtime_error("TTA: header CRC mismatch");
+        }
+
+        const std::uint16_t format = get_le16(header + 4);
+        if (format == TTA_FORMAT_ENCRYPTED) {
+            throw std::runtime_error("TTA: password-protected stream");
+        }
+        if (format != TTA_FORMAT_SIMPLE) {
+            throw std::runtime_error("TTA: unknown format " + std::to_string(format));
+        }
+
+        TtaStream stream;
+        stream.channels = get_le16(header + 6);
+        stream.bits_per_sample = get_le16(header + 8);
+        stream.sample_rate = get_le32(header + 10);
+        stream.samples = get_le32(header + 14);
+        if (stream.channels == 0 || !libttaR_test_nchan(stream.channels) ||
+            stream.bits_per_sample < 8 || stream.bits_per_sample > 24 ||
+            stream.frame_length() == 0) {
+            throw std::runtime_error("TTA: unsupported audio parameters");
+        }
+
+        const std::uint64_t frames = (static_cast<std::uint64_t>(stream.samples) + stream.frame_length() - 1) / stream.frame_length();
+        const std::uint64_t table_size = frames * 4 + 4;
+        if (table_size > size - TTA_HEADER_SIZE) {
+            throw std::runtime_error("TTA: seek table larger than the stream");
+        }
+        Bytes table(static_cast<std::size_t>(table_size));
+        read_exact(in, table.data(), table.size());
+        if (crc_of(table.data(), table.size() - 4) != get_le32(table.data() + table.size() - 4)) {
+            throw std::runtime_error("TTA: seek table CRC mismatch");
+        }
+
+        std::uint64_t frames_size = 0;
+        stream.frame_sizes.reserve(static_cast<std::size_t>(frames));
+        for (std::size_t i = 0; i < frames; ++i) {
+            const std::uint32_t frame_size = get_le32(table.data() + i * 4);
+            if (frame_size < 4) {
+                throw std::runtime_error("TTA: invalid frame size");
+            }
+            stream.frame_sizes.push_back(frame_size);
+            frames_size += frame_size;
+        }
+        stream.frames_offset = offset + TTA_HEADER_SIZE + table_size;
+        if (frames_size > size - TTA_HEADER_SIZE - table_size) {
+            throw std::runtime_error("TTA: frames run past the end of the stream");
+        }
+        return stream;
+    }
+
+    /**
+     * @brief Opens a file and parses the TTA stream between its tags.
+     */
+    TtaStream open_tta(const fs::path& path, std::ifstream& in) {
+        const AudioTagBlocks blocks = AudioMetadataUtil::readTagBlocks(path);
+        in.open(path, std::ios::binary);
+        if (!in) {
+            throw std::runtime_error("TTA: cannot open " + path.string());
+        }
+        TtaStream stream = parse_tta(in, blocks.audio_offset, blocks.audio_size);
+        in.seekg(static_cast<std::streamoff>(stream.frames_offset));
+        return stream;
+    }
+
+    /**
+     * @brief Reads the next frame and checks its CRC.
+     * @param frame Receives the frame data, without the CRC.
+     */
+    void read_frame(std::ifstream& in, const TtaStream& stream, const std::size_t index, Bytes& frame) {
+        frame.resize(stream.frame_sizes[index]);
+        read_exact(in, frame.data(), frame.size());
+        const std::size_t data_size = frame.size() - 4;
+        if (crc_of(frame.data(), data_size) != get_le32(frame.data() + data_size)) {
+            throw std::runtime_error("TTA: CRC mismatch in frame " + std::to_string(index));
+        }
+        frame.resize(data_size);
+    }
+
+    /**
+     * @brief libttaR encoder and decoder for the frames of one stream.
+     *
+     * libttaR codes a single frame per run: the private state is reset by
+     * starting each frame with a fresh user state.
+     */
+    class FrameCodec {
+    public:
+        explicit FrameCodec(const TtaStream& stream)
+            : channels_(stream.channels),
+              samplebytes_(static_cast<LibTTAr_SampleBytes>(stream.sample_bytes())),
+              priv_((libttaR_codecstate_priv_size(stream.channels) + sizeof(std::max_align_t) - 1) / sizeof(std::max_align_t)) {
+        }
+
+        /**
+         * @brief Decodes one frame (without its CRC) into interleaved samples.
+         * @throws std::runtime_error if the frame does not decode to exactly `samples` samples.
+         */
+        void decode(const Bytes& frame, const std::size_t samples, std::vector<std::int32_t>& pcm) {
+            const std::size_t ni32 = samples * channels_;
+            pcm.resize(ni32);
+
+            LibTTAr_CodecState_User user{};
+            LibTTAr_DecMisc misc{};
+            misc.dest_len = ni32;
+            misc.src_len = frame.size();
+            misc.ni32_target = ni32;
+            misc.nbytes_tta_target = frame.size();
+            misc.ni32_perframe = ni32;
+            misc.nbytes_tta_perframe = frame.size();
+            misc.samplebytes = samplebytes_;
+            misc.nchan = channels_;
+
+            const int ret = libttaR_tta_decode(pcm.data(), frame.data(), priv(), &user, &misc);
+            if (ret != LIBTTAr_RET_DONE || user.ni32_total != ni32 || user.nbytes_tta_total != frame.size()) {
+                throw std::runtime_error("TTA: frame decoding failed");
+            }
+        }
+
+        /**
+         * @brief Encodes interleaved samples into one frame, without its CRC.
+         */
+        void encode(const std::vector<std::int32_t>& pcm, const std::size_t samples, Bytes& frame) {
+            const std::size_t ni32 = samples * channels_;
+            frame.resize(ni32 * static_cast<std::size_t>(samplebytes_) +
+                         libttaR_ttabuf_safety_margin(samplebytes_, channels_));
+
+            LibTTAr_CodecState_User user{};
+            LibTTAr_EncMisc misc{};
+            misc.dest_len = frame.size();
+            misc.src_len = ni32;
+            misc.ni32_target = ni32;
+            misc.ni32_perframe = ni32;
+            misc.samplebytes = samplebytes_;
+            misc.nchan = channels_;
+
+            const int ret = libttaR_tta_encode(frame.data(), pcm.data(), priv(), &user, &misc);
+            if (ret != LIBTTAr_RET_DONE || user.ni32_total != ni32) {
+                throw std::runtime_error("TTA: frame encoding failed");
+            }
+            frame.resize(user.nbytes_tta_total);
+        }
+
+    private:
+        LibTTAr_CodecState_Priv* priv() {
+            return reinterpret_cast<LibTTAr_CodecState_Priv*>(priv_.data());
+        }
+
+        unsigned channels_;
+        LibTTAr_SampleBytes samplebytes_;
+        std::vector<std::max_align_t> priv_;   ///< libttaR private state, opaque
+    };
+
+    /**
+     * @brief Appends samples to the MD5 in WAV layout: 8-bit unsigned, wider ones signed little-endian.
+     */
+    void hash_samples(Md5& md5, const std::vector<std::int32_t>& pcm, const unsigned sample_bytes) {
+        Bytes bytes;
+        bytes.reserve(pcm.size() * sample_bytes);
+        for (const std::int32_t sample : pcm) {
+            const auto value = static_cast<std::uint32_t>(sample_bytes == 1 ? sample + 0x80 : sample);
+            for (unsigned b = 0; b < sample_bytes; ++b) {
+                bytes.push_back(static_cast<unsigned char>(value >> (8 * b)));
+            }
+        }
+        md5.update(bytes);
+    }
+
+}
+
+void TtaProcessor::recompress(const fs::path& input,
+                              const fs::path& output,
+                              const bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "Starting TTA re-encoding: " + input.string(), processor_tag());
+
+    auto keep_as_is = [&](const std::string& reason) {
+        Logger::log(LogLevel::Warning, "TTA: Left as-is: " + reason, processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+    };
+
+    try {
+        AudioTagBlocks blocks = AudioMetadataUtil::readTagBlocks(input);
+        std::ifstream in(input, std::ios::binary);
+        if (!in) {
+            throw std::runtime_error("TTA: cannot open " + input.string());
+        }
+        const TtaStream stream = parse_tta(in, blocks.audio_offset, blocks.audio_size);
+
+        std::ofstream out(output, std::ios::binary | std::ios::trunc);
+        if (!out) {
+            throw std::runtime_error("TtaProcessor: cannot create " + output.string());
+        }
+        auto write = [&](const Bytes& bytes) {
+            out.write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+        };
+
+        if (preserve_metadata && !blocks.id3v2.empty()) {
+            AudioMetadataUtil::compactId3v2(blocks.id3v2);
+            write(blocks.id3v2);
+        }
+
+        Bytes header = {'T', 'T', 'A', '1'};
+        put_le16(header, TTA_FORMAT_SIMPLE);
+        put_le16(header, stream.channels);
+        put_le16(header, stream.bits_per_sample);
+        put_le32(header, stream.sample_rate);
+        put_le32(header, stream.samples);
+        put_le32(header, crc_of(header.data(), header.size()));
+        write(header);
+
+        // the seek table is filled in once the frame sizes are known
+        const std::streamoff table_position = out.tellp();
+        write(Bytes(stream.frame_sizes.size() * 4 + 4));
+
+        FrameCodec codec(stream);
+        Bytes frame;
+        std::vector<std::int32_t> pcm;
+        Bytes table;
+        in.seekg(static_cast<std::streamoff>(stream.frames_offset));
+        for (std::size_t i = 0; i < stream.frame_sizes.size(); ++i) {
+            read_frame(in, stream, i, frame);
+            codec.decode(frame, stream.samples_in(i), pcm);
+            codec.encode(pcm, stream.samples_in(i), frame);
+            put_le32(frame, crc_of(frame.data(), frame.size()));
+            put_le32(table, static_cast<std::uint32_t>(frame.size()));
+            write(frame);
+        }
+        put_le32(table, crc_of(table.data(), table.size()));
+
+        if (preserve_metadata) {
+            write(blocks.apev2);
+            write(blocks.id3v1);
+        }
+        out.seekp(table_position);
+        write(table);
+
+        out.close();
+        if (!out) {
+            throw std::runtime_error("TtaProcessor: write failed for " + output.string());
+        }
+        Logger::log(LogLevel::Debug, "TTA: " + std::to_string(stream.frame_sizes.size()) + " frames re-encoded", processor_tag());
+    } catch (const std::exception& e) {
+        std::error_code ec;
+        fs::remove(output, ec);
+        keep_as_is(e.what());
+        return;
+    }
+
+    Logger::log(LogLevel::Info, "TTA re-encoding completed: " + output.string(), processor_tag());
+}
+
+std::optional<ExtractedContent> TtaProcessor::prepare_extraction(const fs::path& input_path) {
+    Logger::log(LogLevel::Info, "TTA: Preparing cover art extraction for: " + input_path.string(), processor_tag());
+
+    ExtractedContent content;
+    content.original_path = input_path;
+    content.temp_dir = make_temp_dir_for(input_path, "tta-processor");
+
+    AudioExtractionState state = AudioMetadataUtil::extractCovers(input_path, content.temp_dir);
+
+    if (state.extracted_covers.empty()) {
+        Logger::log(LogLevel::Debug, "TTA: No embedded cover art found.", processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return std::nullopt;
+    }
+
+    for (const auto& cover_info : state.extracted_covers) {
+        content.extracted_files.push_back(cover_info.temp_file_path);
+    }
+
+    content.extras = std::make_any<AudioExtractionState>(std::move(state));
+    content.format = ContainerFormat::Unknown;
+    return content;
+}
+
+std::filesystem::path TtaProcessor::finalize_extraction(const ExtractedContent &content) {
+    Logger::log(LogLevel::Info, "TTA: Finalizing (re-inserting covers) for: " + content.original_path.string(), processor_tag());
+
+    const AudioExtractionState* state_ptr = std::any_cast<AudioExtractionState>(&content.extras);
+    if (!state_ptr) {
+        Logger::log(LogLevel::Error, "TTA: Failed to retrieve extraction state.", processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return {};
+    }
+
+    const fs::path final_temp_path = fs::temp_directory_path() /
+                                     (content.original_path.stem().string() + "_final" + RandomUtils::random_suffix() + ".tta");
+
+    try {
+        fs::copy_file(content.original_path, final_temp_path, fs::copy_options::overwrite_existing);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Error, "TTA: Failed to copy audio file: " + std::string(e.what()), processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        return {};
+    }
+
+    if (!AudioMetadataUtil::rebuildCovers(final_temp_path, *state_ptr)) {
+        Logger::log(LogLevel::Error, "TTA: rebuildCovers failed", processor_tag());
+        cleanup_temp_dir(content.temp_dir, processor_tag());
+        fs::remove(final_temp_path);
+        return {};
+    }
+
+    cleanup_temp_dir(content.temp_dir, processor_tag());
+    return final_temp_path;
+}
+
+std::string TtaProcessor::get_raw_checksum(const fs::path& file_path) const {
+    std::ifstream in;
+    const TtaStream stream = open_tta(file_path, in);
+    FrameCodec codec(stream);
+
+    Md5 md5;
+    Bytes frame;
+    std::vector<std::int32_t> pcm;
+    for (std::size_t i = 0; i < stream.frame_sizes.size(); ++i) {
+        read_frame(in, stream, i, frame);
+        codec.decode(frame, stream.samples_in(i), pcm);
+        hash_samples(md5, pcm, stream.sample_bytes());
+    }
+
+    std::ostringstream oss;
+    for (const std::uint8_t byte : md5.finish()) {
+        oss << std::hex << std::setw(2) << std::setfill('0') << static_cast<int>(byte);
+    }
+    return oss.str();
+}
+
+bool TtaProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    try {
+        std::ifstream in_a;
+        std::ifstream in_b;
+        const TtaStream tta_a = open_tta(a, in_a);
+        const TtaStream tta_b = open_tta(b, in_b);
+        if (!tta_a.same_format(tta_b)) return false;
+
+        // same sample rate, same frame length: frames line up one to one
+        FrameCodec codec_a(tta_a);
+        FrameCodec codec_b(tta_b);
+        Bytes frame;
+        std::vector<std::int32_t> pcm_a;
+        std::vector<std::int32_t> pcm_b;
+        for (std::size_t i = 0; i < tta_a.frame_sizes.size(); ++i) {
+            read_frame(in_a, tta_a, i, frame);
+            codec_a.decode(frame, tta_a.samples_in(i), pcm_a);
+            read_frame(in_b, tta_b, i, frame);
+            codec_b.decode(frame, tta_b.samples_in(i), pcm_b);
+            if (pcm_a != pcm_b) return false;
+        }
+        return true;
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("TTA: PCM comparison unavailable: ") + e.what(), processor_tag());
+        return files_equal(a, b);
+    }
+}
+
+} // namespace chisel
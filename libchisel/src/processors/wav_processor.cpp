@@ -7,8 +7,16 @@
 #include "../../include/audio_metadata_util.hpp"
 #include "../../include/file_utils.hpp"
 #include "../../include/random_utils.hpp"
+#include <algorithm>
+#include <array>
+#include <cstdint>
+#include <cstring>
+#include <fstream>
+#include <optional>
 #include <stdexcept>
 #include <filesystem>
+#include <string>
+#include <vector>
 #include "file_type.hpp"
 
 namespace chisel {
@@ -18,17 +26,332 @@ static const char* processor_tag() {
     return "WavProcessor";
 }
 
+namespace {
+
+    constexpr std::size_t COPY_BLOCK = 1 << 20;
+    constexpr std::uint16_t WAVE_FORMAT_PCM = 0x0001;
+    constexpr std::uint16_t WAVE_FORMAT_EXTENSIBLE = 0xFFFE;
+    // KSDATAFORMAT_SUBTYPE_PCM without its leading format tag
+    constexpr std::array<unsigned char, 14> PCM_SUBFORMAT_TAIL = {
+        0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71
+    };
+
+    using Bytes = std::vector<unsigned char>;
+
+    /**
+     * @brief One chunk of the RIFF form. The payload of `data` is not loaded.
+     */
+    struct Chunk {
+        std::string id;
+        std::uint64_t offset = 0; ///< Payload position in the file
+        std::uint64_t size = 0;   ///< Payload size, without the pad byte
+        Bytes payload;
+    };
+
+    struct WavLayout {
+        std::vector<Chunk> chunks;
+        std::optional<std::size_t> fmt;
+        std::optional<std::size_t> data;
+        std::uint64_t trailing_offset = 0; ///< Bytes after the last chunk (appended tags, garbage)
+        std::uint64_t file_size = 0;
+    };
+
+    std::uint16_t le16_at(const Bytes& b, std::size_t pos) {
+        return static_cast<std::uint16_t>(b[pos] | b[pos + 1] << 8);
+    }
+
+    std::uint32_t le32_at(const unsigned char* p) {
+        return static_cast<std::uint32_t>(p[0]) | static_cast<std::uint32_t>(p[1]) << 8 |
+               static_cast<std::uint32_t>(p[2]) << 16 | static_cast<std::uint32_t>(p[3]) << 24;
+    }
+
+    void put_le16(Bytes& out, std::uint16_t v) {
+        out.push_back(static_cast<unsigned char>(v));
+        out.push_back(static_cast<unsigned char>(v >> 8));
+    }
+
+    void put_le32(Bytes& out, std::uint32_t v) {
+        for (int i = 0; i < 4; ++i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    bool valid_chunk_id(const unsigned char* p) {
+        return std::all_of(p, p + 4, [](unsigned char c) { return c >= 0x20 && c <= 0x7E; });
+    }
+
+    bool read_at(std::ifstream& in, std::uint64_t offset, unsigned char* dst, std::size_t size) {
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        in.read(reinterpret_cast<char*>(dst), static_cast<std::streamsize>(size));
+        return static_cast<std::size_t>(in.gcount()) == size;
+    }
+
+    /**
+     * @brief Walks the chunks of a RIFF/WAVE file.
+     *
+     * Chunks are followed up to the end of the file rather than the RIFF
+     * size, which writers often get wrong, and a missing pad byte after an
+     * odd-sized chunk is tolerated. A `data` chunk running past the end of
+     * the file (streamed recordings) is clamped.
+     *
+     * @throws std::runtime_error if the file is not a RIFF/WAVE file.
+     */
+    WavLayout parse_wav(std::ifstream& in, const fs::path& path) {
+        WavLayout layout;
+        layout.file_size = fs::file_size(path);
+
+        unsigned char header[12];
+        if (!read_at(in, 0, header, sizeof(header)) || std::memcmp(header, "RIFF", 4) != 0 ||
+            std::memcmp(header + 8, "WAVE", 4) != 0) {
+            throw std::runtime_error("WavProcessor: not a RIFF/WAVE file (RF64 is left as-is)");
+        }
+
+        auto chunk_id_at = [&](std::uint64_t at) {
+            unsigned char id[4];
+            return at + 4 <= layout.file_size && read_at(in, at, id, 4) && valid_chunk_id(id);
+        };
+
+        std::uint64_t pos = 12;
+        unsigned char ch[8];
+        while (pos + 8 <= layout.file_size && read_at(in, pos, ch, 8) && valid_chunk_id(ch)) {
+            Chunk chunk;
+            chunk.id.assign(reinterpret_cast<const char*>(ch), 4);
+            chunk.offset = pos + 8;
+            chunk.size = le32_at(ch + 4);
+
+            const std::uint64_t available = layout.file_size - chunk.offset;
+            if (chunk.size > available) {
+                if (chunk.id != "data") {
+                    throw std::runtime_error("WavProcessor: chunk '" + chunk.id + "' is truncated");
+                }
+                chunk.size = available;
+            }
+            if (chunk.id == "data") {
+                if (layout.data) throw std::runtime_error("WavProcessor: more than one data chunk");
+                layout.data = layout.chunks.size();
+            } else {
+                chunk.payload.resize(chunk.size);
+                if (!read_at(in, chunk.offset, chunk.payload.data(), chunk.payload.size())) {
+                    throw std::runtime_error("WavProcessor: read failed in chunk '" + chunk.id + "'");
+                }
+                if (chunk.id == "fmt ") {
+                    if (layout.fmt) throw std::runtime_error("WavProcessor: more than one fmt chunk");
+                    if (chunk.size < 16) throw std::runtime_error("WavProcessor: fmt chunk too short");
+                    layout.fmt = layout.chunks.size();
+                }
+            }
+
+            pos = chunk.offset + chunk.size;
+            // some writers omit the pad byte: skip it only if that lands on a chunk header
+            if (chunk.size % 2 != 0 && pos < layout.file_size && !(chunk_id_at(pos) && !chunk_id_at(pos + 1))) {
+                ++pos;
+            }
+            layout.chunks.push_back(std::move(chunk));
+        }
+        layout.trailing_offset = std::min(pos, layout.file_size);
+
+        if (!layout.fmt || !layout.data || *layout.fmt > *layout.data) {
+            throw std::runtime_error("WavProcessor: missing fmt or data chunk");
+        }
+        return layout;
+    }
+
+    /**
+     * @brief The fmt chunk in its smallest equivalent form.
+     *
+     * PCM drops any extension bytes, and a WAVE_FORMAT_EXTENSIBLE header
+     * with the PCM subformat, at most two channels in their default
+     * positions and no unused sample bits becomes plain PCM. Other formats
+     * are kept as they are.
+     */
+    Bytes canonical_fmt(const Bytes& fmt) {
+        const std::uint16_t tag = le16_at(fmt, 0);
+        const std::uint16_t channels = le16_at(fmt, 2);
+        const std::uint16_t bits = le16_at(fmt, 14);
+        bool plain_pcm = tag == WAVE_FORMAT_PCM;
+        if (tag == WAVE_FORMAT_EXTENSIBLE && fmt.size() >= 40 && le16_at(fmt, 16) >= 22) {
+            const std::uint16_t valid_bits = le16_at(fmt, 18);
+            const std::uint32_t mask = le32_at(fmt.data() + 20);
+            const std::uint32_t default_mask = channels == 1 ? 0x4 : 0x3;
+            plain_pcm = le16_at(fmt, 24) == WAVE_FORMAT_PCM &&
+                        std::equal(PCM_SUBFORMAT_TAIL.begin(), PCM_SUBFORMAT_TAIL.end(), fmt.begin() + 26) &&
+                        channels >= 1 && channels <= 2 && bits % 8 == 0 &&
+                        (valid_bits == bits || valid_bits == 0) &&
+                        (mask == 0 || mask == default_mask);
+        }
+        if (!plain_pcm) return fmt;
+
+        Bytes out(fmt.begin(), fmt.begin() + 16);
+        out[0] = WAVE_FORMAT_PCM & 0xFF;
+        out[1] = WAVE_FORMAT_PCM >> 8;
+        return out;
+    }
+
+    bool is_pcm(const Bytes& fmt) {
+        const std::uint16_t tag = le16_at(fmt, 0);
+        if (tag == WAVE_FORMAT_PCM) return true;
+        return tag == WAVE_FORMAT_EXTENSIBLE && fmt.size() >= 26 && le16_at(fmt, 24) == WAVE_FORMAT_PCM;
+    }
+
+    /**
+     * @brief Rewrites a LIST/INFO chunk without empty entries and surplus NUL terminators.
+     * @return False if nothing is left.
+     */
+    bool compact_info(Bytes& payload) {
+        Bytes out(payload.begin(), payload.begin() + 4);
+        std::size_t pos = 4;
+        while (pos + 8 <= payload.size()) {
+            const std::size_t size = le32_at(payload.data() + pos + 4);
+            if (!valid_chunk_id(payload.data() + pos) || size > payload.size() - pos - 8) break;
+            const auto first = payload.begin() + static_cast<std::ptrdiff_t>(pos + 8);
+            auto last = first + static_cast<std::ptrdiff_t>(size);
+            while (last != first && *(last - 1) == 0) --last;
+            if (last != first) {
+                out.insert(out.end(), payload.begin() + static_cast<std::ptrdiff_t>(pos),
+                           payload.begin() + static_cast<std::ptrdiff_t>(pos + 4));
+                put_le32(out, static_cast<std::uint32_t>(last - first + 1));
+                out.insert(out.end(), first, last);
+                out.push_back(0);
+                if (out.size() % 2 != 0) out.push_back(0);
+            }
+            pos += 8 + size + (size % 2);
+        }
+        payload = std::move(out);
+        return payload.size() > 4;
+    }
+
+    /**
+     * @brief Copies `size` bytes from `in` at `offset` to `out`.
+     */
+    void copy_range(std::ifstream& in, std::uint64_t offset, std::uint64_t size, std::ofstream& out) {
+        std::vector<char> buf(COPY_BLOCK);
+        in.clear();
+        in.seekg(static_cast<std::streamoff>(offset));
+        while (size > 0) {
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(size, buf.size()));
+            if (!in.read(buf.data(), static_cast<std::streamsize>(n))) {
+                throw std::runtime_error("WavProcessor: read failed");
+            }
+            out.write(buf.data(), static_cast<std::streamsize>(n));
+            size -= n;
+        }
+    }
+
+    bool same_range(std::ifstream& a, std::uint64_t offset_a, std::ifstream& b, std::uint64_t offset_b,
+                    std::uint64_t size) {
+        std::vector<char> buf_a(COPY_BLOCK), buf_b(COPY_BLOCK);
+        a.clear();
+        b.clear();
+        a.seekg(static_cast<std::streamoff>(offset_a));
+        b.seekg(static_cast<std::streamoff>(offset_b));
+        while (size > 0) {
+            const std::size_t n = static_cast<std::size_t>(std::min<std::uint64_t>(size, buf_a.size()));
+            if (!a.read(buf_a.data(), static_cast<std::streamsize>(n)) ||
+                !b.read(buf_b.data(), static_cast<std::streamsize>(n)) ||
+                std::memcmp(buf_a.data(), buf_b.data(), n) != 0) {
+                return false;
+            }
+            size -= n;
+        }
+        return true;
+    }
+
+} // namespace
+
 void WavProcessor::recompress(const fs::path& input,
                               const fs::path& output,
-                              bool /*preserve_metadata*/) {
-    Logger::log(LogLevel::Error, "Recompress called on WavProcessor placeholder.", processor_tag());
+                              bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "WAV: Optimizing chunks of: " + input.string(), processor_tag());
 
-    std::error_code ec;
-    fs::copy_file(input, output, fs::copy_options::overwrite_existing, ec);
+    std::ifstream in(input, std::ios::binary);
+    if (!in) {
+        throw std::runtime_error("WavProcessor: cannot open " + input.string());
+    }
+
+    WavLayout layout;
+    try {
+        layout = parse_wav(in, input);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Warning, std::string("WAV: Left as-is: ") + e.what(), processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
+    }
+
+    const Bytes fmt = canonical_fmt(layout.chunks[*layout.fmt].payload);
+    const bool pcm = is_pcm(fmt);
+
+    // chunk list of the output; the data chunk keeps an empty payload and is streamed
+    std::vector<const Chunk*> kept;
+    std::size_t dropped = 0;
+    for (auto& chunk : layout.chunks) {
+        bool keep = true;
+        if (chunk.id == "fmt ") {
+            chunk.payload = fmt;
+        } else if (chunk.id == "JUNK" || chunk.id == "junk" || chunk.id == "PAD " || chunk.id == "pad " ||
+                   chunk.id == "FLLR") {
+            keep = false;
+        } else if (chunk.id == "fact") {
+            // fact only matters to compressed formats
+            keep = !pcm;
+        } else if (!preserve_metadata && chunk.id != "data") {
+            keep = false;
+        } else if (chunk.id == "LIST" && chunk.payload.size() >= 4 && std::memcmp(chunk.payload.data(), "INFO", 4) == 0) {
+            keep = compact_info(chunk.payload);
+        } else if (chunk.id == "id3 " || chunk.id == "ID3 ") {
+            AudioMetadataUtil::compactId3v2(chunk.payload);
+        }
+        if (keep) {
+            kept.push_back(&chunk);
+        } else {
+            ++dropped;
+        }
+    }
 
-    if (ec) {
-        throw std::runtime_error("Placeholder recompress failed to copy file.");
+    const std::uint64_t data_size = layout.chunks[*layout.data].size;
+    const std::uint64_t trailing = preserve_metadata ? layout.file_size - layout.trailing_offset : 0;
+    std::uint64_t riff_size = 4;
+    for (const Chunk* chunk : kept) {
+        const std::uint64_t size = chunk->id == "data" ? data_size : chunk->payload.size();
+        riff_size += 8 + size + (size % 2);
     }
+    if (riff_size > 0xFFFFFFFFu) {
+        Logger::log(LogLevel::Warning, "WAV: Output would exceed the RIFF size limit, left as-is", processor_tag());
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+        return;
+    }
+
+    std::ofstream out(output, std::ios::binary | std::ios::trunc);
+    if (!out) {
+        throw std::runtime_error("WavProcessor: cannot create " + output.string());
+    }
+    auto write = [&out](const Bytes& bytes) {
+        out.write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+    };
+
+    Bytes head = {'R', 'I', 'F', 'F'};
+    put_le32(head, static_cast<std::uint32_t>(riff_size));
+    head.insert(head.end(), {'W', 'A', 'V', 'E'});
+    write(head);
+    for (const Chunk* chunk : kept) {
+        const std::uint64_t size = chunk->id == "data" ? data_size : chunk->payload.size();
+        Bytes chunk_header(chunk->id.begin(), chunk->id.end());
+        put_le32(chunk_header, static_cast<std::uint32_t>(size));
+        write(chunk_header);
+        if (chunk->id == "data") {
+            copy_range(in, chunk->offset, size, out);
+        } else {
+            write(chunk->payload);
+        }
+        if (size % 2 != 0) out.put('\0');
+    }
+    if (trailing > 0) {
+        copy_range(in, layout.trailing_offset, trailing, out);
+    }
+
+    out.close();
+    if (!out) {
+        throw std::runtime_error("WavProcessor: write failed for " + output.string());
+    }
+    Logger::log(LogLevel::Debug, "WAV: " + std::to_string(dropped) + " chunks dropped", processor_tag());
 }
 
 std::optional<ExtractedContent> WavProcessor::prepare_extraction(const fs::path& input_path) {
@@ -87,4 +410,23 @@ std::filesystem::path WavProcessor::finalize_extraction(const ExtractedContent &
     return final_temp_path;
 }
 
-} // namespace chisel
\ No newline at end of file
+bool WavProcessor::raw_equal(const fs::path& a, const fs::path& b) const {
+    std::ifstream in_a(a, std::ios::binary);
+    std::ifstream in_b(b, std::ios::binary);
+    if (!in_a || !in_b) return false;
+
+    try {
+        const WavLayout wav_a = parse_wav(in_a, a);
+        const WavLayout wav_b = parse_wav(in_b, b);
+        const Chunk& data_a = wav_a.chunks[*wav_a.data];
+        const Chunk& data_b = wav_b.chunks[*wav_b.data];
+        return canonical_fmt(wav_a.chunks[*wav_a.fmt].payload) == canonical_fmt(wav_b.chunks[*wav_b.fmt].payload) &&
+               data_a.size == data_b.size &&
+               same_range(in_a, data_a.offset, in_b, data_b.offset, data_a.size);
+    } catch (const std::exception& e) {
+        Logger::log(LogLevel::Debug, std::string("WAV: Sample comparison unavailable: ") + e.what(), processor_tag());
+        return files_equal(a, b);
+    }
+}
+
+} // namespace chisel
@@ -3,186 +3,393 @@
 //
 
 #include "../../include/wavpack_processor.hpp"
+#include "../../include/best_candidate.hpp"
 #include "../../include/logger.hpp"
+#include "../../include/md5.hpp"
+#include "../../include/stop_scope.hpp"
 #include <wavpack.h>
-#include <stdexcept>
-#include <vector>
+#include <algorithm>
+#include <array>
+#include <cctype>
 #include <cstdio>
+#include <cstring>
 #include <filesystem>
+#include <fstream>
+#include <iomanip>
+#include <memory>
+#include <sstream>
+#include <stdexcept>
+#include <vector>
 #include "file_utils.hpp"
 
 namespace chisel {
 
-void WavPackProcessor::recompress(const std::filesystem::path& input,
-                                  const std::filesystem::path& output,
-                                  bool preserve_metadata) {
-    Logger::log(LogLevel::Info, "Starting WavPack recompression: " + input.string(), "wavpack_processor");
+namespace fs = std::filesystem;
+
+namespace {
+
+using ContextPtr = std::unique_ptr<WavpackContext, decltype(&WavpackCloseFile)>;
+using FilePtr = std::unique_ptr<FILE, decltype(&std::fclose)>;
+
+constexpr int32_t kBlockSamples = 65536;
+
+/// One point of the brute-force search: compression level plus extra-mode level.
+struct EncodeMode {
+    uint32_t flags;
+    int xmode;
+};
 
+// every combination of high / very high with extra modes 1-6, cheapest first
+constexpr auto kSearchModes = [] {
+    std::array<EncodeMode, 12> modes{};
+    std::size_t i = 0;
+    for (const uint32_t level : {static_cast<uint32_t>(CONFIG_HIGH_FLAG), static_cast<uint32_t>(CONFIG_VERY_HIGH_FLAG)}) {
+        for (int xmode = 1; xmode <= 6; ++xmode) modes[i++] = {level, xmode};
+    }
+    return modes;
+}();
+
+std::string describe(const EncodeMode& mode) {
+    return std::string(mode.flags & CONFIG_VERY_HIGH_FLAG ? "very high" : "high") + ", extra " + std::to_string(mode.xmode);
+}
+
+/**
+ * @brief Opens a WavPack file for decoding, together with its .wvc if there is one.
+ * @throws std::runtime_error if libwavpack cannot open the file.
+ */
+ContextPtr open_input(const fs::path& file, const int flags) {
     char error[128]{};
+    WavpackContext* ctx = WavpackOpenFileInput(file.string().c_str(), error, flags, 0);
+    if (!ctx) {
+        throw std::runtime_error("WavPack open failed: " + std::string(error));
+    }
+    return {ctx, &WavpackCloseFile};
+}
+
+std::uintmax_t unit_size(const fs::path& file, const bool hybrid) {
+    return fs::file_size(file) + (hybrid ? fs::file_size(fs::path(file.string() + "c")) : 0);
+}
 
-    // open input context
-    WavpackContext* ctx_in = WavpackOpenFileInput(input.string().c_str(), error, OPEN_TAGS, 0);
-    if (!ctx_in) {
-        Logger::log(LogLevel::Error, std::string("WavPack open failed: ") + error, "wavpack_processor");
-        throw std::runtime_error("WavPack open failed");
+/**
+ * @brief Copies the APEv2 tag items, text and binary, from one context to another.
+ */
+void copy_tags(WavpackContext* from, WavpackContext* to) {
+    const int num_tags = WavpackGetNumTagItems(from);
+    for (int i = 0; i < num_tags; ++i) {
+        char tag_name[256];
+        if (WavpackGetTagItemIndexed(from, i, tag_name, sizeof(tag_name))) {
+            int size = WavpackGetTagItem(from, tag_name, nullptr, 0);
+            if (size > 0) {
+                std::vector<char> value(static_cast<size_t>(size) + 1);
+                if (WavpackGetTagItem(from, tag_name, value.data(), size + 1) > 0) {
+                    if (!WavpackAppendTagItem(to, tag_name, value.data(), size)) {
+                        Logger::log(LogLevel::Warning,
+                                    std::string("Failed to append tag: ") + tag_name,
+                                    "wavpack_processor");
+                    }
+                }
+            }
+        }
     }
 
-    // prepare output
-    FILE* out = chisel::open_file(output.string().c_str(), "wb");
+    // cover art and other binary items are not listed by WavpackGetTagItemIndexed
+    const int num_binary_tags = WavpackGetNumBinaryTagItems(from);
+    for (int i = 0; i < num_binary_tags; ++i) {
+        char tag_name[256];
+        if (WavpackGetBinaryTagItemIndexed(from, i, tag_name, sizeof(tag_name))) {
+            const int size = WavpackGetBinaryTagItem(from, tag_name, nullptr, 0);
+            if (size > 0) {
+                std::vector<char> value(static_cast<size_t>(size));
+                if (WavpackGetBinaryTagItem(from, tag_name, value.data(), size) > 0 &&
+                    !WavpackAppendBinaryTagItem(to, tag_name, value.data(), size)) {
+                    Logger::log(LogLevel::Warning,
+                                std::string("Failed to append binary tag: ") + tag_name,
+                                "wavpack_processor");
+                }
+            }
+        }
+    }
+}
+
+/**
+ * @brief Decodes a WavPack file (and its .wvc) and encodes it again with the given mode.
+ *
+ * The audio parameters, the RIFF header and trailer, and the stored MD5 are
+ * carried over. A hybrid source is encoded as a hybrid pair at its original
+ * bitrate, with the correction file written to output + "c".
+ *
+ * @return Size of the output, plus its .wvc for hybrid pairs.
+ * @throws std::runtime_error if libwavpack fails or an output cannot be written.
+ */
+std::uintmax_t encode(const fs::path& input,
+                      const fs::path& output,
+                      const EncodeMode& mode,
+                      const bool preserve_metadata) {
+    ContextPtr ctx_in = open_input(input, OPEN_WVC | OPEN_TAGS | OPEN_WRAPPER);
+    const int source_mode = WavpackGetMode(ctx_in.get());
+    const bool hybrid = (source_mode & MODE_HYBRID) != 0;
+
+    FilePtr out(chisel::open_file(output, "wb"), &std::fclose);
     if (!out) {
-        WavpackCloseFile(ctx_in);
         throw std::runtime_error("Cannot open output file");
     }
+    FilePtr out_wvc(nullptr, &std::fclose);
+    if (hybrid) {
+        out_wvc.reset(chisel::open_file(fs::path(output.string() + "c"), "wb"));
+        if (!out_wvc) {
+            throw std::runtime_error("Cannot open output correction file");
+        }
+    }
 
-    // open output context
-    WavpackContext* ctx_out = WavpackOpenFileOutput(
+    ContextPtr ctx_out(WavpackOpenFileOutput(
         [](void* id, void* data, int32_t bcount) -> int32_t {
-            return static_cast<int32_t>(std::fwrite(data, 1, static_cast<size_t>(bcount), static_cast<FILE*>(id)));
+            return std::fwrite(data, 1, static_cast<size_t>(bcount), static_cast<FILE*>(id)) == static_cast<size_t>(bcount);
         },
-        out,
-        error
-    );
-
+        out.get(),
+        out_wvc.get()
+    ), &WavpackCloseFile);
     if (!ctx_out) {
-        std::fclose(out);
-        WavpackCloseFile(ctx_in);
-        throw std::runtime_error(std::string("WavPack output open failed: ") + error);
+        throw std::runtime_error("WavPack output open failed");
     }
 
-    // configure encoder
     WavpackConfig config{};
-    config.bytes_per_sample = WavpackGetBytesPerSample(ctx_in);
-    config.bits_per_sample  = WavpackGetBitsPerSample(ctx_in);
-    config.num_channels     = WavpackGetNumChannels(ctx_in);
-    config.sample_rate      = static_cast<int32_t>(WavpackGetSampleRate(ctx_in));
-    config.qmode            = 0;
+    config.bytes_per_sample = WavpackGetBytesPerSample(ctx_in.get());
+    config.bits_per_sample  = WavpackGetBitsPerSample(ctx_in.get());
+    config.num_channels     = WavpackGetNumChannels(ctx_in.get());
+    config.channel_mask     = WavpackGetChannelMask(ctx_in.get());
+    config.sample_rate      = static_cast<int32_t>(WavpackGetSampleRate(ctx_in.get()));
+    config.qmode            = WavpackGetQualifyMode(ctx_in.get());
     config.block_samples    = 0;
-    config.flags            = CONFIG_VERY_HIGH_FLAG | CONFIG_EXTRA_MODE;
-    config.xmode            = 6;
-    config.flags &= ~CONFIG_HYBRID_FLAG;             // force lossless
+    config.flags            = mode.flags | CONFIG_EXTRA_MODE;
+    config.xmode            = mode.xmode;
+    if (source_mode & MODE_FLOAT) {
+        config.float_norm_exp = WavpackGetFloatNormExp(ctx_in.get());
+    }
+    if (hybrid) {
+        // the lossy part keeps its bitrate, the pair still decodes to the original samples
+        config.flags |= CONFIG_HYBRID_FLAG | CONFIG_CREATE_WVC | CONFIG_BITRATE_KBPS;
+        config.bitrate = static_cast<float>(WavpackGetAverageBitrate(ctx_in.get(), 0) / 1000.0);
+    }
+    unsigned char md5[16];
+    const bool has_md5 = WavpackGetMD5Sum(ctx_in.get(), md5) != 0;
+    if (has_md5) {
+        config.flags |= CONFIG_MD5_CHECKSUM;
+    }
+
+    if (!WavpackSetConfiguration64(ctx_out.get(), &config, WavpackGetNumSamples64(ctx_in.get()), nullptr)) {
+        throw std::runtime_error(std::string("Failed to set WavPack configuration: ") + WavpackGetErrorMessage(ctx_out.get()));
+    }
 
-    if (!WavpackSetConfiguration(ctx_out, &config, -1)) {
-        WavpackCloseFile(ctx_out);
-        std::fclose(out);
-        WavpackCloseFile(ctx_in);
-        throw std::runtime_error("Failed to set WavPack configuration");
+    // RIFF header (and any chunk before the audio) of the original file
+    if (WavpackGetWrapperBytes(ctx_in.get())) {
+        WavpackAddWrapper(ctx_out.get(), WavpackGetWrapperData(ctx_in.get()), WavpackGetWrapperBytes(ctx_in.get()));
+        WavpackFreeWrapper(ctx_in.get());
     }
 
-    if (!WavpackPackInit(ctx_out)) {
-        WavpackCloseFile(ctx_out);
-        std::fclose(out);
-        WavpackCloseFile(ctx_in);
+    if (!WavpackPackInit(ctx_out.get())) {
         throw std::runtime_error("WavpackPackInit failed");
     }
 
     const int32_t num_channels = config.num_channels > 0 ? config.num_channels : 1;
-    constexpr int32_t block_size = 65536;
-    std::vector<int32_t> buffer(static_cast<size_t>(block_size) * static_cast<size_t>(num_channels));
+    std::vector<int32_t> buffer(static_cast<size_t>(kBlockSamples) * static_cast<size_t>(num_channels));
 
     uint32_t samples = 0;
-    while ((samples = WavpackUnpackSamples(ctx_in, buffer.data(), block_size)) > 0) {
-        if (!WavpackPackSamples(ctx_out, buffer.data(), static_cast<int32_t>(samples))) {
-            WavpackCloseFile(ctx_out);
-            std::fclose(out);
-            WavpackCloseFile(ctx_in);
+    while ((samples = WavpackUnpackSamples(ctx_in.get(), buffer.data(), kBlockSamples)) > 0) {
+        if (!WavpackPackSamples(ctx_out.get(), buffer.data(), samples)) {
             throw std::runtime_error("Error packing samples");
         }
     }
+    if (WavpackGetNumErrors(ctx_in.get()) > 0) {
+        throw std::runtime_error("Source has CRC errors");
+    }
+
+    if (!WavpackFlushSamples(ctx_out.get())) {
+        throw std::runtime_error("Error flushing samples");
+    }
 
-    if (!WavpackFlushSamples(ctx_out)) {
-        WavpackCloseFile(ctx_out);
-        std::fclose(out);
-        WavpackCloseFile(ctx_in);
+    // chunks after the audio, then the checksum, go in a last metadata-only block
+    WavpackSeekTrailingWrapper(ctx_in.get());
+    if (WavpackGetWrapperBytes(ctx_in.get())) {
+        WavpackAddWrapper(ctx_out.get(), WavpackGetWrapperData(ctx_in.get()), WavpackGetWrapperBytes(ctx_in.get()));
+        WavpackFreeWrapper(ctx_in.get());
+    }
+    if (has_md5) {
+        WavpackStoreMD5Sum(ctx_out.get(), md5);
+    }
+    if (!WavpackFlushSamples(ctx_out.get())) {
         throw std::runtime_error("Error flushing samples");
     }
 
-    // copy metadata
     if (preserve_metadata) {
-        const int num_tags = WavpackGetNumTagItems(ctx_in);
-        for (int i = 0; i < num_tags; ++i) {
-            char tag_name[256];
-            if (WavpackGetTagItemIndexed(ctx_in, i, tag_name, sizeof(tag_name))) {
-                int size = WavpackGetTagItem(ctx_in, tag_name, nullptr, 0);
-                if (size > 0) {
-                    std::vector<char> value(static_cast<size_t>(size) + 1);
-                    if (WavpackGetTagItem(ctx_in, tag_name, value.data(), size + 1) > 0) {
-                        if (!WavpackAppendTagItem(ctx_out, tag_name, value.data(), size)) {
-                            Logger::log(LogLevel::Warning,
-                                        std::string("Failed to append tag: ") + tag_name,
-                                        "wavpack_processor");
-                        }
-                    }
-                }
-            }
-        }
-        if (!WavpackWriteTag(ctx_out)) {
+        copy_tags(ctx_in.get(), ctx_out.get());
+        if (!WavpackWriteTag(ctx_out.get())) {
             Logger::log(LogLevel::Warning, "Failed to write tags", "wavpack_processor");
         }
     }
 
-    WavpackCloseFile(ctx_out);
-    std::fclose(out);
-    WavpackCloseFile(ctx_in);
-
-    Logger::log(LogLevel::Info, "WavPack recompression completed: " + output.string(), "wavpack_processor");
-}
-
-std::string WavPackProcessor::get_raw_checksum(const std::filesystem::path&) const {
-    // TODO: implement checksum of raw WavPack data
-    return "";
+    ctx_out.reset();
+    const bool closed = std::fclose(out.release()) == 0;
+    const bool closed_wvc = !out_wvc || std::fclose(out_wvc.release()) == 0;
+    if (!closed || !closed_wvc) {
+        throw std::runtime_error("Write failed for " + output.string());
+    }
+    return unit_size(output, hybrid);
 }
 
 /**
- * @brief Decodes a WavPack file into a raw PCM audio buffer.
- * @param file The path to the WavPack file.
- * @param sample_rate Output parameter for the sample rate.
- * @param channels Output parameter for the number of channels.
- * @param bps Output parameter for the bits per sample.
- * @return A vector of 32-bit integers representing the decoded PCM data.
+ * @brief Appends decoded samples to the MD5 in the layout WavPack hashes them.
+ *
+ * That is the layout of the original file: bytes_per_sample bytes per sample,
+ * little-endian unless the source was big-endian, 8-bit audio unsigned unless
+ * the source used signed bytes.
  */
-std::vector<int32_t> decode_wavpack_pcm(const std::filesystem::path& file,
-                                        int& sample_rate,
-                                        int& channels,
-                                        int& bps) {
-    char error[128]{};
-    WavpackContext* ctx = WavpackOpenFileInput(file.string().c_str(), error, OPEN_TAGS, 0);
-    if (!ctx) {
-        throw std::runtime_error("WavPack open failed: " + std::string(error));
+void hash_samples(Md5& md5, const int32_t* samples, const std::size_t count, const int bytes_per_sample, const int qmode) {
+    std::vector<std::uint8_t> bytes(count * static_cast<std::size_t>(bytes_per_sample));
+    std::uint8_t* dst = bytes.data();
+    for (std::size_t i = 0; i < count; ++i) {
+        auto value = static_cast<uint32_t>(samples[i]);
+        if (bytes_per_sample == 1 && !(qmode & QMODE_SIGNED_BYTES)) value += 0x80;
+        if (bytes_per_sample > 1 && (qmode & QMODE_UNSIGNED_WORDS)) value ^= 1u << (bytes_per_sample * 8 - 1);
+        for (int b = 0; b < bytes_per_sample; ++b) {
+            const int shift = (qmode & QMODE_BIG_ENDIAN) ? (bytes_per_sample - 1 - b) * 8 : b * 8;
+            *dst++ = static_cast<std::uint8_t>(value >> shift);
+        }
+    }
+    md5.update(bytes);
+}
+
+std::string to_hex(const std::span<const std::uint8_t> digest) {
+    std::ostringstream oss;
+    for (const std::uint8_t byte : digest) {
+        oss << std::hex << std::setw(2) << std::setfill('0') << static_cast<int>(byte);
     }
+    return oss.str();
+}
 
-    sample_rate = static_cast<int>(WavpackGetSampleRate(ctx));
-    channels    = WavpackGetNumChannels(ctx);
-    bps         = WavpackGetBitsPerSample(ctx);
+} // namespace
 
-    if (channels <= 0 || sample_rate <= 0 || bps <= 0) {
-        WavpackCloseFile(ctx);
-        throw std::runtime_error("Invalid WavPack parameters");
+void WavPackProcessor::recompress(const std::filesystem::path& input,
+                                  const std::filesystem::path& output,
+                                  bool preserve_metadata) {
+    Logger::log(LogLevel::Info, "Starting WavPack recompression: " + input.string(), "wavpack_processor");
+
+    auto keep_as_is = [&](const std::string& reason) {
+        Logger::log(LogLevel::Debug, "WavPack: Left as-is: " + reason, "wavpack_processor");
+        fs::copy_file(input, output, fs::copy_options::overwrite_existing);
+    };
+
+    std::string extension = input.extension().string();
+    std::ranges::transform(extension, extension.begin(), [](unsigned char c) { return std::tolower(c); });
+    if (extension == ".wvc") {
+        keep_as_is("correction files are re-encoded together with their .wv");
+        return;
     }
 
-    const int32_t num_channels = channels;
-    constexpr int32_t block_size = 65536;
-    std::vector<int32_t> buffer(static_cast<size_t>(block_size) * static_cast<size_t>(num_channels));
-    std::vector<int32_t> pcm;
+    bool hybrid = false;
+    {
+        const ContextPtr probe = open_input(input, OPEN_WVC | OPEN_DSD_NATIVE);
+        const int mode = WavpackGetMode(probe.get());
+        if (WavpackGetQualifyMode(probe.get()) & QMODE_DSD_AUDIO) {
+            keep_as_is("DSD audio");
+            return;
+        }
+        if ((mode & MODE_HYBRID) && !(mode & MODE_WVC)) {
+            // re-encoding the lossy part alone would lose it a second time
+            keep_as_is("hybrid file without its .wvc correction file");
+            return;
+        }
+        hybrid = (mode & MODE_HYBRID) != 0;
+    }
 
-    uint32_t samples = 0;
-    while ((samples = WavpackUnpackSamples(ctx, buffer.data(), block_size)) > 0) {
-        pcm.insert(pcm.end(), buffer.begin(), buffer.begin() + samples * num_channels);
+    // candidates of a hybrid pair come with their .wvc
+    BestCandidate best(output, ".wv", get_companion_suffixes());
+    std::string chosen;
+    for (const EncodeMode& mode : kSearchModes) {
+        if (stop_requested()) break;
+        const fs::path candidate = best.next_path();
+        std::uintmax_t size = 0;
+        try {
+            size = encode(input, candidate, mode, preserve_metadata);
+        } catch (const std::exception&) {
+            best.discard(candidate);
+            throw;
+        }
+        Logger::log(LogLevel::Debug, "WavPack: " + describe(mode) + ": " + std::to_string(size) + " bytes", "wavpack_processor");
+        if (best.offer(candidate)) chosen = describe(mode);
+    }
+    if (best.empty()) {
+        throw std::runtime_error("WavPack recompression interrupted");
     }
+    best.commit();
 
-    WavpackCloseFile(ctx);
-    return pcm;
+    Logger::log(LogLevel::Debug, "WavPack: Best mode: " + chosen + (hybrid ? " (hybrid pair)" : ""), "wavpack_processor");
+    Logger::log(LogLevel::Info, "WavPack recompression completed: " + output.string(), "wavpack_processor");
 }
 
+std::string WavPackProcessor::get_raw_checksum(const std::filesystem::path& file_path) const {
+    const ContextPtr ctx = open_input(file_path, OPEN_WVC);
+    const int channels = WavpackGetNumChannels(ctx.get());
+    const int bytes_per_sample = WavpackGetBytesPerSample(ctx.get());
+    const int qmode = WavpackGetQualifyMode(ctx.get());
+
+    Md5 md5;
+    std::vector<int32_t> buffer(static_cast<size_t>(kBlockSamples) * static_cast<size_t>(channels));
+    uint32_t samples = 0;
+    while ((samples = WavpackUnpackSamples(ctx.get(), buffer.data(), kBlockSamples)) > 0) {
+        hash_samples(md5, buffer.data(), static_cast<std::size_t>(samples) * channels, bytes_per_sample, qmode);
+    }
+    return to_hex(md5.finish());
+}
 
 bool WavPackProcessor::raw_equal(const std::filesystem::path& a,
                                  const std::filesystem::path& b) const {
-    int ra, ca, bpsa;
-    int rb, cb, bpsb;
-    const auto pcmA = decode_wavpack_pcm(a, ra, ca, bpsa);
-    const auto pcmB = decode_wavpack_pcm(b, rb, cb, bpsb);
+    ContextPtr ctx_a(nullptr, &WavpackCloseFile);
+    ContextPtr ctx_b(nullptr, &WavpackCloseFile);
+    try {
+        ctx_a = open_input(a, OPEN_WVC);
+        ctx_b = open_input(b, OPEN_WVC);
+    } catch (const std::exception& e) {
+        // e.g. a lone .wvc or DSD audio, copied unchanged
+        Logger::log(LogLevel::Debug, std::string("WavPack: PCM comparison unavailable: ") + e.what(), "wavpack_processor");
+        return files_equal(a, b);
+    }
 
-    if (ra != rb || ca != cb || bpsa != bpsb) return false;
-    return pcmA == pcmB;
+    const int channels = WavpackGetNumChannels(ctx_a.get());
+    const int bytes_per_sample = WavpackGetBytesPerSample(ctx_a.get());
+    if (channels <= 0 ||
+        channels != WavpackGetNumChannels(ctx_b.get()) ||
+        WavpackGetSampleRate(ctx_a.get()) != WavpackGetSampleRate(ctx_b.get()) ||
+        WavpackGetBitsPerSample(ctx_a.get()) != WavpackGetBitsPerSample(ctx_b.get()) ||
+        bytes_per_sample != WavpackGetBytesPerSample(ctx_b.get()) ||
+        WavpackGetChannelMask(ctx_a.get()) != WavpackGetChannelMask(ctx_b.get()) ||
+        (WavpackGetMode(ctx_a.get()) & MODE_FLOAT) != (WavpackGetMode(ctx_b.get()) & MODE_FLOAT) ||
+        WavpackGetNumSamples64(ctx_a.get()) != WavpackGetNumSamples64(ctx_b.get())) {
+        return false;
+    }
+
+    unsigned char stored_md5[16];
+    const bool check_md5 = WavpackGetMD5Sum(ctx_b.get(), stored_md5) != 0;
+    const int qmode = WavpackGetQualifyMode(ctx_b.get());
+    Md5 md5;
+
+    std::vector<int32_t> buffer_a(static_cast<size_t>(kBlockSamples) * static_cast<size_t>(channels));
+    std::vector<int32_t> buffer_b(buffer_a.size());
+    while (true) {
+        const uint32_t samples_a = WavpackUnpackSamples(ctx_a.get(), buffer_a.data(), kBlockSamples);
+        const uint32_t samples_b = WavpackUnpackSamples(ctx_b.get(), buffer_b.data(), kBlockSamples);
+        if (samples_a != samples_b) return false;
+        if (samples_a == 0) break;
+        const std::size_t count = static_cast<std::size_t>(samples_a) * channels;
+        if (!std::equal(buffer_a.begin(), buffer_a.begin() + count, buffer_b.begin())) return false;
+        if (check_md5) hash_samples(md5, buffer_b.data(), count, bytes_per_sample, qmode);
+    }
+    if (WavpackGetNumErrors(ctx_a.get()) > 0 || WavpackGetNumErrors(ctx_b.get()) > 0) return false;
+
+    if (check_md5 && std::memcmp(md5.finish().data(), stored_md5, sizeof(stored_md5)) != 0) {
+        Logger::log(LogLevel::Warning, "WavPack: Stored MD5 does not match the audio of " + b.string(), "wavpack_processor");
+        return false;
+    }
+    return true;
 }
 
-} // namespace chisel
\ No newline at end of file
+} // namespace chisel
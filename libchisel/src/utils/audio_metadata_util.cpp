@@ -3,9 +3,12 @@
 //
 
 #include "audio_metadata_util.hpp"
+#include <algorithm>
+#include <cstring>
 #include <fstream>
 #include <iterator>
 #include <setjmp.h>
+#include <stdexcept>
 #include <vector>
 #include <taglib/fileref.h>
 #include "flac/flacfile.h"
@@ -27,6 +30,8 @@
 #include "ape/apefile.h"
 #include "ape/apeitem.h"
 #include "ape/apetag.h"
+#include "mpeg/id3v1/id3v1tag.h"
+#include "trueaudio/trueaudiofile.h"
 #include "wav/wavfile.h"
 
 namespace chisel {
@@ -228,7 +233,7 @@ int getPictureTypeFromApeKey(const TagLib::String &key) {
     return 3; // Default Front
 }
 
-// shared helper to extract cover from ID3v2 tag (MP3, WAV, AIFF)
+// shared helper to extract cover from ID3v2 tag (MP3, WAV, AIFF, TTA)
 void extractId3v2Covers(TagLib::ID3v2::Tag* tag,
                         const std::filesystem::path& temp_dir,
                         std::vector<AudioCoverInfo>& extracted_covers) {
@@ -337,6 +342,12 @@ AudioExtractionState AudioMetadataUtil::extractCovers(const std::filesystem::pat
         return state;
     }
 
+    // tta (id3v2 apic)
+    if (auto *ttaFile = dynamic_cast<TagLib::TrueAudio::File*>(file_ref)) {
+        extractId3v2Covers(ttaFile->ID3v2Tag(), temp_dir, state.extracted_covers);
+        return state;
+    }
+
     // aiff (id3v2 apic)
     if (auto *aiffFile = dynamic_cast<TagLib::RIFF::AIFF::File*>(file_ref)) {
         extractId3v2Covers(aiffFile->tag(), temp_dir, state.extracted_covers);
@@ -539,6 +550,14 @@ bool AudioMetadataUtil::rebuildCovers(const std::filesystem::path &input_path,
         return false;
     }
 
+    // tta
+    if (auto *ttaFile = dynamic_cast<TagLib::TrueAudio::File*>(file_ref)) {
+        if (rebuildId3v2Covers(ttaFile->ID3v2Tag(true), state.extracted_covers)) {
+            return ttaFile->save();
+        }
+        return false;
+    }
+
     // aiff
     if (auto *aiffFile = dynamic_cast<TagLib::RIFF::AIFF::File*>(file_ref)) {
         if (rebuildId3v2Covers(aiffFile->tag(), state.extracted_covers)) {
@@ -661,4 +680,131 @@ bool AudioMetadataUtil::rebuildCovers(const std::filesystem::path &input_path,
     return false;
 }
 
-} // namespace chisel
\ No newline at end of file
+//
+// tag compaction
+//
+
+bool AudioMetadataUtil::copyApeTag(const std::filesystem::path &source_path,
+                                   const std::filesystem::path &target_path) {
+#ifdef _WIN32
+    TagLib::APE::File source(source_path.wstring().c_str(), false);
+    TagLib::APE::File target(target_path.wstring().c_str(), false);
+#else
+    TagLib::APE::File source(source_path.string().c_str(), false);
+    TagLib::APE::File target(target_path.string().c_str(), false);
+#endif
+    if (!source.isValid() || !target.isValid()) {
+        return false;
+    }
+
+    bool changed = false;
+    if (auto *tag = source.APETag()) {
+        auto *out = target.APETag(true);
+        for (const auto &[key, item] : tag->itemListMap()) {
+            out->setItem(key, item);
+        }
+        changed = true;
+    }
+    if (auto *tag = source.ID3v1Tag()) {
+        TagLib::Tag::duplicate(tag, target.ID3v1Tag(true), true);
+        changed = true;
+    }
+    return !changed || target.save();
+}
+
+AudioTagBlocks AudioMetadataUtil::readTagBlocks(const std::filesystem::path &path) {
+    std::ifstream in(path, std::ios::binary);
+    if (!in) {
+        throw std::runtime_error("Cannot open " + path.string());
+    }
+    auto read_at = [&](const std::uint64_t offset, const std::size_t size) {
+        std::vector<unsigned char> bytes(size);
+        in.seekg(static_cast<std::streamoff>(offset));
+        in.read(reinterpret_cast<char *>(bytes.data()), static_cast<std::streamsize>(size));
+        if (!in) {
+            throw std::runtime_error("Read failed: " + path.string());
+        }
+        return bytes;
+    };
+
+    AudioTagBlocks blocks;
+    std::uint64_t begin = 0;
+    std::uint64_t end = std::filesystem::file_size(path);
+
+    // ID3v2: 10-byte header with a syncsafe size, plus a 10-byte footer if flagged
+    if (end >= 10) {
+        const auto header = read_at(0, 10);
+        if (std::memcmp(header.data(), "ID3", 3) == 0 && header[3] != 0xFF &&
+            ((header[6] | header[7] | header[8] | header[9]) & 0x80) == 0) {
+            const std::uint64_t size = 10 +
+                                       (static_cast<std::uint64_t>(header[6]) << 21 |
+                                        static_cast<std::uint64_t>(header[7]) << 14 |
+                                        static_cast<std::uint64_t>(header[8]) << 7 |
+                                        header[9]) +
+                                       ((header[5] & 0x10) ? 10 : 0);
+            if (size <= end) {
+                blocks.id3v2 = read_at(0, size);
+                begin = size;
+            }
+        }
+    }
+
+    // ID3v1: the last 128 bytes, starting with "TAG"
+    if (end - begin >= 128) {
+        auto tag = read_at(end - 128, 128);
+        if (std::memcmp(tag.data(), "TAG", 3) == 0) {
+            blocks.id3v1 = std::move(tag);
+            end -= 128;
+        }
+    }
+
+    // APEv2: 32-byte "APETAGEX" footer; its size covers items and footer, not the optional header
+    if (end - begin >= 32) {
+        const auto footer = read_at(end - 32, 32);
+        if (std::memcmp(footer.data(), "APETAGEX", 8) == 0) {
+            const std::uint64_t size = (static_cast<std::uint64_t>(footer[12]) |
+                                        static_cast<std::uint64_t>(footer[13]) << 8 |
+                                        static_cast<std::uint64_t>(footer[14]) << 16 |
+                                        static_cast<std::uint64_t>(footer[15]) << 24) +
+                                       ((footer[23] & 0x80) ? 32 : 0);
+            if (size >= 32 && size <= end - begin) {
+                blocks.apev2 = read_at(end - size, static_cast<std::size_t>(size));
+                end -= size;
+            }
+        }
+    }
+
+    blocks.audio_offset = begin;
+    blocks.audio_size = end - begin;
+    return blocks;
+}
+
+void AudioMetadataUtil::compactId3v2(std::vector<unsigned char>& tag) {
+    if (tag.size() < 10 || std::memcmp(tag.data(), "ID3", 3) != 0) return;
+    const unsigned char version = tag[3];
+    // unsynchronisation, extended header (its CRC covers the padding) and footer
+    if ((version != 3 && version != 4) || (tag[5] & 0xD0) != 0) return;
+
+    auto synchsafe_at = [&tag](std::size_t pos) -> std::size_t {
+        return (tag[pos] & 0x7Fu) << 21 | (tag[pos + 1] & 0x7Fu) << 14 | (tag[pos + 2] & 0x7Fu) << 7 | (tag[pos + 3] & 0x7Fu);
+    };
+    auto be32_at = [&tag](std::size_t pos) -> std::size_t {
+        return std::size_t{tag[pos]} << 24 | std::size_t{tag[pos + 1]} << 16 | std::size_t{tag[pos + 2]} << 8 | tag[pos + 3];
+    };
+
+    const std::size_t end = std::min(tag.size(), 10 + synchsafe_at(6));
+    std::size_t pos = 10;
+    while (pos + 10 <= end && tag[pos] != 0) {
+        const std::size_t size = version == 4 ? synchsafe_at(pos + 4) : be32_at(pos + 4);
+        if (size > end - pos - 10) return; // malformed, keep as is
+        pos += 10 + size;
+    }
+
+    const std::size_t tag_size = pos - 10;
+    tag.resize(pos);
+    for (int i = 0; i < 4; ++i) {
+        tag[6 + i] = static_cast<unsigned char>((tag_size >> (7 * (3 - i))) & 0x7F);
+    }
+}
+
+} // namespace chisel
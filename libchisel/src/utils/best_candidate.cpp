@@ -0,0 +1,90 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/best_candidate.hpp"
+#include "../../include/random_utils.hpp"
+#include <stdexcept>
+#include <system_error>
+#include <utility>
+
+namespace fs = std::filesystem;
+
+namespace chisel {
+
+    namespace {
+        fs::path companion_of(const fs::path& file, const std::string_view suffix) {
+            fs::path companion = file;
+            companion += suffix;
+            return companion;
+        }
+
+        // rename, or copy when the destination is on another device
+        void move_file(const fs::path& from, const fs::path& to) {
+            std::error_code ec;
+            fs::rename(from, to, ec);
+            if (ec) {
+                fs::copy_file(from, to, fs::copy_options::overwrite_existing);
+                fs::remove(from, ec);
+            }
+        }
+    }
+
+    BestCandidate::BestCandidate(fs::path output, std::string extension,
+                                 const std::span<const std::string_view> companions)
+        : output_(std::move(output)), extension_(std::move(extension)), companions_(companions) {
+    }
+
+    BestCandidate::~BestCandidate() {
+        if (!best_.empty()) discard(best_);
+    }
+
+    fs::path BestCandidate::next_path() const {
+        return output_.parent_path() / (output_.stem().string() + "_cand" + RandomUtils::random_suffix() + extension_);
+    }
+
+    bool BestCandidate::offer(const fs::path& candidate) {
+        std::error_code ec;
+        std::uintmax_t size = fs::file_size(candidate, ec);
+        if (ec || size == 0) {
+            discard(candidate);
+            return false;
+        }
+        for (const auto suffix : companions_) {
+            const auto companion_size = fs::file_size(companion_of(candidate, suffix), ec);
+            if (!ec) size += companion_size;
+        }
+
+        if (!best_.empty() && size >= best_size_) {
+            discard(candidate);
+            return false;
+        }
+        if (!best_.empty()) discard(best_);
+        best_ = candidate;
+        best_size_ = size;
+        return true;
+    }
+
+    void BestCandidate::discard(const fs::path& candidate) const {
+        std::error_code ec;
+        fs::remove(candidate, ec);
+        for (const auto suffix : companions_) {
+            fs::remove(companion_of(candidate, suffix), ec);
+        }
+    }
+
+    void BestCandidate::commit() {
+        if (best_.empty()) {
+            throw std::runtime_error("BestCandidate: no candidate to commit");
+        }
+        move_file(best_, output_);
+        for (const auto suffix : companions_) {
+            std::error_code ec;
+            if (fs::exists(companion_of(best_, suffix), ec)) {
+                move_file(companion_of(best_, suffix), companion_of(output_, suffix));
+            }
+        }
+        best_.clear();
+    }
+
+} // namespace chisel
@@ -10,6 +10,13 @@
 #include "../include/chisel.hpp"
 
 #include "../include/processor_registry.hpp"
+#include "../include/archive_processor.hpp"
+#include "../include/compressed_stream_processor.hpp"
+#include "../include/flac_processor.hpp"
+#include "../include/odf_processor.hpp"
+#include "../include/ooxml_processor.hpp"
+#include "../include/lepton_processor.hpp"
+#include "../include/precomp_processor.hpp"
 #include "../include/processor_executor.hpp"
 #include "../include/event_bus.hpp"
 #include "../include/logger.hpp"
@@ -18,6 +25,7 @@
 #include <mutex>
 #include <thread>
 #include <algorithm>
+#include <limits>
 
 #include "events.hpp"
 #include "file_type.hpp"
@@ -47,9 +55,17 @@ struct Chisel::Impl {
     unsigned numThreads = std::thread::hardware_concurrency() / 2;
     EncodeMode encodeMode = EncodeMode::PIPE;
     std::filesystem::path outputDir;
+    bool leptonArchival = false;
+    bool precompArchival = false;
+    unsigned flacEffort = 0;
+    unsigned flacPadding = 0;
+    unsigned zipIterations = 15;
 
     ChiselObserver* observer = nullptr;
-    std::atomic<ProcessorExecutor*> currentExecutor = nullptr;
+    // guards currentExecutor, so stop() never reaches a destroyed executor
+    std::mutex executorMtx;
+    ProcessorExecutor* currentExecutor = nullptr;
+    bool eventsBridged = false;
 
     Impl() {
         if (numThreads == 0) numThreads = 1;
@@ -64,28 +80,70 @@ struct Chisel::Impl {
         }
     }
 
+    void applyFlacSettings() {
+        const auto effort = static_cast<FlacEffort>(std::min(flacEffort, 2u));
+        for (const auto& processor : registry.all()) {
+            if (auto* flac = dynamic_cast<FlacProcessor*>(processor.get())) {
+                flac->set_effort(effort);
+                FlacMetadataPolicy policy = flac->metadata_policy();
+                policy.padding = flacPadding;
+                flac->set_metadata_policy(policy);
+            }
+        }
+    }
+
+    void applyZipSettings() {
+        auto apply = [this](auto* processor) {
+            if (!processor) return;
+            ZipWriterOptions options = processor->zip_options();
+            options.iterations = static_cast<int>(std::clamp(zipIterations, 1u, static_cast<unsigned>(std::numeric_limits<int>::max())));
+            processor->set_zip_options(options);
+        };
+        for (const auto& processor : registry.all()) {
+            apply(dynamic_cast<ArchiveProcessor*>(processor.get()));
+            apply(dynamic_cast<OOXMLProcessor*>(processor.get()));
+            apply(dynamic_cast<OdfProcessor*>(processor.get()));
+            apply(dynamic_cast<CompressedStreamProcessor*>(processor.get()));
+        }
+    }
+
+    // opt-in processors cannot be removed, so the registry is rebuilt when they change
+    void rebuildRegistry() {
+        registry = ProcessorRegistry();
+        if (leptonArchival) {
+            registry.register_processor(std::make_unique<LeptonProcessor>());
+        }
+        if (precompArchival) {
+            registry.register_processor(std::make_unique<PrecompProcessor>());
+        }
+        applyFlacSettings();
+        applyZipSettings();
+    }
+
+    // subscribes once; the observer is looked up on every event so it can be swapped between runs
     void setupEventBridging() {
-        if (!observer) return;
+        if (eventsBridged) return;
+        eventsBridged = true;
 
         eventBus.subscribe<FileProcessStartEvent>([this](const FileProcessStartEvent& e) {
-            observer->onFileStart(e.path);
+            if (observer) observer->onFileStart(e.path);
         });
 
         eventBus.subscribe<FileProcessCompleteEvent>([this](const FileProcessCompleteEvent& e) {
-            observer->onFileFinish(e.path, e.original_size, e.new_size, e.replaced);
+            if (observer) observer->onFileFinish(e.path, e.original_size, e.new_size, e.replaced);
         });
 
         eventBus.subscribe<FileProcessErrorEvent>([this](const FileProcessErrorEvent& e) {
-            observer->onFileError(e.path, e.error_message);
+            if (observer) observer->onFileError(e.path, e.error_message);
         });
 
         eventBus.subscribe<FileProcessSkippedEvent>([this](const FileProcessSkippedEvent& e) {
             // skipped implies success but no replacement
-            observer->onFileFinish(e.path, 0, 0, false);
+            if (observer) observer->onFileFinish(e.path, 0, 0, false);
         });
 
         eventBus.subscribe<ContainerFinalizeErrorEvent>([this](const ContainerFinalizeErrorEvent& e) {
-            observer->onFileError(e.path, "Container finalize error: " + e.error_message);
+            if (observer) observer->onFileError(e.path, "Container finalize error: " + e.error_message);
         });
     }
 };
@@ -130,6 +188,48 @@ Chisel& Chisel::outputDirectory(const std::filesystem::path& dir) {
     return *this;
 }
 
+Chisel& Chisel::leptonArchival(bool val) {
+    if (val != impl_->leptonArchival) {
+        impl_->leptonArchival = val;
+        impl_->rebuildRegistry();
+    }
+    return *this;
+}
+
+Chisel& Chisel::precompArchival(bool val) {
+    if (val != impl_->precompArchival) {
+        impl_->precompArchival = val;
+        impl_->rebuildRegistry();
+    }
+    return *this;
+}
+
+Chisel& Chisel::flacEffort(unsigned level) {
+    impl_->flacEffort = level;
+    impl_->applyFlacSettings();
+    return *this;
+}
+
+Chisel& Chisel::flacPadding(unsigned bytes) {
+    impl_->flacPadding = std::min(bytes, FlacMetadataPolicy::max_padding);
+    impl_->applyFlacSettings();
+    return *this;
+}
+
+Chisel& Chisel::zipIterations(unsigned iterations) {
+    impl_->zipIterations = iterations;
+    impl_->applyZipSettings();
+    return *this;
+}
+
+void Chisel::restoreLepton(const std::filesystem::path& input, const std::filesystem::path& output) {
+    LeptonProcessor::restore(input, output);
+}
+
+void Chisel::restorePrecomp(const std::filesystem::path& input, const std::filesystem::path& output) {
+    PrecompProcessor::restore(input, output);
+}
+
 void Chisel::setObserver(ChiselObserver* observer) {
     impl_->observer = observer;
 }
@@ -137,33 +237,46 @@ void Chisel::setObserver(ChiselObserver* observer) {
 void Chisel::recompress(const std::vector<std::filesystem::path>& paths) {
     impl_->setupEventBridging();
 
-    // inject bridge sink if observer is present
+    // inject bridge sink if observer is present; it is removed again before returning,
+    // so the observer may be released once recompress() is done
+    const ILogSink* bridge_sink = nullptr;
     if (impl_->observer) {
         auto sink = std::make_unique<BridgeLogSink>(impl_->observer);
+        bridge_sink = sink.get();
         Logger::add_sink(std::move(sink));
     }
 
-    ProcessorExecutor executor(
-        impl_->registry,
-        impl_->preserveMetadata,
-        impl_->verifyChecksums,
-        static_cast<EncodeMode>(impl_->getInternalMode()),
-        impl_->dryRun,
-        impl_->outputDir,
-        impl_->eventBus,
-        impl_->numThreads
-    );
-
-    impl_->currentExecutor.store(&executor);
+    auto clear_executor = [this, bridge_sink] {
+        {
+            std::lock_guard lock(impl_->executorMtx);
+            impl_->currentExecutor = nullptr;
+        }
+        Logger::remove_sink(bridge_sink);
+    };
 
     try {
+        ProcessorExecutor executor(
+            impl_->registry,
+            impl_->preserveMetadata,
+            impl_->verifyChecksums,
+            static_cast<EncodeMode>(impl_->getInternalMode()),
+            impl_->dryRun,
+            impl_->outputDir,
+            impl_->eventBus,
+            impl_->numThreads
+        );
+
+        {
+            std::lock_guard lock(impl_->executorMtx);
+            impl_->currentExecutor = &executor;
+        }
+
         executor.process(paths);
+        clear_executor();
     } catch (...) {
-        impl_->currentExecutor.store(nullptr);
+        clear_executor();
         throw;
     }
-
-    impl_->currentExecutor.store(nullptr);
 }
 
 void Chisel::recompress(const std::filesystem::path& path) {
@@ -180,9 +293,10 @@ void Chisel::recompress(const std::vector<std::string>& paths) {
 }
 
 void Chisel::stop() {
-    auto* exec = impl_->currentExecutor.load();
-    if (exec) {
-        exec->request_stop();
+    if (!impl_) return; // moved-from
+    std::lock_guard lock(impl_->executorMtx);
+    if (impl_->currentExecutor) {
+        impl_->currentExecutor->request_stop();
     }
 }
 
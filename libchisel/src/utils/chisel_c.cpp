@@ -0,0 +1,165 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+/**
+ * @file chisel_c.cpp
+ * @brief Implementation of the C interface declared in chisel_c.h.
+ */
+
+#include "../../include/chisel_c.h"
+#include "../../include/chisel.hpp"
+
+#include <cstdlib>
+#include <cstring>
+#include <exception>
+#include <filesystem>
+#include <memory>
+#include <new>
+#include <string>
+#include <vector>
+
+namespace {
+
+    std::filesystem::path to_path(const char* utf8) {
+        return {reinterpret_cast<const char8_t*>(utf8)};
+    }
+
+    std::string to_utf8(const std::filesystem::path& path) {
+        const std::u8string u8 = path.u8string();
+        return {u8.begin(), u8.end()};
+    }
+
+    // forwards ChiselObserver calls to the C callbacks
+    class CallbackObserver final : public chisel::ChiselObserver {
+    public:
+        CallbackObserver(const ChiselObserverCallbacks& callbacks, void* user_data)
+            : callbacks_(callbacks), user_data_(user_data) {}
+
+        void onFileStart(const std::filesystem::path& path) override {
+            if (callbacks_.on_file_start) {
+                callbacks_.on_file_start(user_data_, to_utf8(path).c_str());
+            }
+        }
+
+        void onFileFinish(const std::filesystem::path& path,
+                          const uintmax_t size_before,
+                          const uintmax_t size_after,
+                          const bool replaced) override {
+            if (callbacks_.on_file_finish) {
+                callbacks_.on_file_finish(user_data_, to_utf8(path).c_str(),
+                                          size_before, size_after, replaced);
+            }
+        }
+
+        void onFileError(const std::filesystem::path& path, const std::string& error) override {
+            if (callbacks_.on_file_error) {
+                callbacks_.on_file_error(user_data_, to_utf8(path).c_str(), error.c_str());
+            }
+        }
+
+        void onLog(const int level, const std::string& msg, const std::string& tag) override {
+            if (callbacks_.on_log) {
+                callbacks_.on_log(user_data_, level, msg.c_str(), tag.c_str());
+            }
+        }
+
+    private:
+        ChiselObserverCallbacks callbacks_;
+        void* user_data_;
+    };
+
+    char* duplicate(const char* message) {
+        const size_t len = std::strlen(message);
+        auto* copy = static_cast<char*>(std::malloc(len + 1));
+        if (copy) {
+            std::memcpy(copy, message, len + 1);
+        }
+        return copy;
+    }
+
+} // namespace
+
+struct ChiselHandle {
+    chisel::Chisel chisel;
+    std::unique_ptr<CallbackObserver> observer;
+};
+
+extern "C" {
+
+ChiselHandle* chisel_new(void) {
+    try {
+        return new ChiselHandle();
+    } catch (...) {
+        return nullptr;
+    }
+}
+
+void chisel_delete(ChiselHandle* handle) {
+    delete handle;
+}
+
+void chisel_set_preserve_metadata(ChiselHandle* handle, const bool value) {
+    if (handle) handle->chisel.preserveMetadata(value);
+}
+
+void chisel_set_verify_checksums(ChiselHandle* handle, const bool value) {
+    if (handle) handle->chisel.verifyChecksums(value);
+}
+
+void chisel_set_dry_run(ChiselHandle* handle, const bool value) {
+    if (handle) handle->chisel.dryRun(value);
+}
+
+void chisel_set_threads(ChiselHandle* handle, const unsigned value) {
+    if (handle) handle->chisel.threads(value);
+}
+
+void chisel_set_mode(ChiselHandle* handle, const int mode) {
+    if (handle) {
+        handle->chisel.mode(mode == CHISEL_MODE_PARALLEL ? chisel::EncodeMode::PARALLEL
+                                                         : chisel::EncodeMode::PIPE);
+    }
+}
+
+void chisel_set_output_directory(ChiselHandle* handle, const char* dir) {
+    if (handle) handle->chisel.outputDirectory(dir ? to_path(dir) : std::filesystem::path());
+}
+
+void chisel_set_observer(ChiselHandle* handle, const ChiselObserverCallbacks* callbacks, void* user_data) {
+    if (!handle) return;
+    auto observer = callbacks ? std::make_unique<CallbackObserver>(*callbacks, user_data) : nullptr;
+    handle->chisel.setObserver(observer.get());
+    handle->observer = std::move(observer);
+}
+
+int chisel_recompress(ChiselHandle* handle, const char* const* paths, const size_t count, char** error_message) {
+    if (error_message) *error_message = nullptr;
+    if (!handle || (!paths && count > 0)) return CHISEL_ERR_NULL_ARGUMENT;
+
+    try {
+        std::vector<std::filesystem::path> inputs;
+        inputs.reserve(count);
+        for (size_t i = 0; i < count; ++i) {
+            if (!paths[i]) return CHISEL_ERR_NULL_ARGUMENT;
+            inputs.push_back(to_path(paths[i]));
+        }
+        handle->chisel.recompress(inputs);
+        return CHISEL_OK;
+    } catch (const std::exception& e) {
+        if (error_message) *error_message = duplicate(e.what());
+    } catch (...) {
+        if (error_message) *error_message = duplicate("unknown error");
+    }
+    return CHISEL_ERR_EXCEPTION;
+}
+
+void chisel_stop(ChiselHandle* handle) {
+    if (handle) handle->chisel.stop();
+}
+
+void chisel_free_error(char* message) {
+    std::free(message);
+}
+
+} // extern "C"
@@ -0,0 +1,256 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/deflate_reconstructor.hpp"
+#include "../../include/logger.hpp"
+#include <algorithm>
+#include <array>
+#include <cstring>
+#include <string>
+#include <zlib.h>
+
+namespace chisel {
+
+namespace {
+
+    constexpr std::size_t CHUNK = 64 * 1024;
+    // small output steps let a wrong candidate fail at its first block; the
+    // size must not change between analysis and reconstruction, since level 0
+    // sizes its stored blocks after the output space
+    constexpr std::size_t OUT_CHUNK = 4 * 1024;
+    constexpr std::size_t MAX_FEED = 1u << 30;
+
+    constexpr std::array<int, 10> LEVEL_ORDER = {6, 9, 1, 2, 3, 4, 5, 7, 8, 0};
+    constexpr std::array<int, 9> MEM_LEVEL_ORDER = {8, 9, 7, 6, 5, 4, 3, 2, 1};
+    // one level per header FLEVEL value, for strategies that ignore the level otherwise
+    constexpr std::array<int, 4> FLEVEL_REPRESENTATIVE = {1, 2, 6, 9};
+
+    const char* processor_tag() {
+        return "DeflateReconstructor";
+    }
+
+    int zlib_window_bits(int window_bits, DeflateWrapper wrapper) {
+        return wrapper == DeflateWrapper::Raw ? -window_bits : window_bits;
+    }
+
+    /**
+     * @brief The FLEVEL value zlib writes into the header for a level/strategy pair.
+     */
+    int header_flevel(int level, int strategy) {
+        if (strategy >= Z_HUFFMAN_ONLY || level < 2) return 0;
+        if (level < 6) return 1;
+        if (level == 6) return 2;
+        return 3;
+    }
+
+    /**
+     * @brief Inflates a single stream, stopping at its end marker.
+     * @return False if the data is corrupt, truncated or needs a preset dictionary.
+     */
+    bool inflate_stream(std::span<const unsigned char> in, DeflateWrapper wrapper,
+                        std::vector<unsigned char>& out, std::size_t& consumed) {
+        z_stream zs{};
+        if (inflateInit2(&zs, zlib_window_bits(15, wrapper)) != Z_OK) {
+            return false;
+        }
+
+        std::vector<unsigned char> buf(CHUNK);
+        std::size_t offset = 0;
+        int ret = Z_OK;
+        while (ret != Z_STREAM_END) {
+            if (zs.avail_in == 0) {
+                if (offset == in.size()) break;
+                const std::size_t feed = std::min(in.size() - offset, MAX_FEED);
+                zs.next_in = const_cast<Bytef*>(in.data() + offset);
+                zs.avail_in = static_cast<uInt>(feed);
+                offset += feed;
+            }
+            zs.next_out = buf.data();
+            zs.avail_out = static_cast<uInt>(buf.size());
+            ret = inflate(&zs, Z_NO_FLUSH);
+            if (ret != Z_OK && ret != Z_STREAM_END && ret != Z_BUF_ERROR) break;
+            out.insert(out.end(), buf.data(), buf.data() + (buf.size() - zs.avail_out));
+        }
+
+        consumed = offset - zs.avail_in;
+        inflateEnd(&zs);
+        return ret == Z_STREAM_END;
+    }
+
+    /**
+     * @brief Compresses `data` with `params`, feeding every OUT_CHUNK of output to `sink`.
+     *
+     * `sink` returns false to abandon the run.
+     * @return True if the whole stream was produced and accepted.
+     */
+    template <typename Sink>
+    bool run_deflate(std::span<const unsigned char> data, const DeflateParams& params,
+                     DeflateWrapper wrapper, Sink&& sink) {
+        z_stream zs{};
+        if (deflateInit2(&zs, params.level, Z_DEFLATED, zlib_window_bits(params.window_bits, wrapper),
+                         params.mem_level, params.strategy) != Z_OK) {
+            return false;
+        }
+
+        std::vector<unsigned char> buf(OUT_CHUNK);
+        std::size_t offset = 0;
+        bool ok = true;
+        int ret = Z_OK;
+        while (ok && ret != Z_STREAM_END) {
+            if (zs.avail_in == 0 && offset < data.size()) {
+                const std::size_t feed = std::min(data.size() - offset, MAX_FEED);
+                zs.next_in = const_cast<Bytef*>(data.data() + offset);
+                zs.avail_in = static_cast<uInt>(feed);
+                offset += feed;
+            }
+            const int flush = offset == data.size() ? Z_FINISH : Z_NO_FLUSH;
+            zs.next_out = buf.data();
+            zs.avail_out = static_cast<uInt>(buf.size());
+            ret = deflate(&zs, flush);
+            if (ret == Z_STREAM_ERROR) {
+                ok = false;
+                break;
+            }
+            const std::size_t produced = buf.size() - zs.avail_out;
+            if (produced > 0) {
+                ok = sink(std::span<const unsigned char>(buf.data(), produced));
+            }
+        }
+
+        deflateEnd(&zs);
+        return ok && ret == Z_STREAM_END;
+    }
+
+    /**
+     * @brief Tells whether compressing `data` with `params` yields exactly `expected`.
+     */
+    bool reproduces(std::span<const unsigned char> data, const DeflateParams& params,
+                    DeflateWrapper wrapper, std::span<const unsigned char> expected) {
+        std::size_t pos = 0;
+        const bool done = run_deflate(data, params, wrapper,
+            [&](std::span<const unsigned char> chunk) {
+                if (chunk.size() > expected.size() - pos ||
+                    std::memcmp(chunk.data(), expected.data() + pos, chunk.size()) != 0) {
+                    return false;
+                }
+                pos += chunk.size();
+                return true;
+            });
+        return done && pos == expected.size();
+    }
+
+    /**
+     * @brief Lists the parameter sets worth trying for a stream, most likely first.
+     */
+    std::vector<DeflateParams> candidates(std::span<const unsigned char> stream,
+                                          DeflateWrapper wrapper, bool exhaustive) {
+        // level 0 writes nothing but stored blocks (BTYPE 00)
+        const std::size_t first_block = wrapper == DeflateWrapper::Zlib ? 2 : 0;
+        const bool stored = stream.size() > first_block && ((stream[first_block] >> 1) & 3) == 0;
+
+        std::vector<int> windows;
+        int flevel = -1;
+        if (wrapper == DeflateWrapper::Zlib) {
+            // CINFO is the window size; zlib never writes windows below 512 bytes
+            windows.push_back(std::max(9, (stream[0] >> 4) + 8));
+            flevel = stream[1] >> 6;
+        } else if (exhaustive) {
+            for (int w = 15; w >= 9; --w) windows.push_back(w);
+        } else {
+            windows.push_back(15);
+        }
+
+        std::vector<int> mem_levels = {8};
+        std::vector<int> strategies = {Z_DEFAULT_STRATEGY};
+        if (exhaustive) {
+            mem_levels.assign(MEM_LEVEL_ORDER.begin(), MEM_LEVEL_ORDER.end());
+            strategies = {Z_DEFAULT_STRATEGY, Z_FILTERED, Z_RLE, Z_HUFFMAN_ONLY};
+        }
+
+        std::vector<DeflateParams> out;
+        for (const int strategy : strategies) {
+            for (const int level : LEVEL_ORDER) {
+                // level 0 stores whatever the strategy, Z_FILTERED only changes the
+                // lazy matcher (levels 4-9), and Z_RLE/Z_HUFFMAN_ONLY ignore the
+                // level beyond the header
+                if (strategy != Z_DEFAULT_STRATEGY && level == 0) continue;
+                if (level == 0 && !stored) continue;
+                if (strategy == Z_FILTERED && level < 4) continue;
+                if ((strategy == Z_RLE || strategy == Z_HUFFMAN_ONLY) &&
+                    level != FLEVEL_REPRESENTATIVE[header_flevel(level, strategy)]) continue;
+                if (flevel >= 0 && header_flevel(level, strategy) != flevel) continue;
+                for (const int window : windows) {
+                    for (const int mem_level : mem_levels) {
+                        out.push_back({level, window, mem_level, strategy});
+                    }
+                }
+            }
+        }
+        return out;
+    }
+
+} // namespace
+
+DeflateAnalysis DeflateReconstructor::analyze(std::span<const unsigned char> compressed,
+                                              DeflateWrapper wrapper,
+                                              DeflateSearch search) {
+    DeflateAnalysis result;
+    if (compressed.empty()) {
+        return result;
+    }
+    if (wrapper == DeflateWrapper::Zlib && compressed.size() < 2) {
+        return result;
+    }
+
+    result.valid = inflate_stream(compressed, wrapper, result.decompressed, result.consumed);
+    if (!result.valid) {
+        result.decompressed.clear();
+        return result;
+    }
+
+    if (search == DeflateSearch::None) {
+        return result;
+    }
+
+    const auto stream = compressed.first(result.consumed);
+    const auto list = candidates(stream, wrapper, search == DeflateSearch::Exhaustive);
+    for (const auto& params : list) {
+        if (reproduces(result.decompressed, params, wrapper, stream)) {
+            result.params = params;
+            break;
+        }
+    }
+
+    if (result.params) {
+        Logger::log(LogLevel::Debug,
+                    "Stream of " + std::to_string(result.consumed) + " bytes reproduced with level " +
+                    std::to_string(result.params->level) + ", window " + std::to_string(result.params->window_bits) +
+                    ", memLevel " + std::to_string(result.params->mem_level) + ", strategy " +
+                    std::to_string(result.params->strategy),
+                    processor_tag());
+    } else {
+        Logger::log(LogLevel::Debug,
+                    "Stream of " + std::to_string(result.consumed) + " bytes not reproducible (" +
+                    std::to_string(list.size()) + " candidates tried)",
+                    processor_tag());
+    }
+    return result;
+}
+
+std::optional<std::vector<unsigned char>> DeflateReconstructor::reconstruct(std::span<const unsigned char> decompressed,
+                                                                            const DeflateParams& params,
+                                                                            DeflateWrapper wrapper) {
+    std::vector<unsigned char> out;
+    const bool done = run_deflate(decompressed, params, wrapper,
+        [&](std::span<const unsigned char> chunk) {
+            out.insert(out.end(), chunk.begin(), chunk.end());
+            return true;
+        });
+    if (!done) {
+        return std::nullopt;
+    }
+    return out;
+}
+
+} // namespace chisel
@@ -6,6 +6,9 @@
 #include "../../include/file_utils.hpp"
 #include "../../include/logger.hpp"
 #include "../../include/random_utils.hpp"
+#include <algorithm>
+#include <fstream>
+#include <iterator>
 #include <system_error>
 
 namespace chisel {
@@ -63,5 +66,18 @@ namespace chisel {
         }
     }
 
+    bool files_equal(const std::filesystem::path& a, const std::filesystem::path& b) {
+        std::error_code ec_a;
+        std::error_code ec_b;
+        if (std::filesystem::file_size(a, ec_a) != std::filesystem::file_size(b, ec_b) || ec_a || ec_b) {
+            return false;
+        }
+        std::ifstream in_a(a, std::ios::binary);
+        std::ifstream in_b(b, std::ios::binary);
+        if (!in_a || !in_b) return false;
+        return std::equal(std::istreambuf_iterator<char>(in_a), std::istreambuf_iterator<char>(),
+                          std::istreambuf_iterator<char>(in_b), std::istreambuf_iterator<char>());
+    }
+
 } // namespace chisel
 
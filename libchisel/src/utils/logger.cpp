@@ -20,6 +20,12 @@ void Logger::clear_sinks() {
     sinks_.clear();
 }
 
+void Logger::remove_sink(const ILogSink* sink) {
+    if (!sink) return;
+    std::lock_guard lock(mtx_);
+    std::erase_if(sinks_, [sink](const std::unique_ptr<ILogSink>& s) { return s.get() == sink; });
+}
+
 void Logger::log(const LogLevel level,
                   const std::string_view msg,
                   const std::string_view tag) {
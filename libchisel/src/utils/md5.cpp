@@ -0,0 +1,111 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/md5.hpp"
+#include <algorithm>
+#include <bit>
+#include <cstring>
+
+namespace chisel {
+
+    namespace {
+        // RFC 1321: per-round shift amounts and the sine-derived constants
+        constexpr std::uint32_t kShifts[64] = {
+            7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+            5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+            4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+            6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21
+        };
+
+        constexpr std::uint32_t kTable[64] = {
+            0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+            0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+            0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+            0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+            0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+            0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+            0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+            0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391
+        };
+    }
+
+    Md5::Md5() noexcept
+        : state_{0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476} {
+    }
+
+    void Md5::transform(const std::uint8_t* block) noexcept {
+        std::uint32_t words[16];
+        for (int i = 0; i < 16; ++i) {
+            words[i] = static_cast<std::uint32_t>(block[i * 4]) |
+                       static_cast<std::uint32_t>(block[i * 4 + 1]) << 8 |
+                       static_cast<std::uint32_t>(block[i * 4 + 2]) << 16 |
+                       static_cast<std::uint32_t>(block[i * 4 + 3]) << 24;
+        }
+
+        std::uint32_t a = state_[0];
+        std::uint32_t b = state_[1];
+        std::uint32_t c = state_[2];
+        std::uint32_t d = state_[3];
+        for (int i = 0; i < 64; ++i) {
+            std::uint32_t f;
+            int g;
+            if (i < 16) {
+                f = (b & c) | (~b & d);
+                g = i;
+            } else if (i < 32) {
+                f = (d & b) | (~d & c);
+                g = (5 * i + 1) % 16;
+            } else if (i < 48) {
+                f = b ^ c ^ d;
+                g = (3 * i + 5) % 16;
+            } else {
+                f = c ^ (b | ~d);
+                g = (7 * i) % 16;
+            }
+            f += a + kTable[i] + words[g];
+            a = d;
+            d = c;
+            c = b;
+            b += std::rotl(f, static_cast<int>(kShifts[i]));
+        }
+        state_[0] += a;
+        state_[1] += b;
+        state_[2] += c;
+        state_[3] += d;
+    }
+
+    void Md5::update(std::span<const std::uint8_t> data) noexcept {
+        std::size_t used = length_ % 64;
+        length_ += data.size();
+        if (used > 0) {
+            const std::size_t take = std::min(data.size(), 64 - used);
+            std::memcpy(buffer_.data() + used, data.data(), take);
+            data = data.subspan(take);
+            used += take;
+            if (used < 64) return;
+            transform(buffer_.data());
+        }
+        while (data.size() >= 64) {
+            transform(data.data());
+            data = data.subspan(64);
+        }
+        if (!data.empty()) std::memcpy(buffer_.data(), data.data(), data.size());
+    }
+
+    std::array<std::uint8_t, 16> Md5::finish() noexcept {
+        const std::uint64_t bits = length_ * 8;
+        // 0x80, zeros up to 56 mod 64, then the bit length, little-endian
+        std::uint8_t padding[72] = {0x80};
+        const std::size_t pad = (length_ % 64 < 56 ? 56 : 120) - length_ % 64;
+        update({padding, pad});
+        std::uint8_t size[8];
+        for (int i = 0; i < 8; ++i) size[i] = static_cast<std::uint8_t>(bits >> (8 * i));
+        update(size);
+
+        std::array<std::uint8_t, 16> digest{};
+        for (int i = 0; i < 16; ++i) digest[i] = static_cast<std::uint8_t>(state_[i / 4] >> (8 * (i % 4)));
+        return digest;
+    }
+
+} // namespace chisel
@@ -11,12 +11,43 @@
 #include <algorithm>
 #include <filesystem>
 #include <fstream>
+#include <cstdint>
 #include <cstdlib>
 #include <vector>
 #include <zlib.h>
 
+namespace
+{
+    /**
+     * @brief Recognizes audio formats that may start with an ID3v2 tag.
+     *
+     * libmagic only looks at the tag and reports such files as audio/mpeg.
+     * @return The MIME type, or an empty string if the stream after the tag is not recognized.
+     */
+    std::string detect_after_id3v2(const std::filesystem::path& path)
+    {
+        std::ifstream in(path, std::ios::binary);
+        unsigned char head[10] = {};
+        if (!in.read(reinterpret_cast<char*>(head), sizeof(head))) return {};
+
+        std::uint64_t offset = 0;
+        if (std::equal(head, head + 3, "ID3") && ((head[6] | head[7] | head[8] | head[9]) & 0x80) == 0)
+        {
+            offset = 10 + (std::uint64_t{head[6]} << 21 | std::uint64_t{head[7]} << 14 |
+                           std::uint64_t{head[8]} << 7 | head[9]) + ((head[5] & 0x10) ? 10 : 0);
+        }
+
+        char signature[4] = {};
+        in.seekg(static_cast<std::streamoff>(offset));
+        if (!in.read(signature, sizeof(signature))) return {};
+        if (std::equal(signature, signature + 4, "TTA1")) return "audio/x-tta";
+        return {};
+    }
+}
+
 std::string chisel::MimeDetector::detect(const std::filesystem::path& path)
 {
+    if (std::string tagged = detect_after_id3v2(path); !tagged.empty()) return tagged;
 #ifndef _WIN32
     const magic_t magic = magic_open(MAGIC_MIME_TYPE | MAGIC_ERROR);
     if (magic == nullptr) return {};
@@ -40,7 +71,9 @@ std::string chisel::MimeDetector::detect(const std::filesystem::path& path)
 bool chisel::MimeDetector::is_mpeg1_layer3(const std::filesystem::path& path)
 {
 #ifndef _WIN32
-    const magic_t magic = magic_open(MAGIC_MIME_TYPE | MAGIC_ERROR);
+    // textual description ("MPEG ADTS, layer III, v1, ..."): the MIME type alone
+    // does not tell MPEG-1 Layer III apart from MPEG-2/2.5 or layers I/II
+    const magic_t magic = magic_open(MAGIC_ERROR);
     if (magic == nullptr) return false;
     if (magic_load(magic, nullptr) != 0)
     {
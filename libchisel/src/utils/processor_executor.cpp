@@ -9,16 +9,45 @@
 #include "../../include/logger.hpp"
 #include "../../include/events.hpp"
 #include "../../include/event_bus.hpp"
+#include "../../include/stop_scope.hpp"
+#include <algorithm>
 #include <filesystem>
 #include <future>
 #include <vector>
 #include <stack>
 #include <string>
+#include <span>
 #include <chrono>
 
 namespace fs = std::filesystem;
 
 namespace chisel {
+    namespace {
+        // companion files share the name of their main file, plus a suffix
+        fs::path companion_path(const fs::path& file, const std::string_view suffix) {
+            fs::path companion = file;
+            companion += suffix;
+            return companion;
+        }
+
+        uintmax_t companions_size(const fs::path& file, const std::span<const std::string_view> companions) {
+            uintmax_t total = 0;
+            for (const auto suffix : companions) {
+                std::error_code ec;
+                const auto size = fs::file_size(companion_path(file, suffix), ec);
+                if (!ec) total += size;
+            }
+            return total;
+        }
+
+        void remove_companions(const fs::path& file, const std::span<const std::string_view> companions) {
+            for (const auto suffix : companions) {
+                std::error_code ec;
+                fs::remove(companion_path(file, suffix), ec);
+            }
+        }
+    }
+
     ProcessorExecutor::ProcessorExecutor(ProcessorRegistry &registry,
                                          const bool preserve_metadata,
                                          const bool verify_checksums,
@@ -87,26 +116,140 @@ namespace chisel {
     void ProcessorExecutor::handle_temp_file(const fs::path& original_file,
                                              const fs::path& temp_file,
                                              const uintmax_t original_size,
-                                             const std::chrono::milliseconds duration) const {
+                                             const std::chrono::milliseconds duration,
+                                             const std::string_view new_extension,
+                                             const std::span<const std::string_view> companions) const {
         std::error_code ec;
         auto new_size = fs::file_size(temp_file, ec);
         if (ec || new_size == 0) {
             Logger::log(LogLevel::Warning, "Temp file is invalid or empty: " + temp_file.string(), "Executor");
             fs::remove(temp_file, ec);
+            remove_companions(temp_file, companions);
             event_bus_.publish(FileProcessErrorEvent{original_file, "Failed to create optimized file"});
             return;
         }
+        new_size += companions_size(temp_file, companions);
+
+        // companions are staged next to the destination first, then moved into
+        // place before the main file, with the companions they replace kept
+        // aside: a failure at any step puts the old pair back, so a new main
+        // file is never paired with a stale companion. A missing one is not
+        // an error
+        auto staged_path = [](const fs::path& dest, const std::string_view suffix) {
+            fs::path staged = companion_path(dest, suffix);
+            staged += ".tmp";
+            return staged;
+        };
+        auto backup_path = [](const fs::path& dest, const std::string_view suffix) {
+            fs::path backup = companion_path(dest, suffix);
+            backup += ".bak";
+            return backup;
+        };
+        auto discard_staged = [&](const fs::path& dest) {
+            for (const auto suffix : companions) {
+                std::error_code cec;
+                fs::remove(staged_path(dest, suffix), cec);
+            }
+        };
+        auto stage_companions = [&](const fs::path& dest) -> std::string {
+            for (const auto suffix : companions) {
+                const fs::path from = companion_path(temp_file, suffix);
+                const fs::path to = staged_path(dest, suffix);
+                std::error_code cec;
+                if (!fs::exists(from, cec)) continue;
+                fs::rename(from, to, cec);
+                if (cec) {
+                    // e.g. temp directory on another device
+                    cec.clear();
+                    fs::copy_file(from, to, fs::copy_options::overwrite_existing, cec);
+                }
+                if (cec) {
+                    discard_staged(dest);
+                    return companion_path(dest, suffix).string() + " (" + cec.message() + ")";
+                }
+                fs::remove(from, cec);
+            }
+            return {};
+        };
+        std::vector<std::string_view> committed;
+        // puts back the companions replaced so far
+        auto roll_back_companions = [&](const fs::path& dest) {
+            for (const auto suffix : committed) {
+                const fs::path final_path = companion_path(dest, suffix);
+                std::error_code cec;
+                fs::remove(final_path, cec);
+                if (fs::exists(backup_path(dest, suffix), cec)) {
+                    fs::rename(backup_path(dest, suffix), final_path, cec);
+                    if (cec) {
+                        Logger::log(LogLevel::Error, "Could not restore " + final_path.string() + " from " +
+                                    backup_path(dest, suffix).string() + " (" + cec.message() + ")", "Executor");
+                    }
+                }
+            }
+            committed.clear();
+            discard_staged(dest);
+        };
+        // same directory as the staged files: plain renames
+        auto commit_companions = [&](const fs::path& dest) -> std::string {
+            for (const auto suffix : companions) {
+                const fs::path from = staged_path(dest, suffix);
+                const fs::path final_path = companion_path(dest, suffix);
+                std::error_code cec;
+                if (!fs::exists(from, cec)) continue;
+                if (fs::exists(final_path, cec)) {
+                    if (fs::exists(backup_path(dest, suffix), cec)) {
+                        roll_back_companions(dest);
+                        return final_path.string() + " (" + backup_path(dest, suffix).string() + " already exists)";
+                    }
+                    fs::rename(final_path, backup_path(dest, suffix), cec);
+                }
+                if (!cec) {
+                    committed.push_back(suffix);
+                    fs::rename(from, final_path, cec);
+                }
+                if (cec) {
+                    roll_back_companions(dest);
+                    return final_path.string() + " (" + cec.message() + ")";
+                }
+            }
+            return {};
+        };
+        auto drop_backups = [&](const fs::path& dest) {
+            for (const auto suffix : committed) {
+                std::error_code cec;
+                fs::remove(backup_path(dest, suffix), cec);
+            }
+            committed.clear();
+        };
+        auto companion_failed = [&](const std::string& error) {
+            Logger::log(LogLevel::Error, "Companion file not replaced: " + error, "Executor");
+            std::error_code rec;
+            fs::remove(temp_file, rec);
+            remove_companions(temp_file, companions);
+            event_bus_.publish(FileProcessErrorEvent{original_file, "Companion file not replaced: " + error});
+        };
 
         bool replaced = false;
 
         if (dry_run_) {
             Logger::log(LogLevel::Info, "[DRY-RUN] Would replace: " + original_file.string(), "Executor");
             fs::remove(temp_file, ec);
+            remove_companions(temp_file, companions);
 
         } else if (has_output_dir_) {
-            const fs::path dest = output_is_directory_
+            fs::path dest = output_is_directory_
                                   ? (output_dir_ / original_file.filename())
                                   : output_dir_;
+            if (output_is_directory_ && !new_extension.empty()) {
+                // appended, not replaced: the original name stays recoverable
+                dest += new_extension;
+            }
+            std::string companion_error = stage_companions(dest);
+            if (companion_error.empty()) companion_error = commit_companions(dest);
+            if (!companion_error.empty()) {
+                companion_failed(companion_error);
+                return;
+            }
 
             int retries = 10;
             while (retries > 0) {
@@ -123,15 +266,37 @@ namespace chisel {
                 const std::string rename_error = ec.message();
                 Logger::log(LogLevel::Error, "Rename failed: " + original_file.string() + " -> " + dest.string() + " (" + rename_error + ")", "Executor");
                 fs::remove(temp_file, ec);
+                remove_companions(temp_file, companions);
+                roll_back_companions(dest);
                 event_bus_.publish(FileProcessErrorEvent{original_file, "Rename failed: " + rename_error});
                 return;
             }
+            drop_backups(dest);
             replaced = true;
 
         } else { // in-place
+            fs::path target = original_file;
+            if (!new_extension.empty()) {
+                // the file changes format: never overwrite an unrelated file
+                target += new_extension;
+                if (fs::exists(target, ec)) {
+                    Logger::log(LogLevel::Error, "Target already exists: " + target.string(), "Executor");
+                    fs::remove(temp_file, ec);
+                    remove_companions(temp_file, companions);
+                    event_bus_.publish(FileProcessErrorEvent{original_file, "Target already exists: " + target.string()});
+                    return;
+                }
+            }
+            std::string companion_error = stage_companions(target);
+            if (companion_error.empty()) companion_error = commit_companions(target);
+            if (!companion_error.empty()) {
+                companion_failed(companion_error);
+                return;
+            }
+
             int retries = 10;
             while (retries > 0) {
-                fs::rename(temp_file, original_file, ec);
+                fs::rename(temp_file, target, ec);
                 if (!ec) break; // success
 
                 if (ec.value() != 32 && ec.value() != 5 && ec.value() != 2) break;
@@ -147,10 +312,19 @@ namespace chisel {
 
                 std::error_code remove_ec;
                 fs::remove(temp_file, remove_ec);
+                remove_companions(temp_file, companions);
+                roll_back_companions(target);
 
                 event_bus_.publish(FileProcessErrorEvent{original_file, "Rename failed: " + rename_error});
                 return;
             }
+            if (target != original_file) {
+                fs::remove(original_file, ec);
+                if (ec) {
+                    Logger::log(LogLevel::Warning, "Could not remove original after conversion: " + original_file.string() + " (" + ec.message() + ")", "Executor");
+                }
+            }
+            drop_backups(target);
             replaced = true;
         }
 
@@ -184,6 +358,15 @@ namespace chisel {
             return;
         }
 
+        // a format-converting processor (e.g. Precomp) takes over a top-level container whole:
+        // its extraction would only rewrite the streams the converter archives
+        if (!nested_files_.contains(path) &&
+            std::ranges::any_of(procs, [](const IProcessor* p) { return !p->get_output_extension().empty(); })) {
+            work_list_.push_back(path);
+            event_bus_.publish(FileAnalyzeCompleteEvent{path, false, true});
+            return;
+        }
+
         IProcessor *processor = procs.front();
 
         const fs::path& current_path = path;
@@ -195,6 +378,7 @@ namespace chisel {
             if (content) {
                 finalize_stack_.push(*content);
                 for (const auto &child: content->extracted_files) {
+                    nested_files_.insert(child);
                     analyze_path(child);
                 }
                 scheduled_for_extraction = true;
@@ -232,6 +416,8 @@ namespace chisel {
                     event_bus_.publish(FileProcessSkippedEvent{file, "Interrupted"});
                     return;
                 }
+                // let processors poll for cancellation during long operations
+                const StopScope stop_scope(st);
                 event_bus_.publish(FileProcessStartEvent{file});
 
                 // collect all candidates
@@ -239,20 +425,47 @@ namespace chisel {
                 if (candidates.empty()) {
                     candidates = registry_.find_by_extension(file.extension().string());
                 }
+
+                // format-converting processors (e.g. Lepton) change what the file is: they never
+                // run inside containers, and replace the regular chain for top-level files
+                std::vector<IProcessor*> converters;
+                std::erase_if(candidates, [&converters](IProcessor* p) {
+                    if (p->get_output_extension().empty()) return false;
+                    converters.push_back(p);
+                    return true;
+                });
+                if (!converters.empty() && !nested_files_.contains(file)) {
+                    candidates = {converters.front()};
+                }
+
                 if (candidates.empty()) {
                     Logger::log(LogLevel::Warning, "no processor for " + file.string(), "Executor");
                     event_bus_.publish(FileProcessSkippedEvent{file, "Unsupported format"});
                     return;
                 }
+                // a converted file must always pass verification before the original goes away
+                const std::string_view output_extension = candidates.front()->get_output_extension();
+                const bool must_verify = verify_checksums_ || !output_extension.empty();
 
                 auto safe_size = [](const fs::path &p) {
                     std::error_code ec;
                     const auto s = fs::file_size(p, ec);
                     return ec ? 0ull : s;
                 };
+                // a file and its companions (e.g. .wv + .wvc) are sized and discarded as one unit
+                const auto companions = candidates.front()->get_companion_suffixes();
+                auto unit_size = [&](const fs::path &p) {
+                    const auto s = safe_size(p);
+                    return s == 0 ? 0ull : s + companions_size(p, companions);
+                };
+                auto remove_unit = [&](const fs::path &p) {
+                    std::error_code ec;
+                    fs::remove(p, ec);
+                    remove_companions(p, companions);
+                };
 
                 try {
-                    const auto orig_size = safe_size(file);
+                    const auto orig_size = unit_size(file);
                     auto start = std::chrono::steady_clock::now();
 
                     if (mode_ == EncodeMode::PIPE) {
@@ -272,13 +485,11 @@ namespace chisel {
                             auto sz = safe_size(tmp);
                             if (sz == 0) {
                                 pipeline_ok = false;
-                                std::error_code ec;
-                                fs::remove(tmp, ec);
+                                remove_unit(tmp);
                                 break;
                             }
                             if (current != file) {
-                                std::error_code ec;
-                                fs::remove(current, ec);
+                                remove_unit(current);
                             }
                             current = tmp;
                             last_tmp = tmp;
@@ -288,29 +499,26 @@ namespace chisel {
                         auto duration = std::chrono::duration_cast<std::chrono::milliseconds>(end - start);
 
                         if (pipeline_ok && !last_tmp.empty()) {
-                            auto new_size = safe_size(last_tmp);
+                            auto new_size = unit_size(last_tmp);
                             // accept the recompressed file only if it is smaller than the original
                             // and, if checksum verification is enabled, the raw checksums match
                             const bool size_improved = (new_size > 0 && new_size < orig_size);
-                            const bool checksum_ok = !verify_checksums_ ||
+                            const bool checksum_ok = !must_verify ||
                                 candidates[0]->raw_equal(file, last_tmp);
 
                             if (size_improved && checksum_ok) {
-                                handle_temp_file(file, last_tmp, orig_size, duration);
+                                handle_temp_file(file, last_tmp, orig_size, duration, output_extension, companions);
                             } else {
                                 if (!checksum_ok) {
-                                    std::error_code ec;
-                                    fs::remove(last_tmp, ec);
+                                    remove_unit(last_tmp);
                                     event_bus_.publish(FileProcessErrorEvent{file, "INTEGRITY CHECK FAILED: Data corruption detected"});
                                 } else {
-                                    std::error_code ec;
-                                    fs::remove(last_tmp, ec);
+                                    remove_unit(last_tmp);
                                     event_bus_.publish(FileProcessSkippedEvent{file, "No size improvement"});
                                 }
                             }
                         } else {
-                            auto err = std::error_code{};
-                            if (!last_tmp.empty()) fs::remove(last_tmp, err);
+                            if (!last_tmp.empty()) remove_unit(last_tmp);
                             if (st.stop_requested()) {
                                 event_bus_.publish(FileProcessSkippedEvent{file, "Interrupted"});
                             } else {
@@ -332,17 +540,15 @@ namespace chisel {
                             Result r{tmp, 0, false};
                             try {
                                 candidates[i]->recompress(file, tmp, preserve_metadata_);
-                                auto sz = safe_size(tmp);
+                                auto sz = unit_size(tmp);
                                 if (sz > 0) {
                                     r.size = sz;
                                     r.success = true;
                                 } else {
-                                    std::error_code ec;
-                                    fs::remove(tmp, ec);
+                                    remove_unit(tmp);
                                 }
                             } catch (...) {
-                                std::error_code ec;
-                                fs::remove(tmp, ec);
+                                remove_unit(tmp);
                             }
                             results.push_back(r);
                         }
@@ -356,18 +562,23 @@ namespace chisel {
                                                             return a.size < b.size;
                                                         });
 
-                        if (best_it != results.end() && best_it->success && best_it->size < orig_size) {
-                            handle_temp_file(file, best_it->tmp, orig_size, duration);
+                        const bool best_ok = best_it != results.end() && best_it->success && best_it->size < orig_size;
+                        if (best_ok && !output_extension.empty() &&
+                            !candidates[best_it - results.begin()]->raw_equal(file, best_it->tmp)) {
+                            for (const auto &r: results) {
+                                remove_unit(r.tmp);
+                            }
+                            event_bus_.publish(FileProcessErrorEvent{file, "INTEGRITY CHECK FAILED: Data corruption detected"});
+                        } else if (best_ok) {
+                            handle_temp_file(file, best_it->tmp, orig_size, duration, output_extension, companions);
                             for (const auto &r: results) {
                                 if (r.tmp != best_it->tmp) {
-                                    std::error_code ec2;
-                                    fs::remove(r.tmp, ec2);
+                                    remove_unit(r.tmp);
                                 }
                             }
                         } else {
                             for (const auto &r: results) {
-                                std::error_code ec;
-                                fs::remove(r.tmp, ec);
+                                remove_unit(r.tmp);
                             }
                             if (st.stop_requested()) {
                                 event_bus_.publish(FileProcessSkippedEvent{file, "Interrupted"});
@@ -377,6 +588,12 @@ namespace chisel {
                         }
                     }
                 } catch (const std::exception &e) {
+                    if (st.stop_requested()) {
+                        Logger::log(LogLevel::Debug, "interrupted on " + file.string() + ": " + std::string(e.what()),
+                                    "Executor");
+                        event_bus_.publish(FileProcessSkippedEvent{file, "Interrupted"});
+                        return;
+                    }
                     Logger::log(LogLevel::Error, "error on " + file.string() + ": " + std::string(e.what()),
                                 "Executor");
                     event_bus_.publish(FileProcessErrorEvent{file, e.what()});
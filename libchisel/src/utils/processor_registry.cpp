@@ -7,6 +7,7 @@
 #include "../../include/ape_processor.hpp"
 #include "../../include/archive_processor.hpp"
 #include "../../include/bmp_processor.hpp"
+#include "../../include/compressed_stream_processor.hpp"
 #include "../../include/flac_processor.hpp"
 #include "../../include/flexigif_processor.hpp"
 #include "../../include/gif_processor.hpp"
@@ -22,9 +23,12 @@
 #include "../../include/pdf_processor.hpp"
 #include "../../include/png_processor.hpp"
 #include "../../include/pnm_processor.hpp"
+#include "../../include/rust_bridge.hpp"
+#include "../../include/rust_processor.hpp"
 #include "../../include/sqlite_processor.hpp"
 #include "../../include/tiff_processor.hpp"
 #include "../../include/tga_processor.hpp"
+#include "../../include/tta_processor.hpp"
 #include "../../include/wav_processor.hpp"
 #include "../../include/wavpack_processor.hpp"
 #include "../../include/webp_processor.hpp"
@@ -38,6 +42,7 @@ ProcessorRegistry::ProcessorRegistry() {
     processors_.push_back(std::make_unique<FlacProcessor>());
     processors_.push_back(std::make_unique<WavPackProcessor>());
     processors_.push_back(std::make_unique<ApeProcessor>());
+    processors_.push_back(std::make_unique<TtaProcessor>());
     processors_.push_back(std::make_unique<JpegProcessor>());
     processors_.push_back(std::make_unique<PngProcessor>());
     processors_.push_back(std::make_unique<ZopfliPngProcessor>());
@@ -49,6 +54,7 @@ ProcessorRegistry::ProcessorRegistry() {
     processors_.push_back(std::make_unique<JxlProcessor>());
     processors_.push_back(std::make_unique<PdfProcessor>());
     processors_.push_back(std::make_unique<ArchiveProcessor>());
+    processors_.push_back(std::make_unique<CompressedStreamProcessor>());
     processors_.push_back(std::make_unique<OOXMLProcessor>());
     processors_.push_back(std::make_unique<OdfProcessor>());
     processors_.push_back(std::make_unique<SqliteProcessor>());
@@ -61,6 +67,20 @@ ProcessorRegistry::ProcessorRegistry() {
     processors_.push_back(std::make_unique<AiffProcessor>());
     processors_.push_back(std::make_unique<BmpProcessor>());
     processors_.push_back(std::make_unique<PnmProcessor>());
+
+    // processors implemented in the Rust bridge
+    const size_t rust_count = chisel_rust_processor_count();
+    for (size_t i = 0; i < rust_count; ++i) {
+        if (const ChiselProcessorVTable* vtable = chisel_rust_processor_get(i)) {
+            processors_.push_back(std::make_unique<RustProcessor>(*vtable));
+        }
+    }
+}
+
+void ProcessorRegistry::register_processor(std::unique_ptr<IProcessor> processor) {
+    if (processor) {
+        processors_.push_back(std::move(processor));
+    }
 }
 
 std::vector<IProcessor*> ProcessorRegistry::find_by_mime(const std::string& mime) const {
@@ -0,0 +1,26 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/stop_scope.hpp"
+#include <utility>
+
+namespace chisel {
+
+    namespace {
+        thread_local std::stop_token current_token;
+    }
+
+    StopScope::StopScope(std::stop_token token) noexcept
+        : previous_(std::exchange(current_token, std::move(token))) {
+    }
+
+    StopScope::~StopScope() {
+        current_token = std::move(previous_);
+    }
+
+    bool stop_requested() noexcept {
+        return current_token.stop_requested();
+    }
+
+} // namespace chisel
@@ -0,0 +1,323 @@
+//
+// Created by Giuseppe Francione on 16/10/26.
+//
+
+#include "../../include/zip_writer.hpp"
+#include "../../include/logger.hpp"
+#include <algorithm>
+#include <cstdlib>
+#include <iterator>
+#include <limits>
+#include <stdexcept>
+#include <zlib.h>
+#include "zopfli.h"
+
+namespace chisel {
+
+namespace {
+
+    constexpr std::uint32_t LOCAL_HEADER_SIG = 0x04034b50;
+    constexpr std::uint32_t CENTRAL_HEADER_SIG = 0x02014b50;
+    constexpr std::uint32_t END_OF_CENTRAL_DIR_SIG = 0x06054b50;
+    constexpr std::uint32_t ZIP64_END_OF_CENTRAL_DIR_SIG = 0x06064b50;
+    constexpr std::uint32_t ZIP64_LOCATOR_SIG = 0x07064b50;
+    constexpr std::uint16_t ZIP64_EXTRA_ID = 0x0001;
+
+    constexpr std::uint16_t METHOD_STORE = 0;
+    constexpr std::uint16_t METHOD_DEFLATE = 8;
+    constexpr std::uint16_t FLAG_UTF8 = 0x0800;
+
+    constexpr std::uint16_t VERSION_DEFAULT = 20; // 2.0: deflate, directories
+    constexpr std::uint16_t VERSION_ZIP64 = 45;   // 4.5: ZIP64 extensions
+    constexpr std::uint16_t MADE_BY_UNIX = 3 << 8;
+
+    // MS-DOS date of 1980-01-01 00:00, the earliest a ZIP entry can carry
+    constexpr std::uint16_t DOS_TIME = 0;
+    constexpr std::uint16_t DOS_DATE = (1 << 5) | 1;
+
+    constexpr std::uint32_t MODE_FILE = 0100644;
+    constexpr std::uint32_t MODE_SYMLINK = 0120777;
+
+    constexpr std::uint64_t MAX_U16 = std::numeric_limits<std::uint16_t>::max();
+    constexpr std::uint64_t MAX_U32 = std::numeric_limits<std::uint32_t>::max();
+
+    const char* processor_tag() {
+        return "ZipWriter";
+    }
+
+    void put16(std::vector<unsigned char>& out, std::uint64_t v) {
+        out.push_back(static_cast<unsigned char>(v));
+        out.push_back(static_cast<unsigned char>(v >> 8));
+    }
+
+    void put32(std::vector<unsigned char>& out, std::uint64_t v) {
+        for (int i = 0; i < 4; ++i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    void put64(std::vector<unsigned char>& out, std::uint64_t v) {
+        for (int i = 0; i < 8; ++i) out.push_back(static_cast<unsigned char>(v >> (8 * i)));
+    }
+
+    /**
+     * @brief Clamps a value to a 32-bit field, using the ZIP64 marker on overflow.
+     */
+    std::uint64_t field32(std::uint64_t v) {
+        return v >= MAX_U32 ? MAX_U32 : v;
+    }
+
+    std::uint32_t crc32_of(std::span<const unsigned char> data) {
+        uLong crc = crc32(0L, Z_NULL, 0);
+        const unsigned char* p = data.data();
+        std::size_t left = data.size();
+        while (left > 0) {
+            const auto chunk = static_cast<uInt>(std::min<std::size_t>(left, 1u << 30));
+            crc = crc32(crc, p, chunk);
+            p += chunk;
+            left -= chunk;
+        }
+        return static_cast<std::uint32_t>(crc);
+    }
+
+    /**
+     * @brief Deflates data with Zopfli into a raw (headerless) deflate stream.
+     */
+    std::vector<unsigned char> zopfli_deflate(const std::vector<unsigned char>& data, const ZipWriterOptions& options) {
+        ZopfliOptions opts;
+        ZopfliInitOptions(&opts);
+        opts.numiterations = options.iterations;
+        opts.blocksplitting = options.block_splitting ? 1 : 0;
+
+        unsigned char* out_data = nullptr;
+        size_t out_size = 0;
+        ZopfliCompress(&opts, ZOPFLI_FORMAT_DEFLATE, data.data(), data.size(), &out_data, &out_size);
+
+        std::vector<unsigned char> result(out_data, out_data + out_size);
+        free(out_data);
+        return result;
+    }
+
+    bool needs_utf8_flag(const std::string& name) {
+        return std::any_of(name.begin(), name.end(), [](char c) {
+            return static_cast<unsigned char>(c) >= 0x80;
+        });
+    }
+
+} // namespace
+
+ZipWriter::ZipWriter(const std::filesystem::path& path, ZipWriterOptions options)
+    : out_(path, std::ios::binary | std::ios::trunc), path_(path), options_(options) {
+    if (!out_) {
+        throw std::runtime_error("ZipWriter: cannot create " + path.string());
+    }
+}
+
+ZipWriter::~ZipWriter() {
+    if (!finished_ && out_.is_open()) {
+        out_.close();
+    }
+}
+
+void ZipWriter::add_file(const std::string& name, const std::vector<unsigned char>& data, bool allow_deflate,
+                         std::span<const unsigned char> original_deflate) {
+    write_entry(name, data, allow_deflate, MODE_FILE, original_deflate);
+}
+
+void ZipWriter::add_file_from(const std::string& name, const std::filesystem::path& source, bool allow_deflate) {
+    std::ifstream ifs(source, std::ios::binary);
+    if (!ifs) {
+        throw std::runtime_error("ZipWriter: cannot open " + source.string());
+    }
+    const std::vector<unsigned char> data((std::istreambuf_iterator<char>(ifs)), std::istreambuf_iterator<char>());
+    if (ifs.bad()) {
+        throw std::runtime_error("ZipWriter: cannot read " + source.string());
+    }
+    write_entry(name, data, allow_deflate, MODE_FILE);
+}
+
+void ZipWriter::add_deflated(const std::string& name, std::span<const unsigned char> deflated,
+                             std::uint32_t crc, std::uint64_t size) {
+    Entry entry;
+    entry.name = name;
+    entry.flags = needs_utf8_flag(name) ? FLAG_UTF8 : 0;
+    entry.method = METHOD_DEFLATE;
+    entry.crc = crc;
+    entry.size = size;
+    entry.compressed_size = deflated.size();
+    entry.external_attributes = MODE_FILE << 16;
+
+    Logger::log(LogLevel::Debug,
+                name + ": " + std::to_string(size) + " -> " + std::to_string(entry.compressed_size) +
+                " bytes (original deflate)",
+                processor_tag());
+
+    write_record(std::move(entry), deflated);
+}
+
+void ZipWriter::add_symlink(const std::string& name, const std::string& target) {
+    write_entry(name, std::vector<unsigned char>(target.begin(), target.end()), false, MODE_SYMLINK);
+}
+
+void ZipWriter::write_entry(const std::string& name, const std::vector<unsigned char>& data,
+                            bool allow_deflate, std::uint32_t mode,
+                            std::span<const unsigned char> original_deflate) {
+    Entry entry;
+    entry.name = name;
+    entry.flags = needs_utf8_flag(name) ? FLAG_UTF8 : 0;
+    entry.crc = crc32_of(data);
+    entry.size = data.size();
+    entry.external_attributes = mode << 16;
+
+    std::vector<unsigned char> deflated;
+    if (allow_deflate && !data.empty()) {
+        deflated = zopfli_deflate(data, options_);
+    }
+    const bool use_original = allow_deflate && !original_deflate.empty() &&
+                              original_deflate.size() < data.size() &&
+                              (deflated.empty() || original_deflate.size() <= deflated.size());
+    const bool use_deflate = !use_original && !deflated.empty() && deflated.size() < data.size();
+    std::span<const unsigned char> payload = data;
+    if (use_original) payload = original_deflate;
+    if (use_deflate) payload = deflated;
+    entry.method = use_original || use_deflate ? METHOD_DEFLATE : METHOD_STORE;
+    entry.compressed_size = payload.size();
+
+    Logger::log(LogLevel::Debug,
+                name + ": " + std::to_string(data.size()) + " -> " + std::to_string(entry.compressed_size) +
+                (use_original ? " bytes (original deflate)" : use_deflate ? " bytes (deflate)" : " bytes (store)"),
+                processor_tag());
+
+    write_record(std::move(entry), payload);
+}
+
+void ZipWriter::write_record(Entry entry, std::span<const unsigned char> payload) {
+    const std::string& name = entry.name;
+    if (finished_) {
+        throw std::runtime_error("ZipWriter: entry added after finish()");
+    }
+    if (name.empty() || name.size() > MAX_U16) {
+        throw std::runtime_error("ZipWriter: invalid entry name '" + name + "'");
+    }
+    entry.offset = offset_;
+
+    // sizes are known up front, so ZIP64 is needed in the local header only
+    // when they overflow; no data descriptor is ever written
+    const bool zip64_sizes = entry.size >= MAX_U32 || entry.compressed_size >= MAX_U32;
+
+    std::vector<unsigned char> header;
+    put32(header, LOCAL_HEADER_SIG);
+    put16(header, zip64_sizes ? VERSION_ZIP64 : VERSION_DEFAULT);
+    put16(header, entry.flags);
+    put16(header, entry.method);
+    put16(header, DOS_TIME);
+    put16(header, DOS_DATE);
+    put32(header, entry.crc);
+    put32(header, zip64_sizes ? MAX_U32 : entry.compressed_size);
+    put32(header, zip64_sizes ? MAX_U32 : entry.size);
+    put16(header, name.size());
+    put16(header, zip64_sizes ? 20 : 0);
+    header.insert(header.end(), name.begin(), name.end());
+    if (zip64_sizes) {
+        put16(header, ZIP64_EXTRA_ID);
+        put16(header, 16);
+        put64(header, entry.size);
+        put64(header, entry.compressed_size);
+    }
+
+    write_bytes(header);
+    write_bytes(payload);
+    entries_.push_back(std::move(entry));
+}
+
+void ZipWriter::write_bytes(std::span<const unsigned char> bytes) {
+    out_.write(reinterpret_cast<const char*>(bytes.data()), static_cast<std::streamsize>(bytes.size()));
+    if (!out_) {
+        throw std::runtime_error("ZipWriter: write failed for " + path_.string());
+    }
+    offset_ += bytes.size();
+}
+
+std::uint32_t ZipWriter::crc_of(std::span<const unsigned char> data) {
+    return crc32_of(data);
+}
+
+void ZipWriter::finish() {
+    if (finished_) return;
+
+    const std::uint64_t cd_offset = offset_;
+    std::vector<unsigned char> cd;
+    for (const auto& e : entries_) {
+        std::vector<unsigned char> extra;
+        if (e.size >= MAX_U32) put64(extra, e.size);
+        if (e.compressed_size >= MAX_U32) put64(extra, e.compressed_size);
+        if (e.offset >= MAX_U32) put64(extra, e.offset);
+        if (!extra.empty()) {
+            std::vector<unsigned char> field;
+            put16(field, ZIP64_EXTRA_ID);
+            put16(field, extra.size());
+            extra.insert(extra.begin(), field.begin(), field.end());
+        }
+        const std::uint16_t version = extra.empty() ? VERSION_DEFAULT : VERSION_ZIP64;
+
+        put32(cd, CENTRAL_HEADER_SIG);
+        put16(cd, MADE_BY_UNIX | version);
+        put16(cd, version);
+        put16(cd, e.flags);
+        put16(cd, e.method);
+        put16(cd, DOS_TIME);
+        put16(cd, DOS_DATE);
+        put32(cd, e.crc);
+        put32(cd, field32(e.compressed_size));
+        put32(cd, field32(e.size));
+        put16(cd, e.name.size());
+        put16(cd, extra.size());
+        put16(cd, 0); // comment length
+        put16(cd, 0); // disk number
+        put16(cd, 0); // internal attributes
+        put32(cd, e.external_attributes);
+        put32(cd, field32(e.offset));
+        cd.insert(cd.end(), e.name.begin(), e.name.end());
+        cd.insert(cd.end(), extra.begin(), extra.end());
+    }
+    write_bytes(cd);
+
+    const std::uint64_t count = entries_.size();
+    const std::uint64_t cd_size = cd.size();
+    const bool zip64 = count >= MAX_U16 || cd_size >= MAX_U32 || cd_offset >= MAX_U32;
+
+    std::vector<unsigned char> tail;
+    if (zip64) {
+        const std::uint64_t zip64_eocd_offset = offset_;
+        put32(tail, ZIP64_END_OF_CENTRAL_DIR_SIG);
+        put64(tail, 44); // size of the remaining record
+        put16(tail, MADE_BY_UNIX | VERSION_ZIP64);
+        put16(tail, VERSION_ZIP64);
+        put32(tail, 0); // this disk
+        put32(tail, 0); // disk with the central directory
+        put64(tail, count);
+        put64(tail, count);
+        put64(tail, cd_size);
+        put64(tail, cd_offset);
+
+        put32(tail, ZIP64_LOCATOR_SIG);
+        put32(tail, 0);
+        put64(tail, zip64_eocd_offset);
+        put32(tail, 1); // total disks
+    }
+    put32(tail, END_OF_CENTRAL_DIR_SIG);
+    put16(tail, 0);
+    put16(tail, 0);
+    put16(tail, std::min(count, MAX_U16));
+    put16(tail, std::min(count, MAX_U16));
+    put32(tail, field32(cd_size));
+    put32(tail, field32(cd_offset));
+    put16(tail, 0); // comment length
+    write_bytes(tail);
+
+    out_.close();
+    if (!out_) {
+        throw std::runtime_error("ZipWriter: cannot close " + path_.string());
+    }
+    finished_ = true;
+}
+
+} // namespace chisel